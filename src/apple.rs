@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Bundle extensions of Apple Photos (`.photoslibrary`) and iPhoto
+/// (`.photolibrary`) libraries.
+const LIBRARY_EXTENSIONS: &[&str] = &["photoslibrary", "photolibrary"];
+
+/// Folders of a library holding the imported originals: `originals/` since
+/// Photos 5 (macOS 10.15), `Masters/` before. Everything else (derivatives,
+/// renders, previews, thumbnails, databases) is skipped.
+const ORIGINALS_DIRS: &[&str] = &["originals", "Masters"];
+
+/// Photos 5+ database, relative to the library.
+const PHOTOS_DB: &str = "database/Photos.sqlite";
+
+/// `ZKIND` of the albums created by the user.
+const USER_ALBUM_KIND: i64 = 2;
+
+/// `true` for an Apple Photos or iPhoto library bundle.
+pub fn is_library(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| LIBRARY_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        && path.is_dir()
+}
+
+/// `true` for an entry directly inside a library bundle that is not one of
+/// its originals folders: such subtrees only hold copies of the originals.
+pub fn is_derivative(path: &Path) -> bool {
+    path.parent().is_some_and(is_library)
+        && !path
+            .file_name()
+            .is_some_and(|name| ORIGINALS_DIRS.iter().any(|d| name == *d))
+}
+
+/// Album titles and keywords of each original of a Photos 5+ library, keyed
+/// by the original's path. Libraries without `database/Photos.sqlite`
+/// (iPhoto, Photos before macOS 10.15) yield an empty map.
+pub fn read_library_tags(library: &Path) -> Result<HashMap<PathBuf, Vec<String>>> {
+    let mut tags: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let db_path = library.join(PHOTOS_DB);
+    if !db_path.is_file() {
+        return Ok(tags);
+    }
+    // `immutable` lets us read a library Photos has open (or a read-only copy)
+    let conn = Connection::open_with_flags(
+        format!("file:{}?immutable=1", db_path.display()),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Impossible d'ouvrir {}", db_path.display()))?;
+
+    // Core Data numbers its tables and join columns differently in each
+    // macOS release: look them up instead of hardcoding them.
+    let asset = ["ZASSET", "ZGENERICASSET"]
+        .into_iter()
+        .find(|t| table_exists(&conn, t))
+        .context("Base Photos non reconnue (table des photos introuvable)")?;
+
+    let mut queries = Vec::new();
+    if let Some((join, album_col, asset_col)) = join_table(&conn, "ALBUMS", "ASSETS") {
+        queries.push(format!(
+            "SELECT a.ZDIRECTORY, a.ZFILENAME, g.ZTITLE FROM {asset} a \
+             JOIN {join} j ON j.{asset_col} = a.Z_PK \
+             JOIN ZGENERICALBUM g ON g.Z_PK = j.{album_col} \
+             WHERE g.ZKIND = {USER_ALBUM_KIND} AND COALESCE(g.ZTRASHEDSTATE, 0) = 0"
+        ));
+    }
+    if let Some((join, keyword_col, attr_col)) = join_table(&conn, "KEYWORDS", "ASSETATTRIBUTES") {
+        queries.push(format!(
+            "SELECT a.ZDIRECTORY, a.ZFILENAME, k.ZTITLE FROM {asset} a \
+             JOIN ZADDITIONALASSETATTRIBUTES aa ON aa.ZASSET = a.Z_PK \
+             JOIN {join} j ON j.{attr_col} = aa.Z_PK \
+             JOIN ZKEYWORD k ON k.Z_PK = j.{keyword_col}"
+        ));
+    }
+
+    for query in queries {
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (Some(dir), Some(file), Some(tag)) = row? else {
+                continue;
+            };
+            let tag = tag.trim();
+            if tag.is_empty() {
+                continue;
+            }
+            let entry = tags
+                .entry(library.join("originals").join(dir).join(file))
+                .or_default();
+            if !entry.iter().any(|t| t == tag) {
+                entry.push(tag.to_string());
+            }
+        }
+    }
+    Ok(tags)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |_| Ok(()),
+    )
+    .is_ok()
+}
+
+/// Core Data join table `Z_<n><left>` with its `Z_<n><left>` and
+/// `Z_<m><right>` columns, e.g. `Z_26ASSETS(Z_26ALBUMS, Z_3ASSETS)`.
+fn join_table(conn: &Connection, left: &str, right: &str) -> Option<(String, String, String)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE 'Z\\_%' ESCAPE '\\'",
+        )
+        .ok()?;
+    let tables: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .ok()?
+        .filter_map(|t| t.ok())
+        .collect();
+    let numbered = |name: &str, suffix: &str| {
+        name.strip_prefix("Z_")
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    };
+    for table in tables {
+        let mut info = conn.prepare(&format!("PRAGMA table_info({table})")).ok()?;
+        let columns: Vec<String> = info
+            .query_map([], |row| row.get(1))
+            .ok()?
+            .filter_map(|c| c.ok())
+            .collect();
+        let left_col = columns.iter().find(|c| numbered(c, left));
+        let right_col = columns.iter().find(|c| numbered(c, right));
+        if let (Some(l), Some(r)) = (left_col, right_col) {
+            return Some((table.clone(), l.clone(), r.clone()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir =
+            std::env::temp_dir().join(format!("photo_sort_apple_test_{}_{id}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn only_originals_are_walked() {
+        let tmp = tmpdir();
+        let library = tmp.join("Photos Library.photoslibrary");
+        fs::create_dir_all(library.join("originals/A")).unwrap();
+        fs::create_dir_all(library.join("resources/derivatives")).unwrap();
+
+        assert!(is_library(&library));
+        assert!(!is_library(&tmp));
+        assert!(!is_derivative(&library.join("originals")));
+        assert!(!is_derivative(&library.join("originals/A")));
+        assert!(is_derivative(&library.join("resources")));
+        assert!(is_derivative(&library.join("database")));
+        assert!(!is_derivative(&tmp.join("resources")));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn reads_albums_and_keywords() {
+        let tmp = tmpdir();
+        let library = tmp.join("Photos Library.photoslibrary");
+        fs::create_dir_all(library.join("database")).unwrap();
+        let conn = Connection::open(library.join(PHOTOS_DB)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZASSET (Z_PK INTEGER PRIMARY KEY, ZDIRECTORY TEXT, ZFILENAME TEXT);
+             CREATE TABLE ZGENERICALBUM (Z_PK INTEGER PRIMARY KEY, ZTITLE TEXT, ZKIND INTEGER, ZTRASHEDSTATE INTEGER);
+             CREATE TABLE Z_28ASSETS (Z_28ALBUMS INTEGER, Z_3ASSETS INTEGER);
+             CREATE TABLE ZADDITIONALASSETATTRIBUTES (Z_PK INTEGER PRIMARY KEY, ZASSET INTEGER);
+             CREATE TABLE ZKEYWORD (Z_PK INTEGER PRIMARY KEY, ZTITLE TEXT);
+             CREATE TABLE Z_1KEYWORDS (Z_1ASSETATTRIBUTES INTEGER, Z_41KEYWORDS INTEGER);
+             INSERT INTO ZASSET VALUES (1, 'A', 'A1.heic'), (2, 'B', 'B2.jpeg');
+             INSERT INTO ZGENERICALBUM VALUES (10, 'Vacances', 2, 0), (11, 'Corbeille', 2, 1), (12, 'Dossier', 4000, 0);
+             INSERT INTO Z_28ASSETS VALUES (10, 1), (11, 1), (12, 1), (10, 2);
+             INSERT INTO ZADDITIONALASSETATTRIBUTES VALUES (100, 1);
+             INSERT INTO ZKEYWORD VALUES (50, 'plage');
+             INSERT INTO Z_1KEYWORDS VALUES (100, 50);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let tags = read_library_tags(&library).unwrap();
+        assert_eq!(
+            tags[&library.join("originals/A/A1.heic")],
+            vec!["Vacances", "plage"]
+        );
+        assert_eq!(tags[&library.join("originals/B/B2.jpeg")], vec!["Vacances"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn library_without_database_has_no_tags() {
+        let tmp = tmpdir();
+        let library = tmp.join("iPhoto Library.photolibrary");
+        fs::create_dir_all(library.join("Masters")).unwrap();
+        assert!(read_library_tags(&library).unwrap().is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::{Context, Result};
+use console::style;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::metadata::{COLOR_LABELS, Metadata};
+use crate::origins;
+use crate::sort::hash_file;
+use crate::xmp::{ImportPolicy, merge_imported};
+
+/// Root of the digiKam tags it uses for labels, not set by the user.
+const DIGIKAM_INTERNAL_TAGS: &str = "_Digikam_Internal_Tags_";
+
+/// Prefix of the digiKam internal tags holding color labels.
+const DIGIKAM_COLOR_PREFIX: &str = "Color Label ";
+
+/// Curation of one photo in a catalog.
+#[derive(Debug, Default, PartialEq)]
+struct Entry {
+    /// Absolute path of the photo when it was cataloged.
+    path: String,
+    tags: Vec<String>,
+    rating: Option<u8>,
+    label: Option<String>,
+    caption: Option<String>,
+}
+
+/// Color label of a catalog in our naming, for the English and French names
+/// used by Lightroom and digiKam.
+fn color_label(name: &str) -> Option<&'static str> {
+    let label = match name.trim().to_lowercase().as_str() {
+        "rouge" => "red",
+        "jaune" => "yellow",
+        "vert" => "green",
+        "bleu" => "blue",
+        "violet" | "magenta" => "purple",
+        "grey" | "gris" => "gray",
+        "noir" => "black",
+        "blanc" => "white",
+        other => return COLOR_LABELS.iter().copied().find(|l| *l == other),
+    };
+    Some(label)
+}
+
+/// Star rating, catalogs storing "not rated" as 0 or -1.
+fn rating(value: Option<i64>) -> Option<u8> {
+    value.filter(|r| (1..=5).contains(r)).map(|r| r as u8)
+}
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |_| Ok(()),
+    )
+    .is_ok()
+}
+
+/// Entries of a Lightroom Classic catalog (`.lrcat`).
+fn read_lightroom(conn: &Connection) -> Result<Vec<Entry>> {
+    let mut entries: HashMap<i64, Entry> = HashMap::new();
+    let caption = if table_exists(conn, "AgLibraryIPTC") {
+        "(SELECT c.caption FROM AgLibraryIPTC c WHERE c.image = i.id_local)"
+    } else {
+        "NULL"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT i.id_local, r.absolutePath || f.pathFromRoot || l.baseName || '.' || l.extension, \
+         i.rating, i.colorLabels, {caption} FROM Adobe_images i \
+         JOIN AgLibraryFile l ON l.id_local = i.rootFile \
+         JOIN AgLibraryFolder f ON f.id_local = l.folder \
+         JOIN AgLibraryRootFolder r ON r.id_local = f.rootFolder"
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<f64>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, path, stars, label, caption) = row?;
+        entries.insert(
+            id,
+            Entry {
+                path,
+                rating: rating(stars.map(|r| r as i64)),
+                label: label.as_deref().and_then(color_label).map(str::to_string),
+                caption: caption.filter(|c| !c.trim().is_empty()),
+                ..Default::default()
+            },
+        );
+    }
+
+    // The keyword tree has a nameless root
+    let mut stmt = conn.prepare(
+        "SELECT ki.image, k.name FROM AgLibraryKeywordImage ki \
+         JOIN AgLibraryKeyword k ON k.id_local = ki.tag WHERE k.name IS NOT NULL",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for row in rows {
+        let (id, keyword) = row?;
+        if let Some(entry) = entries.get_mut(&id)
+            && !entry.tags.contains(&keyword)
+        {
+            entry.tags.push(keyword);
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Entries of a digiKam database (`digikam4.db`).
+fn read_digikam(conn: &Connection) -> Result<Vec<Entry>> {
+    let mut entries: HashMap<i64, Entry> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT i.id, r.specificPath, a.relativePath, i.name, inf.rating, \
+         (SELECT c.comment FROM ImageComments c WHERE c.imageid = i.id AND c.type = 1 LIMIT 1) \
+         FROM Images i \
+         JOIN Albums a ON a.id = i.album \
+         JOIN AlbumRoots r ON r.id = a.albumRoot \
+         LEFT JOIN ImageInformation inf ON inf.imageid = i.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, root, album, name, stars, caption) = row?;
+        let mut path = root.trim_end_matches('/').to_string();
+        for part in [album.trim_matches('/'), name.as_str()] {
+            if !part.is_empty() {
+                path.push('/');
+                path.push_str(part);
+            }
+        }
+        entries.insert(
+            id,
+            Entry {
+                path,
+                rating: rating(stars),
+                caption: caption.filter(|c| !c.trim().is_empty()),
+                ..Default::default()
+            },
+        );
+    }
+
+    // Tags form a tree: labels live under the internal root
+    let mut stmt = conn.prepare("SELECT id, pid, name FROM Tags")?;
+    let tree: HashMap<i64, (i64, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<rusqlite::Result<_>>()?;
+    let internal = |mut id: i64| {
+        let mut seen = HashSet::new();
+        while let Some((parent, name)) = tree.get(&id) {
+            if name == DIGIKAM_INTERNAL_TAGS {
+                return true;
+            }
+            if !seen.insert(id) {
+                break;
+            }
+            id = *parent;
+        }
+        false
+    };
+    let mut stmt = conn.prepare("SELECT imageid, tagid FROM ImageTags")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (image, tag) = row?;
+        let (Some(entry), Some((_, name))) = (entries.get_mut(&image), tree.get(&tag)) else {
+            continue;
+        };
+        if !internal(tag) {
+            if !entry.tags.contains(name) {
+                entry.tags.push(name.clone());
+            }
+        } else if let Some(color) = name.strip_prefix(DIGIKAM_COLOR_PREFIX) {
+            entry.label = color_label(color).map(str::to_string);
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Read a Lightroom catalog or a digiKam database.
+fn read_catalog(path: &Path) -> Result<Vec<Entry>> {
+    // `immutable` lets us read a catalog the other tool has open
+    let conn = Connection::open_with_flags(
+        format!("file:{}?immutable=1", path.display()),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Impossible d'ouvrir {}", path.display()))?;
+    if table_exists(&conn, "Adobe_images") {
+        read_lightroom(&conn)
+    } else if table_exists(&conn, "Images") && table_exists(&conn, "AlbumRoots") {
+        read_digikam(&conn)
+    } else {
+        anyhow::bail!(
+            "{} n'est ni un catalogue Lightroom ni une base digiKam",
+            path.display()
+        )
+    }
+}
+
+/// File name of a path written by any OS.
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Map the keywords, ratings, color labels and captions of a Lightroom
+/// catalog or digiKam database onto the metadata of the sorted library.
+/// Photos are found through their original path (recorded by `sort`), their
+/// content if the original is still there, or a file name unique among the
+/// originals.
+pub fn run_import_catalog(catalog: &Path, dir: &Path, policy: ImportPolicy) -> Result<()> {
+    let entries = read_catalog(catalog)?;
+    let mut metadata = Metadata::load(dir)?;
+
+    let origins = origins::load(dir)?;
+    let by_source: HashMap<&str, &str> = origins
+        .iter()
+        .map(|o| (o.source.as_str(), o.dest.as_str()))
+        .collect();
+    let mut by_name: HashMap<&str, Option<&str>> = HashMap::new();
+    for origin in &origins {
+        by_name
+            .entry(file_name(&origin.source))
+            .and_modify(|dest| {
+                if *dest != Some(origin.dest.as_str()) {
+                    *dest = None;
+                }
+            })
+            .or_insert(Some(origin.dest.as_str()));
+    }
+
+    let mut unresolved: Vec<(&Entry, String)> = Vec::new();
+    let mut incoming = Metadata::default();
+    let mut found = 0usize;
+    let mut add = |incoming: &mut Metadata, file: &str, entry: &Entry| {
+        for tag in &entry.tags {
+            incoming.add_tag(file, tag);
+        }
+        incoming.set_rating(file, entry.rating);
+        incoming.set_label(file, entry.label.as_deref());
+        if let Some(caption) = &entry.caption {
+            incoming.set_caption(file, caption);
+        }
+        found += 1;
+    };
+    for entry in &entries {
+        if entry.tags.is_empty()
+            && entry.rating.is_none()
+            && entry.label.is_none()
+            && entry.caption.is_none()
+        {
+            continue;
+        }
+        if let Some(dest) = by_source.get(entry.path.as_str()) {
+            add(&mut incoming, dest, entry);
+        } else if let Ok(hash) = hash_file(Path::new(&entry.path)) {
+            unresolved.push((entry, hash));
+        } else if let Some(Some(dest)) = by_name.get(file_name(&entry.path)) {
+            add(&mut incoming, dest, entry);
+        } else {
+            unresolved.push((entry, String::new()));
+        }
+    }
+    let wanted: HashSet<String> = unresolved
+        .iter()
+        .map(|(_, hash)| hash.clone())
+        .filter(|hash| !hash.is_empty())
+        .collect();
+    let by_hash = if wanted.is_empty() {
+        HashMap::new()
+    } else {
+        origins::sorted_by_hash(dir, &metadata, &wanted)?
+    };
+    let mut missing = 0usize;
+    for (entry, hash) in unresolved {
+        match by_hash.get(&hash) {
+            Some(dest) => add(&mut incoming, dest, entry),
+            None => missing += 1,
+        }
+    }
+
+    let updated = merge_imported(&mut metadata, &incoming, policy);
+    metadata.save(dir)?;
+
+    println!(
+        "  {} {} photo(s) curée(s) retrouvée(s) dans la photothèque, {} mise(s) à jour",
+        style("✔").green().bold(),
+        style(found).green().bold(),
+        style(updated).green().bold()
+    );
+    if missing > 0 {
+        println!(
+            "  {} {} photo(s) du catalogue introuvable(s) dans la photothèque",
+            style("!").yellow().bold(),
+            missing
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_catalog_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn library(tmp: &Path) -> PathBuf {
+        let library = tmp.join("library");
+        fs::create_dir_all(library.join("2019")).unwrap();
+        for (dest, source) in [
+            ("2019/2019-05-01_10-00-00.jpg", "/home/a/Photos/IMG_1.jpg"),
+            ("2019/2019-05-02_10-00-00.jpg", "/old/disk/IMG_2.jpg"),
+        ] {
+            fs::write(library.join(dest), dest).unwrap();
+            origins::append(
+                &library,
+                &origins::Origin {
+                    dest: dest.into(),
+                    source: source.into(),
+                },
+            )
+            .unwrap();
+        }
+        library
+    }
+
+    #[test]
+    fn imports_lightroom_catalog() {
+        let tmp = tmpdir();
+        let library = library(&tmp);
+        let catalog = tmp.join("Catalog.lrcat");
+        Connection::open(&catalog)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE AgLibraryRootFolder (id_local INTEGER, absolutePath TEXT);
+                 CREATE TABLE AgLibraryFolder (id_local INTEGER, rootFolder INTEGER, pathFromRoot TEXT);
+                 CREATE TABLE AgLibraryFile (id_local INTEGER, folder INTEGER, baseName TEXT, extension TEXT);
+                 CREATE TABLE Adobe_images (id_local INTEGER, rootFile INTEGER, rating REAL, colorLabels TEXT);
+                 CREATE TABLE AgLibraryKeyword (id_local INTEGER, name TEXT);
+                 CREATE TABLE AgLibraryKeywordImage (image INTEGER, tag INTEGER);
+                 CREATE TABLE AgLibraryIPTC (image INTEGER, caption TEXT);
+                 INSERT INTO AgLibraryRootFolder VALUES (1, '/home/a/'), (2, 'D:/Photos/');
+                 INSERT INTO AgLibraryFolder VALUES (10, 1, 'Photos/'), (11, 2, '2019/');
+                 INSERT INTO AgLibraryFile VALUES (100, 10, 'IMG_1', 'jpg'), (101, 11, 'IMG_2', 'jpg');
+                 INSERT INTO Adobe_images VALUES (1000, 100, 4, 'Rouge'), (1001, 101, 0, '');
+                 INSERT INTO AgLibraryKeyword VALUES (1, NULL), (2, 'plage');
+                 INSERT INTO AgLibraryKeywordImage VALUES (1000, 1), (1000, 2), (1001, 2);
+                 INSERT INTO AgLibraryIPTC VALUES (1000, 'Carnac');",
+            )
+            .unwrap();
+
+        run_import_catalog(&catalog, &library, ImportPolicy::Merge).unwrap();
+
+        let meta = Metadata::load(&library).unwrap();
+        let first = "2019/2019-05-01_10-00-00.jpg";
+        assert_eq!(meta.get_tags(first), ["plage"]);
+        assert_eq!(meta.get_rating(first), Some(4));
+        assert_eq!(meta.get_label(first), Some("red"));
+        assert_eq!(meta.get_caption(first), Some("Carnac"));
+        // Found by its file name, the Windows path being unknown here
+        let second = "2019/2019-05-02_10-00-00.jpg";
+        assert_eq!(meta.get_tags(second), ["plage"]);
+        assert_eq!(meta.get_rating(second), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn imports_digikam_database() {
+        let tmp = tmpdir();
+        let library = library(&tmp);
+        let catalog = tmp.join("digikam4.db");
+        Connection::open(&catalog)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE AlbumRoots (id INTEGER, identifier TEXT, specificPath TEXT);
+                 CREATE TABLE Albums (id INTEGER, albumRoot INTEGER, relativePath TEXT);
+                 CREATE TABLE Images (id INTEGER, album INTEGER, name TEXT);
+                 CREATE TABLE ImageInformation (imageid INTEGER, rating INTEGER);
+                 CREATE TABLE ImageComments (imageid INTEGER, type INTEGER, comment TEXT);
+                 CREATE TABLE Tags (id INTEGER, pid INTEGER, name TEXT);
+                 CREATE TABLE ImageTags (imageid INTEGER, tagid INTEGER);
+                 INSERT INTO AlbumRoots VALUES (1, 'volumeid:?uuid=x', '/home/a/');
+                 INSERT INTO Albums VALUES (1, 1, '/Photos');
+                 INSERT INTO Images VALUES (1, 1, 'IMG_1.jpg');
+                 INSERT INTO ImageInformation VALUES (1, 5);
+                 INSERT INTO ImageComments VALUES (1, 1, 'Anniversaire');
+                 INSERT INTO Tags VALUES (1, 0, 'Lieux'), (2, 1, 'Bretagne'),
+                   (3, 0, '_Digikam_Internal_Tags_'), (4, 3, 'Color Label Green'), (5, 3, 'Pick Label Accepted');
+                 INSERT INTO ImageTags VALUES (1, 2), (1, 4), (1, 5);",
+            )
+            .unwrap();
+
+        let mut meta = Metadata::default();
+        meta.set_caption("2019/2019-05-01_10-00-00.jpg", "Déjà légendée");
+        meta.save(&library).unwrap();
+        run_import_catalog(&catalog, &library, ImportPolicy::Ours).unwrap();
+
+        let meta = Metadata::load(&library).unwrap();
+        let file = "2019/2019-05-01_10-00-00.jpg";
+        assert_eq!(meta.get_tags(file), ["Bretagne"]);
+        assert_eq!(meta.get_rating(file), Some(5));
+        assert_eq!(meta.get_label(file), Some("green"));
+        assert_eq!(meta.get_caption(file), Some("Déjà légendée"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn rejects_unknown_database() {
+        let tmp = tmpdir();
+        let path = tmp.join("other.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (x INTEGER);")
+            .unwrap();
+        assert!(read_catalog(&path).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
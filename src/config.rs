@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use crate::sort::{PairPreference, SortOptions};
+
+/// Name of the configuration file (at the root of the library).
+pub const CONFIG_FILE: &str = "photo-sort.toml";
+
+/// Settings chosen with `photo-sort init`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Folders to sort into the library.
+    pub sources: Vec<PathBuf>,
+    /// Sorted library (output dir of `sort`, dir of `serve`).
+    pub library: PathBuf,
+    /// Gallery language.
+    pub language: String,
+    pub layout: LayoutConfig,
+    pub server: ServerConfig,
+}
+
+/// How sorted files are named and laid out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub keep_name: bool,
+    pub auto_orient: bool,
+    pub pair_prefer: Option<PairPreference>,
+    /// Hours without photos that start a new event folder.
+    pub group_events: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address `serve` listens on: this machine only unless set to 0.0.0.0.
+    pub host: IpAddr,
+    pub port: u16,
+    pub guest_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            library: PathBuf::new(),
+            language: "fr".to_string(),
+            layout: LayoutConfig::default(),
+            server: ServerConfig::default(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8080,
+            guest_token: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load `photo-sort.toml` from `dir`, if present.
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("Impossible de lire {}", path.display()))?;
+        let config = toml::from_str(&data)
+            .with_context(|| format!("Configuration invalide : {}", path.display()))?;
+        Ok(Some(config))
+    }
+
+    /// Write `photo-sort.toml` at the root of the library.
+    pub fn save(&self) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.library)?;
+        let path = self.library.join(CONFIG_FILE);
+        let data = toml::to_string_pretty(self)?;
+        std::fs::write(&path, data)
+            .with_context(|| format!("Impossible d'écrire {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn sort_options(&self) -> SortOptions {
+        SortOptions {
+            keep_name: self.layout.keep_name,
+            auto_orient: self.layout.auto_orient,
+            pair_prefer: self.layout.pair_prefer,
+            group_events: self.layout.group_events,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_config_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let tmp = tmpdir();
+        let config = Config {
+            sources: vec![PathBuf::from("/photos/telephone")],
+            library: tmp.join("library"),
+            layout: LayoutConfig {
+                keep_name: true,
+                pair_prefer: Some(PairPreference::Jpeg),
+                ..Default::default()
+            },
+            server: ServerConfig {
+                host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                port: 3000,
+                guest_token: Some("abc".to_string()),
+            },
+            ..Default::default()
+        };
+        let path = config.save().unwrap();
+        assert_eq!(path, tmp.join("library/photo-sort.toml"));
+
+        let loaded = Config::load(&tmp.join("library")).unwrap().unwrap();
+        assert_eq!(loaded, config);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let config: Config = toml::from_str("library = \"/photos\"").unwrap();
+        assert_eq!(config.language, "fr");
+        assert_eq!(config.server.port, 8080);
+        assert!(config.server.host.is_loopback());
+        assert!(!config.layout.keep_name);
+    }
+
+    #[test]
+    fn load_without_file_is_none() {
+        let tmp = tmpdir();
+        assert!(Config::load(&tmp).unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::sort::is_raw;
+use crate::thumb::{generate_once, thumb_is_fresh, THUMB_DIR};
+
+/// Subfolder of the thumbnail cache holding full-size RAW renders.
+const DEVELOPED_DIR: &str = "developed";
+
+/// Embedded previews at least this wide are served as-is, without developing.
+const MIN_PREVIEW_WIDTH: u32 = 1600;
+
+/// External RAW developer, run as `program args…` with `{input}` and
+/// `{output}` replaced by the RAW file and the JPEG to produce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Developer {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Developer {
+    /// Parse `darktable`, `rawtherapee`, or a custom command template such as
+    /// `"my-dev --quality 95 {input} {output}"`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let words: Vec<String> = match spec.trim() {
+            "darktable" => vec!["darktable-cli", "{input}", "{output}"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            "rawtherapee" => vec!["rawtherapee-cli", "-Y", "-j95", "-o", "{output}", "-c", "{input}"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            custom => custom.split_whitespace().map(String::from).collect(),
+        };
+        let Some((program, args)) = words.split_first() else {
+            anyhow::bail!("Commande de développement RAW vide");
+        };
+        if !args.iter().any(|a| a.contains("{input}")) || !args.iter().any(|a| a.contains("{output}")) {
+            anyhow::bail!("La commande de développement doit contenir {{input}} et {{output}}");
+        }
+        Ok(Self {
+            program: program.clone(),
+            args: args.to_vec(),
+        })
+    }
+
+    /// Render `source` into the JPEG `dest` (written atomically).
+    pub fn develop(&self, source: &Path, dest: &Path) -> Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Keep a .jpg extension: developers pick the format from it.
+        let partial = dest.with_extension("partial.jpg");
+        let _ = fs::remove_file(&partial);
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|a| {
+                a.replace("{input}", &source.to_string_lossy())
+                    .replace("{output}", &partial.to_string_lossy())
+            })
+            .collect();
+        let output = Command::new(&self.program)
+            .args(&args)
+            .output()
+            .with_context(|| format!("Impossible de lancer {}", self.program))?;
+        if !output.status.success() || !partial.is_file() {
+            let _ = fs::remove_file(&partial);
+            anyhow::bail!(
+                "{} a échoué : {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        fs::rename(&partial, dest)?;
+        Ok(())
+    }
+}
+
+/// Cache path of the full-size render of a RAW file.
+/// E.g. `/photos/.photo_sort_thumbs/developed/2020/a.cr2.jpg`
+pub fn developed_cache_path(base: &Path, rel: &str) -> PathBuf {
+    base.join(THUMB_DIR)
+        .join(DEVELOPED_DIR)
+        .join(format!("{rel}.jpg"))
+}
+
+/// JPEG preview embedded in the EXIF thumbnail IFD of a RAW file, if any.
+pub fn embedded_preview(path: &Path) -> Option<Vec<u8>> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let offset = exif
+        .get_field(exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let len = exif
+        .get_field(exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    exif.buf().get(offset..offset + len).map(<[u8]>::to_vec)
+}
+
+/// Return a full-size JPEG for a RAW file: its embedded preview when it is
+/// large enough, otherwise a render by `developer` (cached and reused while
+/// newer than the RAW). Returns `None` for non-RAW files, or when no usable
+/// preview exists and no developer is configured.
+pub fn get_or_develop(
+    base: &Path,
+    rel: &str,
+    developer: Option<&Developer>,
+) -> Result<Option<PathBuf>> {
+    let source = base.join(rel);
+    if !is_raw(&source) {
+        return Ok(None);
+    }
+
+    let cached = developed_cache_path(base, rel);
+    if cached.exists() && thumb_is_fresh(&source, &cached) {
+        return Ok(Some(cached));
+    }
+
+    generate_once(&cached, || {
+        if cached.exists() && thumb_is_fresh(&source, &cached) {
+            return Ok(());
+        }
+        let preview = embedded_preview(&source);
+        let preview_width = preview
+            .as_deref()
+            .and_then(|p| image::load_from_memory(p).ok())
+            .map(|img| img.width());
+
+        match (preview, developer) {
+            (Some(preview), _) if preview_width.is_some_and(|w| w >= MIN_PREVIEW_WIDTH) => {
+                write_cache(&cached, &preview)
+            }
+            (_, Some(developer)) => developer.develop(&source, &cached),
+            (Some(preview), None) if preview_width.is_some() => write_cache(&cached, &preview),
+            // Nothing to show: leave the cache empty
+            _ => Ok(()),
+        }
+    })?;
+    Ok(cached.exists().then_some(cached))
+}
+
+fn write_cache(cached: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = cached.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = cached.with_extension("partial");
+    fs::write(&partial, data)
+        .with_context(|| format!("Impossible d'écrire {}", cached.display()))?;
+    fs::rename(&partial, cached)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_develop_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_presets_and_custom() {
+        let dt = Developer::parse("darktable").unwrap();
+        assert_eq!(dt.program, "darktable-cli");
+        let rt = Developer::parse("rawtherapee").unwrap();
+        assert_eq!(rt.program, "rawtherapee-cli");
+        let custom = Developer::parse("dcraw-wrap -q 3 {input} {output}").unwrap();
+        assert_eq!(custom.program, "dcraw-wrap");
+        assert_eq!(custom.args, vec!["-q", "3", "{input}", "{output}"]);
+    }
+
+    #[test]
+    fn parse_requires_placeholders() {
+        assert!(Developer::parse("").is_err());
+        assert!(Developer::parse("dcraw {input}").is_err());
+        assert!(Developer::parse("dcraw -o {output}").is_err());
+    }
+
+    #[test]
+    fn cache_path_keeps_raw_extension() {
+        assert_eq!(
+            developed_cache_path(Path::new("/p"), "2020/a.cr2"),
+            PathBuf::from("/p/.photo_sort_thumbs/developed/2020/a.cr2.jpg")
+        );
+    }
+
+    #[test]
+    fn non_raw_files_are_not_developed() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "jpeg").unwrap();
+        let dev = Developer::parse("cp {input} {output}").unwrap();
+
+        assert!(get_or_develop(&tmp, "2020/a.jpg", Some(&dev)).unwrap().is_none());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn raw_without_preview_needs_developer() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.cr2"), "raw data").unwrap();
+
+        assert!(get_or_develop(&tmp, "2020/a.cr2", None).unwrap().is_none());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn develops_and_reuses_cache() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.cr2"), "raw data").unwrap();
+        let dev = Developer::parse("cp {input} {output}").unwrap();
+
+        let first = get_or_develop(&tmp, "2020/a.cr2", Some(&dev))
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, developed_cache_path(&tmp, "2020/a.cr2"));
+        assert_eq!(fs::read(&first).unwrap(), b"raw data");
+
+        // A failing developer is not called again while the cache is fresh
+        let broken = Developer::parse("false {input} {output}").unwrap();
+        let second = get_or_develop(&tmp, "2020/a.cr2", Some(&broken))
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, second);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_developer_leaves_no_cache() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.cr2"), "raw data").unwrap();
+        let broken = Developer::parse("false {input} {output}").unwrap();
+
+        assert!(get_or_develop(&tmp, "2020/a.cr2", Some(&broken)).is_err());
+        assert!(!developed_cache_path(&tmp, "2020/a.cr2").exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,154 @@
+use image::DynamicImage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::metadata::Metadata;
+use crate::thumb;
+
+/// Most bits two visual hashes may differ by for their photos to count as
+/// near-identical (a re-encode, a resize, a slight crop).
+pub const SIMILAR_DISTANCE: u32 = 6;
+
+/// Why a photo is flagged in the gallery.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Duplicate {
+    /// Another photo has the same content hash.
+    Identical,
+    /// Another photo looks the same, within `SIMILAR_DISTANCE`.
+    Similar,
+}
+
+/// Difference hash: each bit tells whether a pixel of the image shrunk to
+/// 9×8 grays is brighter than its right neighbour. Close images get hashes
+/// differing by few bits.
+pub fn visual_hash(img: &DynamicImage) -> u64 {
+    let small = img
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Compute and cache the visual hash of photos that don't have one yet,
+/// from their thumbnail. Returns the number of entries filled in.
+pub fn cache_visual_hashes(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &mut Metadata,
+) -> usize {
+    let mut added = 0;
+    for file in photos_by_year.values().flatten() {
+        if metadata.get_visual_hash(file).is_some() {
+            continue;
+        }
+        let Ok(Some(cached)) = thumb::get_or_create_thumb(dir, file) else {
+            continue;
+        };
+        if let Ok(img) = thumb::open_image(&cached) {
+            metadata.set_visual_hash(file, Some(visual_hash(&img)));
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Photos of `files` having a byte-identical or near-identical sibling among
+/// them.
+pub fn find_duplicates<'a>(files: &[&'a str], metadata: &Metadata) -> HashMap<&'a str, Duplicate> {
+    let mut found = HashMap::new();
+    let mut by_hash: HashMap<&str, Vec<&str>> = HashMap::new();
+    for &file in files {
+        if let Some(hash) = metadata.files.get(file).and_then(|i| i.hash.as_deref()) {
+            by_hash.entry(hash).or_default().push(file);
+        }
+    }
+    for group in by_hash.values().filter(|g| g.len() > 1) {
+        for &file in group {
+            found.insert(file, Duplicate::Identical);
+        }
+    }
+
+    // Two hashes within SIMILAR_DISTANCE bits share at least one of their 8
+    // bytes: only photos sharing a byte are compared
+    let hashes: Vec<(&str, u64)> = (files.iter())
+        .filter_map(|&f| Some((f, metadata.get_visual_hash(f)?)))
+        .collect();
+    let mut buckets: HashMap<(usize, u8), Vec<usize>> = HashMap::new();
+    for (index, (_, hash)) in hashes.iter().enumerate() {
+        for (byte, value) in hash.to_be_bytes().into_iter().enumerate() {
+            buckets.entry((byte, value)).or_default().push(index);
+        }
+    }
+    for bucket in buckets.values() {
+        for (i, &a) in bucket.iter().enumerate() {
+            for &b in &bucket[i + 1..] {
+                if (hashes[a].1 ^ hashes[b].1).count_ones() <= SIMILAR_DISTANCE {
+                    for file in [hashes[a].0, hashes[b].0] {
+                        found.entry(file).or_insert(Duplicate::Similar);
+                    }
+                }
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(shift: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(90, 80, |x, y| {
+            let v = ((x * 2 + y) as u8).wrapping_add(shift);
+            image::Rgb([v, v, v])
+        }))
+    }
+
+    #[test]
+    fn visual_hash_survives_small_changes() {
+        let original = gradient(0);
+        let resized = original.resize_exact(45, 40, image::imageops::FilterType::Triangle);
+        let distance = visual_hash(&original) ^ visual_hash(&resized);
+        assert!(distance.count_ones() <= SIMILAR_DISTANCE);
+        let flipped = original.fliph();
+        let distance = visual_hash(&original) ^ visual_hash(&flipped);
+        assert!(distance.count_ones() > SIMILAR_DISTANCE);
+    }
+
+    #[test]
+    fn finds_identical_and_similar_photos() {
+        let mut meta = Metadata::default();
+        meta.set_hash("2020/a.jpg", "h1");
+        meta.set_hash("2020/a_copy.jpg", "h1");
+        meta.set_hash("2020/b.jpg", "h2");
+        meta.set_visual_hash("2020/b.jpg", Some(0xF0F0_F0F0_0000_FFFF));
+        meta.set_visual_hash("2021/b_small.jpg", Some(0xF0F0_F0F0_0000_FFF0));
+        meta.set_visual_hash("2021/c.jpg", Some(0x0F0F_0F0F_FFFF_0000));
+        let files = [
+            "2020/a.jpg",
+            "2020/a_copy.jpg",
+            "2020/b.jpg",
+            "2021/b_small.jpg",
+            "2021/c.jpg",
+        ];
+
+        let found = find_duplicates(&files, &meta);
+        assert_eq!(found["2020/a.jpg"], Duplicate::Identical);
+        assert_eq!(found["2020/a_copy.jpg"], Duplicate::Identical);
+        assert_eq!(found["2020/b.jpg"], Duplicate::Similar);
+        assert_eq!(found["2021/b_small.jpg"], Duplicate::Similar);
+        assert!(!found.contains_key("2021/c.jpg"));
+        // Only the photos shown count
+        assert!(find_duplicates(&files[..1], &meta).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Separates the start day from the number in event folder names
+/// (`2020-07-12_event-03`).
+const EVENT_MARKER: &str = "_event-";
+
+/// Where `sort --group-events` puts the photos of a run.
+#[derive(Debug, Default)]
+pub struct EventPlan {
+    /// Event folder (relative to the output dir) of each new photo.
+    pub folders: HashMap<PathBuf, PathBuf>,
+    /// Number of event folders the run creates.
+    pub created: usize,
+}
+
+/// An event folder already present in the library.
+struct SortedEvent {
+    /// Folder relative to the output dir (`2020/2020-07-12_event-03`).
+    folder: PathBuf,
+    year: String,
+    number: u32,
+    /// Capture times of the photos it holds.
+    dates: Vec<NaiveDateTime>,
+}
+
+/// Name of the `number`th event folder of a year, for an event starting at `start`.
+pub fn event_folder_name(start: &NaiveDateTime, number: u32) -> String {
+    format!("{}{EVENT_MARKER}{number:02}", start.format("%Y-%m-%d"))
+}
+
+/// Number of an event folder, `None` if `name` is not one.
+fn event_number(name: &str) -> Option<u32> {
+    let (day, number) = name.split_once(EVENT_MARKER)?;
+    NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?;
+    number.parse().ok()
+}
+
+/// Capture time encoded in a sorted file name (`2020-07-12_14-30-22….jpg`).
+pub fn date_from_sorted_name(name: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(name.get(..19)?, "%Y-%m-%d_%H-%M-%S").ok()
+}
+
+fn sorted_events(output_dir: &Path) -> Vec<SortedEvent> {
+    let mut events = Vec::new();
+    let Ok(years) = fs::read_dir(output_dir) else {
+        return events;
+    };
+    for year in years.filter_map(|e| e.ok()) {
+        let Ok(folders) = fs::read_dir(year.path()) else {
+            continue;
+        };
+        let year = year.file_name().to_string_lossy().to_string();
+        for folder in folders.filter_map(|e| e.ok()) {
+            let name = folder.file_name().to_string_lossy().to_string();
+            let Some(number) = event_number(&name) else {
+                continue;
+            };
+            let dates = fs::read_dir(folder.path())
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| date_from_sorted_name(&e.file_name().to_string_lossy()))
+                .collect();
+            events.push(SortedEvent {
+                folder: Path::new(&year).join(&name),
+                year: year.clone(),
+                number,
+                dates,
+            });
+        }
+    }
+    events
+}
+
+/// Group `photos` into events, together with the photos already sorted into
+/// event folders of `output_dir`: in capture order, a new event starts when
+/// more than `gap` separates two photos. Photos joining an existing event go
+/// to its folder; other events get a new folder, numbered after the last one
+/// of the year they start in.
+pub fn plan_events(
+    output_dir: &Path,
+    photos: &[(PathBuf, NaiveDateTime)],
+    gap: TimeDelta,
+) -> EventPlan {
+    let sorted = sorted_events(output_dir);
+    let mut next_number: HashMap<String, u32> = HashMap::new();
+    // `None` marks the photos of this run.
+    let mut timeline: Vec<(NaiveDateTime, Option<&Path>)> = Vec::new();
+    for event in &sorted {
+        let next = next_number.entry(event.year.clone()).or_insert(1);
+        *next = (*next).max(event.number + 1);
+        timeline.extend(
+            event
+                .dates
+                .iter()
+                .map(|d| (*d, Some(event.folder.as_path()))),
+        );
+    }
+    let first_new = timeline.len();
+    timeline.extend(photos.iter().map(|(_, d)| (*d, None)));
+
+    let mut order: Vec<usize> = (0..timeline.len()).collect();
+    order.sort_by_key(|&i| timeline[i].0);
+
+    let mut plan = EventPlan::default();
+    for event in order.chunk_by(|&a, &b| timeline[b].0 - timeline[a].0 <= gap) {
+        if event.iter().all(|&i| i < first_new) {
+            continue;
+        }
+        let folder = match event.iter().find_map(|&i| timeline[i].1) {
+            Some(folder) => folder.to_path_buf(),
+            None => {
+                let start = timeline[event[0]].0;
+                let year = start.format("%Y").to_string();
+                let number = next_number.entry(year.clone()).or_insert(1);
+                let folder = Path::new(&year).join(event_folder_name(&start, *number));
+                *number += 1;
+                plan.created += 1;
+                folder
+            }
+        };
+        for &i in event.iter().filter(|&&i| i >= first_new) {
+            plan.folders
+                .insert(photos[i - first_new].0.clone(), folder.clone());
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_events_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dt(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn photo(name: &str, date: &str) -> (PathBuf, NaiveDateTime) {
+        (PathBuf::from(name), dt(date))
+    }
+
+    #[test]
+    fn folder_names_roundtrip() {
+        let name = event_folder_name(&dt("2020-07-12 09:00:00"), 3);
+        assert_eq!(name, "2020-07-12_event-03");
+        assert_eq!(event_number(&name), Some(3));
+        assert_eq!(event_number("2020-07-12_event-112"), Some(112));
+        assert_eq!(event_number("raw"), None);
+        assert_eq!(event_number("vacances_event-01"), None);
+        assert_eq!(
+            date_from_sorted_name("2020-07-12_14-30-22.123__IMG_1.jpg"),
+            Some(dt("2020-07-12 14:30:22"))
+        );
+        assert_eq!(date_from_sorted_name("IMG_1.jpg"), None);
+    }
+
+    #[test]
+    fn gaps_split_events_numbered_per_year() {
+        let tmp = tmpdir();
+        let photos = vec![
+            photo("c.jpg", "2020-07-13 10:00:00"),
+            photo("a.jpg", "2020-07-12 09:00:00"),
+            photo("b.jpg", "2020-07-12 15:00:00"),
+            photo("d.jpg", "2021-01-02 10:00:00"),
+        ];
+        let plan = plan_events(&tmp, &photos, TimeDelta::hours(8));
+
+        assert_eq!(plan.created, 3);
+        let folder = |name: &str| plan.folders[Path::new(name)].to_string_lossy().to_string();
+        assert_eq!(folder("a.jpg"), "2020/2020-07-12_event-01");
+        assert_eq!(folder("b.jpg"), "2020/2020-07-12_event-01");
+        assert_eq!(folder("c.jpg"), "2020/2020-07-13_event-02");
+        assert_eq!(folder("d.jpg"), "2021/2021-01-02_event-01");
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn new_photos_join_sorted_events() {
+        let tmp = tmpdir();
+        let trip = tmp.join("2020/2020-07-12_event-04");
+        fs::create_dir_all(&trip).unwrap();
+        fs::write(trip.join("2020-07-12_09-00-00.jpg"), "a").unwrap();
+        fs::write(trip.join("2020-07-12_18-00-00.jpg"), "b").unwrap();
+
+        let photos = vec![
+            photo("late.jpg", "2020-07-13 01:00:00"),
+            photo("next.jpg", "2020-08-01 12:00:00"),
+        ];
+        let plan = plan_events(&tmp, &photos, TimeDelta::hours(8));
+
+        assert_eq!(plan.created, 1);
+        assert_eq!(
+            plan.folders[Path::new("late.jpg")],
+            PathBuf::from("2020/2020-07-12_event-04")
+        );
+        assert_eq!(
+            plan.folders[Path::new("next.jpg")],
+            PathBuf::from("2020/2020-08-01_event-05")
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
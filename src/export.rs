@@ -1,52 +1,100 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use console::style;
 use std::fs;
 use std::path::Path;
 
 use crate::gallery::collect_photos;
 use crate::metadata::Metadata;
+use crate::query::Query;
 
-/// Collect files matching the given tag and/or minimum rating filters.
+/// Collect files matching the given tag, minimum rating, favorite and/or
+/// query filters.
 pub fn filter_files(
     metadata: &Metadata,
     all_files: &[String],
     tag: Option<&str>,
     min_rating: Option<u8>,
+    favorites: bool,
+    query: Option<&Query>,
 ) -> Vec<String> {
     all_files
         .iter()
         .filter(|f| {
-            if let Some(t) = tag {
-                if !metadata.get_tags(f).contains(&t.to_string()) {
-                    return false;
-                }
+            if let Some(t) = tag
+                && !metadata.has_tag(f, t)
+            {
+                return false;
             }
-            if let Some(min) = min_rating {
-                if !metadata.get_rating(f).is_some_and(|r| r >= min) {
-                    return false;
-                }
+            if let Some(min) = min_rating
+                && metadata.get_rating(f).is_none_or(|r| r < min)
+            {
+                return false;
+            }
+            if favorites && !metadata.is_favorite(f) {
+                return false;
             }
-            true
+            query.is_none_or(|q| q.matches(metadata, f))
         })
         .cloned()
         .collect()
 }
 
-pub fn run_export(
-    dir: &Path,
-    dest: &Path,
-    tag: Option<&str>,
-    min_rating: Option<u8>,
-) -> Result<()> {
-    if tag.is_none() && min_rating.is_none() {
-        anyhow::bail!("Spécifiez au moins --tag ou --rating pour filtrer l'export");
+/// Filters of `run_export`; at least one of them must be set.
+#[derive(Debug, Default, Clone)]
+pub struct ExportOptions {
+    pub tag: Option<String>,
+    /// Minimum rating.
+    pub rating: Option<u8>,
+    pub favorites: bool,
+    /// Only this album's photos, in its order.
+    pub album: Option<String>,
+    pub query: Option<Query>,
+    /// Also export the photos marked hidden.
+    pub include_hidden: bool,
+}
+
+pub fn run_export(dir: &Path, dest: &Path, options: &ExportOptions) -> Result<()> {
+    let ExportOptions {
+        tag,
+        rating: min_rating,
+        favorites,
+        album,
+        query,
+        include_hidden,
+    } = options;
+    if tag.is_none() && min_rating.is_none() && !favorites && album.is_none() && query.is_none() {
+        anyhow::bail!(
+            "Spécifiez au moins --tag, --rating, --favorites, --album ou --query pour filtrer l'export"
+        );
     }
 
     let metadata = Metadata::load(dir)?;
     let photos = collect_photos(dir);
-    let all_files: Vec<String> = photos.values().flatten().cloned().collect();
+    let mut all_files: Vec<String> = photos.values().flatten().cloned().collect();
+    if let Some(name) = album {
+        // Album photos still in the library, in the album's order
+        let album = metadata
+            .albums
+            .get(name)
+            .with_context(|| format!("Album inconnu : {name}"))?;
+        all_files = album
+            .iter()
+            .filter(|f| all_files.contains(f))
+            .cloned()
+            .collect();
+    }
+    if !include_hidden {
+        all_files.retain(|f| !metadata.is_hidden(f));
+    }
 
-    let matched = filter_files(&metadata, &all_files, tag, min_rating);
+    let matched = filter_files(
+        &metadata,
+        &all_files,
+        tag.as_deref(),
+        *min_rating,
+        *favorites,
+        query.as_ref(),
+    );
 
     if matched.is_empty() {
         println!("  {} Aucun fichier ne correspond aux filtres.", style("!").yellow().bold());
@@ -101,6 +149,71 @@ pub fn run_export(
     Ok(())
 }
 
+/// Columns of `run_export_csv`. Tags and people are joined with `;`.
+const CSV_HEADER: &[&str] = &[
+    "path",
+    "year",
+    "tags",
+    "people",
+    "rating",
+    "favorite",
+    "hidden",
+    "label",
+    "caption",
+    "latitude",
+    "longitude",
+];
+
+/// Write one row per photo of the library (or per photo matching `query`)
+/// with its curation data, tab-separated if `out` ends in `.tsv`. Hidden
+/// photos are listed too, flagged in their column.
+pub fn run_export_csv(dir: &Path, out: &Path, query: Option<&Query>) -> Result<()> {
+    let metadata = Metadata::load(dir)?;
+    let mut files: Vec<(String, String)> = collect_photos(dir)
+        .into_iter()
+        .flat_map(|(year, files)| files.into_iter().map(move |f| (year.clone(), f)))
+        .filter(|(_, f)| query.is_none_or(|q| q.matches(&metadata, f)))
+        .collect();
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let tsv = out
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tsv"));
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(if tsv { b'\t' } else { b',' })
+        .from_path(out)
+        .with_context(|| format!("Impossible de créer {}", out.display()))?;
+    writer.write_record(CSV_HEADER)?;
+    for (year, file) in &files {
+        let position = metadata.get_position(file);
+        writer.write_record([
+            file.as_str(),
+            year,
+            &metadata.get_tags(file).join(";"),
+            &metadata.get_people(file).join(";"),
+            &metadata
+                .get_rating(file)
+                .map(|r| r.to_string())
+                .unwrap_or_default(),
+            if metadata.is_favorite(file) { "1" } else { "" },
+            if metadata.is_hidden(file) { "1" } else { "" },
+            metadata.get_label(file).unwrap_or_default(),
+            metadata.get_caption(file).unwrap_or_default(),
+            &position.map(|(lat, _)| lat.to_string()).unwrap_or_default(),
+            &position.map(|(_, lon)| lon.to_string()).unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+
+    println!(
+        "  {} {} photo(s) exportée(s) dans {}",
+        style("✔").green().bold(),
+        style(files.len()).green().bold(),
+        style(out.display()).white().bold()
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,7 +256,7 @@ mod tests {
             "2021/c.jpg".to_string(),
         ];
 
-        let result = filter_files(&meta, &all, Some("vacances"), None);
+        let result = filter_files(&meta, &all, Some("vacances"), None, false, None);
         assert_eq!(result, vec!["2020/a.jpg"]);
     }
 
@@ -160,7 +273,7 @@ mod tests {
             "2021/c.jpg".to_string(),
         ];
 
-        let result = filter_files(&meta, &all, None, Some(4));
+        let result = filter_files(&meta, &all, None, Some(4), false, None);
         assert_eq!(result, vec!["2020/a.jpg", "2021/c.jpg"]);
     }
 
@@ -180,16 +293,56 @@ mod tests {
             "2021/c.jpg".to_string(),
         ];
 
-        let result = filter_files(&meta, &all, Some("vacances"), Some(4));
+        let result = filter_files(&meta, &all, Some("vacances"), Some(4), false, None);
         assert_eq!(result, vec!["2020/a.jpg"]);
     }
 
+    #[test]
+    fn filter_by_favorites() {
+        let mut meta = Metadata::default();
+        meta.set_favorite("2020/b.jpg", true);
+        meta.set_favorite("2021/c.jpg", true);
+        meta.set_rating("2021/c.jpg", Some(2));
+
+        let all = vec![
+            "2020/a.jpg".to_string(),
+            "2020/b.jpg".to_string(),
+            "2021/c.jpg".to_string(),
+        ];
+
+        assert_eq!(
+            filter_files(&meta, &all, None, None, true, None),
+            vec!["2020/b.jpg", "2021/c.jpg"]
+        );
+        assert!(filter_files(&meta, &all, None, Some(3), true, None).is_empty());
+    }
+
+    #[test]
+    fn filter_by_query() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.add_tag("2020/b.jpg", "vacances");
+        meta.add_tag("2020/b.jpg", "flou");
+
+        let all = vec![
+            "2020/a.jpg".to_string(),
+            "2020/b.jpg".to_string(),
+            "2021/c.jpg".to_string(),
+        ];
+
+        let query: Query = "tag:vacances NOT tag:flou OR year:2021".parse().unwrap();
+        assert_eq!(
+            filter_files(&meta, &all, None, None, false, Some(&query)),
+            vec!["2020/a.jpg", "2021/c.jpg"]
+        );
+    }
+
     #[test]
     fn filter_no_match_returns_empty() {
         let meta = Metadata::default();
         let all = vec!["2020/a.jpg".to_string()];
 
-        let result = filter_files(&meta, &all, Some("inexistant"), None);
+        let result = filter_files(&meta, &all, Some("inexistant"), None, false, None);
         assert!(result.is_empty());
     }
 
@@ -198,12 +351,26 @@ mod tests {
         let meta = Metadata::default();
         let all = vec!["2020/a.jpg".to_string(), "2020/b.jpg".to_string()];
 
-        let result = filter_files(&meta, &all, None, None);
+        let result = filter_files(&meta, &all, None, None, false, None);
         assert_eq!(result.len(), 2);
     }
 
     // --- run_export ---
 
+    fn tag_options(tag: &str) -> ExportOptions {
+        ExportOptions {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn album_options(album: &str) -> ExportOptions {
+        ExportOptions {
+            album: Some(album.to_string()),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn export_copies_matching_files() {
         let src = tmpdir();
@@ -215,7 +382,7 @@ mod tests {
         meta.add_tag("2021/c.jpg", "vacances");
         meta.save(&src).unwrap();
 
-        run_export(&src, &dest, Some("vacances"), None).unwrap();
+        run_export(&src, &dest, &tag_options("vacances")).unwrap();
 
         assert!(dest.join("a.jpg").exists());
         assert!(dest.join("c.jpg").exists());
@@ -238,7 +405,7 @@ mod tests {
         // Pre-create a.jpg in dest
         std::fs::write(dest.join("a.jpg"), "existing").unwrap();
 
-        run_export(&src, &dest, Some("x"), None).unwrap();
+        run_export(&src, &dest, &tag_options("x")).unwrap();
 
         assert!(dest.join("a.jpg").exists());
         assert!(dest.join("a_1.jpg").exists());
@@ -251,7 +418,7 @@ mod tests {
     fn export_no_filter_errors() {
         let src = tmpdir();
         let dest = tmpdir();
-        assert!(run_export(&src, &dest, None, None).is_err());
+        assert!(run_export(&src, &dest, &ExportOptions::default()).is_err());
         let _ = std::fs::remove_dir_all(&src);
         let _ = std::fs::remove_dir_all(&dest);
     }
@@ -267,7 +434,15 @@ mod tests {
         meta.set_rating("2020/b.jpg", Some(1));
         meta.save(&src).unwrap();
 
-        run_export(&src, &dest, None, Some(3)).unwrap();
+        run_export(
+            &src,
+            &dest,
+            &ExportOptions {
+                rating: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert!(dest.join("a.jpg").exists());
         assert!(!dest.join("b.jpg").exists());
@@ -276,4 +451,91 @@ mod tests {
         let _ = std::fs::remove_dir_all(&src);
         let _ = std::fs::remove_dir_all(&dest);
     }
+
+    #[test]
+    fn export_by_album() {
+        let src = tmpdir();
+        let dest = tmpdir();
+        setup_source(&src);
+
+        let mut meta = Metadata::default();
+        meta.add_to_album("Best of", "2021/c.jpg");
+        meta.add_to_album("Best of", "2020/gone.jpg");
+        meta.add_to_album("Best of", "2020/a.jpg");
+        meta.save(&src).unwrap();
+
+        run_export(&src, &dest, &album_options("Best of")).unwrap();
+        assert!(dest.join("a.jpg").exists());
+        assert!(dest.join("c.jpg").exists());
+        assert!(!dest.join("b.jpg").exists());
+        assert!(run_export(&src, &dest, &album_options("Inconnu")).is_err());
+
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn export_skips_hidden_photos_unless_asked() {
+        let src = tmpdir();
+        let dest = tmpdir();
+        setup_source(&src);
+
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.add_tag("2020/b.jpg", "plage");
+        meta.set_hidden("2020/b.jpg", true);
+        meta.save(&src).unwrap();
+
+        run_export(&src, &dest, &tag_options("plage")).unwrap();
+        assert!(dest.join("a.jpg").exists());
+        assert!(!dest.join("b.jpg").exists());
+
+        let options = ExportOptions {
+            include_hidden: true,
+            ..tag_options("plage")
+        };
+        run_export(&src, &dest, &options).unwrap();
+        assert!(dest.join("b.jpg").exists());
+
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn export_csv_writes_one_row_per_photo() {
+        let tmp = tmpdir();
+        let src = tmp.join("src");
+        fs::create_dir_all(src.join("2020")).unwrap();
+        fs::create_dir_all(src.join("2021")).unwrap();
+        fs::write(src.join("2020/a.jpg"), "a").unwrap();
+        fs::write(src.join("2021/b.jpg"), "b").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.add_tag("2020/a.jpg", "été");
+        meta.set_rating("2020/a.jpg", Some(4));
+        meta.set_caption("2020/a.jpg", "Carnac, \"la plage\"");
+        meta.set_position("2020/a.jpg", Some((47.58, -3.07)));
+        meta.set_hidden("2021/b.jpg", true);
+        meta.save(&src).unwrap();
+
+        let out = tmp.join("photos.csv");
+        run_export_csv(&src, &out, None).unwrap();
+        let csv = fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            r#"2020/a.jpg,2020,plage;été,,4,,,,"Carnac, ""la plage""",47.58,-3.07"#
+        );
+        assert_eq!(lines[2], "2021/b.jpg,2021,,,,,1,,,,");
+
+        let out = tmp.join("photos.tsv");
+        let query: Query = "rating>=4".parse().unwrap();
+        run_export_csv(&src, &out, Some(&query)).unwrap();
+        let tsv = fs::read_to_string(&out).unwrap();
+        assert_eq!(tsv.lines().count(), 2);
+        let row = tsv.lines().nth(1).unwrap();
+        assert!(row.starts_with("2020/a.jpg\t2020\tplage;été\t"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
 }
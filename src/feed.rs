@@ -0,0 +1,197 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::metadata::Metadata;
+use crate::sort::{PROGRESS_FILE, load_progress};
+
+/// Written next to `gallery.html` by `gallery --feed`.
+pub const FEED_FILE: &str = "atom.xml";
+
+/// Number of photos the feed lists.
+const FEED_ENTRIES: usize = 50;
+
+/// Check the base URL given to `--feed`: where `serve` is reachable, without
+/// a trailing slash.
+pub fn parse_base_url(value: &str) -> Result<String> {
+    let value = value.trim().trim_end_matches('/');
+    let host = (value.strip_prefix("http://"))
+        .or_else(|| value.strip_prefix("https://"))
+        .unwrap_or("");
+    if host.is_empty() || host.contains(char::is_whitespace) {
+        anyhow::bail!("URL invalide : {value} (attendu http://hôte[:port][/chemin])");
+    }
+    Ok(value.to_string())
+}
+
+/// Percent-encode a relative path for a URL, keeping its slashes.
+fn url_path(path: &str) -> String {
+    let mut out = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The photos of the gallery most recently sorted into it, newest first: the
+/// sort progress lists them in the order they were placed.
+fn recent_photos<'a>(
+    dir: &Path,
+    photos_by_year: &'a HashMap<String, Vec<String>>,
+) -> Vec<&'a String> {
+    let in_gallery: HashMap<&str, &String> = (photos_by_year.values().flatten())
+        .map(|file| (file.as_str(), file))
+        .collect();
+    let Ok(progress) = load_progress(&dir.join(PROGRESS_FILE)) else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    (progress.processed.iter().rev())
+        .filter_map(|entry| in_gallery.get(entry.dest.as_str()).copied())
+        .filter(|file| seen.insert(*file))
+        .take(FEED_ENTRIES)
+        .collect()
+}
+
+/// When a photo arrived in the gallery: the creation time of its file, its
+/// modification time where the system doesn't keep one.
+fn added_at(path: &Path) -> Option<DateTime<Utc>> {
+    let meta = std::fs::metadata(path).ok()?;
+    let time = meta.created().or_else(|_| meta.modified()).ok()?;
+    Some(time.into())
+}
+
+/// Atom feed of the photos last sorted into `dir`, linking to the gallery
+/// served at `base_url`.
+pub fn render_feed(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    base_url: &str,
+    title: &str,
+) -> String {
+    let mut entries = String::new();
+    let mut updated: Option<DateTime<Utc>> = None;
+    for file in recent_photos(dir, photos_by_year) {
+        let added = added_at(&dir.join(file)).unwrap_or_default();
+        updated = updated.max(Some(added));
+        let url = escape_xml(&format!("{base_url}/{}", url_path(file)));
+        let name = file.rsplit('/').next().unwrap_or(file);
+        let caption = (metadata.files.get(file))
+            .and_then(|info| info.caption.as_deref())
+            .filter(|caption| !caption.is_empty());
+        let content = format!(
+            "<p><a href=\"{base_url}/#photo={path}\"><img src=\"{base_url}/thumb/{path}\" alt=\"{alt}\"></a></p>",
+            path = url_path(file),
+            alt = escape_xml(caption.unwrap_or(name)),
+        );
+        entries.push_str(&format!(
+            "  <entry>\n    <id>{url}</id>\n    <title>{}</title>\n    <updated>{}</updated>\n    \
+             <link rel=\"alternate\" href=\"{}\"/>\n    <link rel=\"enclosure\" href=\"{url}\"/>\n    \
+             <content type=\"html\">{}</content>\n  </entry>\n",
+            escape_xml(caption.unwrap_or(name)),
+            added.to_rfc3339(),
+            escape_xml(&format!("{base_url}/#photo={}", url_path(file))),
+            escape_xml(&content),
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+         <id>{base_url}/</id>\n  <title>{}</title>\n  <updated>{}</updated>\n  \
+         <link rel=\"alternate\" href=\"{base_url}/\"/>\n  <author><name>photo-sort</name></author>\n{entries}</feed>\n",
+        escape_xml(title),
+        updated.unwrap_or_default().to_rfc3339(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{ProcessedEntry, Progress, save_progress};
+
+    fn tmpdir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_feed_test_{}_{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("2020")).unwrap();
+        dir
+    }
+
+    fn entry(dest: &str) -> ProcessedEntry {
+        ProcessedEntry {
+            source: format!("/src/{dest}"),
+            dest: dest.to_string(),
+            size: 1,
+            hash: dest.to_string(),
+            date_source: "exif".to_string(),
+        }
+    }
+
+    #[test]
+    fn base_urls_are_checked() {
+        assert_eq!(
+            parse_base_url("http://maison.local:8080/").unwrap(),
+            "http://maison.local:8080"
+        );
+        assert!(parse_base_url("https://photos.example.org/famille").is_ok());
+        assert!(parse_base_url("maison.local").is_err());
+        assert!(parse_base_url("http://").is_err());
+    }
+
+    #[test]
+    fn feed_lists_the_latest_sorted_photos_first() {
+        let dir = tmpdir("latest");
+        for name in ["2020/a.jpg", "2020/b c.jpg"] {
+            std::fs::write(dir.join(name), b"x").unwrap();
+        }
+        let progress = Progress {
+            processed: vec![
+                entry("2020/a.jpg"),
+                entry("2020/gone.jpg"),
+                entry("2020/b c.jpg"),
+            ],
+        };
+        save_progress(&dir.join(PROGRESS_FILE), &progress).unwrap();
+        let photos = HashMap::from([(
+            "2020".to_string(),
+            vec!["2020/a.jpg".to_string(), "2020/b c.jpg".to_string()],
+        )]);
+        let mut meta = Metadata::default();
+        meta.set_caption("2020/a.jpg", "Plage & soleil");
+
+        let xml = render_feed(&dir, &photos, &meta, "http://maison.local:8080", "Famille");
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains("<title>Famille</title>"));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+        let pos = |s: &str| xml.find(s).unwrap();
+        assert!(pos("<title>b c.jpg</title>") < pos("<title>Plage &amp; soleil</title>"));
+        assert!(xml.contains("<id>http://maison.local:8080/2020/b%20c.jpg</id>"));
+        assert!(xml.contains("href=\"http://maison.local:8080/#photo=2020/a.jpg\""));
+        assert!(xml.contains("&lt;img src=&quot;http://maison.local:8080/thumb/2020/a.jpg&quot;"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn feed_without_sort_progress_is_empty() {
+        let dir = tmpdir("empty");
+        let xml = render_feed(&dir, &HashMap::new(), &Metadata::default(), "http://h", "T");
+        assert!(xml.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+        assert!(!xml.contains("<entry>"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,19 +1,33 @@
-use anyhow::Result;
-use std::collections::HashMap;
-use std::path::Path;
+use anyhow::{Context, Result};
+use base64::prelude::{BASE64_STANDARD, Engine};
+use chrono::{Datelike, NaiveDateTime};
+use indicatif::{ProgressBar, ProgressStyle};
+use minijinja::{Environment, Value, context};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-use crate::metadata::Metadata;
-use crate::sort::is_photo;
+use crate::duplicates::{Duplicate, cache_visual_hashes, find_duplicates};
+use crate::events::date_from_sorted_name;
+use crate::feed::{self, FEED_FILE};
+use crate::i18n::Lang;
+use crate::metadata::{COLOR_LABELS, ExifSummary, FileInfo, Metadata};
+use crate::photoignore::IgnoreRules;
+use crate::sort::{
+    PROGRESS_FILE, RAW_EXTENSIONS, date_from_exif, exif_summary, hash_file, is_photo, load_progress,
+};
+use crate::thumb;
 
 /// Collect all photo relative paths from the output directory, grouped by year.
 pub fn collect_photos(dir: &Path) -> HashMap<String, Vec<String>> {
     let mut by_year: HashMap<String, Vec<String>> = HashMap::new();
 
+    let mut ignore_rules = IgnoreRules::default();
     for entry in WalkDir::new(dir)
         .into_iter()
         .filter_entry(|e| {
-            e.file_name().to_string_lossy() != crate::thumb::THUMB_DIR
+            e.file_name().to_string_lossy() != crate::thumb::THUMB_DIR && ignore_rules.accept(e)
         })
         .filter_map(|e| e.ok())
         .filter(|e| e.file_type().is_file() && is_photo(e.path()))
@@ -25,10 +39,11 @@ pub fn collect_photos(dir: &Path) -> HashMap<String, Vec<String>> {
             .to_string_lossy()
             .to_string();
         // Year is the first path component
-        if let Some(year) = rel.split('/').next() {
-            if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) {
-                by_year.entry(year.to_string()).or_default().push(rel);
-            }
+        if let Some(year) = rel.split('/').next()
+            && year.len() == 4
+            && year.chars().all(|c| c.is_ascii_digit())
+        {
+            by_year.entry(year.to_string()).or_default().push(rel);
         }
     }
 
@@ -40,84 +55,547 @@ pub fn collect_photos(dir: &Path) -> HashMap<String, Vec<String>> {
     by_year
 }
 
-/// Build the full HTML gallery string.
-pub fn generate_html(photos_by_year: &HashMap<String, Vec<String>>, metadata: &Metadata) -> String {
+/// Fill in the cached capture time of photos that don't have one yet, reading
+/// it from EXIF. Photos without one are marked so they are read only once.
+/// Returns the number of capture times added.
+pub fn cache_capture_times(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &mut Metadata,
+) -> usize {
+    let mut added = 0;
+    for file in photos_by_year.values().flatten() {
+        let info = metadata.files.get(file);
+        if info.is_some_and(|i| i.taken.is_some() || i.no_exif_date) {
+            continue;
+        }
+        match date_from_exif(&dir.join(file)) {
+            Some(dt) => {
+                metadata.set_taken(file, Some(dt));
+                added += 1;
+            }
+            None => metadata.files.entry(file.clone()).or_default().no_exif_date = true,
+        }
+    }
+    added
+}
+
+/// Fill in where the date of photos sorted before it was recorded came from,
+/// from the sort progress: by path, or by content hash for photos renamed or
+/// moved since. Returns the number of entries added.
+pub fn cache_date_sources(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &mut Metadata,
+) -> usize {
+    let Ok(progress) = load_progress(&dir.join(PROGRESS_FILE)) else {
+        return 0;
+    };
+    let mut by_dest = HashMap::new();
+    let mut by_hash = HashMap::new();
+    for entry in &progress.processed {
+        let source = entry.date_source.as_str();
+        if matches!(source, "exif" | "dirname" | "filesystem") {
+            by_dest.insert(entry.dest.as_str(), source);
+            by_hash.insert(entry.hash.as_str(), source);
+        }
+    }
+    let mut added = 0;
+    for file in photos_by_year.values().flatten() {
+        if metadata.get_date_source(file).is_some() {
+            continue;
+        }
+        let hash = metadata.files.get(file).and_then(|i| i.hash.as_deref());
+        let source = (by_dest.get(file.as_str()))
+            .or_else(|| by_hash.get(hash?))
+            .copied();
+        if let Some(source) = source {
+            metadata.set_date_source(file, Some(source));
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Read and cache the EXIF essentials of photos that don't have them yet
+/// (all photos with `force`). Returns the number of entries filled in.
+pub fn cache_exif(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &mut Metadata,
+    force: bool,
+) -> usize {
+    let mut added = 0;
+    for file in photos_by_year.values().flatten() {
+        if !force && metadata.get_exif(file).is_some() {
+            continue;
+        }
+        if let Some(summary) = exif_summary(&dir.join(file)) {
+            metadata.set_exif(file, Some(summary));
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Move the metadata of photos renamed or moved outside the tool to their new
+/// path, matching them by content hash. Returns the (old, new) paths relinked.
+pub fn relink_moved_files(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &mut Metadata,
+) -> Vec<(String, String)> {
+    let present: Vec<&str> = photos_by_year.values().flatten().map(String::as_str).collect();
+    let today = chrono::Local::now().date_naive();
+    metadata.relink(&present, today, |file| hash_file(&dir.join(file)).ok())
+}
+
+/// Load the metadata, relinking moved photos and caching capture times and
+/// date sources, and save it back if anything changed.
+pub fn load_metadata(dir: &Path, photos_by_year: &HashMap<String, Vec<String>>) -> Result<Metadata> {
+    let mut metadata = Metadata::load(dir)?;
+    let before = metadata.revision();
+    for (old, new) in relink_moved_files(dir, photos_by_year, &mut metadata) {
+        println!(
+            "  {} Metadata suivie : {old} → {new}",
+            console::style("✔").green().bold()
+        );
+    }
+    cache_capture_times(dir, photos_by_year, &mut metadata);
+    cache_date_sources(dir, photos_by_year, &mut metadata);
+    if metadata.revision() != before {
+        metadata.save(dir)?;
+    }
+    Ok(metadata)
+}
+
+/// Files of one month of a year in the gallery, `None` for undated ones.
+type MonthFiles<'a> = (Option<u32>, Vec<&'a String>);
+
+/// Month section of a year in the grid: `2020-07`, `2020-00` for undated
+/// photos.
+fn section_key(year: &str, month: Option<u32>) -> String {
+    format!("{year}-{:02}", month.unwrap_or(0))
+}
+
+/// A photo as the gallery's script reads it.
+#[derive(Serialize)]
+struct PhotoEntry<'a> {
+    src: &'a str,
+    year: &'a str,
+    name: &'a str,
+    /// Key of the month section showing it.
+    section: String,
+    tags: &'a [String],
+    people: &'a [String],
+    rating: u8,
+    taken: Option<String>,
+    date_override: Option<String>,
+    date_source: Option<&'a str>,
+    /// Day it was taken, from `photo_date`.
+    date: Option<String>,
+    pair: Option<&'a str>,
+    caption: &'a str,
+    favorite: bool,
+    hidden: bool,
+    label: Option<&'a str>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    exif: Option<&'a ExifSummary>,
+    /// Set when another photo of the page has the same content or look.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate: Option<Duplicate>,
+    /// Cached thumbnail shown in the grid of the static gallery.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumb: Option<String>,
+    /// Shown in the lightbox of a standalone gallery, the original being out
+    /// of reach.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+    /// Tiny image shown blurred until the thumbnail is loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placeholder: Option<String>,
+}
+
+/// When a photo was taken: its manual date, else the one its sorted name
+/// carries, else the cached EXIF one.
+fn photo_date(file: &str, metadata: &Metadata) -> Option<NaiveDateTime> {
+    let name = file.rsplit('/').next().unwrap_or(file);
+    (metadata.get_date_override(file))
+        .or_else(|| date_from_sorted_name(name))
+        .or_else(|| metadata.get_taken(file))
+}
+
+/// Default colour scheme of the gallery, until the viewer picks one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Theme {
+    Light,
+    #[default]
+    Dark,
+    /// Follow the system setting
+    Auto,
+}
+
+impl Theme {
+    fn as_str(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+            Theme::Auto => "auto",
+        }
+    }
+}
+
+/// How `generate_html` renders the gallery.
+#[derive(Debug, Clone, Default)]
+pub struct GalleryOptions {
+    pub theme: Theme,
+    pub lang: Lang,
+    /// One file carrying its thumbnails, for `gallery --standalone`.
+    pub standalone: bool,
+    /// With `standalone`, medium previews for the lightbox too.
+    pub previews: bool,
+    /// Tiny previews in the page, shown blurred while thumbnails load.
+    pub placeholders: bool,
+    /// One page per year and an `index.html` linking them.
+    pub split_by_year: bool,
+    /// Page title, instead of "photo-sort gallery".
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    /// CSS color replacing the default blue, from `parse_accent_color`.
+    pub accent_color: Option<String>,
+    /// HTML template replacing the built-in one, read at each render.
+    pub template: Option<PathBuf>,
+    /// Photo list in `gallery_data.json`, fetched by the page, instead of
+    /// inlined in it.
+    pub data_file: bool,
+    /// Base URL of `serve`, from `feed::parse_base_url`: the latest sorted
+    /// photos are then listed in `atom.xml`.
+    pub feed: Option<String>,
+    /// Folder receiving the pages and their thumbnails, instead of the
+    /// photo folder.
+    pub output: Option<PathBuf>,
+    /// Rendered by `serve`: the page then uses its API. Opened from disk or
+    /// from another web server, it stays a static gallery.
+    pub served: bool,
+}
+
+/// Written next to `gallery.html` with `data_file`.
+pub const DATA_FILE: &str = "gallery_data.json";
+
+const GALLERY_TEMPLATE: &str = include_str!("templates/gallery.html");
+const INDEX_TEMPLATE: &str = include_str!("templates/index.html");
+const DEFAULT_TITLE: &str = "photo-sort gallery";
+
+/// Figures of the gallery, for templates.
+#[derive(Serialize)]
+struct Stats<'a> {
+    photos: usize,
+    years: Vec<YearCount<'a>>,
+    tagged: usize,
+    rated: usize,
+    favorites: usize,
+}
+
+#[derive(Serialize)]
+struct YearCount<'a> {
+    year: &'a str,
+    count: usize,
+}
+
+/// Content of `gallery_data.json`: what the page needs, readable by other
+/// tools too.
+#[derive(Serialize)]
+struct GalleryData<'a> {
+    photos: &'a [PhotoEntry<'a>],
+    tags: &'a [String],
+    years: &'a [YearCount<'a>],
+    albums: &'a BTreeMap<String, Vec<String>>,
+    tag_aliases: &'a BTreeMap<String, String>,
+}
+
+/// A rendered gallery, and its data file with `data_file`.
+struct Page {
+    html: String,
+    data: Option<String>,
+}
+
+/// Check an accent color given on the command line: `#rgb`, `#rrggbb` (with
+/// or without alpha) or a CSS color name.
+pub fn parse_accent_color(value: &str) -> Result<String> {
+    let value = value.trim().to_lowercase();
+    let valid = match value.strip_prefix('#') {
+        Some(hex) => {
+            [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => !value.is_empty() && value.chars().all(|c| c.is_ascii_lowercase()),
+    };
+    if !valid {
+        anyhow::bail!("Couleur invalide : {value} (attendu #RRGGBB ou un nom de couleur CSS)");
+    }
+    Ok(value)
+}
+
+/// Largest side of the previews of a standalone gallery.
+const PREVIEW_MAX_SIZE: u32 = 1600;
+const PREVIEW_QUALITY: u8 = 80;
+
+/// Images carried by the page itself, as data URIs.
+#[derive(Default)]
+struct Embedded {
+    /// The thumbnail, in a standalone gallery.
+    thumb: Option<String>,
+    preview: Option<String>,
+    /// Shown blurred while the thumbnail loads, with `placeholders`.
+    placeholder: Option<String>,
+}
+
+fn data_uri(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{mime};base64,{}", BASE64_STANDARD.encode(bytes))
+}
+
+/// Inline what `options` asks for: the cached thumbnail of every photo and a
+/// preview for a standalone gallery, a placeholder with `placeholders`.
+/// Photos that cannot be decoded are left out (the gallery shows them
+/// blank).
+fn embed_photos(
+    dir: &Path,
+    photos_by_year: &HashMap<String, Vec<String>>,
+    options: &GalleryOptions,
+) -> HashMap<String, Embedded> {
+    let rels: Vec<&String> = photos_by_year.values().flatten().collect();
+    let pb = ProgressBar::new(rels.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("  {bar:40.green/dark_gray} {pos}/{len} photos intégrées")
+            .unwrap()
+            .progress_chars("━╸─"),
+    );
+    let mut embedded = HashMap::new();
+    for rel in rels {
+        pb.inc(1);
+        let mut images = Embedded::default();
+        if options.standalone {
+            let Ok(Some(cached)) = thumb::get_or_create_thumb(dir, rel) else {
+                continue;
+            };
+            let Ok(jpeg) = std::fs::read(cached) else {
+                continue;
+            };
+            images.thumb = Some(data_uri("image/jpeg", &jpeg));
+            images.preview = (options.previews)
+                .then(|| thumb::encode_jpeg(&dir.join(rel), PREVIEW_MAX_SIZE, PREVIEW_QUALITY).ok())
+                .flatten()
+                .map(|jpeg| data_uri("image/jpeg", &jpeg));
+        }
+        if options.placeholders
+            && let Ok(Some(png)) = thumb::placeholder(dir, rel)
+        {
+            images.placeholder = Some(data_uri("image/png", &png));
+        }
+        embedded.insert(rel.clone(), images);
+    }
+    pb.finish_and_clear();
+    embedded
+}
+
+/// Page of one year, with `--split-by-year`.
+fn year_page(year: &str) -> String {
+    format!("gallery_{year}.html")
+}
+
+/// The photo on the card of a year: a favorite, else the best rated, else
+/// the first one. Hidden photos are passed over.
+fn year_cover<'a>(files: &'a [String], metadata: &Metadata) -> Option<&'a String> {
+    (files.iter().rev())
+        .filter(|file| !metadata.files.get(*file).is_some_and(|info| info.hidden))
+        .max_by_key(|file| {
+            let info = metadata.files.get(*file);
+            (
+                info.is_some_and(|info| info.favorite),
+                info.and_then(|info| info.rating).unwrap_or(0),
+            )
+        })
+}
+
+/// The `index.html` of `--split-by-year`: one card per year, linking to its
+/// page.
+fn generate_index(
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    options: &GalleryOptions,
+    photo_base: &str,
+) -> Result<String> {
+    let lang = options.lang;
     let mut years: Vec<&String> = photos_by_year.keys().collect();
     years.sort();
+    let mut cards = String::new();
+    for year in years {
+        let files = &photos_by_year[year];
+        let cover = match year_cover(files, metadata) {
+            Some(file) => format!(
+                "<img src=\"{}\" alt=\"\" loading=\"lazy\">",
+                escape_html(&thumb::thumb_url(file).unwrap_or_else(|| format!("{photo_base}{file}")))
+            ),
+            None => "<div class=\"no-cover\"></div>".to_string(),
+        };
+        let key = if files.len() == 1 {
+            "photo_one"
+        } else {
+            "photo_many"
+        };
+        let count = lang.get(key).replace("{n}", &files.len().to_string());
+        cards.push_str(&format!(
+            "  <a class=\"year-card\" href=\"{}\">{cover}<span class=\"year\">{year}</span><span class=\"count\">{}</span></a>\n",
+            year_page(year),
+            escape_html(&count)
+        ));
+    }
 
-    // Collect all tags for the filter sidebar
-    let mut all_tags: Vec<String> = metadata
-        .files
-        .values()
-        .flat_map(|info| info.tags.clone())
-        .collect::<std::collections::HashSet<_>>()
-        .into_iter()
-        .collect();
-    all_tags.sort();
+    render_template(
+        "index.html",
+        INDEX_TEMPLATE,
+        context! {
+            lang => lang.code(),
+            theme => options.theme.as_str(),
+            title => options.title.as_deref().unwrap_or(DEFAULT_TITLE),
+            subtitle => options.subtitle,
+            accent_color => options.accent_color,
+            cards => Value::from_safe_string(cards),
+        },
+    )
+}
 
-    // Build photo entries as JSON for the JS
-    let mut photo_entries = Vec::new();
+/// Build the full HTML gallery string.
+pub fn generate_html(
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    options: &GalleryOptions,
+) -> Result<String> {
+    Ok(render_html(photos_by_year, metadata, options, &HashMap::new(), "")?.html)
+}
+
+/// Build the content of `gallery_data.json`: what `serve` sends a page
+/// reloading its photo list.
+pub fn generate_data(
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    options: &GalleryOptions,
+) -> Result<String> {
+    let options = GalleryOptions {
+        data_file: true,
+        ..options.clone()
+    };
+    let page = render_html(photos_by_year, metadata, &options, &HashMap::new(), "")?;
+    Ok(page.data.unwrap_or_default())
+}
+
+/// Render the gallery of `photos_by_year`; `photo_base` leads from the page
+/// to the photo folder.
+fn render_html(
+    photos_by_year: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    options: &GalleryOptions,
+    embedded: &HashMap<String, Embedded>,
+    photo_base: &str,
+) -> Result<Page> {
+    let lang = options.lang;
+    let t = |key: &str| escape_html(lang.get(key));
+    let month_names = lang.month_names();
+    let mut years: Vec<&String> = photos_by_year.keys().collect();
+    years.sort();
+    // Files of each year by month, undated ones last; the photo list follows
+    // the grid order so the lightbox pages through it as shown
+    let mut sections: Vec<(&String, Vec<MonthFiles>)> = Vec::new();
     for year in &years {
-        if let Some(files) = photos_by_year.get(*year) {
-            for file in files {
-                let info = metadata.files.get(file).cloned().unwrap_or_default();
-                let tags_json: Vec<String> = info.tags.iter().map(|t| format!("\"{}\"", escape_js(t))).collect();
-                let rating = info.rating.unwrap_or(0);
-                photo_entries.push(format!(
-                    "{{\"src\":\"{}\",\"year\":\"{}\",\"name\":\"{}\",\"tags\":[{}],\"rating\":{}}}",
-                    escape_js(file),
-                    escape_js(year),
-                    escape_js(file.rsplit('/').next().unwrap_or(file)),
-                    tags_json.join(","),
-                    rating
-                ));
+        let mut months: Vec<MonthFiles> = Vec::new();
+        for file in photos_by_year.get(*year).into_iter().flatten() {
+            let month = photo_date(file, metadata).map(|dt| dt.month());
+            match months.iter_mut().find(|(m, _)| *m == month) {
+                Some((_, files)) => files.push(file),
+                None => months.push((month, vec![file])),
             }
         }
+        months.sort_by_key(|(month, _)| month.unwrap_or(13));
+        sections.push((year, months));
     }
 
-    let photos_json = format!("[{}]", photo_entries.join(","));
+    // Collect all tags for the filter sidebar, one per spelling group
+    let mut all_tags: Vec<String> = metadata.tag_counts().into_iter().map(|c| c.tag).collect();
+    all_tags.sort();
 
-    let total_count: usize = photos_by_year.values().map(|v| v.len()).sum();
+    let files: Vec<&str> = (sections.iter())
+        .flat_map(|(_, months)| months.iter().flat_map(|(_, files)| files))
+        .map(|file| file.as_str())
+        .collect();
+    let duplicates = find_duplicates(&files, metadata);
 
-    // Build HTML grid sections
-    let mut grid_html = String::new();
-    for year in &years {
-        if let Some(files) = photos_by_year.get(*year) {
-            grid_html.push_str(&format!(
-                "<h2 class=\"year-header\" data-year=\"{year}\">{year} <span class=\"count\">{}</span></h2>\n<div class=\"grid\" data-year=\"{year}\">\n",
-                files.len()
-            ));
-            for (i, file) in files.iter().enumerate() {
-                let info = metadata.files.get(file).cloned().unwrap_or_default();
-                let tags_attr: String = info.tags.join(",");
-                let rating = info.rating.unwrap_or(0);
-                let name = file.rsplit('/').next().unwrap_or(file);
-                let stars_display = if rating > 0 {
-                    "\u{2605}".repeat(rating as usize)
-                } else {
-                    String::new()
-                };
-                grid_html.push_str(&format!(
-                    "  <div class=\"thumb\" data-idx=\"{}\" data-tags=\"{}\" data-rating=\"{}\">\
-                    <img data-src=\"{}\" class=\"lazy\" alt=\"{}\"><div class=\"thumb-stars\">{}</div><div class=\"info\">{}</div></div>\n",
-                    photo_entries.len() - total_count + i,
-                    escape_html(&tags_attr),
-                    rating,
-                    escape_html(file),
-                    escape_html(name),
-                    stars_display,
-                    escape_html(name)
-                ));
+    // Photo list for the script, read as JSON: the grid is drawn from it
+    let no_info = FileInfo::default();
+    let mut photo_entries = Vec::new();
+    for (year, months) in &sections {
+        for (month, files) in months {
+            for &file in files {
+                let info = metadata.files.get(file).unwrap_or(&no_info);
+                let time = |t: NaiveDateTime| t.format("%Y-%m-%dT%H:%M:%S%.3f").to_string();
+                photo_entries.push(PhotoEntry {
+                    src: file,
+                    year,
+                    name: file.rsplit('/').next().unwrap_or(file),
+                    section: section_key(year, *month),
+                    tags: &info.tags,
+                    people: &info.people,
+                    rating: info.rating.unwrap_or(0),
+                    taken: info.taken.map(time),
+                    date_override: metadata.get_date_override(file).map(time),
+                    date_source: info.date_source.as_deref(),
+                    date: photo_date(file, metadata).map(|t| t.format("%Y-%m-%d").to_string()),
+                    pair: metadata.get_pair(file),
+                    caption: info.caption.as_deref().unwrap_or(""),
+                    favorite: info.favorite,
+                    hidden: info.hidden,
+                    label: info.label.as_deref(),
+                    lat: info.lat,
+                    lon: info.lon,
+                    exif: info.exif.as_ref(),
+                    duplicate: duplicates.get(file.as_str()).copied(),
+                    thumb: (embedded.get(file).and_then(|e| e.thumb.clone()))
+                        .or_else(|| thumb::thumb_url(file)),
+                    preview: embedded.get(file).and_then(|e| e.preview.clone()),
+                    placeholder: embedded.get(file).and_then(|e| e.placeholder.clone()),
+                });
             }
-            grid_html.push_str("</div>\n");
         }
     }
+    // No `</` may end the script early
+    let photos_json = serde_json::to_string(&photo_entries)
+        .unwrap_or_else(|_| "[]".to_string())
+        .replace("</", "<\\/");
+
+    // Months to jump to; the script builds the year and month sections from
+    // the photo list as the page is scrolled
+    let mut month_options = String::new();
+    for (year, months) in &sections {
+        month_options.push_str(&format!("<optgroup label=\"{year}\">"));
+        for (month, _) in months {
+            let key = section_key(year, *month);
+            let label = match month {
+                Some(m) => month_names[*m as usize - 1],
+                None => lang.get("undated"),
+            };
+            month_options.push_str(&format!("<option value=\"{key}\">{label} {year}</option>"));
+        }
+        month_options.push_str("</optgroup>");
+    }
 
     // Tags filter HTML
     let mut tags_filter_html = String::new();
     if !all_tags.is_empty() {
-        tags_filter_html.push_str("<div class=\"filter-group\"><span class=\"filter-label\">Tags</span><div class=\"filter-tags\" id=\"filter-tags-container\">");
-        tags_filter_html.push_str("<button class=\"tag-btn active\" data-tag=\"\">Tous</button>");
+        tags_filter_html.push_str(&format!("<div class=\"filter-group\"><span class=\"filter-label\">{}</span><div class=\"filter-tags\" id=\"filter-tags-container\">", t("tags")));
+        tags_filter_html.push_str(&format!(
+            "<button class=\"tag-btn active\" data-tag=\"\">{}</button>",
+            t("all")
+        ));
         for tag in &all_tags {
             tags_filter_html.push_str(&format!(
                 "<button class=\"tag-btn\" data-tag=\"{}\">{}</button>",
@@ -128,713 +606,155 @@ pub fn generate_html(photos_by_year: &HashMap<String, Vec<String>>, metadata: &M
         tags_filter_html.push_str("</div></div>");
     }
 
-    format!(
-        r##"<!DOCTYPE html>
-<html lang="fr">
-<head>
-<meta charset="utf-8">
-<meta name="viewport" content="width=device-width,initial-scale=1">
-<title>photo-sort gallery</title>
-<style>
-*{{margin:0;padding:0;box-sizing:border-box}}
-body{{background:#0a0a0a;color:#e0e0e0;font-family:-apple-system,BlinkMacSystemFont,"Segoe UI",Roboto,sans-serif;overflow-x:hidden}}
-header{{position:sticky;top:0;z-index:100;background:rgba(10,10,10,.95);backdrop-filter:blur(12px);padding:1rem 2rem;display:flex;justify-content:space-between;align-items:center;border-bottom:1px solid #222}}
-header h1{{font-size:1.2rem;font-weight:600;color:#4fc3f7}}
-.controls{{display:flex;gap:.5rem;align-items:center}}
-.controls button,.controls .badge{{background:#1a1a1a;color:#ccc;border:1px solid #333;padding:.4rem .8rem;border-radius:6px;cursor:pointer;font-size:.85rem;transition:all .2s}}
-.controls button:hover,.controls button.active{{background:#4fc3f7;color:#000;border-color:#4fc3f7}}
-.controls button.save-btn{{background:#1a3a1a;color:#6f6;border-color:#363}}
-.controls button.save-btn:hover{{background:#2a5a2a}}
-.controls button.save-btn.has-changes{{animation:pulse 2s infinite}}
-@keyframes pulse{{0%,100%{{opacity:1}}50%{{opacity:.6}}}}
-.controls button.export-btn{{background:#3a1a1a;color:#f96;border-color:#633}}
-.controls button.export-btn:hover{{background:#5a2a2a}}
-.filter-bar{{padding:.8rem 2rem;background:#111;border-bottom:1px solid #1a1a1a;display:flex;gap:1.5rem;align-items:center;flex-wrap:wrap}}
-.filter-group{{display:flex;gap:.5rem;align-items:center}}
-.filter-label{{font-size:.75rem;text-transform:uppercase;color:#666;letter-spacing:.05em}}
-.filter-tags{{display:flex;gap:.3rem;flex-wrap:wrap}}
-.tag-btn{{background:#1a1a1a;color:#aaa;border:1px solid #2a2a2a;padding:.25rem .6rem;border-radius:12px;cursor:pointer;font-size:.8rem;transition:all .2s}}
-.tag-btn:hover,.tag-btn.active{{background:#4fc3f7;color:#000;border-color:#4fc3f7}}
-.rating-filter{{display:flex;gap:.2rem;align-items:center}}
-.rating-filter button{{background:none;border:none;font-size:1.2rem;cursor:pointer;color:#444;transition:color .2s}}
-.rating-filter button.active,.rating-filter button:hover{{color:#ffd700}}
-main{{padding:1rem 2rem 4rem}}
-.year-header{{margin:2rem 0 1rem;font-size:1.5rem;font-weight:300;color:#4fc3f7}}
-.year-header .count{{font-size:.9rem;color:#555}}
-.grid{{display:grid;grid-template-columns:repeat(auto-fill,minmax(200px,1fr));gap:6px}}
-.thumb{{position:relative;aspect-ratio:1;overflow:hidden;border-radius:4px;cursor:pointer;transition:transform .2s}}
-.thumb:hover{{transform:scale(1.03);z-index:1}}
-.thumb img{{width:100%;height:100%;object-fit:cover}}
-.thumb img.lazy{{opacity:0;transition:opacity .3s}}
-.thumb img.loaded{{opacity:1}}
-.thumb .info{{position:absolute;bottom:0;left:0;right:0;padding:.3rem .5rem;background:linear-gradient(transparent,rgba(0,0,0,.8));font-size:.7rem;color:#ccc;opacity:0;transition:opacity .2s}}
-.thumb:hover .info{{opacity:1}}
-.thumb.hidden{{display:none}}
-.thumb .thumb-stars{{position:absolute;top:.3rem;right:.3rem;color:#ffd700;font-size:.7rem;text-shadow:0 1px 3px rgba(0,0,0,.8)}}
-
-/* Lightbox */
-.lightbox{{display:none;position:fixed;inset:0;z-index:1000;background:rgba(0,0,0,.97);flex-direction:column;align-items:center;justify-content:center}}
-.lightbox.open{{display:flex}}
-.lightbox img#lb-img{{max-width:90vw;max-height:70vh;object-fit:contain;border-radius:4px;user-select:none;transition:filter .3s}}
-.lightbox img#lb-img.lb-loading{{filter:blur(6px)}}
-.lb-top-bar{{position:absolute;top:0;left:0;right:0;display:flex;justify-content:space-between;align-items:center;padding:.8rem 1.5rem;z-index:1002}}
-.lb-close{{font-size:2rem;color:#888;cursor:pointer;transition:color .2s}}
-.lb-close:hover{{color:#fff}}
-.lb-actions{{display:flex;gap:.4rem;align-items:center}}
-.lb-action{{color:#888;cursor:pointer;font-size:.85rem;text-decoration:none;padding:.3rem .6rem;border:1px solid #444;border-radius:6px;transition:all .2s;background:none}}
-.lb-action:hover{{color:#fff;border-color:#888}}
-.lb-delete-btn{{color:#f66!important;border-color:#633!important}}
-.lb-delete-btn:hover{{color:#fff!important;background:#a33!important;border-color:#a33!important}}
-.lb-info-btn{{color:#8cf!important;border-color:#346!important}}
-.lb-info-btn:hover{{color:#fff!important;background:#457!important;border-color:#457!important}}
-.lb-move-btn{{color:#fc6!important;border-color:#653!important}}
-.lb-move-btn:hover{{color:#fff!important;background:#a73!important;border-color:#a73!important}}
-.lb-nav{{position:absolute;top:50%;transform:translateY(-50%);font-size:3rem;color:#555;cursor:pointer;user-select:none;padding:1rem;transition:color .2s;z-index:1001}}
-.lb-nav:hover{{color:#fff}}
-.lb-prev{{left:1rem}}
-.lb-next{{right:1rem}}
-.lb-panel{{margin-top:.8rem;text-align:center;color:#999;font-size:.9rem;max-width:600px;width:90vw}}
-.lb-panel .lb-name{{color:#e0e0e0;font-weight:500;margin-bottom:.5rem}}
-.lb-stars{{display:flex;justify-content:center;gap:.15rem;margin:.4rem 0}}
-.lb-stars span{{font-size:1.6rem;cursor:pointer;color:#444;transition:color .15s}}
-.lb-stars span.filled{{color:#ffd700}}
-.lb-stars span:hover,.lb-stars span.hover{{color:#ffed80}}
-.lb-edit-tags{{display:flex;flex-wrap:wrap;justify-content:center;gap:.3rem;margin:.4rem 0;align-items:center}}
-.lb-edit-tags .tag-badge{{background:#1a2a3a;color:#4fc3f7;padding:.2rem .5rem;border-radius:10px;font-size:.8rem;display:inline-flex;align-items:center;gap:.3rem}}
-.lb-edit-tags .tag-badge .tag-remove{{cursor:pointer;color:#f66;font-weight:bold;font-size:.9rem}}
-.lb-edit-tags .tag-badge .tag-remove:hover{{color:#f00}}
-.lb-tag-form{{display:inline-flex;gap:.3rem;align-items:center}}
-.lb-tag-form input{{background:#1a1a1a;border:1px solid #333;color:#e0e0e0;padding:.2rem .5rem;border-radius:10px;font-size:.8rem;width:100px;outline:none}}
-.lb-tag-form input:focus{{border-color:#4fc3f7}}
-.lb-tag-form button{{background:#4fc3f7;color:#000;border:none;padding:.2rem .5rem;border-radius:10px;font-size:.8rem;cursor:pointer}}
-.tag-suggestions{{display:flex;flex-wrap:wrap;justify-content:center;gap:.25rem;margin:.3rem 0}}
-.tag-suggestions .tag-sug{{background:#1a1a1a;color:#888;border:1px dashed #333;padding:.15rem .5rem;border-radius:10px;font-size:.75rem;cursor:pointer;transition:all .2s}}
-.tag-suggestions .tag-sug:hover{{color:#4fc3f7;border-color:#4fc3f7}}
-.lb-slideshow-bar{{position:absolute;bottom:0;left:0;height:3px;background:#4fc3f7;transition:width linear}}
-
-/* Slideshow controls */
-.slideshow-controls{{position:absolute;bottom:1.5rem;display:flex;gap:.5rem;z-index:1002}}
-.slideshow-controls button{{background:rgba(255,255,255,.1);color:#ccc;border:1px solid #444;padding:.4rem .8rem;border-radius:6px;cursor:pointer;font-size:.85rem;transition:all .2s}}
-.slideshow-controls button:hover,.slideshow-controls button.active{{background:#4fc3f7;color:#000;border-color:#4fc3f7}}
-
-/* EXIF modal */
-.exif-modal{{display:none;position:fixed;inset:0;z-index:1100;background:rgba(0,0,0,.7);align-items:center;justify-content:center}}
-.exif-modal.open{{display:flex}}
-.exif-content{{background:#1a1a1a;border:1px solid #333;border-radius:10px;max-width:560px;width:90vw;max-height:80vh;display:flex;flex-direction:column}}
-.exif-header{{display:flex;justify-content:space-between;align-items:center;padding:.8rem 1.2rem;border-bottom:1px solid #2a2a2a}}
-.exif-title{{color:#8cf;font-weight:600;font-size:1rem}}
-.exif-close{{font-size:1.5rem;color:#888;cursor:pointer;transition:color .2s}}
-.exif-close:hover{{color:#fff}}
-.exif-body{{overflow-y:auto;padding:1rem 1.2rem}}
-.exif-body table{{width:100%;border-collapse:collapse}}
-.exif-body td{{padding:.25rem .5rem;font-size:.82rem;border-bottom:1px solid #1f1f1f;vertical-align:top}}
-.exif-body td:first-child{{color:#888;white-space:nowrap;width:40%}}
-.exif-body td:last-child{{color:#ddd;word-break:break-word}}
-.exif-body .exif-loading{{color:#888;text-align:center;padding:2rem 0}}
-
-/* Toast */
-.toast{{position:fixed;bottom:2rem;left:50%;transform:translateX(-50%);background:#2a2a2a;color:#fff;padding:.6rem 1.2rem;border-radius:8px;font-size:.85rem;z-index:2000;opacity:0;transition:opacity .3s;pointer-events:none}}
-.toast.show{{opacity:1}}
-
-@media(max-width:600px){{
-  .grid{{grid-template-columns:repeat(auto-fill,minmax(120px,1fr));gap:3px}}
-  header{{padding:.8rem 1rem}}
-  main{{padding:.5rem 1rem}}
-}}
-</style>
-</head>
-<body>
-<header>
-  <h1>photo-sort gallery</h1>
-  <div class="controls">
-    <button id="btn-slideshow">Diaporama</button>
-    <button id="btn-random">Aléatoire</button>
-    <button id="btn-export" class="export-btn">Exporter filtré</button>
-    <button id="btn-save" class="save-btn">Sauvegarder</button>
-  </div>
-</header>
-<div class="filter-bar">
-  {tags_filter}
-  <div class="filter-group">
-    <span class="filter-label">Note min</span>
-    <div class="rating-filter" id="rating-filter">
-      <button data-rating="0" class="active">&#x2715;</button>
-      <button data-rating="1">&#9733;</button>
-      <button data-rating="2">&#9733;</button>
-      <button data-rating="3">&#9733;</button>
-      <button data-rating="4">&#9733;</button>
-      <button data-rating="5">&#9733;</button>
-    </div>
-  </div>
-</div>
-<main>
-{grid}
-</main>
-
-<div class="lightbox" id="lightbox">
-  <div class="lb-top-bar">
-    <div class="lb-actions">
-      <a class="lb-action" id="lb-download" download>&#x2B07; Télécharger</a>
-      <button class="lb-action lb-rotate" id="lb-rotate-left" title="Rotation gauche">&#x21BA;</button>
-      <button class="lb-action lb-rotate" id="lb-rotate-right" title="Rotation droite">&#x21BB;</button>
-      <button class="lb-action lb-info-btn" id="lb-info" title="Métadonnées">&#x2139; Info</button>
-      <button class="lb-action lb-move-btn" id="lb-move" title="Déplacer">&#x1F4C1; Déplacer</button>
-      <button class="lb-action lb-delete-btn" id="lb-delete" title="Supprimer">&#x1F5D1; Supprimer</button>
-    </div>
-    <span class="lb-close" id="lb-close">&times;</span>
-  </div>
-  <span class="lb-nav lb-prev" id="lb-prev">&#8249;</span>
-  <span class="lb-nav lb-next" id="lb-next">&#8250;</span>
-  <img id="lb-img" src="" alt="">
-  <div class="lb-panel">
-    <div class="lb-name" id="lb-name"></div>
-    <div class="lb-stars" id="lb-stars">
-      <span data-star="1">&#9733;</span>
-      <span data-star="2">&#9733;</span>
-      <span data-star="3">&#9733;</span>
-      <span data-star="4">&#9733;</span>
-      <span data-star="5">&#9733;</span>
-    </div>
-    <div class="lb-edit-tags" id="lb-edit-tags"></div>
-    <div class="tag-suggestions" id="tag-suggestions"></div>
-  </div>
-  <div class="lb-slideshow-bar" id="lb-bar" style="width:0%"></div>
-  <div class="slideshow-controls">
-    <button id="ss-prev">&#9664; Préc</button>
-    <button id="ss-playpause">Pause</button>
-    <button id="ss-next">Suiv &#9654;</button>
-    <button id="ss-random-toggle">Aléatoire</button>
-    <button id="ss-speed-down">-</button>
-    <span id="ss-speed" style="color:#ccc;font-size:.85rem">5s</span>
-    <button id="ss-speed-up">+</button>
-  </div>
-</div>
-
-<div class="exif-modal" id="exif-modal">
-  <div class="exif-content">
-    <div class="exif-header">
-      <span class="exif-title">Métadonnées</span>
-      <span class="exif-close" id="exif-close">&times;</span>
-    </div>
-    <div class="exif-body" id="exif-body"></div>
-  </div>
-</div>
-
-<div class="toast" id="toast"></div>
-
-<script>
-const ALL_PHOTOS={photos_json};
-const photoMap=new Map();
-ALL_PHOTOS.forEach(p=>photoMap.set(p.src,p));
-const thumbMap=new Map();
-document.querySelectorAll('.thumb').forEach(el=>{{
-  const src=el.querySelector('img').getAttribute('data-src');
-  if(src)thumbMap.set(src,el);
-}});
-let filtered=ALL_PHOTOS.slice();
-let filteredSet=new Set(ALL_PHOTOS.map(p=>p.src));
-let currentIdx=0;
-let slideshowInterval=null;
-let slideshowDelay=5000;
-let slideshowRandom=false;
-let activeTag="";
-let minRating=0;
-let hasChanges=false;
-let allTagsCache=null;
-function rebuildTagCache(){{
-  const s=new Set();
-  ALL_PHOTOS.forEach(p=>p.tags.forEach(t=>s.add(t)));
-  allTagsCache=[...s].sort();
-}}
-rebuildTagCache();
-
-function toast(msg){{
-  const t=document.getElementById('toast');
-  t.textContent=msg;t.classList.add('show');
-  setTimeout(()=>t.classList.remove('show'),2000);
-}}
-
-function markDirty(){{
-  hasChanges=true;
-  document.getElementById('btn-save').classList.add('has-changes');
-}}
-
-let prevTagsKey='';
-function refreshFilterBar(){{
-  rebuildTagCache();
-  const key=allTagsCache.join('\0');
-  const container=document.getElementById('filter-tags-container');
-  if(!container)return;
-  if(key===prevTagsKey)return;
-  prevTagsKey=key;
-  container.innerHTML='<button class="tag-btn'+(activeTag?'':' active')+'" data-tag="">Tous</button>';
-  allTagsCache.forEach(tag=>{{
-    const btn=document.createElement('button');
-    btn.className='tag-btn'+(activeTag===tag?' active':'');
-    btn.dataset.tag=tag;btn.textContent=tag;
-    container.appendChild(btn);
-  }});
-  container.querySelectorAll('.tag-btn').forEach(btn=>{{
-    btn.addEventListener('click',()=>{{
-      container.querySelectorAll('.tag-btn').forEach(b=>b.classList.remove('active'));
-      btn.classList.add('active');
-      activeTag=btn.dataset.tag;
-      applyFilters();
-    }});
-  }});
-}}
-
-function applyFilters(){{
-  filtered=ALL_PHOTOS.filter(p=>{{
-    if(activeTag&&!p.tags.includes(activeTag))return false;
-    if(minRating>0&&p.rating<minRating)return false;
-    return true;
-  }});
-  filteredSet=new Set(filtered.map(p=>p.src));
-  const yearCounts={{}};
-  filtered.forEach(p=>{{yearCounts[p.year]=(yearCounts[p.year]||0)+1;}});
-  thumbMap.forEach((el,src)=>{{
-    const match=filteredSet.has(src);
-    el.classList.toggle('hidden',!match);
-    const photo=photoMap.get(src);
-    const starsEl=el.querySelector('.thumb-stars');
-    if(starsEl&&photo)starsEl.textContent=photo.rating?'★'.repeat(photo.rating):'';
-  }});
-  document.querySelectorAll('.year-header').forEach(h=>{{
-    const year=h.dataset.year;
-    const count=yearCounts[year]||0;
-    h.querySelector('.count').textContent=count;
-    h.style.display=count?'':'none';
-    const grid=document.querySelector(`.grid[data-year="${{year}}"]`);
-    if(grid)grid.style.display=count?'':'none';
-  }});
-}}
-
-// Tag filter
-document.querySelectorAll('.tag-btn').forEach(btn=>{{
-  btn.addEventListener('click',()=>{{
-    document.querySelectorAll('.tag-btn').forEach(b=>b.classList.remove('active'));
-    btn.classList.add('active');
-    activeTag=btn.dataset.tag;
-    applyFilters();
-  }});
-}});
-
-// Rating filter
-document.querySelectorAll('#rating-filter button').forEach(btn=>{{
-  btn.addEventListener('click',()=>{{
-    document.querySelectorAll('#rating-filter button').forEach(b=>b.classList.remove('active'));
-    btn.classList.add('active');
-    minRating=parseInt(btn.dataset.rating);
-    applyFilters();
-  }});
-}});
-
-// Lightbox
-const lb=document.getElementById('lightbox');
-const lbImg=document.getElementById('lb-img');
-const lbName=document.getElementById('lb-name');
-const lbBar=document.getElementById('lb-bar');
-
-function renderLbStars(rating){{
-  document.querySelectorAll('#lb-stars span').forEach(s=>{{
-    s.classList.toggle('filled',parseInt(s.dataset.star)<=rating);
-  }});
-}}
-
-function renderLbTags(photo){{
-  const container=document.getElementById('lb-edit-tags');
-  container.innerHTML='';
-  photo.tags.forEach(tag=>{{
-    const badge=document.createElement('span');
-    badge.className='tag-badge';
-    badge.innerHTML=tag+' <span class="tag-remove" data-tag="'+tag+'">&times;</span>';
-    container.appendChild(badge);
-  }});
-  // Add tag form
-  const form=document.createElement('span');
-  form.className='lb-tag-form';
-  form.innerHTML='<input type="text" id="lb-tag-input" placeholder="tag...">'
-    +'<button id="lb-tag-add">+</button>';
-  container.appendChild(form);
-  // Event: remove tag
-  container.querySelectorAll('.tag-remove').forEach(btn=>{{
-    btn.addEventListener('click',()=>removeTag(photo,btn.dataset.tag));
-  }});
-  // Event: add tag
-  const addBtn=document.getElementById('lb-tag-add');
-  const input=document.getElementById('lb-tag-input');
-  addBtn.addEventListener('click',()=>addTag(photo,input.value.trim()));
-  input.addEventListener('keydown',e=>{{if(e.key==='Enter'){{e.preventDefault();addTag(photo,input.value.trim());}}}});
-  renderTagSuggestions(photo);
-}}
-
-function renderTagSuggestions(photo){{
-  const container=document.getElementById('tag-suggestions');
-  container.innerHTML='';
-  const photoTagSet=new Set(photo.tags);
-  const suggestions=allTagsCache.filter(t=>!photoTagSet.has(t));
-  suggestions.forEach(tag=>{{
-    const chip=document.createElement('span');
-    chip.className='tag-sug';
-    chip.textContent=tag;
-    chip.addEventListener('click',()=>addTag(photo,tag));
-    container.appendChild(chip);
-  }});
-}}
-
-function addTag(photo,tag){{
-  if(!tag||photo.tags.includes(tag))return;
-  photo.tags.push(tag);
-  markDirty();
-  rebuildTagCache();
-  renderLbTags(photo);
-  refreshFilterBar();
-  applyFilters();
-  toast('Tag «'+tag+'» ajouté');
-}}
-
-function removeTag(photo,tag){{
-  photo.tags=photo.tags.filter(t=>t!==tag);
-  markDirty();
-  rebuildTagCache();
-  renderLbTags(photo);
-  refreshFilterBar();
-  applyFilters();
-  toast('Tag «'+tag+'» retiré');
-}}
-
-function setRating(photo,rating){{
-  photo.rating=(photo.rating===rating)?0:rating;
-  markDirty();
-  renderLbStars(photo.rating);
-  applyFilters();
-  toast(photo.rating?'Note : '+photo.rating+'/5':'Note supprimée');
-}}
-
-let _lbHiRes=null;
-function showPhoto(idx){{
-  if(filtered.length===0)return;
-  if(_lbHiRes){{_lbHiRes.onload=null;_lbHiRes=null;}}
-  currentIdx=((idx%filtered.length)+filtered.length)%filtered.length;
-  const p=filtered[currentIdx];
-  lbImg.src=getSrc(p.src);
-  lbImg.classList.add('lb-loading');
-  _lbHiRes=new Image();
-  _lbHiRes.onload=function(){{
-    if(filtered[currentIdx]===p){{lbImg.src=p.src;lbImg.classList.remove('lb-loading');}}
-  }};
-  _lbHiRes.src=p.src;
-  lbName.textContent=p.name+' ('+p.year+')';
-  renderLbStars(p.rating);
-  renderLbTags(p);
-  document.getElementById('lb-download').href=p.src;
-}}
-
-// Star click
-document.querySelectorAll('#lb-stars span').forEach(star=>{{
-  star.addEventListener('click',()=>{{
-    if(filtered.length===0)return;
-    setRating(filtered[currentIdx],parseInt(star.dataset.star));
-  }});
-  star.addEventListener('mouseenter',()=>{{
-    const v=parseInt(star.dataset.star);
-    document.querySelectorAll('#lb-stars span').forEach(s=>{{
-      s.classList.toggle('hover',parseInt(s.dataset.star)<=v);
-    }});
-  }});
-  star.addEventListener('mouseleave',()=>{{
-    document.querySelectorAll('#lb-stars span').forEach(s=>s.classList.remove('hover'));
-  }});
-}});
-
-function openLightbox(idx){{
-  showPhoto(idx);
-  lb.classList.add('open');
-  document.body.style.overflow='hidden';
-}}
-
-function closeLightbox(){{
-  lb.classList.remove('open');
-  document.body.style.overflow='';
-  stopSlideshow();
-}}
-
-document.getElementById('lb-close').addEventListener('click',closeLightbox);
-document.getElementById('lb-prev').addEventListener('click',()=>{{showPhoto(currentIdx-1);resetSlideshowTimer();}});
-document.getElementById('lb-next').addEventListener('click',()=>{{showPhoto(currentIdx+1);resetSlideshowTimer();}});
-
-document.querySelectorAll('.thumb').forEach(el=>{{
-  el.addEventListener('click',()=>{{
-    const src=el.querySelector('img').getAttribute('data-src');
-    const idx=filtered.findIndex(p=>p.src===src);
-    if(idx>=0)openLightbox(idx);
-  }});
-}});
-
-document.addEventListener('keydown',e=>{{
-  if(!lb.classList.contains('open'))return;
-  if(e.key==='Escape')closeLightbox();
-  if(e.key==='ArrowLeft'){{showPhoto(currentIdx-1);resetSlideshowTimer();}}
-  if(e.key==='ArrowRight'){{showPhoto(currentIdx+1);resetSlideshowTimer();}}
-  if(e.key>='1'&&e.key<='5')setRating(filtered[currentIdx],parseInt(e.key));
-  if(e.key==='0')setRating(filtered[currentIdx],0);
-}});
-
-// Slideshow
-function startSlideshow(random){{
-  slideshowRandom=random;
-  if(filtered.length===0)return;
-  if(!lb.classList.contains('open'))openLightbox(random?Math.floor(Math.random()*filtered.length):0);
-  document.getElementById('ss-random-toggle').classList.toggle('active',slideshowRandom);
-  document.getElementById('ss-playpause').textContent='Pause';
-  document.querySelector('.slideshow-controls').style.display='flex';
-  runSlideshowTick();
-}}
-
-function runSlideshowTick(){{
-  clearInterval(slideshowInterval);
-  lbBar.style.transition='none';lbBar.style.width='0%';
-  requestAnimationFrame(()=>{{requestAnimationFrame(()=>{{
-    lbBar.style.transition='width '+slideshowDelay+'ms linear';lbBar.style.width='100%';
-  }})}});
-  slideshowInterval=setTimeout(()=>{{
-    if(slideshowRandom)showPhoto(Math.floor(Math.random()*filtered.length));
-    else showPhoto(currentIdx+1);
-    runSlideshowTick();
-  }},slideshowDelay);
-}}
-
-function resetSlideshowTimer(){{if(slideshowInterval)runSlideshowTick();}}
-
-function stopSlideshow(){{
-  clearInterval(slideshowInterval);slideshowInterval=null;
-  lbBar.style.width='0%';
-  document.querySelector('.slideshow-controls').style.display='none';
-  document.getElementById('ss-playpause').textContent='Pause';
-}}
-
-document.getElementById('btn-slideshow').addEventListener('click',()=>startSlideshow(false));
-document.getElementById('btn-random').addEventListener('click',()=>startSlideshow(true));
-
-document.getElementById('ss-playpause').addEventListener('click',()=>{{
-  const btn=document.getElementById('ss-playpause');
-  if(slideshowInterval){{clearInterval(slideshowInterval);slideshowInterval=null;lbBar.style.transition='none';btn.textContent='Reprendre';}}
-  else{{btn.textContent='Pause';runSlideshowTick();}}
-}});
-
-document.getElementById('ss-prev').addEventListener('click',()=>{{showPhoto(currentIdx-1);resetSlideshowTimer();}});
-document.getElementById('ss-next').addEventListener('click',()=>{{showPhoto(currentIdx+1);resetSlideshowTimer();}});
-
-document.getElementById('ss-random-toggle').addEventListener('click',()=>{{
-  slideshowRandom=!slideshowRandom;
-  document.getElementById('ss-random-toggle').classList.toggle('active',slideshowRandom);
-}});
-
-document.getElementById('ss-speed-down').addEventListener('click',()=>{{
-  slideshowDelay=Math.min(slideshowDelay+1000,15000);
-  document.getElementById('ss-speed').textContent=(slideshowDelay/1000)+'s';
-  if(slideshowInterval)runSlideshowTick();
-}});
-
-document.getElementById('ss-speed-up').addEventListener('click',()=>{{
-  slideshowDelay=Math.max(slideshowDelay-1000,1000);
-  document.getElementById('ss-speed').textContent=(slideshowDelay/1000)+'s';
-  if(slideshowInterval)runSlideshowTick();
-}});
-
-const isServed=window.location.protocol.startsWith('http');
-
-// Save metadata
-function saveMetadata(){{
-  const meta={{files:{{}}}};
-  ALL_PHOTOS.forEach(p=>{{
-    if(p.tags.length||p.rating){{
-      const entry={{}};
-      if(p.tags.length)entry.tags=p.tags;
-      if(p.rating)entry.rating=p.rating;
-      meta.files[p.src]=entry;
-    }}
-  }});
-  const json=JSON.stringify(meta,null,2);
-  if(isServed){{
-    fetch('/api/metadata',{{method:'POST',headers:{{'Content-Type':'application/json'}},body:json}})
-      .then(r=>r.json())
-      .then(d=>{{
-        if(d.ok){{hasChanges=false;document.getElementById('btn-save').classList.remove('has-changes');toast('Metadata sauvegardé');}}
-        else toast('Erreur: '+(d.error||'inconnue'));
-      }}).catch(e=>toast('Erreur réseau: '+e));
-  }}else{{
-    const blob=new Blob([json],{{type:'application/json'}});
-    const a=document.createElement('a');a.href=URL.createObjectURL(blob);
-    a.download='.photo_sort_metadata.json';a.click();URL.revokeObjectURL(a.href);
-    hasChanges=false;document.getElementById('btn-save').classList.remove('has-changes');
-    toast('Metadata sauvegardé (téléchargé)');
-  }}
-}}
-
-document.getElementById('btn-save').addEventListener('click',saveMetadata);
-
-// Delete photo
-function deletePhoto(){{
-  if(filtered.length===0)return;
-  const p=filtered[currentIdx];
-  if(!confirm('Supprimer définitivement '+p.name+' ?'))return;
-  if(isServed){{
-    fetch('/api/photo?path='+encodeURIComponent(p.src),{{method:'DELETE'}})
-      .then(r=>r.json())
-      .then(d=>{{
-        if(d.ok){{
-          const gi=ALL_PHOTOS.indexOf(p);
-          if(gi>=0)ALL_PHOTOS.splice(gi,1);
-          photoMap.delete(p.src);
-          filtered=filtered.filter(x=>x!==p);
-          filteredSet.delete(p.src);
-          // Remove from grid
-          const thumbEl=thumbMap.get(p.src);
-          if(thumbEl){{thumbEl.remove();thumbMap.delete(p.src);}}
-          applyFilters();refreshFilterBar();
-          if(filtered.length===0)closeLightbox();
-          else showPhoto(Math.min(currentIdx,filtered.length-1));
-          toast(p.name+' supprimé');
-        }}else toast('Erreur: '+(d.error||'inconnue'));
-      }}).catch(e=>toast('Erreur réseau: '+e));
-  }}else{{
-    toast('Suppression disponible uniquement via photo-sort serve');
-  }}
-}}
-
-document.getElementById('lb-delete').addEventListener('click',deletePhoto);
-
-// Move photo
-function movePhoto(){{
-  if(filtered.length===0)return;
-  const p=filtered[currentIdx];
-  const dest=prompt('Déplacer '+p.name+' vers quel dossier (ex: 2021) ?',p.year);
-  if(!dest||dest===p.year)return;
-  if(isServed){{
-    fetch('/api/move',{{method:'POST',headers:{{'Content-Type':'application/json'}},
-      body:JSON.stringify({{src:p.src,dest_dir:dest}})}})
-      .then(r=>r.json())
-      .then(d=>{{
-        if(d.ok){{
-          const oldSrc=p.src;
-          p.src=d.new_path;p.year=dest;p.name=p.src.split('/').pop();
-          // Update maps and grid image
-          photoMap.delete(oldSrc);photoMap.set(p.src,p);
-          const movedEl=thumbMap.get(oldSrc);
-          if(movedEl){{
-            thumbMap.delete(oldSrc);thumbMap.set(p.src,movedEl);
-            const img=movedEl.querySelector('img');
-            if(img){{img.setAttribute('data-src',p.src);img.src=getSrc(p.src);}}
-          }}
-          markDirty();applyFilters();refreshFilterBar();
-          showPhoto(currentIdx);
-          toast(p.name+' déplacé vers '+dest);
-        }}else toast('Erreur: '+(d.error||'inconnue'));
-      }}).catch(e=>toast('Erreur réseau: '+e));
-  }}else{{
-    toast('Déplacement disponible uniquement via photo-sort serve');
-  }}
-}}
-
-document.getElementById('lb-move').addEventListener('click',movePhoto);
-
-// Rotate photo
-function rotatePhoto(angle){{
-  if(filtered.length===0)return;
-  const p=filtered[currentIdx];
-  if(isServed){{
-    fetch('/api/rotate',{{method:'POST',headers:{{'Content-Type':'application/json'}},
-      body:JSON.stringify({{path:p.src,angle:angle}})}})
-      .then(r=>r.json())
-      .then(d=>{{
-        if(d.ok){{
-          // Force reload image by appending cache-buster
-          lbImg.src=p.src+'?t='+Date.now();
-          toast('Photo tournée de '+angle+'°');
-        }}else toast('Erreur: '+(d.error||'inconnue'));
-      }}).catch(e=>toast('Erreur réseau: '+e));
-  }}else{{
-    toast('Rotation disponible uniquement via photo-sort serve');
-  }}
-}}
-
-document.getElementById('lb-rotate-left').addEventListener('click',()=>rotatePhoto(270));
-document.getElementById('lb-rotate-right').addEventListener('click',()=>rotatePhoto(90));
-
-// EXIF info
-function escH(s){{return String(s).replace(/&/g,'&amp;').replace(/</g,'&lt;').replace(/>/g,'&gt;').replace(/"/g,'&quot;');}}
-function showExifInfo(){{
-  if(filtered.length===0)return;
-  const p=filtered[currentIdx];
-  const modal=document.getElementById('exif-modal');
-  const body=document.getElementById('exif-body');
-  body.innerHTML='<div class="exif-loading">Chargement…</div>';
-  modal.classList.add('open');
-  if(!isServed){{body.innerHTML='<div class="exif-loading">Disponible uniquement via photo-sort serve</div>';return;}}
-  fetch('/api/exif?path='+encodeURIComponent(p.src))
-    .then(r=>r.json())
-    .then(data=>{{
-      if(!data.length){{body.innerHTML='<div class="exif-loading">Aucune métadonnée</div>';return;}}
-      let html='<table>';
-      data.forEach(d=>{{html+='<tr><td>'+escH(d.tag)+'</td><td>'+escH(d.value)+'</td></tr>';}});
-      html+='</table>';
-      body.innerHTML=html;
-    }}).catch(()=>{{body.innerHTML='<div class="exif-loading">Erreur de chargement</div>';}});
-}}
-function closeExifModal(){{document.getElementById('exif-modal').classList.remove('open');}}
-document.getElementById('lb-info').addEventListener('click',showExifInfo);
-document.getElementById('exif-close').addEventListener('click',closeExifModal);
-document.getElementById('exif-modal').addEventListener('click',e=>{{if(e.target.id==='exif-modal')closeExifModal();}});
-document.addEventListener('keydown',e=>{{if(e.key==='Escape'&&document.getElementById('exif-modal').classList.contains('open')){{closeExifModal();e.stopPropagation();}}}},true);
-
-// Export filtered
-function exportFiltered(){{
-  if(filtered.length===0){{toast('Aucune photo à exporter');return;}}
-  const list=filtered.map(p=>p.src).join('\n');
-  const blob=new Blob([list],{{type:'text/plain'}});
-  const a=document.createElement('a');
-  a.href=URL.createObjectURL(blob);
-  a.download='export_list.txt';
-  a.click();
-  URL.revokeObjectURL(a.href);
-  toast(filtered.length+' fichiers dans export_list.txt');
-}}
-
-document.getElementById('btn-export').addEventListener('click',exportFiltered);
-
-// Warn on unsaved changes
-window.addEventListener('beforeunload',e=>{{
-  if(hasChanges){{e.preventDefault();e.returnValue='';}}
-}});
-
-// Lazy loading with IntersectionObserver
-function getSrc(dataSrc){{return isServed?'/thumb/'+dataSrc:dataSrc;}}
-const lazyObserver=new IntersectionObserver((entries)=>{{
-  entries.forEach(entry=>{{
-    if(entry.isIntersecting){{
-      const img=entry.target;
-      const dataSrc=img.getAttribute('data-src');
-      if(dataSrc){{
-        img.src=getSrc(dataSrc);
-        img.addEventListener('load',()=>img.classList.add('loaded'),{{once:true}});
-        lazyObserver.unobserve(img);
-      }}
-    }}
-  }});
-}},{{rootMargin:'200px'}});
-document.querySelectorAll('img.lazy').forEach(img=>lazyObserver.observe(img));
-
-// Init
-document.querySelector('.slideshow-controls').style.display='none';
-</script>
-</body>
-</html>"##,
-        tags_filter = tags_filter_html,
-        grid = grid_html,
-        photos_json = photos_json,
-    )
+    // People filter
+    let mut all_people: Vec<&String> = metadata
+        .files
+        .values()
+        .flat_map(|info| &info.people)
+        .collect::<std::collections::HashSet<_>>()
+        .into_iter()
+        .collect();
+    all_people.sort();
+    let mut people_filter_html = String::new();
+    if !all_people.is_empty() {
+        people_filter_html.push_str(&format!("<div class=\"filter-group\"><span class=\"filter-label\">{}</span><div class=\"filter-tags\" id=\"filter-people-container\">", t("people")));
+        people_filter_html.push_str(&format!(
+            "<button class=\"tag-btn active\" data-person=\"\">{}</button>",
+            t("all")
+        ));
+        for person in all_people {
+            people_filter_html.push_str(&format!(
+                "<button class=\"tag-btn\" data-person=\"{}\">{}</button>",
+                escape_html(person),
+                escape_html(person)
+            ));
+        }
+        people_filter_html.push_str("</div></div>");
+    }
+
+    // Album filter
+    let mut album_filter_html = String::new();
+    if !metadata.albums.is_empty() {
+        album_filter_html.push_str(&format!("<div class=\"filter-group album-filter\"><span class=\"filter-label\">{}</span><select id=\"album-filter\"><option value=\"\">{}</option>", t("album"), t("all")));
+        for name in metadata.albums.keys() {
+            album_filter_html.push_str(&format!(
+                "<option value=\"{}\">{}</option>",
+                escape_html(name),
+                escape_html(name)
+            ));
+        }
+        album_filter_html.push_str("</select></div>");
+    }
+    // Color label filter: a chip per label in use, in the usual order
+    let used_labels: HashSet<&str> = photo_entries.iter().filter_map(|p| p.label).collect();
+    let mut label_filter_html = String::new();
+    if !used_labels.is_empty() {
+        label_filter_html.push_str(&format!("<div class=\"filter-group label-filter\"><span class=\"filter-label\">{}</span><div class=\"filter-tags\" id=\"filter-labels-container\">", t("labels")));
+        label_filter_html.push_str(&format!(
+            "<button class=\"tag-btn active\" data-label=\"\">{}</button>",
+            t("all")
+        ));
+        for label in COLOR_LABELS.iter().filter(|l| used_labels.contains(*l)) {
+            label_filter_html.push_str(&format!(
+                "<button class=\"tag-btn label-chip\" data-label=\"{label}\" title=\"{}\"></button>",
+                t(&format!("label_{label}"))
+            ));
+        }
+        label_filter_html.push_str("</div></div>");
+    }
+    // A year page still saves the metadata of the other years
+    let other_files: BTreeMap<&String, &FileInfo> = if options.split_by_year {
+        let in_page: HashSet<&String> = photos_by_year.values().flatten().collect();
+        (metadata.files.iter())
+            .filter(|(file, _)| !in_page.contains(file))
+            .collect()
+    } else {
+        BTreeMap::new()
+    };
+    let other_files_json = serde_json::to_string(&other_files)
+        .unwrap_or_else(|_| "{}".to_string())
+        .replace("</", "<\\/");
+    let albums_json = serde_json::to_string(&metadata.albums)
+        .unwrap_or_else(|_| "{}".to_string())
+        .replace("</", "<\\/");
+    let tag_aliases_json = serde_json::to_string(&metadata.tag_aliases)
+        .unwrap_or_else(|_| "{}".to_string())
+        .replace("</", "<\\/");
+    let stats = Stats {
+        photos: photo_entries.len(),
+        years: (sections.iter())
+            .map(|(year, months)| YearCount {
+                year,
+                count: months.iter().map(|(_, files)| files.len()).sum(),
+            })
+            .collect(),
+        tagged: photo_entries.iter().filter(|p| !p.tags.is_empty()).count(),
+        rated: photo_entries.iter().filter(|p| p.rating > 0).count(),
+        favorites: photo_entries.iter().filter(|p| p.favorite).count(),
+    };
+    let data = if options.data_file {
+        let data = GalleryData {
+            photos: &photo_entries,
+            tags: &all_tags,
+            years: &stats.years,
+            albums: &metadata.albums,
+            tag_aliases: &metadata.tag_aliases,
+        };
+        Some(serde_json::to_string(&data)?)
+    } else {
+        None
+    };
+
+    let source = match &options.template {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Impossible de lire le modèle {}", path.display()))?,
+        None => GALLERY_TEMPLATE.to_string(),
+    };
+    let safe = Value::from_safe_string;
+    let html = render_template(
+        "gallery.html",
+        &source,
+        context! {
+            lang => lang.code(),
+            theme => options.theme.as_str(),
+            title => options.title.as_deref().unwrap_or(DEFAULT_TITLE),
+            subtitle => options.subtitle,
+            accent_color => options.accent_color,
+            split_by_year => options.split_by_year,
+            standalone => options.standalone,
+            served => options.served,
+            photo_base_json => safe(serde_json::to_string(photo_base)?.replace("</", "<\\/")),
+            data_file => options.data_file.then_some(DATA_FILE),
+            t => lang.strings(),
+            tags => all_tags,
+            stats => stats,
+            raw_exts => RAW_EXTENSIONS.join("|"),
+            meta_rev => metadata.revision(),
+            tags_filter => safe(tags_filter_html),
+            people_filter => safe(people_filter_html),
+            album_filter => safe(album_filter_html),
+            label_filter => safe(label_filter_html),
+            month_options => safe(month_options),
+            photos_json => safe(photos_json),
+            albums_json => safe(albums_json),
+            other_files_json => safe(other_files_json),
+            tag_aliases_json => safe(tag_aliases_json),
+            month_names_json => safe(serde_json::to_string(&month_names).unwrap_or_default()),
+            strings_json => safe(serde_json::to_string(&lang.strings()).unwrap_or_default()),
+        },
+    )?;
+    Ok(Page { html, data })
+}
+
+/// Render a page, escaping every value not marked safe.
+fn render_template(name: &str, source: &str, context: Value) -> Result<String> {
+    let mut env = Environment::new();
+    env.add_template(name, source)
+        .with_context(|| format!("Modèle {name} invalide"))?;
+    let template = env.get_template(name)?;
+    template
+        .render(context)
+        .with_context(|| format!("Impossible de rendre le modèle {name}"))
 }
 
 fn escape_html(s: &str) -> String {
@@ -844,23 +764,115 @@ fn escape_html(s: &str) -> String {
         .replace('"', "&quot;")
 }
 
-fn escape_js(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('"', "\\\"")
-        .replace('\n', "\\n")
+/// URL prefix leading from the folder `from` to `to`, both canonical:
+/// `../photos/`, empty for the same folder.
+fn relative_url(from: &Path, to: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut url = "../".repeat(from.len() - common);
+    for part in &to[common..] {
+        url.push_str(&part.as_os_str().to_string_lossy());
+        url.push('/');
+    }
+    url
 }
 
-pub fn run_gallery(dir: &Path) -> Result<()> {
-    let metadata = Metadata::load(dir)?;
+/// Copy the cached thumbnails of `rels` next to pages written outside the
+/// photo folder. Returns the number copied.
+fn copy_thumbnails(dir: &Path, out: &Path, rels: &[String]) -> Result<usize> {
+    let mut copied = 0;
+    for rel in rels {
+        let cached = thumb::thumb_cache_path(dir, rel);
+        let dest = thumb::thumb_cache_path(out, rel);
+        if !cached.is_file() || thumb::thumb_is_fresh(&cached, &dest) {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&cached, &dest)
+            .with_context(|| format!("Impossible de copier {}", cached.display()))?;
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+pub fn run_gallery(dir: &Path, options: &GalleryOptions) -> Result<()> {
     let photos = collect_photos(dir);
+    let mut metadata = load_metadata(dir, &photos)?;
 
     let total: usize = photos.values().map(|v| v.len()).sum();
     if total == 0 {
         anyhow::bail!("Aucune photo trouvée dans {}", dir.display());
     }
 
-    let html = generate_html(&photos, &metadata);
-    let output_path = dir.join("gallery.html");
+    // Opened from disk, the grid shows the cached thumbnails, not the originals
+    let rels: Vec<String> = photos.values().flatten().cloned().collect();
+    thumb::prewarm_thumbnails(dir, &rels);
+    if cache_visual_hashes(dir, &photos, &mut metadata) > 0 {
+        metadata.save(dir)?;
+    }
+
+    let embedded = if options.standalone || options.placeholders {
+        embed_photos(dir, &photos, options)
+    } else {
+        HashMap::new()
+    };
+
+    // Written elsewhere, the pages take their thumbnails along and reach the
+    // originals through a relative path
+    let out = options.output.as_deref().unwrap_or(dir);
+    let photo_base = match &options.output {
+        Some(out) => {
+            std::fs::create_dir_all(out)
+                .with_context(|| format!("Impossible de créer {}", out.display()))?;
+            if !options.standalone {
+                copy_thumbnails(dir, out, &rels)?;
+            }
+            relative_url(&out.canonicalize()?, &dir.canonicalize()?)
+        }
+        None => String::new(),
+    };
+
+    if let Some(base_url) = &options.feed {
+        let title = options.title.as_deref().unwrap_or(DEFAULT_TITLE);
+        let xml = feed::render_feed(dir, &photos, &metadata, base_url, title);
+        std::fs::write(out.join(FEED_FILE), xml)?;
+    }
+
+    if options.split_by_year {
+        let mut years: Vec<&String> = photos.keys().collect();
+        years.sort();
+        for year in &years {
+            let page = HashMap::from([((*year).clone(), photos[*year].clone())]);
+            let html = render_html(&page, &metadata, options, &embedded, &photo_base)?.html;
+            std::fs::write(out.join(year_page(year)), html)?;
+        }
+        let output_path = out.join("index.html");
+        let index = generate_index(&photos, &metadata, options, &photo_base)?;
+        std::fs::write(&output_path, index)?;
+        println!(
+            "{} photos dans {} pages annuelles → {}",
+            total,
+            years.len(),
+            output_path.display()
+        );
+        return Ok(());
+    }
+
+    let (html, output_path) = if options.standalone {
+        (
+            render_html(&photos, &metadata, options, &embedded, &photo_base)?.html,
+            out.join("gallery_standalone.html"),
+        )
+    } else {
+        let page = render_html(&photos, &metadata, options, &embedded, &photo_base)?;
+        if let Some(data) = &page.data {
+            std::fs::write(out.join(DATA_FILE), data)?;
+        }
+        (page.html, out.join("gallery.html"))
+    };
     std::fs::write(&output_path, &html)?;
 
     println!(
@@ -956,7 +968,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.starts_with("<!DOCTYPE html>"));
         assert!(html.contains("<title>photo-sort gallery</title>"));
@@ -970,7 +982,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("2020-01-01_00-00-00.jpg"));
         assert!(html.contains("2020-06-15_12-00-00.jpg"));
@@ -984,10 +996,190 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("<optgroup label=\"2020\">"));
+        assert!(html.contains("<optgroup label=\"2021\">"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_groups_photos_by_month() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        std::fs::write(tmp.join("2020/IMG_0001.jpg"), "fake").unwrap();
+        std::fs::write(tmp.join("2020/IMG_0002.jpg"), "fake").unwrap();
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        let taken = chrono::NaiveDate::from_ymd_opt(2020, 6, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        meta.set_taken("2020/IMG_0002.jpg", Some(taken));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("<option value=\"2020-01\">janvier 2020</option>"));
+        assert!(html.contains("<option value=\"2021-03\">mars 2021</option>"));
+        // Months in order, undated photos last, and the lightbox list alike
+        let pos = |s: &str| html.find(s).unwrap();
+        assert!(pos("value=\"2020-01\"") < pos("value=\"2020-06\""));
+        assert!(pos("value=\"2020-06\"") < pos("value=\"2020-00\">sans date"));
+        assert!(pos("\"src\":\"2020/IMG_0002.jpg\"") < pos("\"src\":\"2020/IMG_0001.jpg\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_draws_the_grid_from_a_json_payload() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_caption("2020/2020-01-01_00-00-00.jpg", "fin </script>\u{1}");
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        // No section in the page: the script adds the years as it is
+        // scrolled, and fills the months near the viewport
+        assert!(html.contains("<main>\n<div id=\"more-sections\"></div>\n</main>"));
+        assert!(!html.contains("<div class=\"thumb\""));
+        let start = html.find("id=\"photo-data\">").unwrap() + "id=\"photo-data\">".len();
+        let end = start + html[start..].find("</script>").unwrap();
+        let photos: Vec<serde_json::Value> = serde_json::from_str(&html[start..end]).unwrap();
+        assert_eq!(photos.len(), 3);
+        assert_eq!(photos[0]["section"], "2020-01");
+        assert_eq!(photos[0]["caption"], "fin </script>\u{1}");
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_carries_photo_dates_for_the_timeline() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        let day = chrono::NaiveDate::from_ymd_opt(2021, 12, 24).unwrap();
+        meta.set_date_override("2021/2021-03-10_09-00-00.jpg", day.and_hms_opt(20, 0, 0));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("id=\"timeline\""));
+        assert!(html.contains("\"date\":\"2020-06-15\""));
+        assert!(html.contains("\"date\":\"2021-12-24\""));
+        assert!(html.contains("const MONTH_NAMES=[\"janvier\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_starts_with_the_default_theme() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("<html lang=\"fr\" data-theme=\"dark\">"));
+        assert!(html.contains("id=\"btn-theme\""));
+
+        let options = GalleryOptions {
+            theme: Theme::Auto,
+            ..Default::default()
+        };
+        let html = generate_html(&photos, &meta, &options).unwrap();
+        assert!(html.contains("data-theme=\"auto\""));
+        assert!(html.contains("html[data-theme=\"light\"] body{"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
 
-        assert!(html.contains("data-year=\"2020\""));
-        assert!(html.contains("data-year=\"2021\""));
+    #[test]
+    fn html_carries_the_custom_title_and_accent() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("<h1>photo-sort gallery</h1></div>"));
+        assert!(!html.contains("class=\"subtitle\""));
+        assert!(!html.contains(":root,html[data-theme"));
+
+        let options = GalleryOptions {
+            title: Some("Photos Famille <Martin>".to_string()),
+            subtitle: Some("Depuis 1987".to_string()),
+            accent_color: Some(parse_accent_color("#E07A5F").unwrap()),
+            ..Default::default()
+        };
+        let html = generate_html(&photos, &meta, &options).unwrap();
+        assert!(html.contains("<title>Photos Famille &lt;Martin&gt;</title>"));
+        let header =
+            "<h1>Photos Famille &lt;Martin&gt;</h1><div class=\"subtitle\">Depuis 1987</div>";
+        assert!(html.contains(header));
+        assert!(html.contains(":root,html[data-theme=\"light\"]{--accent:#e07a5f}"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn accent_colors_are_checked() {
+        assert_eq!(parse_accent_color(" Tomato ").unwrap(), "tomato");
+        assert_eq!(parse_accent_color("#abc").unwrap(), "#abc");
+        assert!(parse_accent_color("#abcde").is_err());
+        assert!(parse_accent_color("red;}body{display:none").is_err());
+        assert!(parse_accent_color("").is_err());
+    }
+
+    #[test]
+    fn html_can_come_from_a_custom_template() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/2020-01-01_00-00-00.jpg", "<plage>");
+        meta.set_favorite("2021/2021-03-10_09-00-00.jpg", true);
+        let template = tmp.join("custom.html");
+        std::fs::write(
+            &template,
+            "<h1>{{ title }}</h1>{% for tag in tags %}<i>{{ tag }}</i>{% endfor %}\n\
+             {{ stats.photos }} photos, {{ stats.favorites }} favori\n\
+             {% for y in stats.years %}{{ y.year }}:{{ y.count }} {% endfor %}\n\
+             <script>const P={{ photos_json }};</script>",
+        )
+        .unwrap();
+        let options = GalleryOptions {
+            title: Some("Famille".to_string()),
+            template: Some(template.clone()),
+            ..Default::default()
+        };
+
+        let html = generate_html(&photos, &meta, &options).unwrap();
+        assert!(html.starts_with("<h1>Famille</h1><i>&lt;plage&gt;</i>\n"));
+        assert!(html.contains("3 photos, 1 favori\n2020:2 2021:1 \n"));
+        assert!(html.contains("const P=[{\"src\":\"2020/2020-01-01_00-00-00.jpg\""));
+
+        std::fs::write(&template, "{% for tag in tags %}").unwrap();
+        let err = generate_html(&photos, &meta, &options).unwrap_err();
+        assert!(format!("{err:#}").contains("Modèle gallery.html invalide"));
+        let options = GalleryOptions {
+            template: Some(tmp.join("missing.html")),
+            ..Default::default()
+        };
+        assert!(generate_html(&photos, &meta, &options).is_err());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_speaks_the_chosen_language() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/2020-01-01_00-00-00.jpg", "plage");
+        let options = GalleryOptions {
+            lang: Lang::En,
+            ..Default::default()
+        };
+        let html = generate_html(&photos, &meta, &options).unwrap();
+
+        assert!(html.contains("<html lang=\"en\""));
+        assert!(html.contains("<button id=\"btn-slideshow\">Slideshow</button>"));
+        assert!(html.contains("<option value=\"2020-01\">January 2020</option>"));
+        assert!(html.contains("data-tag=\"\">All</button>"));
+        assert!(html.contains("\"tag_added\":\"Tag “{tag}” added\""));
+        assert!(!html.contains("Diaporama") && !html.contains("Sauvegarder"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -997,12 +1189,14 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("btn-slideshow"));
         assert!(html.contains("btn-random"));
         assert!(html.contains("ss-playpause"));
         assert!(html.contains("ss-random-toggle"));
+        assert!(html.contains("<button id=\"ss-fullscreen\" title=\"Plein écran\">"));
+        assert!(html.contains("lb.requestFullscreen()"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1015,7 +1209,7 @@ mod tests {
         meta.add_tag("2020/2020-01-01_00-00-00.jpg", "vacances");
         meta.add_tag("2020/2020-01-01_00-00-00.jpg", "plage");
 
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
         assert!(html.contains("\"vacances\""));
         assert!(html.contains("\"plage\""));
         // Tag filter buttons
@@ -1024,6 +1218,165 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn html_has_people_filter_separate_from_tags() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        assert!(
+            !generate_html(&photos, &meta, &GalleryOptions::default())
+                .unwrap()
+                .contains("id=\"filter-people-container\"")
+        );
+
+        meta.add_person("2020/2020-01-01_00-00-00.jpg", "maman");
+        meta.add_tag("2020/2020-01-01_00-00-00.jpg", "plage");
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"filter-people-container\""));
+        assert!(html.contains("data-person=\"maman\""));
+        assert!(!html.contains("data-tag=\"maman\""));
+        assert!(html.contains("\"people\":[\"maman\"]"));
+        assert!(html.contains("entry.people=p.people"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_album_filter_when_metadata_has_albums() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        assert!(
+            !generate_html(&photos, &meta, &GalleryOptions::default())
+                .unwrap()
+                .contains("id=\"album-filter\"")
+        );
+
+        meta.add_to_album("Noël </script>", "2020/2020-01-01_00-00-00.jpg");
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"album-filter\""));
+        assert!(html.contains("<option value=\"Noël &lt;/script&gt;\">"));
+        assert!(html.contains("const ALBUMS={\"Noël <\\/script>\":[\"2020/2020-01-01_00-00-00.jpg\"]};"));
+        assert!(html.contains("meta.albums=ALBUMS"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_label_chips_when_photos_have_labels() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        assert!(
+            !generate_html(&photos, &meta, &GalleryOptions::default())
+                .unwrap()
+                .contains("id=\"filter-labels-container\"")
+        );
+
+        meta.set_label("2021/2021-03-10_09-00-00.jpg", Some("red"));
+        meta.set_label("2020/2020-01-01_00-00-00.jpg", Some("blue"));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"filter-labels-container\""));
+        // Only the labels in use, in the usual order
+        let pos = |s: &str| html.find(s).unwrap();
+        assert!(pos("data-label=\"red\" title=\"Rouge\"") < pos("data-label=\"blue\" title=\"Bleu\""));
+        assert!(!html.contains("data-label=\"green\" title="));
+        assert!(html.contains("if(activeLabel&&p.label!==activeLabel)return false;"));
+        assert!(html.contains("<span class=\"thumb-label\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_query_search_box() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"search\""));
+        assert!(html.contains("function parseQuery(text)"));
+        assert!(html.contains("if(searchFn&&!searchFn(p))return false;"));
+        assert!(html.contains("search:searchText"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_groups_tag_spellings() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.files
+            .entry("2020/2020-01-01_00-00-00.jpg".into())
+            .or_default()
+            .tags = vec!["Noël".into()];
+        meta.add_tag("2020/2020-06-15_12-00-00.jpg", "noel");
+        meta.add_tag("2021/2021-03-10_09-00-00.jpg", "noel");
+        meta.set_tag_alias("xmas", "noel");
+
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("data-tag=\"noel\""));
+        assert!(!html.contains("data-tag=\"Noël\""));
+        assert!(html.contains("const TAG_ALIASES={\"xmas\":\"noel\"};"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_favorite_toggle() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_favorite("2020/2020-01-01_00-00-00.jpg", true);
+        meta.set_position("2020/2020-01-01_00-00-00.jpg", Some((48.5, -2.25)));
+
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("\"lat\":48.5,\"lon\":-2.25"));
+        assert!(html.contains("\"favorite\":true"));
+        assert!(html.contains("\"favorite\":false"));
+        assert!(html.contains("id=\"lb-fav\""));
+        assert!(html.contains("entry.favorite=true"));
+        assert!(html.contains("id=\"btn-favorites\">&#9829; Favoris</button>"));
+        assert!(html.contains("if(favoritesOnly&&!p.favorite)return false;"));
+        assert!(html.contains("<span class=\"thumb-fav\">"));
+        assert!(html.contains("\"lat\":null,\"lon\":null"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_filters_hidden_photos() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_hidden("2020/2020-01-01_00-00-00.jpg", true);
+
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("\"hidden\":true"));
+        assert!(html.contains("\"hidden\":false"));
+        assert!(html.contains("id=\"lb-hide\""));
+        assert!(html.contains("id=\"btn-show-hidden\""));
+        assert!(html.contains("if(p.hidden&&!showHidden)return false;"));
+        assert!(html.contains("entry.hidden=true"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_editable_captions() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_caption("2020/2020-01-01_00-00-00.jpg", "Le \"grand\" jour");
+
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("\"caption\":\"Le \\\"grand\\\" jour\""));
+        assert!(html.contains("id=\"lb-caption\""));
+        assert!(html.contains("entry.caption=p.caption"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn html_contains_rating_in_data() {
         let tmp = tmpdir();
@@ -1032,7 +1385,7 @@ mod tests {
         let mut meta = Metadata::default();
         meta.set_rating("2020/2020-01-01_00-00-00.jpg", Some(4));
 
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
         assert!(html.contains("\"rating\":4"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
@@ -1043,7 +1396,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("ArrowLeft"));
         assert!(html.contains("ArrowRight"));
@@ -1051,13 +1404,34 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn html_is_usable_with_a_keyboard_and_a_screen_reader() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("id=\"lightbox\" role=\"dialog\" aria-modal=\"true\""));
+        assert!(html.contains("<button class=\"lb-close\" id=\"lb-close\" aria-label=\"Fermer\">"));
+        assert!(html.contains("<button class=\"lb-nav lb-prev\" id=\"lb-prev\""));
+        // Thumbnails are focusable, described by their caption or tags
+        assert!(html.contains("role=\"button\" tabindex=\"0\" data-src="));
+        assert!(html.contains("alt=\"'+escH(photoAlt(p))+'\""));
+        assert!(html.contains("return p.caption||(words.length?words.join(', '):p.name);"));
+        // Focus moves into the lightbox and comes back
+        assert!(html.contains("document.getElementById('lb-close').focus("));
+        assert!(html.contains("lbReturnFocus.focus("));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn html_has_rating_filter() {
         let tmp = tmpdir();
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("rating-filter"));
         assert!(html.contains("data-rating=\"5\""));
@@ -1071,7 +1445,7 @@ mod tests {
         let tmp = tmpdir();
         setup_photos(&tmp);
 
-        run_gallery(&tmp).unwrap();
+        run_gallery(&tmp, &GalleryOptions::default()).unwrap();
         assert!(tmp.join("gallery.html").exists());
 
         let content = std::fs::read_to_string(tmp.join("gallery.html")).unwrap();
@@ -1079,10 +1453,241 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn run_gallery_points_the_grid_at_thumbnails() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photo = tmp.join("2021").join("2021-03-10_09-00-00.jpg");
+        let img = image::RgbImage::from_pixel(600, 400, image::Rgb([40, 90, 160]));
+        img.save(photo).unwrap();
+
+        run_gallery(&tmp, &GalleryOptions::default()).unwrap();
+        let thumb = ".photo_sort_thumbs/2021/2021-03-10_09-00-00.jpg";
+        assert!(tmp.join(thumb).is_file());
+        let content = std::fs::read_to_string(tmp.join("gallery.html")).unwrap();
+        assert!(content.contains(&format!("\"thumb\":\"{thumb}\"")));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_writes_into_an_output_folder() {
+        let tmp = tmpdir();
+        let photos_dir = tmp.join("photos");
+        std::fs::create_dir_all(&photos_dir).unwrap();
+        setup_photos(&photos_dir);
+        let photo = photos_dir.join("2021").join("2021-03-10_09-00-00.jpg");
+        let img = image::RgbImage::from_pixel(600, 400, image::Rgb([40, 90, 160]));
+        img.save(photo).unwrap();
+        let out = tmp.join("site").join("galerie");
+        let options = GalleryOptions {
+            output: Some(out.clone()),
+            ..Default::default()
+        };
+        run_gallery(&photos_dir, &options).unwrap();
+
+        assert!(!photos_dir.join("gallery.html").exists());
+        let html = std::fs::read_to_string(out.join("gallery.html")).unwrap();
+        // Thumbnails come along, the originals are reached from the page
+        assert!(out.join(".photo_sort_thumbs/2021/2021-03-10_09-00-00.jpg").is_file());
+        assert!(html.contains("const PHOTO_BASE=\"../../photos/\";"));
+        assert!(html.contains("const isServed=false&&"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn relative_urls_lead_to_the_photo_folder() {
+        let url = |from: &str, to: &str| relative_url(Path::new(from), Path::new(to));
+        assert_eq!(url("/srv/www", "/srv/www"), "");
+        assert_eq!(url("/srv/www", "/srv/www/photos"), "photos/");
+        assert_eq!(url("/srv/www/galerie", "/home/me/Photos"), "../../../home/me/Photos/");
+    }
+
+    #[test]
+    fn run_gallery_standalone_carries_its_images() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photo = tmp.join("2021").join("2021-03-10_09-00-00.jpg");
+        let img = image::RgbImage::from_pixel(2400, 1200, image::Rgb([40, 90, 160]));
+        img.save(photo).unwrap();
+
+        let options = GalleryOptions {
+            standalone: true,
+            previews: true,
+            ..Default::default()
+        };
+        run_gallery(&tmp, &options).unwrap();
+        assert!(!tmp.join("gallery.html").exists());
+        let content = std::fs::read_to_string(tmp.join("gallery_standalone.html")).unwrap();
+        assert!(content.contains("const STANDALONE=true;"));
+        assert!(content.contains("\"thumb\":\"data:image/jpeg;base64,"));
+        assert!(content.contains("\"preview\":\"data:image/jpeg;base64,"));
+        // The fake photos cannot be decoded: no image, but still listed
+        assert!(content.contains("\"src\":\"2020/2020-01-01_00-00-00.jpg\""));
+        assert_eq!(content.matches("\"preview\":").count(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_embeds_placeholders() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photo = tmp.join("2020").join("2020-01-01_00-00-00.jpg");
+        let img = image::RgbImage::from_pixel(640, 480, image::Rgb([200, 120, 40]));
+        img.save(photo).unwrap();
+
+        let options = GalleryOptions {
+            placeholders: true,
+            ..Default::default()
+        };
+        run_gallery(&tmp, &options).unwrap();
+        let html = std::fs::read_to_string(tmp.join("gallery.html")).unwrap();
+        let placeholders = html.matches("\"placeholder\":\"data:image/png;base64,");
+        assert_eq!(placeholders.count(), 1);
+        // The thumbnails still come from the cache
+        assert!(html.contains("\"thumb\":\".photo_sort_thumbs/2020/2020-01-01_00-00-00.jpg\""));
+        assert!(html.contains("'<img class=\"ph\" src=\"'+p.placeholder+'\" alt=\"\">'"));
+        let cached = thumb::thumb_cache_path(&tmp, "2020/2020-01-01_00-00-00.jpg");
+        let png = image::open(cached.with_extension("placeholder.png")).unwrap();
+        assert_eq!((png.width(), png.height()), (16, 12));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_flags_duplicates() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let gradient = image::RgbImage::from_fn(400, 300, |x, y| {
+            let v = (x / 2 + y / 3) as u8;
+            image::Rgb([v, v / 2, 255 - v])
+        });
+        let first = tmp.join("2020/2020-01-01_00-00-00.jpg");
+        gradient.save(first).unwrap();
+        let smaller = image::imageops::resize(&gradient, 200, 150, image::imageops::Triangle);
+        let copy = tmp.join("2021/2021-03-10_09-00-00.jpg");
+        smaller.save(copy).unwrap();
+        let mut meta = Metadata::default();
+        meta.set_hash("2020/2020-06-15_12-00-00.jpg", "same");
+        meta.set_hash("2021/2021-03-10_09-00-00.jpg", "same");
+        meta.save(&tmp).unwrap();
+
+        run_gallery(&tmp, &GalleryOptions::default()).unwrap();
+        let meta = Metadata::load(&tmp).unwrap();
+        let hash = meta.get_visual_hash("2020/2020-01-01_00-00-00.jpg");
+        assert!(hash.is_some());
+        let html = std::fs::read_to_string(tmp.join("gallery.html")).unwrap();
+        let entry = |name: &str| {
+            let start = html.find(&format!("\"name\":\"{name}\"")).unwrap();
+            html[start..].split('}').next().unwrap().to_string()
+        };
+        assert!(entry("2020-01-01_00-00-00.jpg").contains("\"duplicate\":\"similar\""));
+        assert!(entry("2020-06-15_12-00-00.jpg").contains("\"duplicate\":\"identical\""));
+        // Identical wins over similar
+        assert!(entry("2021-03-10_09-00-00.jpg").contains("\"duplicate\":\"identical\""));
+        assert!(html.contains("id=\"btn-duplicates\""));
+        assert!(html.contains("if(duplicatesOnly&&!p.duplicate)return false;"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_writes_a_data_file() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/2020-01-01_00-00-00.jpg", "plage");
+        meta.add_to_album("Été", "2020/2020-06-15_12-00-00.jpg");
+        meta.save(&tmp).unwrap();
+        let options = GalleryOptions {
+            data_file: true,
+            ..Default::default()
+        };
+        run_gallery(&tmp, &options).unwrap();
+
+        let html = std::fs::read_to_string(tmp.join("gallery.html")).unwrap();
+        assert!(!html.contains("id=\"photo-data\""));
+        assert!(html.contains("fetch('gallery_data.json')"));
+        assert!(html.contains("const ALL_PHOTOS=DATA.photos;"));
+        let data = std::fs::read_to_string(tmp.join(DATA_FILE)).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(data["photos"].as_array().unwrap().len(), 3);
+        assert_eq!(data["photos"][0]["tags"][0], "plage");
+        assert_eq!(data["tags"], serde_json::json!(["plage"]));
+        assert_eq!(data["years"][1]["year"], "2021");
+        assert_eq!(data["years"][1]["count"], 1);
+        assert_eq!(data["albums"]["Été"][0], "2020/2020-06-15_12-00-00.jpg");
+
+        // Without the option, nothing is fetched
+        let photos = collect_photos(&tmp);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"photo-data\"") && !html.contains("const ALL_PHOTOS=DATA"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_writes_a_feed() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let entry = |dest: &str| crate::sort::ProcessedEntry {
+            source: format!("/src/{dest}"),
+            dest: dest.to_string(),
+            size: 1,
+            hash: dest.to_string(),
+            date_source: "exif".to_string(),
+        };
+        let progress = crate::sort::Progress {
+            processed: vec![entry("2020/2020-01-01_00-00-00.jpg")],
+        };
+        crate::sort::save_progress(&tmp.join(PROGRESS_FILE), &progress).unwrap();
+        run_gallery(&tmp, &GalleryOptions::default()).unwrap();
+        assert!(!tmp.join(FEED_FILE).exists());
+
+        let options = GalleryOptions {
+            feed: Some("http://maison.local:8080".to_string()),
+            ..Default::default()
+        };
+        run_gallery(&tmp, &options).unwrap();
+        let xml = std::fs::read_to_string(tmp.join(FEED_FILE)).unwrap();
+        assert!(xml.contains("<title>photo-sort gallery</title>"));
+        assert!(xml.contains("<id>http://maison.local:8080/2020/2020-01-01_00-00-00.jpg</id>"));
+        assert_eq!(xml.matches("<entry>").count(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn run_gallery_splits_by_year() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/2020-06-15_12-00-00.jpg", Some(4));
+        meta.add_tag("2021/2021-03-10_09-00-00.jpg", "ski");
+        meta.save(&tmp).unwrap();
+
+        let options = GalleryOptions {
+            split_by_year: true,
+            ..Default::default()
+        };
+        run_gallery(&tmp, &options).unwrap();
+        assert!(!tmp.join("gallery.html").exists());
+        let index = std::fs::read_to_string(tmp.join("index.html")).unwrap();
+        assert!(index.contains("<a class=\"year-card\" href=\"gallery_2020.html\">"));
+        // The best rated photo is the cover
+        assert!(index.contains(".photo_sort_thumbs/2020/2020-06-15_12-00-00.jpg"));
+        assert!(index.contains("<span class=\"count\">2 photos</span>"));
+        assert!(index.contains("<span class=\"count\">1 photo</span>"));
+
+        let page = std::fs::read_to_string(tmp.join("gallery_2020.html")).unwrap();
+        assert!(page.contains("href=\"index.html\">&larr; Toutes les années</a>"));
+        assert!(!page.contains("\"src\":\"2021/"));
+        // Saving from the 2020 page keeps the 2021 metadata
+        let others = "const OTHER_FILES={\"2021/2021-03-10_09-00-00.jpg\":{\"tags\":[\"ski\"]";
+        assert!(page.contains(others));
+        assert!(tmp.join("gallery_2021.html").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn run_gallery_empty_dir_errors() {
         let tmp = tmpdir();
-        assert!(run_gallery(&tmp).is_err());
+        assert!(run_gallery(&tmp, &GalleryOptions::default()).is_err());
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1094,7 +1699,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("lb-tag-input"));
         assert!(html.contains("lb-tag-add"));
@@ -1107,7 +1712,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         // The JS function to render tags with remove buttons
         assert!(html.contains("removeTag"));
@@ -1122,7 +1727,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("lb-stars"));
         assert!(html.contains("setRating"));
@@ -1137,7 +1742,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("btn-save"));
         assert!(html.contains("saveMetadata"));
@@ -1152,7 +1757,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("lb-download"));
         let _ = std::fs::remove_dir_all(&tmp);
@@ -1165,12 +1770,182 @@ mod tests {
         let photos = collect_photos(&tmp);
         let mut meta = Metadata::default();
         meta.add_tag("2020/2020-01-01_00-00-00.jpg", "vacances");
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("id=\"filter-tags-container\""));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn html_has_sort_order_select() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let options = GalleryOptions {
+            lang: Lang::En,
+            ..Default::default()
+        };
+        let html = generate_html(&photos, &Metadata::default(), &options).unwrap();
+
+        assert!(html.contains("<select id=\"sort-order\">"));
+        assert!(html.contains("<option value=\"date_desc\">Newest first</option>"));
+        assert!(html.contains("<option value=\"random\">Random</option>"));
+        // Applied to the filtered list, which the grid and the slideshow share
+        assert!(html.contains("  sortPhotos(filtered);"));
+        assert!(html.contains("sort:sortOrder"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_keeps_the_view_in_the_url_hash() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("function hashState(withPosition=true)"));
+        assert!(html.contains("h.set('photo',filtered[currentIdx].src)"));
+        assert!(html.contains("history.pushState({lightbox:true},'','#'+hashState());"));
+        assert!(html.contains("window.addEventListener('popstate',()=>applyHash(false));"));
+        assert!(html.contains("if(linked)applyHash(true);else updateHash();"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_starts_a_slideshow_from_kiosk_parameters() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        // ?slideshow=1&random=1&delay=8&tag=famille, filters read like the hash
+        assert!(html.contains("const kiosk=KIOSK.get('slideshow')==='1';"));
+        assert!(html.contains("applyHash(false,KIOSK);"));
+        assert!(html.contains("startSlideshow(KIOSK.get('random')==='1');"));
+        assert!(html.contains("if(kiosk)startKiosk();"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_shows_a_map_for_located_photos() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        let info = meta.files.entry("2020/2020-01-01_00-00-00.jpg".to_string()).or_default();
+        info.lat = Some(48.8584);
+        info.lon = Some(2.2945);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("\"lat\":48.8584,\"lon\":2.2945"));
+        assert!(html.contains("id=\"lb-map-frame\""));
+        assert!(html.contains("https://www.openstreetmap.org/export/embed.html?bbox="));
+        // The nearby link filters the grid, undone from the filter bar
+        assert!(html.contains(">&#x1F4CD; Photos à proximité</button>"));
+        assert!(html.contains("if(nearPoint&&!(p.lat!==null&&distanceKm(p,nearPoint)<=NEAR_KM))return false;"));
+        assert!(html.contains("id=\"btn-near\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_a_thumbnail_size_slider() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(html.contains("<input type=\"range\" id=\"grid-size\" min=\"0\" max=\"2\""));
+        assert!(html.contains("minmax(var(--thumb-size,200px),1fr)"));
+        assert!(html.contains("const GRID_SIZES=[120,200,340];"));
+        assert!(html.contains("gridSize:gridSize"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_follows_live_updates_when_served() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(html.contains("const events=new EventSource('/api/events');"));
+        assert!(html.contains("fetch('/api/gallery')"));
+        assert!(html.contains("function applyServerData(data){"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn data_carries_the_photo_list() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let data = generate_data(&photos, &Metadata::default(), &GalleryOptions::default());
+        let data: serde_json::Value = serde_json::from_str(&data.unwrap()).unwrap();
+        assert_eq!(data["photos"].as_array().unwrap().len(), 3);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_rates_photos_from_the_grid() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(html.contains("'<div class=\"thumb-rate\" role=\"group\""));
+        assert!(html.contains("function quickRate(src,rating){"));
+        assert!(html.contains("if(pending){saveMetadata();return;}"));
+        assert!(html.contains("{method:'PATCH',"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_takes_dropped_photos_when_served() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(!html.contains("id=\"drop-zone\""));
+        let options = GalleryOptions {
+            served: true,
+            ..GalleryOptions::default()
+        };
+        let html = generate_html(&photos, &Metadata::default(), &options).unwrap();
+        assert!(html.contains("<div class=\"drop-zone\" id=\"drop-zone\">"));
+        assert!(html.contains("fetch('/api/upload',{method:'POST',body:form})"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_a_histogram_in_the_lightbox() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"lb-histogram-btn\""));
+        assert!(html.contains("<canvas class=\"lb-histogram\" id=\"lb-histogram\" width=\"256\""));
+        assert!(html.contains("lbImg.addEventListener('load',drawHistogram);"));
+        assert!(html.contains("histogram:histogramOpen"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_quick_tag_shortcuts() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("id=\"lb-keys\" title=\"Raccourcis clavier\""));
+        assert!(html.contains("id=\"keys-grid\""));
+        assert!(html.contains("toggleQuickTag(filtered[currentIdx],e.key.toLowerCase())"));
+        assert!(html.contains("quickTags:quickTags"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn html_has_thumb_stars_in_grid() {
         let tmp = tmpdir();
@@ -1178,10 +1953,10 @@ mod tests {
         let photos = collect_photos(&tmp);
         let mut meta = Metadata::default();
         meta.set_rating("2020/2020-01-01_00-00-00.jpg", Some(3));
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("thumb-stars"));
-        assert!(html.contains("\u{2605}\u{2605}\u{2605}"));
+        assert!(html.contains("\"rating\":3"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1191,13 +1966,27 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("btn-export"));
         assert!(html.contains("exportFiltered"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn html_has_copy_list_button() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains(">Copier la liste</button>"));
+        assert!(html.contains("const list=filtered.map(p=>p.src).join('\\n')+'\\n';"));
+        assert!(html.contains("navigator.clipboard.writeText(list)"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     // --- Slideshow controls ---
 
     #[test]
@@ -1206,7 +1995,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("ss-prev"));
         assert!(html.contains("ss-next"));
@@ -1224,7 +2013,7 @@ mod tests {
         meta.add_tag("2020/2020-01-01_00-00-00.jpg", "vacances");
         meta.add_tag("2020/2020-06-15_12-00-00.jpg", "plage");
 
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         // Tag suggestions container and function
         assert!(html.contains("tag-suggestions"));
@@ -1240,11 +2029,12 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
-        assert!(html.contains("lb-delete"));
-        assert!(html.contains("deletePhoto"));
-        assert!(html.contains("confirm"));
+        // Only shown once served, where DELETE /api/photo exists
+        assert!(html.contains("id=\"lb-delete\" style=\"display:none\""));
+        assert!(html.contains("getElementById('lb-delete').style.display=''"));
+        assert!(html.contains("confirm(tr('confirm_delete'"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1254,10 +2044,12 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
-        assert!(html.contains("lb-move"));
-        assert!(html.contains("movePhoto"));
+        // Only shown once served; a month picked re-dates the photo
+        assert!(html.contains("id=\"lb-move\" style=\"display:none\""));
+        assert!(html.contains("<input type=\"month\" id=\"lb-move-month\" required>"));
+        assert!(html.contains("body:JSON.stringify({src:p.src,date:date})"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1267,11 +2059,14 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
-
-        assert!(html.contains("lb-rotate-left"));
-        assert!(html.contains("lb-rotate-right"));
-        assert!(html.contains("rotatePhoto"));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        // Only shown once served, where /api/rotate exists
+        assert!(html.contains("id=\"lb-rotate-left\" style=\"display:none\""));
+        assert!(html.contains("id=\"lb-rotate-right\" style=\"display:none\""));
+        assert!(html.contains("getElementById('lb-rotate-left').style.display=''"));
+        // The new pixels are fetched past the browser's cache
+        assert!(html.contains("p.version=Date.now()"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
@@ -1281,13 +2076,209 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         // In serve mode, save uses fetch to /api/metadata
         assert!(html.contains("/api/metadata"));
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn html_has_guest_inbox_panel() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("btn-inbox"));
+        assert!(html.contains("inbox-modal"));
+        assert!(html.contains("/api/inbox/"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    // --- Burst stacking ---
+
+    #[test]
+    fn html_persists_prefs() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let html =
+            generate_html(&photos, &Metadata::default(), &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("function savePrefs()"));
+        assert!(html.contains("fetch('/api/prefs')"));
+        assert!(html.contains("localStorage"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_embeds_metadata_revision_and_merge_ui() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains(&format!("let metaRev=\"{}\";", meta.revision())));
+        assert!(html.contains("fetch('/api/metadata/merge'"));
+        assert!(html.contains("id=\"merge-modal\""));
+        assert!(html.contains("id=\"btn-import\""));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_burst_stacking_toggle() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        let dt = chrono::NaiveDateTime::parse_from_str("2020-01-01 00:00:00", "%Y-%m-%d %H:%M:%S")
+            .unwrap();
+        meta.set_taken("2020/2020-01-01_00-00-00.jpg", Some(dt));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+
+        assert!(html.contains("btn-bursts"));
+        assert!(html.contains("groupBursts"));
+        assert!(html.contains("\"taken\":\"2020-01-01T00:00:00.000\""));
+        assert!(html.contains("\"taken\":null"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn cache_capture_times_marks_files_without_exif() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        assert_eq!(cache_capture_times(&tmp, &photos, &mut meta), 0);
+        assert_eq!(meta.files.len(), photos.values().flatten().count());
+        assert!(
+            meta.files
+                .values()
+                .all(|i| i.no_exif_date && i.taken.is_none())
+        );
+
+        // Marked files are not read again
+        let before = meta.revision();
+        assert_eq!(cache_capture_times(&tmp, &photos, &mut meta), 0);
+        assert_eq!(meta.revision(), before);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn cache_date_sources_reads_the_sort_progress() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let entry = |dest: &str, hash: &str, date_source: &str| crate::sort::ProcessedEntry {
+            source: format!("/source/{dest}"),
+            dest: dest.to_string(),
+            size: 4,
+            hash: hash.to_string(),
+            date_source: date_source.to_string(),
+        };
+        let progress = crate::sort::Progress {
+            processed: vec![
+                entry("2020/2020-01-01_00-00-00.jpg", "h1", "filesystem"),
+                // Renamed since: found by its hash
+                entry("2019/2019-06-15_12-00-00.jpg", "h2", "dirname"),
+                entry("2021/2021-03-10_09-00-00.jpg", "h3", "quarantine"),
+            ],
+        };
+        crate::sort::save_progress(&tmp.join(PROGRESS_FILE), &progress).unwrap();
+        let mut meta = Metadata::default();
+        meta.set_hash("2020/2020-06-15_12-00-00.jpg", "h2");
+
+        assert_eq!(cache_date_sources(&tmp, &photos, &mut meta), 2);
+        assert_eq!(
+            meta.get_date_source("2020/2020-01-01_00-00-00.jpg"),
+            Some("filesystem")
+        );
+        assert_eq!(
+            meta.get_date_source("2020/2020-06-15_12-00-00.jpg"),
+            Some("dirname")
+        );
+        assert_eq!(meta.get_date_source("2021/2021-03-10_09-00-00.jpg"), None);
+        assert_eq!(cache_date_sources(&tmp, &photos, &mut meta), 0);
+
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("\"date_source\":\"filesystem\""));
+        assert!(html.contains("id=\"btn-doubtful\""));
+        assert!(html.contains("if(p.date_source)entry.date_source=p.date_source;"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_carries_cached_exif() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let mut meta = Metadata::default();
+        let file = &photos["2020"][0];
+        let summary = crate::metadata::ExifSummary {
+            model: Some("EOS </script>".to_string()),
+            iso: Some(200),
+            ..Default::default()
+        };
+        meta.set_exif(file, Some(summary));
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains(r#""exif":{"model":"EOS <\/script>","iso":200}"#));
+        assert!(html.contains("\"exif\":null"));
+        assert!(html.contains("if(p.exif)entry.exif=p.exif;"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_a_stats_panel() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let options = GalleryOptions {
+            lang: Lang::En,
+            ..Default::default()
+        };
+        let html = generate_html(&photos, &meta, &options).unwrap();
+        assert!(html.contains("<button id=\"btn-stats\" title=\"Photos per year, tags, ratings and cameras\">Stats</button>"));
+        assert!(html.contains("<div class=\"stats-panel\" id=\"stats-panel\"></div>"));
+        // Drawn by the script from the photo list, folding kept in prefs
+        assert!(html.contains("function renderStats()"));
+        assert!(html.contains("stats:statsOpen"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_a_shuffle_button() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("id=\"btn-shuffle\""));
+        assert!(html.contains(">Mélanger</button>"));
+        // The grid is then cut in chunks instead of month sections
+        assert!(html.contains("function appendChunk()"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_has_an_on_this_day_section() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let photos = collect_photos(&tmp);
+        let meta = Metadata::default();
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
+        assert!(html.contains("<section class=\"on-this-day\" id=\"on-this-day\"></section>"));
+        assert!(html.contains("id=\"btn-on-this-day\""));
+        assert!(html.contains(">Ce jour-là</button>"));
+        // Filled from the full dates of the photo list
+        assert!(html.contains("\"date\":\"2020-06-15\""));
+        assert!(html.contains("function renderOnThisDay()"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     // --- Lazy loading ---
 
     #[test]
@@ -1296,11 +2287,10 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         // Grid images should use data-src, not src
-        assert!(html.contains("data-src=\"2020/2020-01-01_00-00-00.jpg\""));
-        assert!(html.contains("class=\"lazy\""));
+        assert!(html.contains("<img data-src=\"'+escH(p.src)+'\" class=\"lazy\""));
         // Should NOT have src= on grid images (only data-src)
         assert!(!html.contains("<img src=\"2020/"));
         let _ = std::fs::remove_dir_all(&tmp);
@@ -1312,7 +2302,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("IntersectionObserver"));
         assert!(html.contains("lazyObserver"));
@@ -1326,7 +2316,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("getSrc"));
         assert!(html.contains("/thumb/"));
@@ -1339,7 +2329,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         assert!(html.contains("img.lazy"));
         assert!(html.contains("img.loaded"));
@@ -1353,7 +2343,7 @@ mod tests {
         setup_photos(&tmp);
         let photos = collect_photos(&tmp);
         let meta = Metadata::default();
-        let html = generate_html(&photos, &meta);
+        let html = generate_html(&photos, &meta, &GalleryOptions::default()).unwrap();
 
         // applyFilters and click handler should use getAttribute('data-src')
         assert!(html.contains("getAttribute('data-src')"));
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use image::{DynamicImage, RgbImage};
+use libheif_rs::{
+    Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, Image, ItemId, LibHeif,
+    RgbChroma,
+};
+use std::path::Path;
+
+/// Extensions decoded through libheif.
+const HEIF_EXTENSIONS: &[&str] = &["heic", "heif"];
+
+/// Quality used when re-encoding a HEIC file (after a rotation).
+const HEIF_QUALITY: u8 = 90;
+
+/// Return `true` for `.heic` / `.heif` files.
+pub fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| HEIF_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn open_context(path: &Path) -> Result<HeifContext<'static>> {
+    let name = path.to_str().context("Chemin non UTF-8")?;
+    HeifContext::read_from_file(name)
+        .with_context(|| format!("Impossible de lire le fichier HEIF : {}", path.display()))
+}
+
+/// Raw Exif block of the primary image, as stored in the file
+/// (4-byte big-endian offset to the TIFF header, then the payload).
+fn raw_exif_block(ctx: &HeifContext) -> Result<Option<Vec<u8>>> {
+    let handle = ctx.primary_image_handle()?;
+    let mut ids: [ItemId; 1] = [0];
+    if handle.metadata_block_ids(&mut ids, b"Exif") == 0 {
+        return Ok(None);
+    }
+    Ok(Some(handle.metadata(ids[0])?))
+}
+
+/// Strip the HEIF offset prefix of an Exif block and return the TIFF data.
+fn tiff_payload(block: &[u8]) -> Option<&[u8]> {
+    let offset = u32::from_be_bytes(block.get(..4)?.try_into().ok()?) as usize;
+    block.get(4 + offset..)
+}
+
+/// Read the Exif data of a HEIC file through libheif.
+pub fn read_exif(path: &Path) -> Result<exif::Exif> {
+    let ctx = open_context(path)?;
+    let block = raw_exif_block(&ctx)?.context("Pas de bloc Exif")?;
+    let tiff = tiff_payload(&block).context("Bloc Exif invalide")?;
+    Ok(exif::Reader::new().read_raw(tiff.to_vec())?)
+}
+
+/// Decode the primary image of a HEIC file to RGB.
+/// libheif already applies the container's rotation/mirroring.
+pub fn open(path: &Path) -> Result<DynamicImage> {
+    let ctx = open_context(path)?;
+    let handle = ctx.primary_image_handle()?;
+    let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
+    let planes = decoded.planes();
+    let plane = planes.interleaved.context("Plan RGB absent")?;
+
+    let (width, height) = (plane.width, plane.height);
+    let row_len = width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    let img = RgbImage::from_raw(width, height, pixels).context("Image HEIF invalide")?;
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+/// Encode `img` as HEIC into `path`, keeping the Exif block of the file it replaces.
+pub fn save(img: &DynamicImage, path: &Path) -> Result<()> {
+    let exif = open_context(path)
+        .ok()
+        .and_then(|ctx| raw_exif_block(&ctx).ok().flatten());
+
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let mut image = Image::new(width, height, ColorSpace::Rgb(RgbChroma::Rgb))?;
+    image.create_plane(Channel::Interleaved, width, height, 8)?;
+    {
+        let mut planes = image.planes_mut();
+        let plane = planes.interleaved.as_mut().context("Plan RGB absent")?;
+        let row_len = width as usize * 3;
+        for (dst, src) in plane
+            .data
+            .chunks_mut(plane.stride)
+            .zip(rgb.as_raw().chunks(row_len))
+        {
+            dst[..row_len].copy_from_slice(src);
+        }
+    }
+
+    let lib = LibHeif::new();
+    let mut encoder = lib
+        .encoder_for_format(CompressionFormat::Hevc)
+        .context("Aucun encodeur HEVC disponible dans libheif")?;
+    encoder.set_quality(EncoderQuality::Lossy(HEIF_QUALITY))?;
+    let mut ctx = HeifContext::new()?;
+    let handle = ctx.encode_image(&image, &mut encoder, None)?;
+    if let Some(block) = exif.as_deref().and_then(tiff_payload) {
+        ctx.add_exif_metadata(&handle, block)?;
+    }
+    let name = path.to_str().context("Chemin non UTF-8")?;
+    ctx.write_to_file(name)
+        .with_context(|| format!("Impossible d'écrire {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_heif_matches_extensions() {
+        assert!(is_heif(Path::new("IMG_0001.HEIC")));
+        assert!(is_heif(Path::new("a.heif")));
+        assert!(!is_heif(Path::new("a.jpg")));
+    }
+
+    #[test]
+    fn tiff_payload_skips_offset() {
+        let block = [0, 0, 0, 6, b'E', b'x', b'i', b'f', 0, 0, b'I', b'I', 42, 0];
+        assert_eq!(tiff_payload(&block), Some(&[b'I', b'I', 42, 0][..]));
+        assert_eq!(tiff_payload(&[0, 0]), None);
+        assert_eq!(tiff_payload(&[0, 0, 0, 9, 1]), None);
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::BTreeMap;
+
+/// Language of the gallery's interface.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Lang {
+    #[default]
+    Fr,
+    En,
+}
+
+impl Lang {
+    /// Code for the `lang` attribute of the page.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Fr => "fr",
+            Lang::En => "en",
+        }
+    }
+
+    pub fn month_names(self) -> [&'static str; 12] {
+        match self {
+            Lang::Fr => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Lang::En => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+        }
+    }
+
+    fn table(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::Fr => FR,
+            Lang::En => EN,
+        }
+    }
+
+    /// The string for `key`; the key itself if the table lacks it.
+    pub fn get(self, key: &str) -> &str {
+        (self.table().iter())
+            .find(|(k, _)| *k == key)
+            .map_or(key, |(_, text)| text)
+    }
+
+    /// Every string, for the script of the gallery.
+    pub fn strings(self) -> BTreeMap<&'static str, &'static str> {
+        self.table().iter().copied().collect()
+    }
+}
+
+/// Interface strings by key, `{name}` standing for a value filled in by the
+/// script.
+const FR: &[(&str, &str)] = &[
+    ("slideshow", "Diaporama"),
+    ("random", "Aléatoire"),
+    ("export_filtered", "Exporter filtré"),
+    ("copy_list", "Copier la liste"),
+    (
+        "copy_list_title",
+        "Copier les chemins des photos filtrées, un par ligne, dans le presse-papiers",
+    ),
+    ("import", "Importer"),
+    (
+        "import_title",
+        "Fusionner un fichier .photo_sort_metadata.json",
+    ),
+    ("undo", "Annuler"),
+    ("undo_title", "Annuler la dernière sauvegarde des metadata"),
+    ("theme_title", "Thème clair / sombre"),
+    ("save", "Sauvegarder"),
+    ("all", "Tous"),
+    ("tags", "Tags"),
+    ("people", "Personnes"),
+    ("album", "Album"),
+    ("labels", "Étiquettes"),
+    ("label_red", "Rouge"),
+    ("label_orange", "Orange"),
+    ("label_yellow", "Jaune"),
+    ("label_green", "Vert"),
+    ("label_blue", "Bleu"),
+    ("label_purple", "Violet"),
+    ("label_gray", "Gris"),
+    ("label_black", "Noir"),
+    ("label_white", "Blanc"),
+    ("min_rating", "Note min"),
+    ("favorites", "Favoris"),
+    ("duplicates", "Doublons"),
+    ("dup_identical", "Copie identique d'une autre photo"),
+    ("dup_similar", "Presque identique à une autre photo"),
+    ("search", "Recherche"),
+    ("jump_to", "Aller à"),
+    ("all_years", "Toutes les années"),
+    ("month", "Mois…"),
+    ("undated", "sans date"),
+    ("sort", "Tri"),
+    ("grid_size", "Taille"),
+    ("grid_size_title", "Taille des vignettes : petites, moyennes ou grandes"),
+    ("size_small", "Petites"),
+    ("size_medium", "Moyennes"),
+    ("size_large", "Grandes"),
+    ("sort_date", "Plus anciennes"),
+    ("sort_date_desc", "Plus récentes"),
+    ("sort_rating", "Mieux notées"),
+    ("sort_name", "Nom"),
+    ("sort_random", "Aléatoire"),
+    ("shuffle", "Mélanger"),
+    (
+        "shuffle_title",
+        "Toute la sélection dans un ordre aléatoire, sans les sections ; un nouveau clic revient au classement par date",
+    ),
+    ("hidden", "Masquées"),
+    ("show_hidden_title", "Afficher les photos masquées"),
+    ("show", "Afficher"),
+    ("bursts", "Rafales"),
+    ("stack", "Empiler"),
+    (
+        "burst_gap_title",
+        "Écart maximal entre deux photos d'une rafale",
+    ),
+    ("collapse_burst", "Replier la rafale"),
+    ("photo_one", "{n} photo"),
+    ("photo_many", "{n} photos"),
+    ("download", "Télécharger"),
+    ("rotate_left", "Rotation gauche"),
+    ("rotate_right", "Rotation droite"),
+    ("metadata", "Métadonnées"),
+    ("info", "Info"),
+    ("hide_title", "Masquer (H)"),
+    ("hide", "Masquer"),
+    ("unhide", "Réafficher"),
+    ("move", "Déplacer"),
+    ("delete", "Supprimer"),
+    ("caption_placeholder", "Ajouter une légende…"),
+    ("favorite_title", "Favori (F)"),
+    ("shortcuts", "Raccourcis clavier"),
+    (
+        "shortcuts_intro",
+        "← → : photo précédente / suivante · 0-5 : note · F : favori · H : masquer · T : saisir un tag. Chaque lettre ci-dessous ajoute ou retire le tag qui lui est associé.",
+    ),
+    ("prev", "Préc"),
+    ("next", "Suiv"),
+    ("close", "Fermer"),
+    ("slower", "Plus lent"),
+    ("faster", "Plus rapide"),
+    ("pause", "Pause"),
+    ("resume", "Reprendre"),
+    ("fullscreen", "Plein écran"),
+    ("inbox_title", "Photos envoyées par des invités"),
+    ("inbox_empty", "Aucune photo en attente"),
+    (
+        "inbox_reload",
+        "Sauvegardez puis rechargez la page pour voir les photos ajoutées",
+    ),
+    ("approve", "Approuver"),
+    ("reject", "Rejeter"),
+    ("conflicts_title", "Conflits de metadata"),
+    (
+        "conflicts_intro",
+        "Ces fichiers ont été modifiés des deux côtés. Choisissez quoi garder pour chacun.",
+    ),
+    ("all_current", "Tout serveur"),
+    ("all_incoming", "Tout importé"),
+    ("merge", "Fusionner"),
+    ("merge_current", "Serveur : {v}"),
+    ("merge_incoming", "Importé : {v}"),
+    ("merge_both", "Les deux"),
+    ("merge_highest", "La plus haute"),
+    ("no_tags", "aucun"),
+    ("no_rating", "aucune"),
+    ("rating", "Note"),
+    ("q_unclosed_quote", "guillemet non fermé"),
+    ("q_rating", "note « {v} »"),
+    ("q_missing_value", "valeur manquante après « {field}: »"),
+    ("q_year", "année « {v} »"),
+    ("q_is", "« is:{v} » (is:favorite ou is:hidden)"),
+    ("q_unknown_field", "champ inconnu « {field} »"),
+    ("q_no_term_before", "{op} sans terme avant"),
+    ("q_unclosed_paren", "parenthèse non fermée"),
+    ("q_extra_paren", "parenthèse fermante en trop"),
+    ("q_missing_term", "terme manquant en fin de requête"),
+    ("q_invalid", "Requête invalide : {err}"),
+    ("caption_saved", "Légende enregistrée"),
+    ("caption_removed", "Légende supprimée"),
+    ("tag_added", "Tag «{tag}» ajouté"),
+    ("add_tag", "Ajouter un tag"),
+    ("remove_tag", "Retirer le tag «{tag}»"),
+    ("tag_removed", "Tag «{tag}» retiré"),
+    ("rating_set", "Note : {n}/5"),
+    ("rating_removed", "Note supprimée"),
+    ("favorite_added", "Ajouté aux favoris"),
+    ("favorite_removed", "Retiré des favoris"),
+    ("photo_hidden", "Photo masquée"),
+    ("photo_unhidden", "Photo réaffichée"),
+    ("saved", "Metadata sauvegardé"),
+    ("saved_download", "Metadata sauvegardé (téléchargé)"),
+    ("error", "Erreur: {error}"),
+    ("error_unknown", "inconnue"),
+    ("error_network", "Erreur réseau: {error}"),
+    ("error_plain", "Erreur"),
+    (
+        "unsaved_lost",
+        "Les modifications non sauvegardées seront perdues. Continuer ?",
+    ),
+    ("invalid_metadata", "Fichier metadata invalide"),
+    ("confirm_delete", "Mettre {name} à la corbeille ?"),
+    ("deleted", "{name} mis à la corbeille"),
+    (
+        "move_title",
+        "Redater : choisir le mois, la photo est renommée et reclassée",
+    ),
+    ("moved", "{name} déplacé vers {dest}"),
+    ("rotated", "Photo tournée de {angle}°"),
+    ("camera", "Appareil"),
+    ("lens", "Objectif"),
+    ("dimensions", "Dimensions"),
+    ("shot", "Prise de vue"),
+    ("date_override", "Date (corrigée)"),
+    ("date_taken", "Date de prise de vue"),
+    ("loading", "Chargement…"),
+    ("loading_error", "Erreur de chargement"),
+    ("no_metadata", "Aucune métadonnée"),
+    ("all_metadata", "Toutes les métadonnées"),
+    ("served_only", "Disponible uniquement via photo-sort serve"),
+    ("nothing_to_export", "Aucune photo à exporter"),
+    ("exported", "{n} fichiers dans export_list.txt"),
+    ("copied", "{n} chemins copiés"),
+    ("copy_failed", "Impossible d'écrire dans le presse-papiers"),
+    (
+        "data_error",
+        "Impossible de charger gallery_data.json : ouvrez la galerie depuis un serveur web",
+    ),
+    ("stats", "Statistiques"),
+    ("stats_title", "Photos par année, tags, notes et appareils"),
+    ("stats_years", "Photos par année"),
+    ("stats_ratings", "Notes"),
+    ("stats_cameras", "Appareils"),
+    ("on_this_day", "Ce jour-là"),
+    (
+        "on_this_day_title",
+        "Les photos prises à la même date les années précédentes, en haut de la galerie",
+    ),
+    ("years_ago_one", "Il y a {n} an"),
+    ("years_ago_many", "Il y a {n} ans"),
+    ("unrated", "Sans note"),
+    ("doubtful_dates", "Dates douteuses"),
+    (
+        "doubtful_dates_title",
+        "Photos classées sans date EXIF : nom du dossier ou date du fichier",
+    ),
+    ("date_src_exif", "EXIF"),
+    ("date_src_exif_title", "Date lue dans les données EXIF"),
+    ("date_src_dirname", "dossier"),
+    (
+        "date_src_dirname_title",
+        "Date tirée du nom du dossier d'origine",
+    ),
+    ("date_src_filesystem", "fichier"),
+    (
+        "date_src_filesystem_title",
+        "Date de modification du fichier : peut-être fausse",
+    ),
+    ("date_src_manual", "corrigée"),
+    ("date_src_manual_title", "Date corrigée à la main"),
+    ("map_title", "Lieu de la prise de vue"),
+    ("histogram", "Histogramme"),
+    ("histogram_title", "Histogramme RVB de la photo, pour juger l'exposition"),
+    ("rate_title", "Noter {n}/5"),
+    ("drop_photos", "Déposer les photos pour les ranger dans la bibliothèque"),
+    ("upload_sending", "Envoi de {n} fichier(s)…"),
+    ("upload_done", "{n} photo(s) ajoutée(s)"),
+    ("upload_duplicates", "{n} doublon(s) ignoré(s)"),
+    ("upload_errors", "{n} fichier(s) refusé(s)"),
+    ("upload_failed", "Échec de l'envoi"),
+    ("nearby", "Photos à proximité"),
+    ("near", "À proximité"),
+    ("near_clear_title", "Photos prises à moins de 2 km ; cliquer pour retirer le filtre"),
+];
+
+const EN: &[(&str, &str)] = &[
+    ("slideshow", "Slideshow"),
+    ("random", "Shuffle"),
+    ("export_filtered", "Export filtered"),
+    ("copy_list", "Copy list"),
+    (
+        "copy_list_title",
+        "Copy the paths of the filtered photos, one per line, to the clipboard",
+    ),
+    ("import", "Import"),
+    ("import_title", "Merge a .photo_sort_metadata.json file"),
+    ("undo", "Undo"),
+    ("undo_title", "Undo the last metadata save"),
+    ("theme_title", "Light / dark theme"),
+    ("save", "Save"),
+    ("all", "All"),
+    ("tags", "Tags"),
+    ("people", "People"),
+    ("album", "Album"),
+    ("labels", "Labels"),
+    ("label_red", "Red"),
+    ("label_orange", "Orange"),
+    ("label_yellow", "Yellow"),
+    ("label_green", "Green"),
+    ("label_blue", "Blue"),
+    ("label_purple", "Purple"),
+    ("label_gray", "Gray"),
+    ("label_black", "Black"),
+    ("label_white", "White"),
+    ("min_rating", "Min rating"),
+    ("favorites", "Favorites"),
+    ("duplicates", "Duplicates"),
+    ("dup_identical", "Identical copy of another photo"),
+    ("dup_similar", "Nearly identical to another photo"),
+    ("search", "Search"),
+    ("jump_to", "Go to"),
+    ("all_years", "All years"),
+    ("month", "Month…"),
+    ("undated", "undated"),
+    ("sort", "Sort"),
+    ("grid_size", "Size"),
+    ("grid_size_title", "Thumbnail size: small, medium or large"),
+    ("size_small", "Small"),
+    ("size_medium", "Medium"),
+    ("size_large", "Large"),
+    ("sort_date", "Oldest first"),
+    ("sort_date_desc", "Newest first"),
+    ("sort_rating", "Top rated"),
+    ("sort_name", "Name"),
+    ("sort_random", "Random"),
+    ("shuffle", "Shuffle grid"),
+    (
+        "shuffle_title",
+        "The whole selection in random order, without the sections; click again to go back to date order",
+    ),
+    ("hidden", "Hidden"),
+    ("show_hidden_title", "Show hidden photos"),
+    ("show", "Show"),
+    ("bursts", "Bursts"),
+    ("stack", "Stack"),
+    (
+        "burst_gap_title",
+        "Longest gap between two photos of a burst",
+    ),
+    ("collapse_burst", "Collapse the burst"),
+    ("photo_one", "{n} photo"),
+    ("photo_many", "{n} photos"),
+    ("download", "Download"),
+    ("rotate_left", "Rotate left"),
+    ("rotate_right", "Rotate right"),
+    ("metadata", "Metadata"),
+    ("info", "Info"),
+    ("hide_title", "Hide (H)"),
+    ("hide", "Hide"),
+    ("unhide", "Unhide"),
+    ("move", "Move"),
+    ("delete", "Delete"),
+    ("caption_placeholder", "Add a caption…"),
+    ("favorite_title", "Favorite (F)"),
+    ("shortcuts", "Keyboard shortcuts"),
+    (
+        "shortcuts_intro",
+        "← →: previous / next photo · 0-5: rating · F: favorite · H: hide · T: type a tag. Each letter below adds or removes the tag bound to it.",
+    ),
+    ("prev", "Prev"),
+    ("next", "Next"),
+    ("close", "Close"),
+    ("slower", "Slower"),
+    ("faster", "Faster"),
+    ("pause", "Pause"),
+    ("resume", "Resume"),
+    ("fullscreen", "Fullscreen"),
+    ("inbox_title", "Photos sent by guests"),
+    ("inbox_empty", "No photo waiting"),
+    (
+        "inbox_reload",
+        "Save, then reload the page to see the added photos",
+    ),
+    ("approve", "Approve"),
+    ("reject", "Reject"),
+    ("conflicts_title", "Metadata conflicts"),
+    (
+        "conflicts_intro",
+        "These files were changed on both sides. Choose what to keep for each.",
+    ),
+    ("all_current", "All server"),
+    ("all_incoming", "All imported"),
+    ("merge", "Merge"),
+    ("merge_current", "Server: {v}"),
+    ("merge_incoming", "Imported: {v}"),
+    ("merge_both", "Both"),
+    ("merge_highest", "The highest"),
+    ("no_tags", "none"),
+    ("no_rating", "none"),
+    ("rating", "Rating"),
+    ("q_unclosed_quote", "unclosed quote"),
+    ("q_rating", "rating “{v}”"),
+    ("q_missing_value", "missing value after “{field}:”"),
+    ("q_year", "year “{v}”"),
+    ("q_is", "“is:{v}” (is:favorite or is:hidden)"),
+    ("q_unknown_field", "unknown field “{field}”"),
+    ("q_no_term_before", "{op} without a term before"),
+    ("q_unclosed_paren", "unclosed parenthesis"),
+    ("q_extra_paren", "extra closing parenthesis"),
+    ("q_missing_term", "missing term at the end of the query"),
+    ("q_invalid", "Invalid query: {err}"),
+    ("caption_saved", "Caption saved"),
+    ("caption_removed", "Caption removed"),
+    ("tag_added", "Tag “{tag}” added"),
+    ("add_tag", "Add a tag"),
+    ("remove_tag", "Remove the tag “{tag}”"),
+    ("tag_removed", "Tag “{tag}” removed"),
+    ("rating_set", "Rating: {n}/5"),
+    ("rating_removed", "Rating removed"),
+    ("favorite_added", "Added to favorites"),
+    ("favorite_removed", "Removed from favorites"),
+    ("photo_hidden", "Photo hidden"),
+    ("photo_unhidden", "Photo shown again"),
+    ("saved", "Metadata saved"),
+    ("saved_download", "Metadata saved (downloaded)"),
+    ("error", "Error: {error}"),
+    ("error_unknown", "unknown"),
+    ("error_network", "Network error: {error}"),
+    ("error_plain", "Error"),
+    ("unsaved_lost", "Unsaved changes will be lost. Continue?"),
+    ("invalid_metadata", "Invalid metadata file"),
+    ("confirm_delete", "Move {name} to the trash?"),
+    ("deleted", "{name} moved to the trash"),
+    (
+        "move_title",
+        "Re-date: pick the month, the photo is renamed and re-filed",
+    ),
+    ("moved", "{name} moved to {dest}"),
+    ("rotated", "Photo rotated by {angle}°"),
+    ("camera", "Camera"),
+    ("lens", "Lens"),
+    ("dimensions", "Dimensions"),
+    ("shot", "Exposure"),
+    ("date_override", "Date (corrected)"),
+    ("date_taken", "Date taken"),
+    ("loading", "Loading…"),
+    ("loading_error", "Loading failed"),
+    ("no_metadata", "No metadata"),
+    ("all_metadata", "All metadata"),
+    ("served_only", "Only available with photo-sort serve"),
+    ("nothing_to_export", "No photo to export"),
+    ("exported", "{n} files in export_list.txt"),
+    ("copied", "{n} paths copied"),
+    ("copy_failed", "Could not write to the clipboard"),
+    (
+        "data_error",
+        "Could not load gallery_data.json: open the gallery from a web server",
+    ),
+    ("stats", "Stats"),
+    ("stats_title", "Photos per year, tags, ratings and cameras"),
+    ("stats_years", "Photos per year"),
+    ("stats_ratings", "Ratings"),
+    ("stats_cameras", "Cameras"),
+    ("on_this_day", "On this day"),
+    (
+        "on_this_day_title",
+        "Photos taken on this date in earlier years, at the top of the gallery",
+    ),
+    ("years_ago_one", "{n} year ago"),
+    ("years_ago_many", "{n} years ago"),
+    ("unrated", "Unrated"),
+    ("doubtful_dates", "Doubtful dates"),
+    (
+        "doubtful_dates_title",
+        "Photos sorted without an EXIF date: folder name or file date",
+    ),
+    ("date_src_exif", "EXIF"),
+    ("date_src_exif_title", "Date read from the EXIF data"),
+    ("date_src_dirname", "folder"),
+    (
+        "date_src_dirname_title",
+        "Date taken from the source folder name",
+    ),
+    ("date_src_filesystem", "file"),
+    (
+        "date_src_filesystem_title",
+        "File modification date: may be wrong",
+    ),
+    ("date_src_manual", "corrected"),
+    ("date_src_manual_title", "Date corrected by hand"),
+    ("map_title", "Where it was taken"),
+    ("histogram", "Histogram"),
+    ("histogram_title", "RGB histogram of the photo, to judge the exposure"),
+    ("rate_title", "Rate {n}/5"),
+    ("drop_photos", "Drop the photos to sort them into the library"),
+    ("upload_sending", "Sending {n} file(s)…"),
+    ("upload_done", "{n} photo(s) added"),
+    ("upload_duplicates", "{n} duplicate(s) skipped"),
+    ("upload_errors", "{n} file(s) refused"),
+    ("upload_failed", "Upload failed"),
+    ("nearby", "Nearby photos"),
+    ("near", "Nearby"),
+    ("near_clear_title", "Photos taken within 2 km; click to clear the filter"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_language_has_every_string() {
+        let keys = |lang: Lang| lang.strings().into_keys().collect::<Vec<_>>();
+        assert_eq!(keys(Lang::Fr), keys(Lang::En));
+        assert_eq!(Lang::Fr.strings().len(), FR.len());
+        assert_eq!(Lang::En.get("save"), "Save");
+        assert_eq!(Lang::Fr.get("missing_key"), "missing_key");
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::journal;
+use crate::origins::{self, Origin};
+use crate::sort::{self, ProcessedEntry, SortOptions};
+
+/// Name of the quarantine directory for guest uploads (inside the photo base dir).
+pub const INBOX_DIR: &str = "inbox";
+
+/// Result of approving an inbox file.
+#[derive(Debug, PartialEq)]
+pub enum Approval {
+    /// Copied into the library at the given relative path.
+    Sorted(String),
+    /// The library already contains the same content; the upload was discarded.
+    Duplicate,
+}
+
+/// Return the inbox directory for a given photo base dir.
+pub fn inbox_dir(base: &Path) -> PathBuf {
+    base.join(INBOX_DIR)
+}
+
+/// List the files waiting in the inbox, sorted by name.
+pub fn list_inbox(base: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(inbox_dir(base)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()) && sort::is_photo(&e.path()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Keep only the final component of an uploaded file name.
+/// Returns `None` for empty names, hidden files or non-photo extensions.
+pub fn sanitize_upload_name(name: &str) -> Option<String> {
+    let name = name.replace('\\', "/");
+    let name = name.rsplit('/').next()?.trim();
+    if name.is_empty() || name.starts_with('.') || !sort::is_photo(Path::new(name)) {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// Resolve an inbox file name to its path, rejecting anything that is not a
+/// plain file name.
+fn inbox_file(base: &Path, name: &str) -> Option<PathBuf> {
+    let clean = sanitize_upload_name(name)?;
+    if clean != name {
+        return None;
+    }
+    Some(inbox_dir(base).join(clean))
+}
+
+/// Store an uploaded file in the inbox. An existing file with the same name is
+/// never overwritten: a `_1`, `_2`… suffix is added instead.
+/// Returns the name actually used.
+pub fn store_upload(base: &Path, name: &str, data: &[u8]) -> Result<String> {
+    let name = sanitize_upload_name(name).context("Nom de fichier invalide")?;
+    let dir = inbox_dir(base);
+    fs::create_dir_all(&dir).context("Impossible de créer le dossier inbox")?;
+
+    let path = Path::new(&name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = path.extension().unwrap_or_default().to_string_lossy().to_string();
+
+    let mut candidate = name.clone();
+    let mut counter = 1u32;
+    while dir.join(&candidate).exists() {
+        candidate = format!("{stem}_{counter}.{ext}");
+        counter += 1;
+    }
+
+    fs::write(dir.join(&candidate), data)
+        .with_context(|| format!("Impossible d'enregistrer {candidate}"))?;
+    Ok(candidate)
+}
+
+/// Move an inbox file into the sorted library using the standard pipeline:
+/// BLAKE3 deduplication against the progress file, date detection, renaming
+/// and origin tracking.
+pub fn approve(base: &Path, name: &str) -> Result<Approval> {
+    let src = inbox_file(base, name).context("Nom de fichier invalide")?;
+    if !src.is_file() {
+        anyhow::bail!("Fichier introuvable dans l'inbox : {name}");
+    }
+
+    let progress_path = base.join(sort::PROGRESS_FILE);
+    let mut progress = sort::load_progress(&progress_path)?;
+
+    let hash = sort::hash_file(&src)?;
+    let origin = format!("{INBOX_DIR}/{name}");
+    let delete = journal::Operation::Delete {
+        path: origin.clone(),
+    };
+    if progress.processed.iter().any(|e| e.hash == hash) {
+        journal::run(base, &delete, || Ok(fs::remove_file(&src)?))?;
+        return Ok(Approval::Duplicate);
+    }
+
+    let size = fs::metadata(&src).map(|m| m.len()).unwrap_or(0);
+    let placement = sort::place_file(&src, base, None, &SortOptions::default(), &hash)?;
+    let dest_path = placement.dest;
+
+    let dest_rel = dest_path
+        .strip_prefix(base)
+        .unwrap_or(&dest_path)
+        .to_string_lossy()
+        .to_string();
+    if placement.copied {
+        let _ = origins::append(
+            base,
+            &Origin {
+                dest: dest_rel.clone(),
+                source: origin,
+            },
+        );
+    }
+
+    progress.processed.push(ProcessedEntry {
+        source: src.to_string_lossy().to_string(),
+        dest: dest_rel.clone(),
+        size,
+        hash,
+        date_source: placement.date_source.as_str().to_string(),
+    });
+    sort::save_progress(&progress_path, &progress)?;
+
+    journal::run(base, &delete, || Ok(fs::remove_file(&src)?))?;
+    Ok(Approval::Sorted(dest_rel))
+}
+
+/// Sort an upload straight into the library, without waiting for review:
+/// stored in the inbox, then approved at once. A file the pipeline fails on
+/// stays in the inbox.
+pub fn import(base: &Path, name: &str, data: &[u8]) -> Result<Approval> {
+    let stored = store_upload(base, name, data)?;
+    approve(base, &stored)
+}
+
+/// Delete an inbox file without importing it.
+pub fn reject(base: &Path, name: &str) -> Result<()> {
+    let path = inbox_file(base, name).context("Nom de fichier invalide")?;
+    let delete = journal::Operation::Delete {
+        path: format!("{INBOX_DIR}/{name}"),
+    };
+    journal::run(base, &delete, || {
+        fs::remove_file(&path).with_context(|| format!("Fichier introuvable dans l'inbox : {name}"))
+    })
+}
+
+/// Build the standalone upload page shown to guests holding the share link.
+pub fn guest_page_html(token: &str) -> String {
+    let token_js = token.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="fr">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width,initial-scale=1">
+<title>photo-sort — envoi de photos</title>
+<style>
+*{{margin:0;padding:0;box-sizing:border-box}}
+body{{background:#0a0a0a;color:#e0e0e0;font-family:-apple-system,BlinkMacSystemFont,"Segoe UI",Roboto,sans-serif;display:flex;justify-content:center;padding:3rem 1rem}}
+.card{{background:#1a1a1a;border:1px solid #333;border-radius:10px;padding:2rem;max-width:480px;width:100%}}
+h1{{font-size:1.2rem;font-weight:600;color:#4fc3f7;margin-bottom:1rem}}
+p{{color:#999;font-size:.9rem;margin-bottom:1.2rem}}
+input[type=file]{{width:100%;color:#ccc;margin-bottom:1rem}}
+button{{background:#4fc3f7;color:#000;border:none;padding:.5rem 1rem;border-radius:6px;cursor:pointer;font-size:.9rem}}
+button:disabled{{opacity:.5;cursor:default}}
+ul{{list-style:none;margin-top:1rem;font-size:.85rem}}
+li{{padding:.2rem 0;color:#aaa}}
+li.ok{{color:#6f6}}
+li.err{{color:#f66}}
+</style>
+</head>
+<body>
+<div class="card">
+  <h1>Envoyer des photos</h1>
+  <p>Les photos envoyées seront vérifiées avant d'être ajoutées à la bibliothèque.</p>
+  <input type="file" id="files" multiple accept="image/*,.heic,.heif,.cr2,.cr3,.nef,.arw,.dng,.orf,.rw2,.raf">
+  <button id="send">Envoyer</button>
+  <ul id="log"></ul>
+</div>
+<script>
+const TOKEN="{token_js}";
+document.getElementById('send').addEventListener('click',async()=>{{
+  const btn=document.getElementById('send');
+  const files=[...document.getElementById('files').files];
+  const log=document.getElementById('log');
+  if(!files.length)return;
+  btn.disabled=true;
+  for(const f of files){{
+    const li=document.createElement('li');
+    li.textContent=f.name+'…';log.appendChild(li);
+    try{{
+      const r=await fetch('/api/inbox?token='+encodeURIComponent(TOKEN)+'&name='+encodeURIComponent(f.name),{{method:'POST',body:f}});
+      const d=await r.json();
+      if(d.ok){{li.textContent=f.name+' — envoyé';li.className='ok';}}
+      else{{li.textContent=f.name+' — '+(d.error||'erreur');li.className='err';}}
+    }}catch(e){{li.textContent=f.name+' — erreur réseau';li.className='err';}}
+  }}
+  btn.disabled=false;
+}});
+</script>
+</body>
+</html>"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_inbox_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // --- sanitize_upload_name ---
+
+    #[test]
+    fn sanitize_keeps_plain_photo_name() {
+        assert_eq!(sanitize_upload_name("IMG_1234.jpg").unwrap(), "IMG_1234.jpg");
+    }
+
+    #[test]
+    fn sanitize_strips_directories() {
+        assert_eq!(sanitize_upload_name("../../etc/a.jpg").unwrap(), "a.jpg");
+        assert_eq!(sanitize_upload_name("C:\\Users\\x\\b.JPG").unwrap(), "b.JPG");
+    }
+
+    #[test]
+    fn sanitize_rejects_non_photos_and_hidden() {
+        assert!(sanitize_upload_name("script.sh").is_none());
+        assert!(sanitize_upload_name(".hidden.jpg").is_none());
+        assert!(sanitize_upload_name("").is_none());
+        assert!(sanitize_upload_name("dir/").is_none());
+    }
+
+    // --- store_upload / list_inbox ---
+
+    #[test]
+    fn store_upload_writes_into_inbox() {
+        let tmp = tmpdir();
+        let name = store_upload(&tmp, "photo.jpg", b"data").unwrap();
+        assert_eq!(name, "photo.jpg");
+        assert_eq!(fs::read(tmp.join("inbox/photo.jpg")).unwrap(), b"data");
+        assert_eq!(list_inbox(&tmp), vec!["photo.jpg"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn store_upload_never_overwrites() {
+        let tmp = tmpdir();
+        store_upload(&tmp, "photo.jpg", b"a").unwrap();
+        let second = store_upload(&tmp, "photo.jpg", b"b").unwrap();
+        assert_eq!(second, "photo_1.jpg");
+        assert_eq!(fs::read(tmp.join("inbox/photo.jpg")).unwrap(), b"a");
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn store_upload_rejects_non_photo() {
+        let tmp = tmpdir();
+        assert!(store_upload(&tmp, "evil.html", b"<script>").is_err());
+        assert!(list_inbox(&tmp).is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn list_inbox_missing_dir_is_empty() {
+        let tmp = tmpdir();
+        assert!(list_inbox(&tmp).is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    // --- approve / reject ---
+
+    #[test]
+    fn approve_sorts_into_library() {
+        let tmp = tmpdir();
+        store_upload(&tmp, "photo.jpg", b"guest photo").unwrap();
+
+        let result = approve(&tmp, "photo.jpg").unwrap();
+        let Approval::Sorted(rel) = result else {
+            panic!("expected Sorted");
+        };
+        assert!(tmp.join(&rel).exists());
+        assert!(!tmp.join("inbox/photo.jpg").exists());
+
+        let progress = sort::load_progress(&tmp.join(sort::PROGRESS_FILE)).unwrap();
+        assert_eq!(progress.processed.len(), 1);
+        assert_eq!(progress.processed[0].dest, rel);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn approve_discards_duplicate_content() {
+        let tmp = tmpdir();
+        store_upload(&tmp, "a.jpg", b"same bytes").unwrap();
+        store_upload(&tmp, "b.jpg", b"same bytes").unwrap();
+
+        assert!(matches!(approve(&tmp, "a.jpg").unwrap(), Approval::Sorted(_)));
+        assert_eq!(approve(&tmp, "b.jpg").unwrap(), Approval::Duplicate);
+        assert!(list_inbox(&tmp).is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn approve_rejects_traversal() {
+        let tmp = tmpdir();
+        assert!(approve(&tmp, "../secret.jpg").is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn import_sorts_an_upload_at_once() {
+        let tmp = tmpdir();
+        let Approval::Sorted(rel) = import(&tmp, "IMG_1.jpg", b"phone photo").unwrap() else {
+            panic!("expected Sorted");
+        };
+        assert!(tmp.join(&rel).exists());
+        assert!(list_inbox(&tmp).is_empty());
+        assert_eq!(
+            import(&tmp, "IMG_2.jpg", b"phone photo").unwrap(),
+            Approval::Duplicate
+        );
+        assert!(import(&tmp, "notes.txt", b"x").is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn reject_deletes_upload() {
+        let tmp = tmpdir();
+        store_upload(&tmp, "photo.jpg", b"x").unwrap();
+        reject(&tmp, "photo.jpg").unwrap();
+        assert!(list_inbox(&tmp).is_empty());
+        assert!(reject(&tmp, "photo.jpg").is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn guest_page_embeds_token() {
+        let html = guest_page_html("abc123");
+        assert!(html.contains("const TOKEN=\"abc123\""));
+        assert!(html.contains("/api/inbox"));
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::Result;
+use console::style;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::prefs;
+use crate::sort::{self, PairPreference};
+
+/// Languages offered by the wizard.
+const LANGUAGES: &[&str] = &["fr", "en"];
+
+/// Print `question` (with `hint` between brackets) and read the trimmed answer.
+fn read_answer(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+    hint: &str,
+) -> Result<String> {
+    if hint.is_empty() {
+        write!(out, "  {} ", style(question).bold())?;
+    } else {
+        write!(out, "  {} [{}] ", style(question).bold(), style(hint).dim())?;
+    }
+    out.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        anyhow::bail!("Configuration interrompue");
+    }
+    Ok(line.trim().to_string())
+}
+
+/// Ask `question`; an empty answer means `default`.
+fn ask(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+    default: &str,
+) -> Result<String> {
+    let answer = read_answer(input, out, question, default)?;
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer
+    })
+}
+
+/// Yes/no question; accepts o/oui/y/yes and n/non/no.
+fn ask_yes_no(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+    default: bool,
+) -> Result<bool> {
+    let hint = if default { "O/n" } else { "o/N" };
+    loop {
+        match read_answer(input, out, question, hint)?
+            .to_lowercase()
+            .as_str()
+        {
+            "" => return Ok(default),
+            "o" | "oui" | "y" | "yes" => return Ok(true),
+            "n" | "non" | "no" => return Ok(false),
+            _ => writeln!(out, "  {} Répondez o ou n", style("!").yellow().bold())?,
+        }
+    }
+}
+
+/// Ask until the answer is one of `choices`.
+fn ask_choice(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    question: &str,
+    choices: &[&str],
+    default: &str,
+) -> Result<String> {
+    loop {
+        let answer = ask(
+            input,
+            out,
+            &format!("{question} ({})", choices.join("/")),
+            default,
+        )?;
+        let answer = answer.to_lowercase();
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        writeln!(
+            out,
+            "  {} Choix possibles : {}",
+            style("!").yellow().bold(),
+            choices.join(", ")
+        )?;
+    }
+}
+
+/// Interactive questions of `photo-sort init`. Returns the configuration and
+/// whether the first sort should be run right away.
+pub fn ask_config(input: &mut impl BufRead, out: &mut impl Write) -> Result<(Config, bool)> {
+    let mut config = Config::default();
+
+    writeln!(
+        out,
+        "\n{}  {}\n",
+        style("photo-sort").bold().cyan(),
+        style("configuration").dim()
+    )?;
+
+    writeln!(
+        out,
+        "  {}",
+        style("Dossiers contenant les photos à trier (vide pour terminer)").dim()
+    )?;
+    loop {
+        let answer = ask(input, out, "Dossier source :", "")?;
+        if answer.is_empty() {
+            if config.sources.is_empty() {
+                writeln!(
+                    out,
+                    "  {} Indiquez au moins un dossier",
+                    style("!").yellow().bold()
+                )?;
+                continue;
+            }
+            break;
+        }
+        match Path::new(&answer).canonicalize() {
+            Ok(dir) if dir.is_dir() => config.sources.push(dir),
+            _ => writeln!(
+                out,
+                "  {} Dossier introuvable : {answer}",
+                style("!").yellow().bold()
+            )?,
+        }
+    }
+
+    let first = &config.sources[0];
+    let default_library = first.with_file_name(format!(
+        "{}_sorted",
+        first.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let library = ask(
+        input,
+        out,
+        "Dossier de la photothèque :",
+        &default_library.to_string_lossy(),
+    )?;
+    config.library = PathBuf::from(library);
+
+    config.layout.keep_name = ask_yes_no(
+        input,
+        out,
+        "Garder le nom d'origine dans les noms de fichiers ?",
+        false,
+    )?;
+    config.layout.auto_orient = ask_yes_no(
+        input,
+        out,
+        "Redresser les photos selon leur orientation EXIF ?",
+        false,
+    )?;
+    config.layout.pair_prefer = match ask_choice(
+        input,
+        out,
+        "Paires RAW+JPEG : format gardé dans le dossier de l'année",
+        &["les-deux", "jpeg", "raw"],
+        "les-deux",
+    )?
+    .as_str()
+    {
+        "jpeg" => Some(PairPreference::Jpeg),
+        "raw" => Some(PairPreference::Raw),
+        _ => None,
+    };
+
+    config.language = ask_choice(input, out, "Langue de la galerie", LANGUAGES, "fr")?;
+
+    config.server.port = loop {
+        let answer = ask(input, out, "Port du serveur :", "8080")?;
+        match answer.parse::<u16>() {
+            Ok(port) if port > 0 => break port,
+            _ => writeln!(
+                out,
+                "  {} Port invalide : {answer}",
+                style("!").yellow().bold()
+            )?,
+        }
+    };
+    if ask_yes_no(
+        input,
+        out,
+        "Autoriser l'envoi de photos par des invités ?",
+        false,
+    )? {
+        config.server.guest_token = Some(prefs::new_client_id());
+        // Guests send from their own devices
+        config.server.host = std::net::Ipv4Addr::UNSPECIFIED.into();
+    }
+
+    let run_now = ask_yes_no(input, out, "Lancer le premier tri maintenant ?", true)?;
+    Ok((config, run_now))
+}
+
+/// `photo-sort init`: ask the questions on the terminal, write
+/// `photo-sort.toml` in the library and optionally run the first sort.
+pub fn run_init() -> Result<()> {
+    let (config, run_now) = ask_config(&mut std::io::stdin().lock(), &mut std::io::stdout())?;
+    let path = config.save()?;
+    println!(
+        "\n  {} Configuration enregistrée dans {}",
+        style("✔").green().bold(),
+        style(path.display()).white().bold()
+    );
+
+    if run_now {
+        let options = config.sort_options();
+        for source in &config.sources {
+            sort::run_sort(source, &config.library, &options)?;
+        }
+    }
+
+    println!(
+        "  {} Pour parcourir les photos : {}",
+        style("→").cyan(),
+        style(format!("photo-sort serve {}", config.library.display()))
+            .cyan()
+            .bold()
+    );
+    if let Some(token) = &config.server.guest_token {
+        println!(
+            "  {} Lien invité : http://localhost:{}/guest?token={token}",
+            style("→").cyan(),
+            config.server.port
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn run(answers: &str) -> Result<(Config, bool, String)> {
+        let mut out = Vec::new();
+        let (config, run_now) = ask_config(&mut Cursor::new(answers.as_bytes()), &mut out)?;
+        Ok((config, run_now, String::from_utf8_lossy(&out).to_string()))
+    }
+
+    #[test]
+    fn defaults_after_one_source() {
+        let src = std::env::temp_dir().canonicalize().unwrap();
+        let answers = format!("{}\n\n\n\n\n\n\n\n\n\n", src.display());
+        let (config, run_now, _) = run(&answers).unwrap();
+
+        assert_eq!(config.sources, vec![src.clone()]);
+        assert_eq!(
+            config.library,
+            src.with_file_name(format!(
+                "{}_sorted",
+                src.file_name().unwrap().to_string_lossy()
+            ))
+        );
+        assert!(!config.layout.keep_name);
+        assert_eq!(config.layout.pair_prefer, None);
+        assert_eq!(config.language, "fr");
+        assert_eq!(config.server.port, 8080);
+        assert!(config.server.guest_token.is_none());
+        assert!(config.server.host.is_loopback());
+        assert!(run_now);
+    }
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let src = std::env::temp_dir().canonicalize().unwrap();
+        let answers = format!(
+            "\n/nonexistent/photo_sort\n{}\n\n/lib\npeut-être\no\nn\nRAW\nde\nen\n99999\n3000\no\nn\n",
+            src.display()
+        );
+        let (config, run_now, output) = run(&answers).unwrap();
+
+        assert!(output.contains("Indiquez au moins un dossier"));
+        assert!(output.contains("Dossier introuvable"));
+        assert!(output.contains("Port invalide"));
+        assert_eq!(config.library, PathBuf::from("/lib"));
+        assert!(config.layout.keep_name);
+        assert!(!config.layout.auto_orient);
+        assert_eq!(config.layout.pair_prefer, Some(PairPreference::Raw));
+        assert_eq!(config.language, "en");
+        assert_eq!(config.server.port, 3000);
+        assert!(config.server.guest_token.is_some());
+        assert!(config.server.host.is_unspecified());
+        assert!(!run_now);
+    }
+
+    #[test]
+    fn end_of_input_aborts() {
+        assert!(run("").is_err());
+    }
+}
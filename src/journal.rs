@@ -0,0 +1,393 @@
+use anyhow::{Context, Result};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use crate::metadata::Metadata;
+use crate::thumb;
+use crate::trash;
+
+/// Write-ahead journal of destructive operations (at the root of the library).
+pub const JOURNAL_FILE: &str = ".photo_sort_journal.jsonl";
+
+/// Held while an operation runs or the journal is replayed, so that a
+/// `recover` in another process never replays an operation still running.
+const LOCK_FILE: &str = ".photo_sort_journal.lock";
+
+/// Serializes appends from the server threads.
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Record ids: the process start time in nanoseconds, plus a counter, so that
+/// records of different runs never collide.
+static NEXT_ID: LazyLock<AtomicU64> = LazyLock::new(|| {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    AtomicU64::new(now.as_nanos() as u64)
+});
+
+/// A destructive operation on the library. Paths are relative to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Rename `from` to `to` and move its metadata along.
+    Move { from: String, to: String },
+    /// Remove `path` and its metadata.
+    Delete { path: String },
+    /// Move `path` and its metadata into the trash entry `id`.
+    Trash { path: String, id: String },
+}
+
+/// One journal line: the intent to run an operation, or its completion.
+#[derive(Serialize, Deserialize, Debug)]
+struct Record {
+    id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    intent: Option<Operation>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    done: bool,
+}
+
+/// Outcome of replaying the journal after an interruption.
+#[derive(Debug, Default, PartialEq)]
+pub struct Recovery {
+    /// Interrupted operations that were completed.
+    pub completed: usize,
+    /// Interrupted operations left alone, with the reason.
+    pub conflicts: Vec<String>,
+}
+
+/// Take the journal lock of `dir`, released when the returned file is dropped.
+/// Each call opens its own handle, so it serializes threads too.
+fn lock(dir: &Path) -> Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))
+        .context("Impossible de créer le verrou du journal")?;
+    file.lock()
+        .context("Impossible de verrouiller le journal")?;
+    Ok(file)
+}
+
+fn append(dir: &Path, record: &Record) -> Result<()> {
+    let _guard = JOURNAL_LOCK.lock().unwrap();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(JOURNAL_FILE))
+        .context("Impossible d'ouvrir le journal")?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    // The intent must be on disk before the operation starts
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Record `op` in the journal, run `apply` (which performs it, metadata
+/// included), then mark it done. If the process dies in between, `recover`
+/// finishes the operation on the next start. A failing `apply` is marked done
+/// too: it is reported to the caller, not replayed. `apply` must not run
+/// another journaled operation.
+pub fn run<T>(dir: &Path, op: &Operation, apply: impl FnOnce() -> Result<T>) -> Result<T> {
+    let _lock = lock(dir)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    append(
+        dir,
+        &Record {
+            id,
+            intent: Some(op.clone()),
+            done: false,
+        },
+    )?;
+    let result = apply();
+    append(
+        dir,
+        &Record {
+            id,
+            intent: None,
+            done: true,
+        },
+    )?;
+    result
+}
+
+/// Roll forward the operations of the journal that were not marked done,
+/// then clear the journal. Must run before anything else uses the library.
+pub fn recover(dir: &Path) -> Result<Recovery> {
+    let path = dir.join(JOURNAL_FILE);
+    let mut recovery = Recovery::default();
+    if !path.exists() {
+        return Ok(recovery);
+    }
+    let _lock = lock(dir)?;
+    // Another process may have replayed it while we waited
+    if !path.exists() {
+        return Ok(recovery);
+    }
+    let data = fs::read_to_string(&path).context("Impossible de lire le journal")?;
+    let mut pending: Vec<(u64, Operation)> = Vec::new();
+    let mut done: HashSet<u64> = HashSet::new();
+    // A torn last line (crash while appending) is ignored
+    for record in data
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Record>(l).ok())
+    {
+        match record.intent {
+            Some(op) => pending.push((record.id, op)),
+            None if record.done => {
+                done.insert(record.id);
+            }
+            None => {}
+        }
+    }
+    pending.retain(|(id, _)| !done.contains(id));
+
+    if !pending.is_empty() {
+        let mut metadata = Metadata::load(dir)?;
+        for (_, op) in &pending {
+            match roll_forward(dir, op, &mut metadata) {
+                Ok(()) => recovery.completed += 1,
+                Err(e) => recovery.conflicts.push(e.to_string()),
+            }
+        }
+        metadata.save(dir)?;
+    }
+    fs::remove_file(&path)?;
+    Ok(recovery)
+}
+
+fn roll_forward(dir: &Path, op: &Operation, metadata: &mut Metadata) -> Result<()> {
+    match op {
+        Operation::Move { from, to } => {
+            let (src, dest) = (dir.join(from), dir.join(to));
+            match (src.exists(), dest.exists()) {
+                (true, false) => {
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::rename(&src, &dest)?;
+                }
+                // The rename happened, only the bookkeeping is missing
+                (false, true) => {}
+                (true, true) => {
+                    anyhow::bail!("Déplacement {from} → {to} : les deux fichiers existent")
+                }
+                (false, false) => {
+                    anyhow::bail!("Déplacement {from} → {to} : fichier introuvable")
+                }
+            }
+            thumb::invalidate_thumb(dir, from);
+            metadata.rename_file(from, to);
+        }
+        Operation::Delete { path } => {
+            let full = dir.join(path);
+            if full.exists() {
+                fs::remove_file(&full)?;
+            }
+            thumb::invalidate_thumb(dir, path);
+            metadata.forget_file(path);
+        }
+        Operation::Trash { path, id } => {
+            trash::put(dir, path, id, metadata)?;
+        }
+    }
+    Ok(())
+}
+
+/// `recover`, reporting what was done on the terminal.
+pub fn recover_and_report(dir: &Path) -> Result<()> {
+    let recovery = recover(dir)?;
+    if recovery.completed > 0 {
+        println!(
+            "  {} Journal : {} opération(s) interrompue(s) terminée(s)",
+            style("✔").green().bold(),
+            recovery.completed
+        );
+    }
+    for conflict in &recovery.conflicts {
+        eprintln!("  {} Journal : {conflict}", style("!").yellow().bold());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicU32;
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_journal_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Journal an operation without running it, as if the process died.
+    fn interrupted(dir: &Path, op: Operation) {
+        append(
+            dir,
+            &Record {
+                id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+                intent: Some(op),
+                done: false,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn completed_operations_are_not_replayed() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "a").unwrap();
+        let op = Operation::Delete {
+            path: "2020/a.jpg".into(),
+        };
+        run(&tmp, &op, || Ok(())).unwrap();
+        let failed: Result<()> = run(&tmp, &op, || anyhow::bail!("échec"));
+        assert!(failed.is_err());
+
+        assert_eq!(recover(&tmp).unwrap(), Recovery::default());
+        assert!(tmp.join("2020/a.jpg").exists());
+        assert!(!tmp.join(JOURNAL_FILE).exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn recover_waits_for_running_operations() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "a").unwrap();
+        let (started, wait) = std::sync::mpsc::channel();
+        let running = {
+            let tmp = tmp.clone();
+            std::thread::spawn(move || {
+                let op = Operation::Delete {
+                    path: "2020/a.jpg".into(),
+                };
+                run(&tmp, &op, || {
+                    started.send(()).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(200));
+                    Ok(fs::remove_file(tmp.join("2020/a.jpg"))?)
+                })
+            })
+        };
+        wait.recv().unwrap();
+
+        // Not replayed under the running operation's feet
+        assert_eq!(recover(&tmp).unwrap(), Recovery::default());
+        running.join().unwrap().unwrap();
+        assert!(!tmp.join(JOURNAL_FILE).exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn interrupted_move_is_rolled_forward() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "a").unwrap();
+        fs::write(tmp.join("2020/b.jpg"), "b").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.add_tag("2020/b.jpg", "noel");
+        meta.save(&tmp).unwrap();
+
+        // Died before the rename, and after the rename but before the metadata
+        interrupted(
+            &tmp,
+            Operation::Move {
+                from: "2020/a.jpg".into(),
+                to: "2021/a.jpg".into(),
+            },
+        );
+        fs::create_dir_all(tmp.join("2019")).unwrap();
+        fs::rename(tmp.join("2020/b.jpg"), tmp.join("2019/b.jpg")).unwrap();
+        interrupted(
+            &tmp,
+            Operation::Move {
+                from: "2020/b.jpg".into(),
+                to: "2019/b.jpg".into(),
+            },
+        );
+
+        let recovery = recover(&tmp).unwrap();
+        assert_eq!(recovery.completed, 2);
+        assert!(tmp.join("2021/a.jpg").exists());
+        assert!(!tmp.join("2020/a.jpg").exists());
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2021/a.jpg"), ["vacances"]);
+        assert_eq!(meta.get_tags("2019/b.jpg"), ["noel"]);
+        assert!(meta.get_tags("2020/a.jpg").is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn interrupted_delete_is_completed_and_conflicts_reported() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "a").unwrap();
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.jpg", Some(3));
+        meta.save(&tmp).unwrap();
+
+        interrupted(
+            &tmp,
+            Operation::Delete {
+                path: "2020/a.jpg".into(),
+            },
+        );
+        interrupted(
+            &tmp,
+            Operation::Move {
+                from: "2020/missing.jpg".into(),
+                to: "2021/missing.jpg".into(),
+            },
+        );
+
+        let recovery = recover(&tmp).unwrap();
+        assert_eq!(recovery.completed, 1);
+        assert_eq!(recovery.conflicts.len(), 1);
+        assert!(!tmp.join("2020/a.jpg").exists());
+        assert_eq!(Metadata::load(&tmp).unwrap().get_rating("2020/a.jpg"), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn interrupted_trashing_keeps_the_metadata_in_the_trash() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), "a").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.save(&tmp).unwrap();
+
+        interrupted(
+            &tmp,
+            Operation::Trash {
+                path: "2020/a.jpg".into(),
+                id: "20200101-120000".into(),
+            },
+        );
+
+        assert_eq!(recover(&tmp).unwrap().completed, 1);
+        assert!(!tmp.join("2020/a.jpg").exists());
+        let mut meta = Metadata::load(&tmp).unwrap();
+        assert!(meta.get_tags("2020/a.jpg").is_empty());
+        trash::restore(&tmp, "20200101-120000", &mut meta).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["vacances"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// A stream without news gets a comment this often: it finds out when the
+/// browser went away, and proxies don't close it.
+const KEEPALIVE: Duration = Duration::from_secs(20);
+
+/// A change of the library, pushed to the galleries open on `/api/events` so
+/// that several people curating at once see each other's work.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Change {
+    /// A photo arrived in the library (approved from the inbox…).
+    Added {
+        path: String,
+    },
+    Removed {
+        path: String,
+    },
+    Moved {
+        from: String,
+        to: String,
+    },
+    /// Its pixels changed (rotation): its images are to fetch again.
+    Rotated {
+        path: String,
+    },
+    /// Tags, ratings… saved, the metadata now at revision `rev`.
+    Metadata {
+        rev: String,
+    },
+}
+
+impl Change {
+    fn name(&self) -> &'static str {
+        match self {
+            Change::Added { .. } => "added",
+            Change::Removed { .. } => "removed",
+            Change::Moved { .. } => "moved",
+            Change::Rotated { .. } => "rotated",
+            Change::Metadata { .. } => "metadata",
+        }
+    }
+
+    /// The change as a Server-Sent Events message.
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string());
+        format!("event: {}\ndata: {data}\n\n", self.name())
+    }
+}
+
+/// The event streams open, each fed through its own channel.
+#[derive(Default)]
+pub struct Broadcaster {
+    subscribers: Mutex<Vec<Sender<Change>>>,
+}
+
+impl Broadcaster {
+    pub fn subscribe(&self) -> Receiver<Change> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// End every open stream: the server is stopping.
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+
+    /// Send `change` to every open stream, forgetting the closed ones.
+    pub fn publish(&self, change: Change) {
+        (self.subscribers.lock().unwrap()).retain(|tx| tx.send(change.clone()).is_ok());
+    }
+}
+
+/// Write the changes received to `out` as they come, until the browser goes
+/// away or the broadcaster does.
+pub fn stream(out: &mut dyn Write, changes: &Receiver<Change>) {
+    // Reconnecting browsers wait a little rather than hammer the server
+    if out
+        .write_all(b"retry: 3000\n\n")
+        .and_then(|()| out.flush())
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let message = match changes.recv_timeout(KEEPALIVE) {
+            Ok(change) => change.to_sse(),
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if out
+            .write_all(message.as_bytes())
+            .and_then(|()| out.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_become_sse_messages() {
+        let change = Change::Moved {
+            from: "2020/a.jpg".to_string(),
+            to: "2021/a.jpg".to_string(),
+        };
+        assert_eq!(
+            change.to_sse(),
+            "event: moved\ndata: {\"from\":\"2020/a.jpg\",\"to\":\"2021/a.jpg\"}\n\n"
+        );
+        let change = Change::Metadata {
+            rev: "abc".to_string(),
+        };
+        assert_eq!(
+            change.to_sse(),
+            "event: metadata\ndata: {\"rev\":\"abc\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn closed_streams_are_forgotten() {
+        let broadcaster = Broadcaster::default();
+        let open = broadcaster.subscribe();
+        drop(broadcaster.subscribe());
+        let removed = Change::Removed {
+            path: "2020/a.jpg".to_string(),
+        };
+        broadcaster.publish(removed.clone());
+        assert_eq!(open.try_recv().unwrap(), removed);
+        assert_eq!(broadcaster.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn stream_writes_changes_until_the_broadcaster_is_gone() {
+        let broadcaster = Broadcaster::default();
+        let changes = broadcaster.subscribe();
+        broadcaster.publish(Change::Added {
+            path: "2020/b.jpg".to_string(),
+        });
+        drop(broadcaster);
+        let mut out = Vec::new();
+        stream(&mut out, &changes);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "retry: 3000\n\nevent: added\ndata: {\"path\":\"2020/b.jpg\"}\n\n"
+        );
+    }
+}
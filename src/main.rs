@@ -1,9 +1,33 @@
+mod apple;
+mod catalog;
+mod config;
+mod develop;
+mod duplicates;
+mod events;
 mod export;
+mod feed;
 mod gallery;
+#[cfg(feature = "heif")]
+mod heif;
+mod i18n;
+mod inbox;
+mod init;
+mod journal;
+mod live;
 mod metadata;
+mod multipart;
+mod origins;
+mod photoignore;
+mod prefs;
+mod query;
+mod redate;
 mod serve;
+mod snapshot;
 mod sort;
+mod takeout;
 mod thumb;
+mod trash;
+mod xmp;
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
@@ -15,10 +39,23 @@ use std::path::PathBuf;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Extensions reconnues comme photos, à la place de la liste par défaut
+    /// (ex: jpg,png,webp)
+    #[arg(long, global = true, value_delimiter = ',', conflicts_with = "also_ext")]
+    extensions: Vec<String>,
+    /// Extensions à ajouter à la liste par défaut (ex: webp,gif)
+    #[arg(long, global = true, value_delimiter = ',')]
+    also_ext: Vec<String>,
+    /// Refuser des metadata incorrectes (note hors de 1 à 5, position
+    /// invalide…) au lieu de les corriger
+    #[arg(long, global = true)]
+    strict: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Configurer photo-sort pas à pas (sources, photothèque, serveur…)
+    Init,
     /// Trier les photos par année
     Sort {
         /// Chemin du dossier source contenant les photos
@@ -26,6 +63,46 @@ enum Commands {
         /// Dossier de sortie (par défaut : <source>_sorted/)
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Conserver le nom d'origine (ex: 2020-07-15_14-30-22__IMG_1234.jpg)
+        #[arg(long)]
+        keep_name: bool,
+        /// Appliquer l'orientation EXIF aux pixels des JPEG/PNG copiés
+        #[arg(long)]
+        auto_orient: bool,
+        /// Paires RAW+JPEG : ne garder que ce format dans le dossier de l'année
+        /// (l'autre va dans <année>/raw/)
+        #[arg(long, value_enum)]
+        pair_prefer: Option<sort::PairPreference>,
+        /// Copier les fichiers illisibles dans _quarantine/ au lieu de les
+        /// dater par le système de fichiers
+        #[arg(long)]
+        quarantine: bool,
+        /// Ne copier que les photos datées de ce jour ou après (AAAA-MM-JJ)
+        #[arg(long)]
+        from: Option<chrono::NaiveDate>,
+        /// Ne copier que les photos datées de ce jour ou avant (AAAA-MM-JJ)
+        #[arg(long)]
+        to: Option<chrono::NaiveDate>,
+        /// Dater les fichiers copiés (date de modification) à leur date de prise de vue
+        #[arg(long)]
+        touch: bool,
+        /// Fuseau horaire des dates (dossiers et noms) : local, UTC, +02:00
+        /// ou Europe/Paris. Les heures EXIF portant un décalage
+        /// (OffsetTimeOriginal) y sont converties
+        #[arg(long)]
+        timezone: Option<sort::Zone>,
+        /// Regrouper les photos par événement (2020/2020-07-12_event-03/) :
+        /// un nouvel événement commence après ce nombre d'heures sans photo
+        #[arg(long, value_name = "HEURES", value_parser = clap::value_parser!(u32).range(1..))]
+        group_events: Option<u32>,
+        /// Relire chaque copie et la comparer (BLAKE3) à la source ; une copie
+        /// différente est supprimée et le fichier n'est pas marqué traité
+        #[arg(long)]
+        verify_copy: bool,
+        /// Bibliothèques Apple Photos (.photoslibrary) : ajouter les albums
+        /// et mots-clés de chaque photo comme tags
+        #[arg(long)]
+        apple_albums: bool,
     },
     /// Ajouter ou retirer un tag sur un fichier
     Tag {
@@ -39,6 +116,35 @@ enum Commands {
         #[arg(short, long)]
         remove: bool,
     },
+    /// Identifier une personne sur un fichier (ou la retirer)
+    Person {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Nom de la personne
+        name: String,
+        /// Retirer la personne au lieu de l'ajouter
+        #[arg(short, long)]
+        remove: bool,
+    },
+    /// Lister les tags utilisés, du plus fréquent au plus rare
+    Tags {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Détailler le nombre de photos par année
+        #[arg(short, long)]
+        by_year: bool,
+        /// Sortie JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Gérer les alias de tags (xmas → noel), appliqués à l'ajout d'un tag
+    /// et dans les filtres
+    TagAlias {
+        #[command(subcommand)]
+        action: TagAliasAction,
+    },
     /// Noter un fichier (1-5)
     Rate {
         /// Dossier de sortie (contenant .photo_sort_metadata.json)
@@ -48,18 +154,194 @@ enum Commands {
         /// Note de 1 à 5 (0 pour supprimer)
         rating: u8,
     },
+    /// Marquer un fichier comme favori (indépendamment de sa note)
+    Favorite {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Retirer le fichier des favoris
+        #[arg(short, long)]
+        remove: bool,
+    },
+    /// Masquer un fichier (photo ratée) : il reste sur le disque mais
+    /// n'apparaît plus dans la galerie ni dans les exports
+    Hide {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Ne plus masquer le fichier
+        #[arg(short, long)]
+        remove: bool,
+    },
+    /// Légender un fichier (texte vide pour supprimer la légende)
+    Caption {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Légende
+        text: String,
+    },
+    /// Fixer à la main la date de prise de vue d'une photo (scan, appareil
+    /// mal réglé) ; elle est renommée et reclassée dans le dossier de l'année
+    Redate {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Date : 1987-06-01 08:15[:30], ou 1987-06-01 seul (midi)
+        #[arg(value_parser = redate::parse_datetime)]
+        date: chrono::NaiveDateTime,
+    },
+    /// Coordonnées GPS des photos (fixées à la main ou lues dans l'EXIF)
+    Geotag {
+        #[command(subcommand)]
+        action: GeotagAction,
+    },
+    /// Gérer les albums (sélections ordonnées de photos, indépendantes des tags)
+    Album {
+        #[command(subcommand)]
+        action: AlbumAction,
+    },
+    /// Corbeille des photos supprimées depuis la galerie
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Écrire la note et les tags dans le XMP des JPEG eux-mêmes (modifie
+    /// les fichiers)
+    Embed {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Chemins relatifs des fichiers (par défaut : tous les JPEG)
+        files: Vec<String>,
+    },
+    /// Importer notes, mots-clés et positions des sidecars XMP (a.cr2.xmp,
+    /// a.xmp) présents à côté des photos triées
+    XmpImport {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// En cas de désaccord : garder les metadata (ours), prendre le
+        /// sidecar (theirs) ou fusionner (merge : union des tags, note la
+        /// plus haute)
+        #[arg(long, value_enum, default_value = "merge")]
+        policy: xmp::ImportPolicy,
+    },
+    /// Importer mots-clés, notes, étiquettes de couleur et légendes d'un
+    /// catalogue Lightroom (.lrcat) ou d'une base digiKam (digikam4.db)
+    ImportCatalog {
+        /// Catalogue Lightroom ou base digiKam
+        catalog: PathBuf,
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// En cas de désaccord : garder les metadata (ours), prendre le
+        /// catalogue (theirs) ou fusionner (merge : union des tags, note la
+        /// plus haute)
+        #[arg(long, value_enum, default_value = "merge")]
+        policy: xmp::ImportPolicy,
+    },
+    /// Importer les métadonnées Google Photos (date, description, personnes,
+    /// position, favori) des sidecars .json d'un export Takeout
+    ImportTakeout {
+        /// Dossier de l'export Takeout décompressé
+        takeout: PathBuf,
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+    },
     /// Générer une galerie HTML avec lightbox et diaporama
     Gallery {
         /// Dossier de sortie contenant les photos triées
         dir: PathBuf,
+        /// Thème par défaut (auto : celui du système), tant que le visiteur
+        /// n'en a pas choisi un
+        #[arg(long, value_enum, default_value = "dark")]
+        theme: gallery::Theme,
+        /// Langue de l'interface
+        #[arg(long, value_enum, default_value = "fr")]
+        lang: i18n::Lang,
+        /// Un seul fichier gallery_standalone.html embarquant les miniatures,
+        /// lisible sans le dossier des photos (clé USB, pièce jointe)
+        #[arg(long)]
+        standalone: bool,
+        /// Avec --standalone, embarquer aussi un aperçu moyen de chaque photo
+        /// pour la lightbox
+        #[arg(long, requires = "standalone")]
+        previews: bool,
+        /// Embarquer un minuscule aperçu de chaque photo, affiché flouté le
+        /// temps que sa miniature se charge
+        #[arg(long, conflicts_with = "standalone")]
+        placeholders: bool,
+        /// Une page par année (gallery_<année>.html) et un index.html qui
+        /// les présente, pour les très grandes bibliothèques
+        #[arg(long, conflicts_with = "standalone")]
+        split_by_year: bool,
+        /// Écrire la liste des photos dans gallery_data.json, chargé par la
+        /// page (à ouvrir via un serveur web) et lisible par d'autres outils
+        #[arg(long, conflicts_with_all = ["standalone", "split_by_year"])]
+        data_json: bool,
+        /// Titre de la page (par défaut : photo-sort gallery)
+        #[arg(long)]
+        title: Option<String>,
+        /// Sous-titre affiché sous le titre
+        #[arg(long)]
+        subtitle: Option<String>,
+        /// Couleur d'accent à la place du bleu (#RRGGBB ou nom CSS)
+        #[arg(long, value_parser = gallery::parse_accent_color)]
+        accent_color: Option<String>,
+        /// Modèle HTML MiniJinja remplaçant celui intégré
+        #[arg(long)]
+        template: Option<PathBuf>,
+        /// Écrire un flux atom.xml des dernières photos triées, avec des liens
+        /// vers la galerie servie à cette adresse (ex: http://maison.local:8080)
+        #[arg(long, value_name = "URL", value_parser = feed::parse_base_url)]
+        feed: Option<String>,
+        /// Dossier où écrire la galerie et ses miniatures (par défaut : le
+        /// dossier des photos), pour la publier sans toucher aux photos
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
     /// Lancer la galerie dans le navigateur avec serveur local
     Serve {
         /// Dossier contenant les photos triées
         dir: PathBuf,
-        /// Port du serveur (par défaut : 8080)
-        #[arg(short, long, default_value_t = 8080)]
-        port: u16,
+        /// Adresse d'écoute (par défaut : celle de photo-sort.toml, sinon
+        /// 127.0.0.1, cette machine seulement ; 0.0.0.0 pour tout le réseau)
+        #[arg(long)]
+        host: Option<std::net::IpAddr>,
+        /// Port du serveur (par défaut : celui de photo-sort.toml, sinon 8080)
+        #[arg(short, long)]
+        port: Option<u16>,
+        /// Activer l'envoi de photos par des invités (lien /guest?token=<TOKEN>)
+        #[arg(long)]
+        guest_token: Option<String>,
+        /// Protéger la galerie par identifiant et mot de passe (HTTP Basic)
+        #[arg(long, value_name = "UTILISATEUR:MOT_DE_PASSE", value_parser = serve::BasicAuth::parse)]
+        auth: Option<serve::BasicAuth>,
+        /// Protéger la galerie par un jeton : en-tête Authorization: Bearer
+        /// <TOKEN> ou lien /?token=<TOKEN>
+        #[arg(long)]
+        token: Option<String>,
+        /// Développeur RAW externe pour la lightbox : darktable, rawtherapee
+        /// ou commande avec {input} et {output}
+        #[arg(long)]
+        raw_developer: Option<String>,
+        /// Langue de l'interface
+        #[arg(long, value_enum, default_value = "fr")]
+        lang: i18n::Lang,
+        /// Titre de la page (par défaut : photo-sort gallery)
+        #[arg(long)]
+        title: Option<String>,
+        /// Sous-titre affiché sous le titre
+        #[arg(long)]
+        subtitle: Option<String>,
+        /// Couleur d'accent à la place du bleu (#RRGGBB ou nom CSS)
+        #[arg(long, value_parser = gallery::parse_accent_color)]
+        accent_color: Option<String>,
+        /// Modèle HTML MiniJinja remplaçant celui intégré
+        #[arg(long)]
+        template: Option<PathBuf>,
     },
     /// Exporter les fichiers correspondant à un filtre
     Export {
@@ -73,9 +355,483 @@ enum Commands {
         /// Filtrer par note minimale (1-5)
         #[arg(short, long)]
         rating: Option<u8>,
+        /// Ne garder que les favoris
+        #[arg(short, long)]
+        favorites: bool,
+        /// Filtrer par album
+        #[arg(short, long)]
+        album: Option<String>,
+        /// Filtrer par requête (ex: "tag:vacances AND rating>=4 AND NOT tag:flou")
+        #[arg(short, long)]
+        query: Option<query::Query>,
+        /// Exporter aussi les photos masquées
+        #[arg(long)]
+        include_hidden: bool,
+    },
+    /// Lister les photos correspondant à une requête (toutes sans requête)
+    List {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Requête : tag:, person:, album:, caption:, year:2019..2021,
+        /// rating>=4, is:favorite, is:hidden, combinés par AND, OR, NOT et
+        /// parenthèses
+        query: Option<query::Query>,
+    },
+    /// Archiver une année dans un ZIP vérifié (photos, metadata, sommes de contrôle)
+    Snapshot {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Année à archiver
+        #[arg(short, long)]
+        year: String,
+        /// Fichier ZIP à créer
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+    /// Retrouver l'origine d'une photo triée (ou la destination d'un original)
+    Origin {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Chemin trié (2020/…jpg), chemin d'origine ou simple nom de fichier
+        file: String,
+    },
+    /// Vérifier l'intégrité des snapshots enregistrés
+    Verify {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+    },
+    /// Lire et mémoriser dans les metadata l'EXIF des photos triées (appareil,
+    /// objectif, dimensions, ISO, ouverture, vitesse, date de prise de vue) et
+    /// leur empreinte visuelle, qui repère les doublons dans la galerie
+    Scan {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Relire aussi les photos déjà lues
+        #[arg(long)]
+        force: bool,
+    },
+    /// Copies de sauvegarde des metadata
+    Metadata {
+        #[command(subcommand)]
+        action: MetadataAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetadataAction {
+    /// Lister les copies de sauvegarde, de la plus récente à la plus ancienne
+    Backups {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+    },
+    /// Remettre une copie de sauvegarde (la plus récente par défaut) ; les
+    /// metadata actuelles sont d'abord sauvegardées
+    Restore {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Copie à remettre (nom du fichier ou horodatage, ex: 20200715-143022)
+        backup: Option<String>,
+    },
+    /// Exporter les metadata en CSV (une ligne par photo : chemin, année,
+    /// tags, note, légende, GPS…), en TSV si le fichier finit par .tsv
+    ExportCsv {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Fichier CSV (ou TSV) à écrire
+        out: PathBuf,
+        /// Ne garder que les photos correspondant à une requête
+        #[arg(short, long)]
+        query: Option<query::Query>,
+    },
+    /// Annuler la dernière modification des metadata (commande, galerie…) ;
+    /// à répéter pour remonter l'historique
+    Undo {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+    },
+    /// Répartir les metadata dans un fichier par année
+    /// (2020/.photo_sort_metadata.json), pour les très grandes photothèques
+    Shard {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Revenir à un seul fichier
+        #[arg(long)]
+        off: bool,
     },
 }
 
+fn run_metadata(action: MetadataAction) -> Result<()> {
+    match action {
+        MetadataAction::Backups { dir } => {
+            let backups = metadata::backup::list(&dir)?;
+            if backups.is_empty() {
+                println!("Aucune sauvegarde");
+            }
+            for backup in &backups {
+                let meta = metadata::backup::read(&dir, backup);
+                let files = meta.map_or_else(
+                    |_| "illisible".to_string(),
+                    |m| format!("{} fichier(s)", m.files.len()),
+                );
+                println!("{}  {}  ({files})", backup.created, backup.name);
+            }
+            Ok(())
+        }
+        MetadataAction::Restore { dir, backup } => {
+            let restored = metadata::Metadata::restore(&dir, backup.as_deref())?;
+            println!("Metadata restaurées depuis {}", restored.name);
+            Ok(())
+        }
+        MetadataAction::ExportCsv { dir, out, query } => {
+            export::run_export_csv(&dir, &out, query.as_ref())
+        }
+        MetadataAction::Undo { dir } => {
+            match metadata::Metadata::load(&dir)?.undo(&dir)? {
+                Some(entry) => println!(
+                    "Modification du {} annulée ({} fichier(s), {} album(s))",
+                    entry.time.format("%Y-%m-%d %H:%M:%S"),
+                    entry.files.len(),
+                    entry.albums.len()
+                ),
+                None => println!("Rien à annuler"),
+            }
+            Ok(())
+        }
+        MetadataAction::Shard { dir, off } => {
+            let shards = metadata::shard::set_sharded(&dir, !off)?;
+            if off {
+                println!("Metadata regroupées dans un seul fichier");
+            } else {
+                println!("Metadata réparties en {shards} fichier(s) par dossier");
+            }
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum AlbumAction {
+    /// Créer un album vide
+    Create {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Nom de l'album
+        name: String,
+    },
+    /// Ajouter des fichiers à la fin d'un album, dans l'ordre donné
+    Add {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Nom de l'album
+        name: String,
+        /// Chemins relatifs des fichiers (ex: 2008/2008-07-15_14-30-22.jpg)
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Retirer des fichiers d'un album, ou supprimer l'album si aucun
+    /// fichier n'est donné (les photos sont conservées)
+    Remove {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Nom de l'album
+        name: String,
+        /// Chemins relatifs des fichiers à retirer
+        files: Vec<String>,
+    },
+    /// Lister les albums, ou le contenu d'un album
+    List {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Nom de l'album à détailler
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagAliasAction {
+    /// Faire d'un tag l'alias d'un autre et renommer les tags déjà posés
+    /// (un alias ne différant que par la casse ou les accents fixe
+    /// l'orthographe)
+    Add {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Alias (ex: xmas)
+        alias: String,
+        /// Tag désigné (ex: noel)
+        tag: String,
+    },
+    /// Supprimer un alias (les tags déjà renommés restent)
+    Remove {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Alias à supprimer
+        alias: String,
+    },
+    /// Lister les alias
+    List {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum GeotagAction {
+    /// Fixer la position d'un fichier (degrés décimaux, sud et ouest négatifs)
+    #[command(allow_negative_numbers = true)]
+    Set {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+        /// Latitude (ex: 48.8584)
+        lat: f64,
+        /// Longitude (ex: 2.2945)
+        lon: f64,
+    },
+    /// Supprimer la position d'un fichier
+    Clear {
+        /// Dossier de sortie (contenant .photo_sort_metadata.json)
+        dir: PathBuf,
+        /// Chemin relatif du fichier
+        file: String,
+    },
+    /// Lire les coordonnées GPS EXIF des photos triées qui n'ont pas encore
+    /// de position
+    Scan {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashAction {
+    /// Lister les photos de la corbeille, les plus récentes d'abord
+    List {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+    },
+    /// Remettre une photo à sa place, avec ses tags, sa note et ses albums
+    Restore {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Identifiant de la photo dans la corbeille (voir `trash list`)
+        id: String,
+    },
+    /// Supprimer définitivement les photos de la corbeille
+    Empty {
+        /// Dossier contenant les photos triées
+        dir: PathBuf,
+        /// Garder les photos supprimées depuis moins de JOURS jours
+        #[arg(long, value_name = "JOURS")]
+        older_than: Option<u32>,
+    },
+}
+
+fn run_trash(action: TrashAction) -> Result<()> {
+    match action {
+        TrashAction::List { dir } => trash::run_list(&dir),
+        TrashAction::Restore { dir, id } => trash::run_restore(&dir, &id),
+        TrashAction::Empty { dir, older_than } => trash::run_empty(&dir, older_than),
+    }
+}
+
+fn run_geotag(action: GeotagAction) -> Result<()> {
+    match action {
+        GeotagAction::Set {
+            dir,
+            file,
+            lat,
+            lon,
+        } => {
+            if !metadata::is_valid_position(lat, lon) {
+                anyhow::bail!("Position invalide : latitude entre -90 et 90, longitude entre -180 et 180");
+            }
+            let mut meta = metadata::Metadata::load(&dir)?;
+            meta.set_position(&file, Some((lat, lon)));
+            println!("Position {lat}, {lon} attribuée à {file}");
+            meta.save(&dir)
+        }
+        GeotagAction::Clear { dir, file } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            meta.set_position(&file, None);
+            println!("Position supprimée pour {file}");
+            meta.save(&dir)
+        }
+        GeotagAction::Scan { dir } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            let mut located = 0usize;
+            for file in gallery::collect_photos(&dir).values().flatten() {
+                if meta.get_position(file).is_some() {
+                    continue;
+                }
+                if let Some(position) = sort::exif_gps(&dir.join(file)) {
+                    meta.set_position(file, Some(position));
+                    located += 1;
+                }
+            }
+            println!("{located} photo(s) géolocalisée(s) depuis l'EXIF");
+            meta.save(&dir)
+        }
+    }
+}
+
+fn run_scan(dir: &std::path::Path, force: bool) -> Result<()> {
+    let mut meta = metadata::Metadata::load(dir)?;
+    let photos = gallery::collect_photos(dir);
+    let read = gallery::cache_exif(dir, &photos, &mut meta, force);
+    let dated = gallery::cache_capture_times(dir, &photos, &mut meta);
+    let rels: Vec<String> = photos.values().flatten().cloned().collect();
+    thumb::prewarm_thumbnails(dir, &rels);
+    let hashed = duplicates::cache_visual_hashes(dir, &photos, &mut meta);
+    println!(
+        "EXIF lu pour {read} photo(s), {dated} date(s) de prise de vue et {hashed} empreinte(s) visuelle(s) ajoutée(s)"
+    );
+    meta.save(dir)
+}
+
+fn run_tags(dir: &std::path::Path, by_year: bool, json: bool) -> Result<()> {
+    let mut counts = metadata::Metadata::load(dir)?.tag_counts();
+    if !by_year {
+        for count in &mut counts {
+            count.years.clear();
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&counts)?);
+        return Ok(());
+    }
+    if counts.is_empty() {
+        println!("Aucun tag");
+    }
+    for count in &counts {
+        let years: Vec<String> = count
+            .years
+            .iter()
+            .map(|(year, n)| format!("{year}: {n}"))
+            .collect();
+        if years.is_empty() {
+            println!("{:>6}  {}", count.count, count.tag);
+        } else {
+            println!("{:>6}  {}  ({})", count.count, count.tag, years.join(", "));
+        }
+    }
+    Ok(())
+}
+
+fn run_list(dir: &std::path::Path, query: Option<&query::Query>) -> Result<()> {
+    let meta = metadata::Metadata::load(dir)?;
+    let mut files: Vec<String> = gallery::collect_photos(dir).into_values().flatten().collect();
+    files.sort();
+    for file in files
+        .iter()
+        .filter(|f| query.is_none_or(|q| q.matches(&meta, f)))
+    {
+        println!("{file}");
+    }
+    Ok(())
+}
+
+fn run_album(action: AlbumAction) -> Result<()> {
+    match action {
+        AlbumAction::Create { dir, name } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            if !meta.create_album(&name) {
+                anyhow::bail!("L'album «{name}» existe déjà");
+            }
+            println!("Album «{name}» créé");
+            meta.save(&dir)
+        }
+        AlbumAction::Add { dir, name, files } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            if !meta.albums.contains_key(&name) {
+                anyhow::bail!("Album inconnu : {name} (créez-le avec photo-sort album create)");
+            }
+            for file in &files {
+                if !dir.join(file).is_file() {
+                    anyhow::bail!("Fichier introuvable : {file}");
+                }
+            }
+            for file in &files {
+                meta.add_to_album(&name, file);
+            }
+            println!("{} fichier(s) ajouté(s) à l'album «{name}»", files.len());
+            meta.save(&dir)
+        }
+        AlbumAction::Remove { dir, name, files } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            if !meta.albums.contains_key(&name) {
+                anyhow::bail!("Album inconnu : {name}");
+            }
+            if files.is_empty() {
+                meta.delete_album(&name);
+                println!("Album «{name}» supprimé");
+            } else {
+                for file in &files {
+                    meta.remove_from_album(&name, file);
+                }
+                println!("{} fichier(s) retiré(s) de l'album «{name}»", files.len());
+            }
+            meta.save(&dir)
+        }
+        AlbumAction::List { dir, name } => {
+            let meta = metadata::Metadata::load(&dir)?;
+            match name {
+                Some(name) => {
+                    let files = meta
+                        .albums
+                        .get(&name)
+                        .with_context(|| format!("Album inconnu : {name}"))?;
+                    for file in files {
+                        println!("{file}");
+                    }
+                }
+                None if meta.albums.is_empty() => println!("Aucun album"),
+                None => {
+                    for (name, files) in &meta.albums {
+                        println!("{name} ({} photo(s))", files.len());
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn run_tag_alias(action: TagAliasAction) -> Result<()> {
+    match action {
+        TagAliasAction::Add { dir, alias, tag } => {
+            if tag.trim().is_empty() {
+                anyhow::bail!("Tag vide");
+            }
+            let mut meta = metadata::Metadata::load(&dir)?;
+            let changed = meta.set_tag_alias(&alias, &tag);
+            println!(
+                "«{alias}» → «{}» ({changed} fichier(s) mis à jour)",
+                meta.canonical_tag(&alias)
+            );
+            meta.save(&dir)
+        }
+        TagAliasAction::Remove { dir, alias } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            if !meta.remove_tag_alias(&alias) {
+                anyhow::bail!("Alias inconnu : {alias}");
+            }
+            println!("Alias «{alias}» supprimé");
+            meta.save(&dir)
+        }
+        TagAliasAction::List { dir } => {
+            let meta = metadata::Metadata::load(&dir)?;
+            if meta.tag_aliases.is_empty() {
+                println!("Aucun alias");
+            }
+            for (alias, tag) in &meta.tag_aliases {
+                println!("{alias} → {tag}");
+            }
+            Ok(())
+        }
+    }
+}
+
 fn resolve_output_dir(source: &std::path::Path, output: Option<PathBuf>) -> Result<PathBuf> {
     let source = source
         .canonicalize()
@@ -104,14 +860,51 @@ fn resolve_output_dir(source: &std::path::Path, output: Option<PathBuf>) -> Resu
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(list) = sort::photo_extensions_from(&cli.extensions, &cli.also_ext) {
+        sort::set_photo_extensions(list);
+    }
+    metadata::validate::set_strict(cli.strict);
 
     match cli.command {
-        Commands::Sort { source, output } => {
+        Commands::Init => init::run_init(),
+        Commands::Sort {
+            source,
+            output,
+            keep_name,
+            auto_orient,
+            pair_prefer,
+            quarantine,
+            from,
+            to,
+            touch,
+            timezone,
+            group_events,
+            verify_copy,
+            apple_albums,
+        } => {
             let source = source
                 .canonicalize()
                 .with_context(|| format!("Dossier source introuvable : {}", source.display()))?;
+            if let (Some(from), Some(to)) = (from, to)
+                && from > to
+            {
+                anyhow::bail!("--from ({from}) est postérieur à --to ({to})");
+            }
             let output_dir = resolve_output_dir(&source, output)?;
-            sort::run_sort(&source, &output_dir)
+            let options = sort::SortOptions {
+                keep_name,
+                auto_orient,
+                pair_prefer,
+                quarantine,
+                from,
+                to,
+                touch,
+                timezone,
+                group_events,
+                verify_copy,
+                apple_albums,
+            };
+            sort::run_sort(&source, &output_dir, &options)
         }
         Commands::Tag {
             dir,
@@ -129,6 +922,26 @@ fn main() -> Result<()> {
             }
             meta.save(&dir)
         }
+        Commands::Person {
+            dir,
+            file,
+            name,
+            remove,
+        } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            if remove {
+                meta.remove_person(&file, &name);
+                println!("{name} retiré(e) de {file}");
+            } else {
+                meta.add_person(&file, &name);
+                println!("{name} ajouté(e) à {file}");
+            }
+            let people = meta.get_people(&file);
+            if !people.is_empty() {
+                println!("Personnes : {}", people.join(", "));
+            }
+            meta.save(&dir)
+        }
         Commands::Rate { dir, file, rating } => {
             if rating > 5 {
                 anyhow::bail!("La note doit être entre 0 et 5");
@@ -143,13 +956,150 @@ fn main() -> Result<()> {
             }
             meta.save(&dir)
         }
-        Commands::Gallery { dir } => gallery::run_gallery(&dir),
-        Commands::Serve { dir, port } => serve::run_serve(&dir, port),
+        Commands::Favorite { dir, file, remove } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            meta.set_favorite(&file, !remove);
+            if remove {
+                println!("{file} retiré des favoris");
+            } else {
+                println!("{file} ajouté aux favoris");
+            }
+            meta.save(&dir)
+        }
+        Commands::Hide { dir, file, remove } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            meta.set_hidden(&file, !remove);
+            if remove {
+                println!("{file} n'est plus masqué");
+            } else {
+                println!("{file} masqué");
+            }
+            meta.save(&dir)
+        }
+        Commands::Redate { dir, file, date } => redate::run_redate(&dir, &file, date),
+        Commands::Caption { dir, file, text } => {
+            let mut meta = metadata::Metadata::load(&dir)?;
+            meta.set_caption(&file, &text);
+            match meta.get_caption(&file) {
+                Some(caption) => println!("Légende de {file} : «{caption}»"),
+                None => println!("Légende supprimée pour {file}"),
+            }
+            meta.save(&dir)
+        }
+        Commands::Tags { dir, by_year, json } => run_tags(&dir, by_year, json),
+        Commands::TagAlias { action } => run_tag_alias(action),
+        Commands::Album { action } => run_album(action),
+        Commands::Geotag { action } => run_geotag(action),
+        Commands::Trash { action } => run_trash(action),
+        Commands::Embed { dir, files } => xmp::run_embed(&dir, &files),
+        Commands::XmpImport { dir, policy } => xmp::run_xmp_import(&dir, policy),
+        Commands::ImportCatalog {
+            catalog,
+            dir,
+            policy,
+        } => catalog::run_import_catalog(&catalog, &dir, policy),
+        Commands::ImportTakeout { takeout, dir } => takeout::run_import_takeout(&takeout, &dir),
+        Commands::Gallery {
+            dir,
+            theme,
+            lang,
+            standalone,
+            previews,
+            placeholders,
+            split_by_year,
+            data_json,
+            title,
+            subtitle,
+            accent_color,
+            template,
+            feed,
+            output,
+        } => gallery::run_gallery(
+            &dir,
+            &gallery::GalleryOptions {
+                theme,
+                lang,
+                standalone,
+                previews,
+                placeholders,
+                split_by_year,
+                data_file: data_json,
+                title,
+                subtitle,
+                accent_color,
+                template,
+                feed,
+                output,
+                ..Default::default()
+            },
+        ),
+        Commands::Serve {
+            dir,
+            host,
+            port,
+            guest_token,
+            auth,
+            token,
+            raw_developer,
+            lang,
+            title,
+            subtitle,
+            accent_color,
+            template,
+        } => {
+            let raw_developer = raw_developer
+                .as_deref()
+                .map(develop::Developer::parse)
+                .transpose()?;
+            // Flags win over the settings saved by `photo-sort init`
+            let server = config::Config::load(&dir)?.unwrap_or_default().server;
+            serve::run_serve(
+                &dir,
+                host.unwrap_or(server.host),
+                port.unwrap_or(server.port),
+                serve::ServeOptions {
+                    guest_token: guest_token.or(server.guest_token),
+                    auth,
+                    token,
+                    raw_developer,
+                    gallery: gallery::GalleryOptions {
+                        lang,
+                        title,
+                        subtitle,
+                        accent_color,
+                        template,
+                        served: true,
+                        ..Default::default()
+                    },
+                },
+            )
+        }
         Commands::Export {
             dir,
             dest,
             tag,
             rating,
-        } => export::run_export(&dir, &dest, tag.as_deref(), rating),
+            favorites,
+            album,
+            query,
+            include_hidden,
+        } => export::run_export(
+            &dir,
+            &dest,
+            &export::ExportOptions {
+                tag,
+                rating,
+                favorites,
+                album,
+                query,
+                include_hidden,
+            },
+        ),
+        Commands::List { dir, query } => run_list(&dir, query.as_ref()),
+        Commands::Snapshot { dir, year, out } => snapshot::run_snapshot(&dir, &year, &out),
+        Commands::Origin { dir, file } => origins::run_origin(&dir, &file),
+        Commands::Verify { dir } => snapshot::run_verify(&dir),
+        Commands::Scan { dir, force } => run_scan(&dir, force),
+        Commands::Metadata { action } => run_metadata(action),
     }
 }
@@ -1,62 +1,758 @@
 use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::path::Path;
 
+pub mod backup;
+pub mod history;
+pub mod shard;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod validate;
+
 const METADATA_FILE: &str = ".photo_sort_metadata.json";
 
+/// Advisory lock taken while the metadata is saved, shared by the CLI and
+/// the server.
+const LOCK_FILE: &str = ".photo_sort_metadata.lock";
+
+/// Days the entry of a file that vanished is kept, in case the file comes
+/// back (moved out of the library and back, renamed…), before it is dropped.
+const MISSING_DAYS: i64 = 30;
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub struct FileInfo {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tags: Vec<String>,
+    /// People in the photo, kept apart from the topical tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub people: Vec<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rating: Option<u8>,
+    /// Capture time read from EXIF, cached so the gallery can group bursts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taken: Option<NaiveDateTime>,
+    /// The EXIF was read and holds no capture time, so it isn't read again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_exif_date: bool,
+    /// Capture time set by hand (`redate`), for scans and cameras with a
+    /// wrong clock; it wins over `taken`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_override: Option<NaiveDateTime>,
+    /// Where `sort` found the date the file was filed under: `exif`,
+    /// `dirname` or `filesystem` (the last two being the doubtful ones).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_source: Option<String>,
+    /// Relative path of the RAW (or JPEG) companion shot with this file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pair: Option<String>,
+    /// Free text describing the photo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// GPS latitude in decimal degrees (south is negative).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    /// GPS longitude in decimal degrees (west is negative).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+    /// Marked as a favorite, independently of the rating.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub favorite: bool,
+    /// Hidden from the gallery and exports (a blurry or rejected shot) while
+    /// staying on disk.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+    /// Color label, one of `COLOR_LABELS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// BLAKE3 hash of the content, so the entry can follow the file when it
+    /// is renamed or moved outside the tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Difference hash of the thumbnail, in hexadecimal, cached so the
+    /// gallery can flag near-identical photos.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visual_hash: Option<String>,
+    /// Since when the file is gone from the library without its content
+    /// turning up elsewhere; the entry is dropped after `MISSING_DAYS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing_since: Option<NaiveDate>,
+    /// Camera and shot settings read from EXIF, cached at sort time or by
+    /// `scan`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exif: Option<ExifSummary>,
+}
+
+/// The EXIF essentials shown by the gallery, each `None` when not recorded.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct ExifSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens: Option<String>,
+    /// Pixel dimensions, as stored (before any EXIF rotation).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso: Option<u32>,
+    /// F-number (2.8 for f/2.8).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aperture: Option<f64>,
+    /// Exposure time as displayed by cameras (`1/250`, `2`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<String>,
+    /// Focal length in millimeters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focal_length: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct Metadata {
     pub files: HashMap<String, FileInfo>,
+    /// Albums: name → relative paths, in the order chosen by the user.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub albums: BTreeMap<String, Vec<String>>,
+    /// Tag aliases: folded alias (see `tag_key`) → tag it stands for, applied
+    /// when a tag is added.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tag_aliases: BTreeMap<String, String>,
+    /// Folders whose files are saved in a metadata file of their own
+    /// (`2020/.photo_sort_metadata.json`); `None` when all are saved here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shards: Option<BTreeSet<String>>,
+    /// Content as last loaded or saved, to tell our changes from those saved
+    /// meanwhile by another process.
+    #[serde(skip)]
+    base: Option<Box<Metadata>>,
+}
+
+/// Which side to keep for a conflicting field when merging metadata.
+/// `Both` is the union of the tags, or the higher rating.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pick {
+    #[default]
+    Current,
+    Incoming,
+    Both,
+}
+
+/// Per-file choices for `Metadata::merge`.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub struct Resolution {
+    #[serde(default)]
+    pub tags: Pick,
+    #[serde(default)]
+    pub rating: Pick,
+}
+
+/// A file whose tags and/or rating differ between two metadata sets.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Conflict {
+    pub file: String,
+    pub current: FileInfo,
+    pub incoming: FileInfo,
+    pub tags: bool,
+    pub rating: bool,
+}
+
+/// Changes to the metadata of one file, applied on top of what is saved
+/// instead of replacing it all: several people editing at once don't undo
+/// each other's work.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileChange {
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub add_people: Vec<String>,
+    pub remove_people: Vec<String>,
+    /// New rating, 0 removing it.
+    pub rating: Option<u8>,
+    pub favorite: Option<bool>,
+    pub hidden: Option<bool>,
+    /// New caption, empty removing it.
+    pub caption: Option<String>,
+    /// New color label, empty removing it.
+    pub label: Option<String>,
+}
+
+/// Color labels, as named in the metadata (Lightroom and digiKam colors).
+pub const COLOR_LABELS: &[&str] = &[
+    "red", "orange", "yellow", "green", "blue", "purple", "gray", "black", "white",
+];
+
+/// A tag with the number of photos carrying it, in total and per year.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub years: BTreeMap<String, usize>,
+}
+
+/// `true` for a usable position: within range, and not the (0, 0) some
+/// cameras record when they have no fix.
+pub fn is_valid_position(lat: f64, lon: f64) -> bool {
+    (-90.0..=90.0).contains(&lat)
+        && (-180.0..=180.0).contains(&lon)
+        && (lat, lon) != (0.0, 0.0)
+}
+
+/// Form under which tags are compared: trimmed, lowercase and without
+/// accents, so "Noël", "noel" and "NOEL" are one tag.
+pub fn tag_key(tag: &str) -> String {
+    tag.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ñ' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ý' | 'ÿ' => 'y',
+            c => c,
+        })
+        .collect()
+}
+
+fn same_tags(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().all(|t| b.contains(t))
+}
+
+/// Take the lock file of `dir`, exclusive to save or shared to load, released
+/// when the returned file is dropped. It also serializes threads of the same
+/// process, each opening its own handle.
+fn lock(dir: &Path, exclusive: bool) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))
+        .context("Impossible de créer le verrou metadata")?;
+    if exclusive {
+        file.lock()
+    } else {
+        file.lock_shared()
+    }
+    .context("Impossible de verrouiller les metadata")?;
+    Ok(file)
+}
+
+/// Write a metadata file aside then rename it: a crash leaves the old file or
+/// the new one, never a truncated one.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let written = (|| -> Result<()> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.context("Impossible de sauvegarder les metadata"));
+    }
+    Ok(())
+}
+
+/// Three-way merge of two maps; `both` resolves a key changed on both sides.
+fn merge_maps<V: Clone + PartialEq>(
+    base: BTreeMap<&String, &V>,
+    ours: BTreeMap<&String, &V>,
+    theirs: BTreeMap<&String, &V>,
+    both: impl Fn(Option<&V>, &V, &V) -> V,
+) -> Vec<(String, V)> {
+    let keys: BTreeSet<&String> = [&base, &ours, &theirs]
+        .into_iter()
+        .flat_map(|map| map.keys().copied())
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let [b, o, t] = [&base, &ours, &theirs].map(|map| map.get(key).copied());
+            let value = if o == b {
+                t.cloned()
+            } else if t == b || t == o {
+                o.cloned()
+            } else {
+                match (o, t) {
+                    (Some(o), Some(t)) => Some(both(b, o, t)),
+                    (o, t) => o.or(t).cloned(),
+                }
+            };
+            value.map(|v| (key.clone(), v))
+        })
+        .collect()
+}
+
+/// The values recorded on one side (before or after) of history changes.
+fn side<V>(
+    changes: &BTreeMap<String, history::Change<V>>,
+    pick: impl Fn(&history::Change<V>) -> Option<&V>,
+) -> BTreeMap<&String, &V> {
+    changes
+        .iter()
+        .filter_map(|(key, change)| Some((key, pick(change)?)))
+        .collect()
+}
+
+/// Merge a file entry changed on both sides: each field ours changed keeps
+/// our value, the others take theirs.
+fn merge_fields(base: Option<&FileInfo>, ours: &FileInfo, theirs: &FileInfo) -> FileInfo {
+    let fields = |info: &FileInfo| match serde_json::to_value(info) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let base = fields(base.unwrap_or(&FileInfo::default()));
+    let ours_fields = fields(ours);
+    let mut merged = fields(theirs);
+    for key in ours_fields.keys().chain(base.keys()) {
+        if ours_fields.get(key) != base.get(key) {
+            match ours_fields.get(key) {
+                Some(value) => merged.insert(key.clone(), value.clone()),
+                None => merged.remove(key),
+            };
+        }
+    }
+    serde_json::from_value(serde_json::Value::Object(merged)).unwrap_or_else(|_| ours.clone())
 }
 
 impl Metadata {
+    /// Load the metadata of a library, corrected where edited by hand (see
+    /// `validate`). With the `sqlite` feature, it lives in
+    /// `.photo_sort_metadata.db`, created from the JSON file on first use.
     pub fn load(dir: &Path) -> Result<Self> {
+        // Not to read a save half written; a read-only library goes without
+        let _lock = lock(dir, false).ok();
+        let mut meta = Self::load_stored(dir)?;
+        // Merged against as stored, so the next save keeps the corrections
+        meta.base = Some(Box::new(meta.content()));
+        validate::check(&mut meta)?;
+        Ok(meta)
+    }
+
+    /// Save the metadata under the lock file. If another process saved since
+    /// `self` was loaded, its changes are merged in first (ours win on the
+    /// fields both sides changed), so `self` ends up holding the saved state.
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        self.save_recorded(dir, None)
+    }
+
+    /// `save`, recording the changes in the history; `undoes` is the history
+    /// entry the changes revert.
+    fn save_recorded(&mut self, dir: &Path, undoes: Option<usize>) -> Result<()> {
+        let _lock = lock(dir, true)?;
+        let stored = Self::load_stored(dir)?;
+        let base = self.base.take().unwrap_or_default();
+        if stored.revision() != base.revision() {
+            let merged = Self::merge_three_way(&base, self, &stored);
+            self.files = merged.files;
+            self.albums = merged.albums;
+            self.tag_aliases = merged.tag_aliases;
+        }
+        // Saved in the layout found on disk
+        self.shards = stored.shards.clone();
+        backup::rotate(dir, &stored, false)?;
+        let saved = self
+            .store(dir)
+            .and_then(|()| history::record(dir, &stored, self, undoes));
+        self.base = Some(match saved {
+            Ok(()) => Box::new(self.content()),
+            Err(_) => base,
+        });
+        saved
+    }
+
+    /// Revert the latest save not undone yet and save the result. Fields
+    /// edited since on the same files are kept. Returns the undone entry,
+    /// `None` when the history has nothing left to undo.
+    pub fn undo(&mut self, dir: &Path) -> Result<Option<history::Entry>> {
+        let Some((index, entry)) = history::last_undoable(dir)? else {
+            return Ok(None);
+        };
+        // Three-way merge where the undone save is the common state: its
+        // changes are reverted, anything edited on top of it stays
+        let current: BTreeMap<&String, &FileInfo> = (entry.files.keys())
+            .filter_map(|f| Some((f, self.files.get(f)?)))
+            .collect();
+        let files = merge_maps(
+            side(&entry.files, |c| c.after.as_ref()),
+            side(&entry.files, |c| c.before.as_ref()),
+            current,
+            merge_fields,
+        );
+        let current: BTreeMap<&String, &Vec<String>> = (entry.albums.keys())
+            .filter_map(|a| Some((a, self.albums.get(a)?)))
+            .collect();
+        let albums = merge_maps(
+            side(&entry.albums, |c| c.after.as_ref()),
+            side(&entry.albums, |c| c.before.as_ref()),
+            current,
+            |_, ours, _| ours.clone(),
+        );
+        for file in entry.files.keys() {
+            self.files.remove(file);
+        }
+        for album in entry.albums.keys() {
+            self.albums.remove(album);
+        }
+        self.files.extend(files);
+        self.albums.extend(albums);
+        self.save_recorded(dir, Some(index))?;
+        Ok(Some(entry))
+    }
+
+    /// Put back a backup (the newest without `name`), backing up the current
+    /// metadata first so the restore can be undone. Returns the backup used.
+    pub fn restore(dir: &Path, name: Option<&str>) -> Result<backup::Backup> {
+        let backups = backup::list(dir)?;
+        let chosen = match name {
+            Some(name) => backups
+                .into_iter()
+                .find(|b| b.name == name || b.name.ends_with(&format!("bak-{name}")))
+                .with_context(|| format!("Sauvegarde introuvable : {name}"))?,
+            None => backups
+                .into_iter()
+                .next()
+                .context("Aucune sauvegarde des metadata")?,
+        };
+        let mut restored = backup::read(dir, &chosen)?;
+        let _lock = lock(dir, true)?;
+        let stored = Self::load_stored(dir)?;
+        restored.shards = stored.shards.clone();
+        backup::rotate(dir, &stored, true)?;
+        restored.store(dir)?;
+        history::record(dir, &stored, &restored, None)?;
+        Ok(chosen)
+    }
+
+    /// Replace the content with `new`, a full copy rebuilt by a client (the
+    /// gallery), keeping what it doesn't know: content hashes, EXIF date
+    /// markers, tag aliases and the state the next save merges against.
+    pub fn replace(&mut self, mut new: Metadata) {
+        new.keep_cached(self);
+        new.tag_aliases = std::mem::take(&mut self.tag_aliases);
+        new.shards = self.shards.take();
+        new.base = self.base.take();
+        *self = new;
+    }
+
+    fn load_stored(dir: &Path) -> Result<Self> {
+        #[cfg(feature = "sqlite")]
+        {
+            sqlite::load(dir)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            Self::load_json(dir)
+        }
+    }
+
+    fn store(&self, dir: &Path) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        {
+            sqlite::save(self, dir)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            self.save_json(dir)
+        }
+    }
+
+    /// `true` if it holds changes not saved yet (a save that failed…).
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.base
+            .as_ref()
+            .is_none_or(|base| base.revision() != self.revision())
+    }
+
+    /// Copy of the files, albums and aliases, without the saved state.
+    fn content(&self) -> Metadata {
+        Metadata {
+            files: self.files.clone(),
+            albums: self.albums.clone(),
+            tag_aliases: self.tag_aliases.clone(),
+            shards: None,
+            base: None,
+        }
+    }
+
+    /// Three-way merge of `ours` and `theirs`, both derived from `base`: an
+    /// entry changed on one side only takes that side; changed on both, it is
+    /// merged field by field, ours winning. An edit beats a removal.
+    fn merge_three_way(base: &Metadata, ours: &Metadata, theirs: &Metadata) -> Metadata {
+        Metadata {
+            files: merge_maps(
+                base.files.iter().collect(),
+                ours.files.iter().collect(),
+                theirs.files.iter().collect(),
+                merge_fields,
+            )
+            .into_iter()
+            .collect(),
+            albums: merge_maps(
+                base.albums.iter().collect(),
+                ours.albums.iter().collect(),
+                theirs.albums.iter().collect(),
+                |_, ours, _| ours.clone(),
+            )
+            .into_iter()
+            .collect(),
+            tag_aliases: merge_maps(
+                base.tag_aliases.iter().collect(),
+                ours.tag_aliases.iter().collect(),
+                theirs.tag_aliases.iter().collect(),
+                |_, ours, _| ours.clone(),
+            )
+            .into_iter()
+            .collect(),
+            shards: None,
+            base: None,
+        }
+    }
+
+    fn load_json(dir: &Path) -> Result<Self> {
         let path = dir.join(METADATA_FILE);
         if path.exists() {
             let data =
                 std::fs::read_to_string(&path).context("Impossible de lire le fichier metadata")?;
-            let meta: Metadata =
+            let mut meta: Metadata =
                 serde_json::from_str(&data).context("Fichier metadata invalide")?;
+            shard::load(dir, &mut meta)?;
             Ok(meta)
         } else {
             Ok(Metadata::default())
         }
     }
 
-    pub fn save(&self, dir: &Path) -> Result<()> {
-        let path = dir.join(METADATA_FILE);
-        let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(&path, json).context("Impossible de sauvegarder les metadata")?;
-        Ok(())
+    #[cfg_attr(feature = "sqlite", allow(dead_code))]
+    fn save_json(&self, dir: &Path) -> Result<()> {
+        if self.shards.is_some() {
+            return shard::save(self, dir);
+        }
+        let data = serde_json::to_string_pretty(self)?;
+        write_file(&dir.join(METADATA_FILE), data.as_bytes())
     }
 
+    /// Add a tag, through its alias if it has one, unless the file already
+    /// has it under any spelling.
     pub fn add_tag(&mut self, file: &str, tag: &str) {
+        let tag = self.canonical_tag(tag);
+        let key = tag_key(&tag);
         let info = self.files.entry(file.to_string()).or_default();
-        if !info.tags.contains(&tag.to_string()) {
-            info.tags.push(tag.to_string());
+        if !info.tags.iter().any(|t| tag_key(t) == key) {
+            info.tags.push(tag);
         }
     }
 
+    /// Remove a tag, whatever its spelling.
     pub fn remove_tag(&mut self, file: &str, tag: &str) {
+        let key = tag_key(&self.canonical_tag(tag));
+        if let Some(info) = self.files.get_mut(file) {
+            info.tags.retain(|t| tag_key(t) != key);
+        }
+    }
+
+    /// `true` if the file has `tag`, under any spelling or alias.
+    pub fn has_tag(&self, file: &str, tag: &str) -> bool {
+        let key = tag_key(&self.canonical_tag(tag));
+        self.get_tags(file).iter().any(|t| tag_key(t) == key)
+    }
+
+    /// The tag `tag` stands for: its alias target, else itself trimmed.
+    pub fn canonical_tag(&self, tag: &str) -> String {
+        match self.tag_aliases.get(&tag_key(tag)) {
+            Some(target) => target.clone(),
+            None => tag.trim().to_string(),
+        }
+    }
+
+    /// Make `alias` stand for `tag` (an alias of the same key only fixes the
+    /// spelling) and rewrite the tags already given. Returns the number of
+    /// files changed.
+    pub fn set_tag_alias(&mut self, alias: &str, tag: &str) -> usize {
+        let tag = self.canonical_tag(tag);
+        let key = tag_key(alias);
+        for target in self.tag_aliases.values_mut() {
+            if tag_key(target) == key {
+                *target = tag.clone();
+            }
+        }
+        self.tag_aliases.insert(key, tag);
+
+        let mut changed = 0;
+        for info in self.files.values_mut() {
+            let mut tags: Vec<String> = Vec::new();
+            for t in &info.tags {
+                let t = match self.tag_aliases.get(&tag_key(t)) {
+                    Some(target) => target.clone(),
+                    None => t.clone(),
+                };
+                if !tags.iter().any(|kept| tag_key(kept) == tag_key(&t)) {
+                    tags.push(t);
+                }
+            }
+            if tags != info.tags {
+                info.tags = tags;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    pub fn remove_tag_alias(&mut self, alias: &str) -> bool {
+        self.tag_aliases.remove(&tag_key(alias)).is_some()
+    }
+
+    pub fn add_person(&mut self, file: &str, person: &str) {
+        let info = self.files.entry(file.to_string()).or_default();
+        if !info.people.iter().any(|p| p == person) {
+            info.people.push(person.to_string());
+        }
+    }
+
+    pub fn remove_person(&mut self, file: &str, person: &str) {
         if let Some(info) = self.files.get_mut(file) {
-            info.tags.retain(|t| t != tag);
+            info.people.retain(|p| p != person);
         }
     }
 
+    pub fn get_people(&self, file: &str) -> &[String] {
+        self.files.get(file).map(|i| i.people.as_slice()).unwrap_or(&[])
+    }
+
     pub fn set_rating(&mut self, file: &str, rating: Option<u8>) {
         let info = self.files.entry(file.to_string()).or_default();
         info.rating = rating;
     }
 
+    pub fn set_taken(&mut self, file: &str, taken: Option<NaiveDateTime>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.taken = taken;
+    }
+
+    pub fn get_taken(&self, file: &str) -> Option<NaiveDateTime> {
+        self.files.get(file).and_then(|i| i.taken)
+    }
+
+    pub fn set_date_override(&mut self, file: &str, date: Option<NaiveDateTime>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.date_override = date;
+    }
+
+    pub fn get_date_override(&self, file: &str) -> Option<NaiveDateTime> {
+        self.files.get(file).and_then(|i| i.date_override)
+    }
+
+    pub fn set_date_source(&mut self, file: &str, source: Option<&str>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.date_source = source.map(str::to_string);
+    }
+
+    pub fn get_date_source(&self, file: &str) -> Option<&str> {
+        self.files.get(file)?.date_source.as_deref()
+    }
+
+    /// Link a RAW+JPEG pair in both directions.
+    pub fn set_pair(&mut self, a: &str, b: &str) {
+        self.files.entry(a.to_string()).or_default().pair = Some(b.to_string());
+        self.files.entry(b.to_string()).or_default().pair = Some(a.to_string());
+    }
+
+    pub fn get_pair(&self, file: &str) -> Option<&str> {
+        self.files.get(file).and_then(|i| i.pair.as_deref())
+    }
+
+    /// Set (or clear) the position of a file, as (latitude, longitude).
+    pub fn set_position(&mut self, file: &str, position: Option<(f64, f64)>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.lat = position.map(|p| p.0);
+        info.lon = position.map(|p| p.1);
+    }
+
+    pub fn get_position(&self, file: &str) -> Option<(f64, f64)> {
+        let info = self.files.get(file)?;
+        Some((info.lat?, info.lon?))
+    }
+
+    pub fn set_favorite(&mut self, file: &str, favorite: bool) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.favorite = favorite;
+    }
+
+    pub fn is_favorite(&self, file: &str) -> bool {
+        self.files.get(file).is_some_and(|i| i.favorite)
+    }
+
+    pub fn set_hidden(&mut self, file: &str, hidden: bool) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.hidden = hidden;
+    }
+
+    pub fn is_hidden(&self, file: &str) -> bool {
+        self.files.get(file).is_some_and(|i| i.hidden)
+    }
+
+    /// Set (or clear) the color label of a file. Unknown colors are refused.
+    pub fn set_label(&mut self, file: &str, label: Option<&str>) -> bool {
+        if label.is_some_and(|l| !COLOR_LABELS.contains(&l)) {
+            return false;
+        }
+        let info = self.files.entry(file.to_string()).or_default();
+        info.label = label.map(str::to_string);
+        true
+    }
+
+    pub fn get_label(&self, file: &str) -> Option<&str> {
+        self.files.get(file).and_then(|i| i.label.as_deref())
+    }
+
+    /// Set the caption of a file; an empty text removes it.
+    pub fn set_caption(&mut self, file: &str, caption: &str) {
+        let caption = caption.trim();
+        let info = self.files.entry(file.to_string()).or_default();
+        info.caption = (!caption.is_empty()).then(|| caption.to_string());
+    }
+
+    pub fn get_caption(&self, file: &str) -> Option<&str> {
+        self.files.get(file).and_then(|i| i.caption.as_deref())
+    }
+
+    pub fn set_hash(&mut self, file: &str, hash: &str) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.hash = Some(hash.to_string());
+    }
+
+    pub fn set_visual_hash(&mut self, file: &str, hash: Option<u64>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.visual_hash = hash.map(|h| format!("{h:016x}"));
+    }
+
+    pub fn get_visual_hash(&self, file: &str) -> Option<u64> {
+        let hash = self.files.get(file)?.visual_hash.as_deref()?;
+        u64::from_str_radix(hash, 16).ok()
+    }
+
+    pub fn set_exif(&mut self, file: &str, exif: Option<ExifSummary>) {
+        let info = self.files.entry(file.to_string()).or_default();
+        info.exif = exif;
+    }
+
+    pub fn get_exif(&self, file: &str) -> Option<&ExifSummary> {
+        self.files.get(file).and_then(|i| i.exif.as_ref())
+    }
+
     pub fn get_tags(&self, file: &str) -> &[String] {
         self.files.get(file).map(|i| i.tags.as_slice()).unwrap_or(&[])
     }
@@ -65,11 +761,342 @@ impl Metadata {
         self.files.get(file).and_then(|i| i.rating)
     }
 
+    /// Apply the changes of one file. Nothing is changed if one of them is
+    /// invalid (rating above 5, unknown color).
+    pub fn apply(&mut self, file: &str, change: &FileChange) -> Result<()> {
+        if let Some(rating) = change.rating.filter(|&r| r > 5) {
+            anyhow::bail!("Note invalide : {rating} (attendu 0 à 5)");
+        }
+        let label = change.label.as_deref().filter(|l| !l.is_empty());
+        if let Some(label) = label.filter(|l| !COLOR_LABELS.contains(l)) {
+            anyhow::bail!("Couleur inconnue : {label}");
+        }
+        for tag in &change.remove_tags {
+            self.remove_tag(file, tag);
+        }
+        for tag in &change.add_tags {
+            self.add_tag(file, tag);
+        }
+        for person in &change.remove_people {
+            self.remove_person(file, person);
+        }
+        for person in &change.add_people {
+            self.add_person(file, person);
+        }
+        if let Some(rating) = change.rating {
+            self.set_rating(file, (rating > 0).then_some(rating));
+        }
+        if let Some(favorite) = change.favorite {
+            self.set_favorite(file, favorite);
+        }
+        if let Some(hidden) = change.hidden {
+            self.set_hidden(file, hidden);
+        }
+        if let Some(caption) = &change.caption {
+            self.set_caption(file, caption);
+        }
+        if change.label.is_some() {
+            self.set_label(file, label);
+        }
+        Ok(())
+    }
+
+    /// Create an empty album. Returns `false` if it already exists.
+    pub fn create_album(&mut self, name: &str) -> bool {
+        if self.albums.contains_key(name) {
+            return false;
+        }
+        self.albums.insert(name.to_string(), Vec::new());
+        true
+    }
+
+    /// Delete an album (the photos themselves are kept).
+    pub fn delete_album(&mut self, name: &str) -> bool {
+        self.albums.remove(name).is_some()
+    }
+
+    /// Append `file` to the album, unless it is already in it.
+    pub fn add_to_album(&mut self, name: &str, file: &str) {
+        let album = self.albums.entry(name.to_string()).or_default();
+        if !album.iter().any(|f| f == file) {
+            album.push(file.to_string());
+        }
+    }
+
+    pub fn remove_from_album(&mut self, name: &str, file: &str) {
+        if let Some(album) = self.albums.get_mut(name) {
+            album.retain(|f| f != file);
+        }
+    }
+
+    /// Follow a file renamed or moved within the library: its tags, rating…
+    /// and its place in albums.
+    pub fn rename_file(&mut self, from: &str, to: &str) {
+        if let Some(info) = self.files.remove(from) {
+            if let Some(pair) = &info.pair
+                && let Some(companion) = self.files.get_mut(pair)
+            {
+                companion.pair = Some(to.to_string());
+            }
+            self.files.insert(to.to_string(), info);
+        }
+        for album in self.albums.values_mut() {
+            for file in album.iter_mut().filter(|f| *f == from) {
+                *file = to.to_string();
+            }
+        }
+    }
+
+    /// Reattach the entries of files that vanished from `present` to the file
+    /// now holding the same content, for files renamed or moved outside the
+    /// tool. `hash_of` hashes a file of `present`; it is only called for
+    /// files without a known hash, and for new files when some entries lost
+    /// their file. Their hash is recorded, so each file is hashed once.
+    /// Entries still without a file are marked missing from `today`, and
+    /// dropped `MISSING_DAYS` later. Returns the (old, new) paths relinked,
+    /// sorted.
+    pub fn relink(
+        &mut self,
+        present: &[&str],
+        today: NaiveDate,
+        mut hash_of: impl FnMut(&str) -> Option<String>,
+    ) -> Vec<(String, String)> {
+        let present: HashSet<&str> = present.iter().copied().collect();
+        for (file, info) in &mut self.files {
+            if present.contains(file.as_str()) {
+                info.missing_since = None;
+                // Hashes of the entries recorded before hashing existed
+                if info.hash.is_none() {
+                    info.hash = hash_of(file);
+                }
+            }
+        }
+
+        let mut orphans: HashMap<String, String> = self
+            .files
+            .iter()
+            .filter(|(file, _)| !present.contains(file.as_str()))
+            .filter_map(|(file, info)| Some((info.hash.clone()?, file.clone())))
+            .collect();
+        let mut relinked = Vec::new();
+        if orphans.is_empty() {
+            return relinked;
+        }
+        let mut untracked: Vec<&str> = present
+            .iter()
+            .copied()
+            .filter(|f| !self.files.contains_key(*f))
+            .collect();
+        untracked.sort();
+        for file in untracked {
+            let Some(hash) = hash_of(file) else {
+                continue;
+            };
+            match orphans.remove(&hash) {
+                Some(old) => {
+                    self.rename_file(&old, file);
+                    relinked.push((old, file.to_string()));
+                }
+                None => self.set_hash(file, &hash),
+            }
+        }
+
+        for file in orphans.into_values() {
+            let Some(info) = self.files.get_mut(&file) else {
+                continue;
+            };
+            let since = *info.missing_since.get_or_insert(today);
+            if (today - since).num_days() > MISSING_DAYS {
+                self.forget_file(&file);
+            }
+        }
+        relinked.sort();
+        relinked
+    }
+
+    /// Carry the content and visual hashes of `previous`, which photos have no
+    /// EXIF date and which files went missing, over to `self`, for metadata
+    /// rebuilt by a client that doesn't know them (the gallery).
+    pub fn keep_cached(&mut self, previous: &Metadata) {
+        for (file, info) in &previous.files {
+            if let Some(hash) = &info.hash {
+                self.files.entry(file.clone()).or_default().hash = Some(hash.clone());
+            }
+            if let Some(hash) = &info.visual_hash {
+                self.files.entry(file.clone()).or_default().visual_hash = Some(hash.clone());
+            }
+            if let Some(since) = info.missing_since {
+                self.files.entry(file.clone()).or_default().missing_since = Some(since);
+            }
+            if info.no_exif_date {
+                let ours = self.files.entry(file.clone()).or_default();
+                ours.no_exif_date = ours.taken.is_none();
+            }
+        }
+    }
+
+    /// Drop everything known about a deleted file.
+    pub fn forget_file(&mut self, file: &str) {
+        self.files.remove(file);
+        for album in self.albums.values_mut() {
+            album.retain(|f| f != file);
+        }
+    }
+
+    /// Content fingerprint (tags, ratings, albums…) used to detect that the
+    /// metadata changed since a client loaded it.
+    pub fn revision(&self) -> String {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let mut json = serde_json::to_string(&files).unwrap_or_default();
+        if !self.albums.is_empty() {
+            json.push_str(&serde_json::to_string(&self.albums).unwrap_or_default());
+        }
+        if !self.tag_aliases.is_empty() {
+            json.push_str(&serde_json::to_string(&self.tag_aliases).unwrap_or_default());
+        }
+        blake3::hash(json.as_bytes()).to_hex()[..16].to_string()
+    }
+
+    /// Files present on both sides whose tags or rating differ, sorted by path.
+    /// Files known to only one side never conflict.
+    pub fn conflicts(&self, incoming: &Metadata) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = incoming
+            .files
+            .iter()
+            .filter_map(|(file, theirs)| {
+                let ours = self.files.get(file)?;
+                let tags = !same_tags(&ours.tags, &theirs.tags);
+                let rating = ours.rating != theirs.rating;
+                (tags || rating).then(|| Conflict {
+                    file: file.clone(),
+                    current: ours.clone(),
+                    incoming: theirs.clone(),
+                    tags,
+                    rating,
+                })
+            })
+            .collect();
+        conflicts.sort_by(|a, b| a.file.cmp(&b.file));
+        conflicts
+    }
+
+    /// Merge `incoming` into `self`. New files are taken as-is; conflicting
+    /// fields follow `resolutions` (keeping the current value by default).
+    pub fn merge(&mut self, incoming: &Metadata, resolutions: &HashMap<String, Resolution>) {
+        for (file, theirs) in &incoming.files {
+            let Some(ours) = self.files.get_mut(file) else {
+                self.files.insert(file.clone(), theirs.clone());
+                continue;
+            };
+            let resolution = resolutions.get(file).copied().unwrap_or_default();
+            match resolution.tags {
+                Pick::Current => {}
+                Pick::Incoming => ours.tags = theirs.tags.clone(),
+                Pick::Both => {
+                    for tag in &theirs.tags {
+                        if !ours.tags.contains(tag) {
+                            ours.tags.push(tag.clone());
+                        }
+                    }
+                }
+            }
+            ours.rating = match resolution.rating {
+                Pick::Current => ours.rating,
+                Pick::Incoming => theirs.rating,
+                Pick::Both => ours.rating.max(theirs.rating),
+            };
+            for person in &theirs.people {
+                if !ours.people.contains(person) {
+                    ours.people.push(person.clone());
+                }
+            }
+            ours.taken = ours.taken.or(theirs.taken);
+            ours.no_exif_date = ours.taken.is_none() && (ours.no_exif_date || theirs.no_exif_date);
+            ours.date_override = ours.date_override.or(theirs.date_override);
+            if ours.date_source.is_none() {
+                ours.date_source = theirs.date_source.clone();
+            }
+            if ours.pair.is_none() {
+                ours.pair = theirs.pair.clone();
+            }
+            if ours.lat.is_none() || ours.lon.is_none() {
+                ours.lat = theirs.lat;
+                ours.lon = theirs.lon;
+            }
+            ours.favorite |= theirs.favorite;
+            ours.hidden |= theirs.hidden;
+            if ours.label.is_none() {
+                ours.label = theirs.label.clone();
+            }
+            if ours.hash.is_none() {
+                ours.hash = theirs.hash.clone();
+            }
+            if ours.visual_hash.is_none() {
+                ours.visual_hash = theirs.visual_hash.clone();
+            }
+            if ours.exif.is_none() {
+                ours.exif = theirs.exif.clone();
+            }
+            // The incoming side holds the latest edit of a caption
+            if theirs.caption.is_some() {
+                ours.caption = theirs.caption.clone();
+            }
+        }
+        // Albums are merged as a union, new photos going to the end
+        for (name, files) in &incoming.albums {
+            self.albums.entry(name.clone()).or_default();
+            for file in files {
+                self.add_to_album(name, file);
+            }
+        }
+        for (alias, tag) in &incoming.tag_aliases {
+            self.tag_aliases
+                .entry(alias.clone())
+                .or_insert_with(|| tag.clone());
+        }
+    }
+
+    /// Every tag with its photo count, most used first (then alphabetically).
+    /// Spellings of one tag (see `tag_key`) count together, under the most
+    /// used one. The year of a photo is the first component of its path.
+    pub fn tag_counts(&self) -> Vec<TagCount> {
+        // Per key: photos per year, and uses of each spelling
+        type Group<'a> = (BTreeMap<String, usize>, BTreeMap<&'a str, usize>);
+        let mut groups: HashMap<String, Group> = HashMap::new();
+        for (file, info) in &self.files {
+            let year = file.split('/').next().unwrap_or_default();
+            let mut seen = HashSet::new();
+            for tag in &info.tags {
+                let key = tag_key(tag);
+                let (years, spellings) = groups.entry(key.clone()).or_default();
+                *spellings.entry(tag).or_default() += 1;
+                if seen.insert(key) {
+                    *years.entry(year.to_string()).or_default() += 1;
+                }
+            }
+        }
+        let mut counts: Vec<TagCount> = groups
+            .into_values()
+            .map(|(years, spellings)| TagCount {
+                tag: spellings
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+                    .map(|(tag, _)| tag.to_string())
+                    .unwrap_or_default(),
+                count: years.values().sum(),
+                years,
+            })
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        counts
+    }
+
     #[allow(dead_code)]
     pub fn files_with_tag(&self, tag: &str) -> Vec<String> {
         self.files
             .iter()
-            .filter(|(_, info)| info.tags.contains(&tag.to_string()))
+            .filter(|(path, _)| self.has_tag(path, tag))
             .map(|(path, _)| path.clone())
             .collect()
     }
@@ -157,6 +1184,21 @@ mod tests {
         assert!(meta.get_tags("nonexistent.jpg").is_empty());
     }
 
+    #[test]
+    fn people_are_separate_from_tags() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/photo.jpg", "plage");
+        meta.add_person("2020/photo.jpg", "maman");
+        meta.add_person("2020/photo.jpg", "maman");
+        meta.add_person("2020/photo.jpg", "Léa");
+        assert_eq!(meta.get_people("2020/photo.jpg"), ["maman", "Léa"]);
+        assert_eq!(meta.get_tags("2020/photo.jpg"), ["plage"]);
+
+        meta.remove_person("2020/photo.jpg", "maman");
+        assert_eq!(meta.get_people("2020/photo.jpg"), ["Léa"]);
+        assert!(meta.get_people("2020/unknown.jpg").is_empty());
+    }
+
     // --- Ratings ---
 
     #[test]
@@ -188,6 +1230,138 @@ mod tests {
         assert_eq!(meta.get_rating("nonexistent.jpg"), None);
     }
 
+    // --- Capture time ---
+
+    #[test]
+    fn set_and_get_taken() {
+        let mut meta = Metadata::default();
+        let dt = NaiveDateTime::parse_from_str("2020-07-15 14:30:22", "%Y-%m-%d %H:%M:%S").unwrap();
+        meta.set_taken("2020/photo.jpg", Some(dt));
+        assert_eq!(meta.get_taken("2020/photo.jpg"), Some(dt));
+        assert_eq!(meta.get_taken("unknown.jpg"), None);
+    }
+
+    #[test]
+    fn set_pair_links_both_files() {
+        let mut meta = Metadata::default();
+        meta.set_pair("2020/a.jpg", "2020/raw/a.cr2");
+        assert_eq!(meta.get_pair("2020/a.jpg"), Some("2020/raw/a.cr2"));
+        assert_eq!(meta.get_pair("2020/raw/a.cr2"), Some("2020/a.jpg"));
+        assert_eq!(meta.get_pair("2020/b.jpg"), None);
+    }
+
+    #[test]
+    fn taken_survives_roundtrip() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        let dt = NaiveDateTime::parse_from_str("2020-07-15 14:30:22", "%Y-%m-%d %H:%M:%S").unwrap();
+        meta.set_taken("2020/photo.jpg", Some(dt));
+        meta.save(&tmp).unwrap();
+        assert_eq!(Metadata::load(&tmp).unwrap().get_taken("2020/photo.jpg"), Some(dt));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn set_and_clear_position() {
+        let mut meta = Metadata::default();
+        meta.set_position("2020/photo.jpg", Some((48.8584, 2.2945)));
+        assert_eq!(meta.get_position("2020/photo.jpg"), Some((48.8584, 2.2945)));
+        meta.set_position("2020/photo.jpg", None);
+        assert_eq!(meta.get_position("2020/photo.jpg"), None);
+        assert_eq!(meta.get_position("2020/unknown.jpg"), None);
+
+        assert!(is_valid_position(-33.86, 151.21));
+        assert!(!is_valid_position(0.0, 0.0));
+        assert!(!is_valid_position(91.0, 2.0));
+        assert!(!is_valid_position(45.0, -181.0));
+    }
+
+    #[test]
+    fn favorite_is_independent_of_rating() {
+        let mut meta = Metadata::default();
+        assert!(!meta.is_favorite("2020/photo.jpg"));
+        meta.set_rating("2020/photo.jpg", Some(2));
+        meta.set_favorite("2020/photo.jpg", true);
+        assert!(meta.is_favorite("2020/photo.jpg"));
+        assert_eq!(meta.get_rating("2020/photo.jpg"), Some(2));
+
+        let json = serde_json::to_string(&meta).unwrap();
+        assert!(json.contains("\"favorite\":true"));
+        meta.set_favorite("2020/photo.jpg", false);
+        assert!(!serde_json::to_string(&meta).unwrap().contains("favorite"));
+    }
+
+    #[test]
+    fn set_and_clear_label() {
+        let mut meta = Metadata::default();
+        assert!(meta.set_label("2020/photo.jpg", Some("red")));
+        assert_eq!(meta.get_label("2020/photo.jpg"), Some("red"));
+        assert!(!meta.set_label("2020/photo.jpg", Some("pink")));
+        assert_eq!(meta.get_label("2020/photo.jpg"), Some("red"));
+        assert!(meta.set_label("2020/photo.jpg", None));
+        assert_eq!(meta.get_label("2020/photo.jpg"), None);
+    }
+
+    #[test]
+    fn file_changes_apply_on_top_of_the_saved_ones() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/photo.jpg", "plage");
+        meta.add_tag("2020/photo.jpg", "flou");
+        meta.set_caption("2020/photo.jpg", "Vacances");
+        let change: FileChange = serde_json::from_str(
+            r#"{"add_tags":["mer"],"remove_tags":["flou"],"add_people":["Léa"],"rating":4,"label":"red"}"#,
+        )
+        .unwrap();
+        meta.apply("2020/photo.jpg", &change).unwrap();
+        assert_eq!(meta.get_tags("2020/photo.jpg"), ["plage", "mer"]);
+        assert_eq!(meta.get_people("2020/photo.jpg"), ["Léa"]);
+        assert_eq!(meta.get_rating("2020/photo.jpg"), Some(4));
+        assert_eq!(meta.get_label("2020/photo.jpg"), Some("red"));
+        assert_eq!(meta.get_caption("2020/photo.jpg"), Some("Vacances"));
+
+        let clear = FileChange {
+            rating: Some(0),
+            label: Some(String::new()),
+            ..FileChange::default()
+        };
+        meta.apply("2020/photo.jpg", &clear).unwrap();
+        assert_eq!(meta.get_rating("2020/photo.jpg"), None);
+        assert_eq!(meta.get_label("2020/photo.jpg"), None);
+
+        let invalid = FileChange {
+            add_tags: vec!["nuit".to_string()],
+            label: Some("pink".to_string()),
+            ..FileChange::default()
+        };
+        assert!(meta.apply("2020/photo.jpg", &invalid).is_err());
+        assert!(!meta.has_tag("2020/photo.jpg", "nuit"));
+        assert!(serde_json::from_str::<FileChange>(r#"{"set_tags":[]}"#).is_err());
+    }
+
+    #[test]
+    fn set_and_clear_caption() {
+        let mut meta = Metadata::default();
+        meta.set_caption("2020/photo.jpg", "  Anniversaire de Léa  ");
+        assert_eq!(meta.get_caption("2020/photo.jpg"), Some("Anniversaire de Léa"));
+        meta.set_caption("2020/photo.jpg", "");
+        assert_eq!(meta.get_caption("2020/photo.jpg"), None);
+        assert_eq!(meta.get_caption("2020/unknown.jpg"), None);
+    }
+
+    #[test]
+    fn merge_takes_incoming_caption() {
+        let mut ours = Metadata::default();
+        ours.set_caption("2020/a.jpg", "Plage");
+        ours.set_caption("2020/b.jpg", "Montagne");
+        let mut theirs = Metadata::default();
+        theirs.set_caption("2020/a.jpg", "Plage de Biarritz");
+        theirs.add_tag("2020/b.jpg", "ski");
+
+        ours.merge(&theirs, &HashMap::new());
+        assert_eq!(ours.get_caption("2020/a.jpg"), Some("Plage de Biarritz"));
+        assert_eq!(ours.get_caption("2020/b.jpg"), Some("Montagne"));
+    }
+
     // --- Filters ---
 
     #[test]
@@ -202,6 +1376,52 @@ mod tests {
         assert_eq!(files, vec!["a.jpg", "b.jpg"]);
     }
 
+    #[test]
+    fn tag_spellings_and_aliases_are_one_tag() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "Noël");
+        meta.add_tag("2020/a.jpg", "NOEL");
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["Noël"]);
+        meta.add_tag("2020/b.jpg", "noel");
+        meta.add_tag("2020/c.jpg", "xmas");
+        meta.add_tag("2020/c.jpg", " plage ");
+        assert_eq!(meta.get_tags("2020/c.jpg"), ["xmas", "plage"]);
+
+        assert_eq!(meta.set_tag_alias("XMAS", "noel"), 1);
+        assert_eq!(meta.get_tags("2020/c.jpg"), ["noel", "plage"]);
+        meta.add_tag("2020/d.jpg", "Xmas");
+        assert_eq!(meta.get_tags("2020/d.jpg"), ["noel"]);
+        assert!(meta.has_tag("2020/a.jpg", "xmas"));
+        let counts = meta.tag_counts();
+        assert_eq!((counts[0].tag.as_str(), counts[0].count), ("noel", 4));
+
+        // Fixing the spelling also redirects the aliases to it
+        assert_eq!(meta.set_tag_alias("noel", "Noël"), 3);
+        assert_eq!(meta.tag_aliases["xmas"], "Noël");
+        assert_eq!(meta.get_tags("2020/b.jpg"), ["Noël"]);
+
+        meta.remove_tag("2020/a.jpg", "noël");
+        assert!(meta.get_tags("2020/a.jpg").is_empty());
+        assert!(meta.remove_tag_alias("Xmas"));
+        assert!(!meta.remove_tag_alias("xmas"));
+    }
+
+    #[test]
+    fn tag_counts_sorted_by_frequency() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2019/a.jpg", "plage");
+        meta.add_tag("2020/b.jpg", "plage");
+        meta.add_tag("2020/c.jpg", "plage");
+        meta.add_tag("2020/c.jpg", "noel");
+        meta.add_tag("2020/d.jpg", "anniversaire");
+        meta.set_rating("2020/e.jpg", Some(3));
+
+        let counts = meta.tag_counts();
+        let order: Vec<(&str, usize)> = counts.iter().map(|c| (c.tag.as_str(), c.count)).collect();
+        assert_eq!(order, [("plage", 3), ("anniversaire", 1), ("noel", 1)]);
+        assert_eq!(counts[0].years, BTreeMap::from([("2019".into(), 1), ("2020".into(), 2)]));
+    }
+
     #[test]
     fn files_with_tag_returns_empty_for_unknown_tag() {
         let meta = Metadata::default();
@@ -228,6 +1448,206 @@ mod tests {
         assert!(meta.files_with_min_rating(1).is_empty());
     }
 
+    // --- Merge ---
+
+    #[test]
+    fn conflicts_ignore_tag_order_and_one_sided_files() {
+        let mut current = Metadata::default();
+        current.add_tag("a.jpg", "x");
+        current.add_tag("a.jpg", "y");
+        current.set_rating("b.jpg", Some(2));
+        current.add_tag("only_current.jpg", "z");
+        let mut incoming = Metadata::default();
+        incoming.add_tag("a.jpg", "y");
+        incoming.add_tag("a.jpg", "x");
+        incoming.set_rating("b.jpg", Some(4));
+        incoming.add_tag("only_incoming.jpg", "z");
+
+        let conflicts = current.conflicts(&incoming);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].file, "b.jpg");
+        assert!(conflicts[0].rating);
+        assert!(!conflicts[0].tags);
+    }
+
+    #[test]
+    fn merge_follows_resolutions() {
+        let mut current = Metadata::default();
+        current.add_tag("a.jpg", "x");
+        current.set_rating("a.jpg", Some(2));
+        current.add_tag("b.jpg", "keep");
+        let mut incoming = Metadata::default();
+        incoming.add_tag("a.jpg", "y");
+        incoming.set_rating("a.jpg", Some(4));
+        incoming.add_tag("b.jpg", "drop");
+        incoming.add_tag("c.jpg", "new");
+
+        let resolutions = HashMap::from([(
+            "a.jpg".to_string(),
+            Resolution {
+                tags: Pick::Both,
+                rating: Pick::Incoming,
+            },
+        )]);
+        current.merge(&incoming, &resolutions);
+
+        assert_eq!(current.get_tags("a.jpg"), &["x", "y"]);
+        assert_eq!(current.get_rating("a.jpg"), Some(4));
+        // No resolution: the current value wins
+        assert_eq!(current.get_tags("b.jpg"), &["keep"]);
+        assert_eq!(current.get_tags("c.jpg"), &["new"]);
+    }
+
+    #[test]
+    fn revision_tracks_content() {
+        let mut a = Metadata::default();
+        a.add_tag("a.jpg", "x");
+        a.add_tag("b.jpg", "y");
+        let mut b = Metadata::default();
+        b.add_tag("b.jpg", "y");
+        b.add_tag("a.jpg", "x");
+        assert_eq!(a.revision(), b.revision());
+        b.set_rating("a.jpg", Some(1));
+        assert_ne!(a.revision(), b.revision());
+    }
+
+    // --- Albums ---
+
+    #[test]
+    fn albums_keep_order_and_ignore_duplicates() {
+        let mut meta = Metadata::default();
+        assert!(meta.create_album("Noël 2020"));
+        assert!(!meta.create_album("Noël 2020"));
+        meta.add_to_album("Noël 2020", "2020/b.jpg");
+        meta.add_to_album("Noël 2020", "2020/a.jpg");
+        meta.add_to_album("Noël 2020", "2020/b.jpg");
+        assert_eq!(meta.albums["Noël 2020"], ["2020/b.jpg", "2020/a.jpg"]);
+
+        meta.remove_from_album("Noël 2020", "2020/b.jpg");
+        assert_eq!(meta.albums["Noël 2020"], ["2020/a.jpg"]);
+        assert!(meta.delete_album("Noël 2020"));
+        assert!(meta.albums.is_empty());
+    }
+
+    #[test]
+    fn albums_follow_renamed_and_deleted_files() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.add_to_album("Été", "2020/a.jpg");
+        meta.add_to_album("Été", "2020/b.jpg");
+        meta.add_to_album("Best of", "2020/b.jpg");
+
+        meta.rename_file("2020/a.jpg", "2021/a.jpg");
+        meta.forget_file("2020/b.jpg");
+        assert_eq!(meta.get_tags("2021/a.jpg"), ["vacances"]);
+        assert_eq!(meta.albums["Été"], ["2021/a.jpg"]);
+        assert!(meta.albums["Best of"].is_empty());
+    }
+
+    #[test]
+    fn relink_follows_content_to_new_path() {
+        let hashes: HashMap<&str, &str> = [
+            ("2020/a.jpg", "h-a"),
+            ("2021/renamed.jpg", "h-b"),
+            ("2021/new.jpg", "h-new"),
+        ]
+        .into();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "noel");
+        meta.set_rating("2020/b.jpg", Some(5));
+        meta.set_hash("2020/b.jpg", "h-b");
+        meta.set_pair("2020/b.jpg", "2020/raw/b.cr2");
+        meta.add_to_album("Best of", "2020/b.jpg");
+        meta.set_rating("2020/lost.jpg", Some(1));
+
+        let present = ["2020/a.jpg", "2021/renamed.jpg", "2021/new.jpg"];
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let relinked = meta.relink(&present, today, |f| hashes.get(f).map(|h| h.to_string()));
+        assert_eq!(
+            relinked,
+            [("2020/b.jpg".to_string(), "2021/renamed.jpg".to_string())]
+        );
+        assert_eq!(meta.get_rating("2021/renamed.jpg"), Some(5));
+        assert_eq!(meta.get_pair("2020/raw/b.cr2"), Some("2021/renamed.jpg"));
+        assert_eq!(meta.albums["Best of"], ["2021/renamed.jpg"]);
+        // Missing hashes are filled in; entries without one stay put
+        assert_eq!(meta.files["2020/a.jpg"].hash.as_deref(), Some("h-a"));
+        assert_eq!(meta.get_rating("2020/lost.jpg"), Some(1));
+        // New files hashed while looking for a match keep their hash
+        assert_eq!(meta.files["2021/new.jpg"].hash.as_deref(), Some("h-new"));
+    }
+
+    #[test]
+    fn relink_hashes_nothing_new_without_orphans() {
+        let mut meta = Metadata::default();
+        meta.set_hash("2020/a.jpg", "h-a");
+        let mut calls = 0;
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let relinked = meta.relink(&["2020/a.jpg", "2020/b.jpg"], today, |_| {
+            calls += 1;
+            None
+        });
+        assert!(relinked.is_empty());
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn relink_hashes_each_file_once_and_forgets_lost_files() {
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/gone.jpg", Some(3));
+        meta.set_hash("2020/gone.jpg", "h-gone");
+        meta.add_to_album("Été", "2020/gone.jpg");
+        let present = ["2020/a.jpg", "2020/b.jpg"];
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, 1).unwrap() + chrono::Days::new(d);
+
+        let mut hashed = Vec::new();
+        let relinked = meta.relink(&present, day(0), |f| {
+            hashed.push(f.to_string());
+            Some(format!("h-{f}"))
+        });
+        assert!(relinked.is_empty());
+        assert_eq!(hashed, ["2020/a.jpg", "2020/b.jpg"]);
+        assert_eq!(meta.files["2020/gone.jpg"].missing_since, Some(day(0)));
+
+        // The next pass, while the orphan is still there, hashes nothing
+        let mut calls = 0;
+        meta.relink(&present, day(1), |_| {
+            calls += 1;
+            None
+        });
+        assert_eq!(calls, 0);
+        assert_eq!(meta.files["2020/gone.jpg"].missing_since, Some(day(0)));
+
+        // A file coming back is no longer missing
+        let back = ["2020/a.jpg", "2020/b.jpg", "2020/gone.jpg"];
+        meta.relink(&back, day(2), |_| None);
+        assert_eq!(meta.files["2020/gone.jpg"].missing_since, None);
+
+        // Missing for too long, it is forgotten
+        meta.relink(&present, day(3), |_| None);
+        meta.relink(&present, day(3 + MISSING_DAYS as u64), |_| None);
+        assert!(meta.files.contains_key("2020/gone.jpg"));
+        meta.relink(&present, day(4 + MISSING_DAYS as u64), |_| None);
+        assert!(!meta.files.contains_key("2020/gone.jpg"));
+        assert!(meta.albums["Été"].is_empty());
+    }
+
+    #[test]
+    fn merge_unions_albums() {
+        let mut ours = Metadata::default();
+        ours.add_to_album("Été", "2020/a.jpg");
+        let mut theirs = Metadata::default();
+        theirs.add_to_album("Été", "2020/b.jpg");
+        theirs.add_to_album("Été", "2020/a.jpg");
+        theirs.create_album("Vide");
+        let rev = ours.revision();
+
+        ours.merge(&theirs, &HashMap::new());
+        assert_eq!(ours.albums["Été"], ["2020/a.jpg", "2020/b.jpg"]);
+        assert!(ours.albums["Vide"].is_empty());
+        assert_ne!(ours.revision(), rev);
+    }
+
     // --- Persistence ---
 
     #[test]
@@ -262,6 +1682,26 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn load_corrects_hand_edited_json_and_save_keeps_it() {
+        let tmp = tmpdir();
+        std::fs::write(
+            tmp.join(".photo_sort_metadata.json"),
+            r#"{"files":{"2020/a.jpg":{"tags":["plage "],"rating":9},"2020/b.jpg":{}}}"#,
+        )
+        .unwrap();
+        let mut meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["plage"]);
+        assert_eq!(meta.get_rating("2020/a.jpg"), None);
+        assert!(!meta.files.contains_key("2020/b.jpg"));
+
+        meta.save(&tmp).unwrap();
+        let loaded = Metadata::load_stored(&tmp).unwrap();
+        assert_eq!(loaded.files.len(), 1);
+        assert_eq!(loaded.get_rating("2020/a.jpg"), None);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn tags_and_rating_on_same_file() {
         let mut meta = Metadata::default();
@@ -271,4 +1711,85 @@ mod tests {
         assert_eq!(meta.get_tags("photo.jpg"), &["famille"]);
         assert_eq!(meta.get_rating("photo.jpg"), Some(5));
     }
+
+    #[test]
+    fn concurrent_saves_keep_both_edits() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.jpg", Some(3));
+        meta.add_tag("2020/b.jpg", "plage");
+        meta.add_to_album("Été", "2020/a.jpg");
+        meta.save(&tmp).unwrap();
+
+        // The server and the CLI load the same state, then both save
+        let mut server = Metadata::load(&tmp).unwrap();
+        let mut cli = Metadata::load(&tmp).unwrap();
+        server.add_tag("2020/a.jpg", "famille");
+        server.set_rating("2020/b.jpg", Some(2));
+        server.save(&tmp).unwrap();
+        cli.set_rating("2020/a.jpg", Some(5));
+        cli.set_rating("2020/b.jpg", Some(4));
+        cli.forget_file("2020/b.jpg");
+        cli.add_to_album("Été", "2020/c.jpg");
+        cli.save(&tmp).unwrap();
+
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert_eq!(loaded.get_tags("2020/a.jpg"), ["famille"]);
+        assert_eq!(loaded.get_rating("2020/a.jpg"), Some(5));
+        // Edited by the server while the CLI removed it: the edit wins
+        assert_eq!(loaded.get_tags("2020/b.jpg"), ["plage"]);
+        assert_eq!(loaded.albums["Été"], ["2020/a.jpg", "2020/c.jpg"]);
+        // The saver now holds the merged state
+        assert_eq!(cli.revision(), loaded.revision());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn replaced_metadata_keeps_removals() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.set_hash("2020/a.jpg", "abc");
+        meta.files.get_mut("2020/a.jpg").unwrap().no_exif_date = true;
+        meta.add_tag("2020/b.jpg", "neige");
+        meta.save(&tmp).unwrap();
+
+        let mut other = Metadata::load(&tmp).unwrap();
+        other.set_rating("2020/c.jpg", Some(4));
+        other.save(&tmp).unwrap();
+
+        // A client sends everything back without b.jpg
+        let mut edited = Metadata::default();
+        edited.add_tag("2020/a.jpg", "plage");
+        meta.replace(edited);
+        meta.save(&tmp).unwrap();
+
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert!(!loaded.files.contains_key("2020/b.jpg"));
+        assert_eq!(loaded.get_rating("2020/c.jpg"), Some(4));
+        assert_eq!(loaded.files["2020/a.jpg"].hash.as_deref(), Some("abc"));
+        assert!(loaded.files["2020/a.jpg"].no_exif_date);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn saves_from_threads_are_not_lost() {
+        let tmp = tmpdir();
+        Metadata::default().save(&tmp).unwrap();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let tmp = tmp.clone();
+                std::thread::spawn(move || {
+                    let mut meta = Metadata::load(&tmp).unwrap();
+                    meta.add_tag(&format!("2020/{i}.jpg"), "plage");
+                    meta.save(&tmp).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(Metadata::load(&tmp).unwrap().files.len(), 8);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
 }
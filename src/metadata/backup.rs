@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use std::fs;
+use std::path::Path;
+
+use super::{METADATA_FILE, Metadata};
+
+/// Backups kept; the oldest are deleted past this count.
+const BACKUP_COUNT: usize = 10;
+
+/// Minimum age of the newest backup before a save makes another one: the
+/// server saves on every click, which would otherwise rotate them all away
+/// within a minute.
+const BACKUP_INTERVAL: TimeDelta = TimeDelta::minutes(10);
+
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// A copy of the metadata, `.photo_sort_metadata.json.bak-20200715-143022`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub name: String,
+    pub created: NaiveDateTime,
+}
+
+fn prefix() -> String {
+    format!("{METADATA_FILE}.bak-")
+}
+
+/// Backups of `dir`, newest first.
+pub fn list(dir: &Path) -> Result<Vec<Backup>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let prefix = prefix();
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let stamp = name.strip_prefix(&prefix)?.get(..15)?;
+            let created = NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).ok()?;
+            Some(Backup { name, created })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Read the metadata saved in a backup.
+pub fn read(dir: &Path, backup: &Backup) -> Result<Metadata> {
+    let data = fs::read_to_string(dir.join(&backup.name))
+        .with_context(|| format!("Impossible de lire {}", backup.name))?;
+    serde_json::from_str(&data).with_context(|| format!("Sauvegarde invalide : {}", backup.name))
+}
+
+/// Back up `stored`, the metadata about to be overwritten, unless the newest
+/// backup is recent (`force` skips that check), then delete the oldest ones.
+pub fn rotate(dir: &Path, stored: &Metadata, force: bool) -> Result<()> {
+    if stored.files.is_empty() && stored.albums.is_empty() {
+        return Ok(());
+    }
+    let now = Local::now().naive_local();
+    let backups = list(dir)?;
+    if !force
+        && backups
+            .first()
+            .is_some_and(|newest| now - newest.created < BACKUP_INTERVAL)
+    {
+        return Ok(());
+    }
+
+    let stamp = now.format(STAMP_FORMAT).to_string();
+    let mut name = format!("{}{stamp}", prefix());
+    let mut counter = 2;
+    while dir.join(&name).exists() {
+        name = format!("{}{stamp}-{counter}", prefix());
+        counter += 1;
+    }
+    fs::write(dir.join(&name), serde_json::to_string_pretty(stored)?)
+        .context("Impossible de sauvegarder une copie des metadata")?;
+
+    for old in list(dir)?.iter().skip(BACKUP_COUNT) {
+        let _ = fs::remove_file(dir.join(&old.name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_backup_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saves_back_up_previous_metadata_at_intervals() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.save(&tmp).unwrap();
+        assert!(list(&tmp).unwrap().is_empty());
+
+        meta.set_rating("2020/a.jpg", Some(4));
+        meta.save(&tmp).unwrap();
+        meta.set_rating("2020/a.jpg", Some(5));
+        meta.save(&tmp).unwrap();
+
+        // Only the first overwrite is backed up: the backup is recent
+        let backups = list(&tmp).unwrap();
+        assert_eq!(backups.len(), 1);
+        let saved = read(&tmp, &backups[0]).unwrap();
+        assert_eq!(saved.get_tags("2020/a.jpg"), ["plage"]);
+        assert_eq!(saved.get_rating("2020/a.jpg"), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_backups() {
+        let tmp = tmpdir();
+        for day in 1..=12 {
+            fs::write(tmp.join(format!("{}202001{day:02}-120000", prefix())), "{}").unwrap();
+        }
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        rotate(&tmp, &meta, false).unwrap();
+
+        let backups = list(&tmp).unwrap();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        assert_eq!(
+            read(&tmp, &backups[0]).unwrap().get_tags("2020/a.jpg"),
+            ["plage"]
+        );
+        assert!(
+            backups
+                .last()
+                .unwrap()
+                .name
+                .ends_with("bak-20200104-120000")
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn restore_puts_back_a_backup_and_saves_the_current_state() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.save(&tmp).unwrap();
+        meta.remove_tag("2020/a.jpg", "plage");
+        meta.add_tag("2020/a.jpg", "neige");
+        meta.save(&tmp).unwrap();
+
+        let restored = Metadata::restore(&tmp, None).unwrap();
+        assert_eq!(
+            Metadata::load(&tmp).unwrap().get_tags("2020/a.jpg"),
+            ["plage"]
+        );
+        // The replaced metadata was backed up too, under another name
+        let backups = list(&tmp).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(
+            read(&tmp, &backups[0]).unwrap().get_tags("2020/a.jpg"),
+            ["neige"]
+        );
+        assert_eq!(backups[1], restored);
+        assert!(Metadata::restore(&tmp, Some("19990101-000000")).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
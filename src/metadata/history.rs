@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::{FileInfo, Metadata};
+
+/// One line per save that changed something, oldest first.
+pub const HISTORY_FILE: &str = ".photo_sort_metadata_history.jsonl";
+
+/// A value before and after a save; `None` when absent (added or removed).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<T>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<T>,
+}
+
+/// The changes made by one save.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: NaiveDateTime,
+    /// Line of the entry this save reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, Change<FileInfo>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub albums: BTreeMap<String, Change<Vec<String>>>,
+}
+
+/// What the user edited: the hashes, the cached capture time (or its absence),
+/// date source and EXIF, and the missing mark are left out, they are filled in
+/// by the tool.
+/// An entry holding nothing else counts as none.
+fn edited(info: Option<&FileInfo>) -> Option<FileInfo> {
+    let info = info.map(|info| FileInfo {
+        hash: None,
+        visual_hash: None,
+        missing_since: None,
+        taken: None,
+        no_exif_date: false,
+        date_source: None,
+        exif: None,
+        ..info.clone()
+    });
+    info.filter(|info| *info != FileInfo::default())
+}
+
+/// Append the changes from `stored` to `saved`, if any (always for an undo,
+/// so the undone entry is skipped next time).
+pub fn record(
+    dir: &Path,
+    stored: &Metadata,
+    saved: &Metadata,
+    undoes: Option<usize>,
+) -> Result<()> {
+    let keys: HashSet<&String> = stored.files.keys().chain(saved.files.keys()).collect();
+    let files: BTreeMap<String, Change<FileInfo>> = keys
+        .into_iter()
+        .filter(|f| edited(stored.files.get(*f)) != edited(saved.files.get(*f)))
+        // Recorded in full, so an undo puts the hash back too
+        .map(|f| {
+            let change = Change {
+                before: stored.files.get(f).cloned(),
+                after: saved.files.get(f).cloned(),
+            };
+            (f.clone(), change)
+        })
+        .collect();
+    let names: HashSet<&String> = stored.albums.keys().chain(saved.albums.keys()).collect();
+    let albums: BTreeMap<String, Change<Vec<String>>> = names
+        .into_iter()
+        .map(|name| {
+            let change = Change {
+                before: stored.albums.get(name).cloned(),
+                after: saved.albums.get(name).cloned(),
+            };
+            (name.clone(), change)
+        })
+        .filter(|(_, change)| change.before != change.after)
+        .collect();
+    if undoes.is_none() && files.is_empty() && albums.is_empty() {
+        return Ok(());
+    }
+
+    let entry = Entry {
+        time: Local::now().naive_local(),
+        undoes,
+        files,
+        albums,
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(HISTORY_FILE))
+        .context("Impossible d'ouvrir l'historique des metadata")?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)
+        .context("Impossible d'écrire l'historique des metadata")?;
+    Ok(())
+}
+
+/// All entries with their line number, oldest first.
+pub fn load(dir: &Path) -> Result<Vec<(usize, Entry)>> {
+    let path = dir.join(HISTORY_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path).context("Impossible de lire l'historique des metadata")?;
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let entry = serde_json::from_str(line)
+                .with_context(|| format!("Historique invalide, ligne {}", index + 1))?;
+            Ok((index, entry))
+        })
+        .collect()
+}
+
+/// The latest save neither undone nor itself an undo.
+pub fn last_undoable(dir: &Path) -> Result<Option<(usize, Entry)>> {
+    let entries = load(dir)?;
+    let undone: HashSet<usize> = entries.iter().filter_map(|(_, e)| e.undoes).collect();
+    Ok(entries
+        .into_iter()
+        .rev()
+        .find(|(index, e)| e.undoes.is_none() && !undone.contains(index)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_history_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn records_edits_but_not_hashes() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.add_to_album("Été", "2020/a.jpg");
+        meta.save(&tmp).unwrap();
+        meta.set_hash("2020/a.jpg", "abc");
+        meta.save(&tmp).unwrap();
+        meta.forget_file("2020/a.jpg");
+        meta.save(&tmp).unwrap();
+
+        let entries = load(&tmp).unwrap();
+        assert_eq!(entries.len(), 2);
+        let (_, first) = &entries[0];
+        assert_eq!(first.files["2020/a.jpg"].before, None);
+        assert_eq!(
+            first.albums["Été"].after.as_deref(),
+            Some(&["2020/a.jpg".to_string()][..])
+        );
+        let (_, deleted) = &entries[1];
+        let before = deleted.files["2020/a.jpg"].before.as_ref().unwrap();
+        assert_eq!(before.hash.as_deref(), Some("abc"));
+        assert_eq!(deleted.files["2020/a.jpg"].after, None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn undo_walks_back_through_saves() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.save(&tmp).unwrap();
+
+        // A bulk change…
+        meta.set_rating("2020/a.jpg", Some(1));
+        meta.set_rating("2020/b.jpg", Some(1));
+        meta.save(&tmp).unwrap();
+        // …then another process captions a.jpg directly
+        let mut other = Metadata::load(&tmp).unwrap();
+        other.set_caption("2020/a.jpg", "Carnac");
+        other.save(&tmp).unwrap();
+
+        let mut meta = Metadata::load(&tmp).unwrap();
+        let undone = meta.undo(&tmp).unwrap().unwrap();
+        assert_eq!(undone.files.len(), 1);
+        assert_eq!(meta.get_caption("2020/a.jpg"), None);
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert_eq!(loaded.get_rating("2020/a.jpg"), Some(1));
+        assert_eq!(loaded.get_caption("2020/a.jpg"), None);
+
+        // Undoing again goes further back, not undoing the undo
+        Metadata::load(&tmp).unwrap().undo(&tmp).unwrap().unwrap();
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert_eq!(loaded.get_rating("2020/a.jpg"), None);
+        assert_eq!(loaded.get_rating("2020/b.jpg"), None);
+        assert_eq!(loaded.get_tags("2020/a.jpg"), ["plage"]);
+        assert!(!loaded.files.contains_key("2020/b.jpg"));
+
+        Metadata::load(&tmp).unwrap().undo(&tmp).unwrap().unwrap();
+        assert!(Metadata::load(&tmp).unwrap().files.is_empty());
+        assert!(Metadata::load(&tmp).unwrap().undo(&tmp).unwrap().is_none());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use super::{FileInfo, METADATA_FILE, Metadata, lock, write_file};
+
+/// Content of a shard: the files of one folder, by path relative to it, so
+/// the folder reads as a library of its own. Sorted, for an unchanged shard
+/// to serialize the same.
+#[derive(Serialize)]
+struct Shard<'a> {
+    files: BTreeMap<&'a str, &'a FileInfo>,
+}
+
+/// Add the files of the shards listed in `root`, the root metadata file.
+pub(super) fn load(dir: &Path, root: &mut Metadata) -> Result<()> {
+    for folder in root.shards.iter().flatten() {
+        let path = dir.join(folder).join(METADATA_FILE);
+        // A folder emptied since is no longer listed at the next save
+        if !path.exists() {
+            continue;
+        }
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Impossible de lire {folder}/{METADATA_FILE}"))?;
+        let shard: Metadata = serde_json::from_str(&data)
+            .with_context(|| format!("Fichier metadata invalide : {folder}/{METADATA_FILE}"))?;
+        root.files.extend(
+            (shard.files.into_iter()).map(|(file, info)| (format!("{folder}/{file}"), info)),
+        );
+    }
+    Ok(())
+}
+
+/// Save `meta` sharded: one file per top-level folder, rewritten only if its
+/// content changed, and the root file with the albums, the aliases, the
+/// files outside any folder and the list of shards.
+pub(super) fn save(meta: &Metadata, dir: &Path) -> Result<()> {
+    let mut shards: BTreeMap<&str, Shard> = BTreeMap::new();
+    let mut root_files = HashMap::new();
+    for (file, info) in &meta.files {
+        match file.split_once('/') {
+            // Entries of a folder gone from disk stay in the root file
+            Some((folder, rest)) if dir.join(folder).is_dir() => {
+                let shard = shards.entry(folder).or_insert_with(|| Shard {
+                    files: BTreeMap::new(),
+                });
+                shard.files.insert(rest, info);
+            }
+            _ => {
+                root_files.insert(file.clone(), info.clone());
+            }
+        }
+    }
+
+    for (folder, shard) in &shards {
+        let path = dir.join(folder).join(METADATA_FILE);
+        let data = serde_json::to_string_pretty(shard)?;
+        if fs::read(&path).is_ok_and(|old| old == data.as_bytes()) {
+            continue;
+        }
+        write_file(&path, data.as_bytes())?;
+    }
+    let root = Metadata {
+        files: root_files,
+        albums: meta.albums.clone(),
+        tag_aliases: meta.tag_aliases.clone(),
+        shards: Some(shards.keys().map(|f| f.to_string()).collect()),
+        base: None,
+    };
+    write_file(
+        &dir.join(METADATA_FILE),
+        serde_json::to_string_pretty(&root)?.as_bytes(),
+    )?;
+
+    // Shards of folders left without metadata
+    for folder in meta.shards.iter().flatten() {
+        if !shards.contains_key(folder.as_str()) {
+            let _ = fs::remove_file(dir.join(folder).join(METADATA_FILE));
+        }
+    }
+    Ok(())
+}
+
+/// Switch a library to one metadata file per top-level folder, or back to a
+/// single file. Returns the number of shards.
+pub fn set_sharded(dir: &Path, sharded: bool) -> Result<usize> {
+    if cfg!(feature = "sqlite") {
+        anyhow::bail!("Les metadata SQLite ne se répartissent pas par dossier");
+    }
+    let _lock = lock(dir, true)?;
+    let mut meta = Metadata::load_json(dir)?;
+    let previous = meta.shards.take();
+    if sharded {
+        meta.shards = Some(previous.unwrap_or_default());
+        meta.save_json(dir)?;
+        return Ok(Metadata::load_json(dir)?.shards.unwrap_or_default().len());
+    }
+    meta.save_json(dir)?;
+    for folder in previous.iter().flatten() {
+        let _ = fs::remove_file(dir.join(folder).join(METADATA_FILE));
+    }
+    Ok(0)
+}
+
+#[cfg(all(test, not(feature = "sqlite")))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir =
+            std::env::temp_dir().join(format!("photo_sort_shard_test_{}_{id}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn shards_hold_their_folder_and_only_change_when_needed() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::create_dir_all(tmp.join("2021")).unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.set_rating("2021/b.jpg", Some(4));
+        meta.add_tag("1999/gone.jpg", "ancien");
+        meta.add_to_album("Été", "2020/a.jpg");
+        meta.save(&tmp).unwrap();
+
+        assert_eq!(set_sharded(&tmp, true).unwrap(), 2);
+        let shard = fs::read_to_string(tmp.join("2020").join(METADATA_FILE)).unwrap();
+        assert!(shard.contains("\"a.jpg\""));
+        let root = fs::read_to_string(tmp.join(METADATA_FILE)).unwrap();
+        assert!(root.contains("1999/gone.jpg") && !root.contains("plage"));
+        let mut meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["plage"]);
+        assert_eq!(meta.albums["Été"], ["2020/a.jpg"]);
+
+        // Editing 2021 leaves the 2020 shard untouched
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        let shard_2020 = fs::File::options()
+            .write(true)
+            .open(tmp.join("2020").join(METADATA_FILE))
+            .unwrap();
+        shard_2020.set_modified(old).unwrap();
+        meta.set_rating("2021/b.jpg", Some(5));
+        meta.save(&tmp).unwrap();
+        let modified = |folder: &str| {
+            fs::metadata(tmp.join(folder).join(METADATA_FILE))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        assert_eq!(modified("2020"), old);
+        assert_ne!(modified("2021"), old);
+
+        // A folder without metadata left loses its shard
+        meta.forget_file("2021/b.jpg");
+        meta.save(&tmp).unwrap();
+        assert!(!tmp.join("2021").join(METADATA_FILE).exists());
+        let meta = Metadata::load(&tmp).unwrap();
+        assert!(!meta.files.contains_key("2021/b.jpg"));
+
+        assert_eq!(set_sharded(&tmp, false).unwrap(), 0);
+        assert!(!tmp.join("2020").join(METADATA_FILE).exists());
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["plage"]);
+        assert!(meta.shards.is_none());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, Transaction, params};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{METADATA_FILE, Metadata};
+
+/// Database holding the metadata instead of `.photo_sort_metadata.json`.
+pub const DB_FILE: &str = ".photo_sort_metadata.db";
+
+/// Suffix given to the JSON file once imported into the database.
+const MIGRATED_SUFFIX: &str = ".migrated";
+
+/// One JSON document per file and per album: new `FileInfo` fields need no
+/// schema change. Tag aliases map to their plain target.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS files (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS albums (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS tag_aliases (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+";
+
+fn open(dir: &Path) -> Result<Connection> {
+    let conn =
+        Connection::open(dir.join(DB_FILE)).context("Impossible d'ouvrir la base metadata")?;
+    // The server saves from several threads
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+/// Load the database, importing (then setting aside) the JSON file the
+/// first time.
+pub fn load(dir: &Path) -> Result<Metadata> {
+    if !dir.join(DB_FILE).exists() {
+        let json = dir.join(METADATA_FILE);
+        if !json.exists() {
+            return Ok(Metadata::default());
+        }
+        let meta = Metadata::load_json(dir)?;
+        save(&meta, dir)?;
+        fs::rename(&json, dir.join(format!("{METADATA_FILE}{MIGRATED_SUFFIX}")))
+            .context("Impossible de mettre de côté l'ancien fichier metadata")?;
+        return Ok(meta);
+    }
+
+    let conn = open(dir)?;
+    let mut meta = Metadata::default();
+    for (path, info) in rows(&conn, "files")? {
+        let info = serde_json::from_str(&info)
+            .with_context(|| format!("Entrée metadata invalide : {path}"))?;
+        meta.files.insert(path, info);
+    }
+    for (name, files) in rows(&conn, "albums")? {
+        let files =
+            serde_json::from_str(&files).with_context(|| format!("Album invalide : {name}"))?;
+        meta.albums.insert(name, files);
+    }
+    meta.tag_aliases = rows(&conn, "tag_aliases")?.into_iter().collect();
+    Ok(meta)
+}
+
+/// Store `meta` in one transaction, writing only the rows that changed.
+pub fn save(meta: &Metadata, dir: &Path) -> Result<()> {
+    let mut conn = open(dir)?;
+    let tx = conn.transaction()?;
+    let files = meta
+        .files
+        .iter()
+        .map(|(path, info)| Ok((path.as_str(), serde_json::to_string(info)?)))
+        .collect::<Result<_>>()?;
+    sync(&tx, "files", files)?;
+    let albums = meta
+        .albums
+        .iter()
+        .map(|(name, files)| Ok((name.as_str(), serde_json::to_string(files)?)))
+        .collect::<Result<_>>()?;
+    sync(&tx, "albums", albums)?;
+    let aliases = meta
+        .tag_aliases
+        .iter()
+        .map(|(alias, tag)| (alias.as_str(), tag.clone()))
+        .collect();
+    sync(&tx, "tag_aliases", aliases)?;
+    tx.commit()
+        .context("Impossible de sauvegarder les metadata")?;
+    Ok(())
+}
+
+fn rows(conn: &Connection, table: &str) -> Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare(&format!("SELECT key, value FROM {table}"))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Make `table` hold exactly `wanted`.
+fn sync(tx: &Transaction, table: &str, wanted: HashMap<&str, String>) -> Result<()> {
+    let stored = rows(tx, table)?;
+    let mut upsert = tx.prepare(&format!(
+        "INSERT OR REPLACE INTO {table} (key, value) VALUES (?1, ?2)"
+    ))?;
+    for (key, value) in &wanted {
+        if stored.get(*key) != Some(value) {
+            upsert.execute(params![key, value])?;
+        }
+    }
+    let mut delete = tx.prepare(&format!("DELETE FROM {table} WHERE key = ?1"))?;
+    for key in stored.keys().filter(|k| !wanted.contains_key(k.as_str())) {
+        delete.execute(params![key])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_sqlite_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migrates_json_file_on_first_load() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.add_to_album("Été", "2020/a.jpg");
+        meta.set_tag_alias("Noël", "noel");
+        meta.save_json(&tmp).unwrap();
+
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert_eq!(loaded.get_tags("2020/a.jpg"), ["vacances"]);
+        assert_eq!(loaded.albums["Été"], ["2020/a.jpg"]);
+        assert_eq!(loaded.canonical_tag("NOËL"), "noel");
+        assert!(tmp.join(DB_FILE).exists());
+        assert!(!tmp.join(METADATA_FILE).exists());
+        assert!(
+            tmp.join(format!("{METADATA_FILE}{MIGRATED_SUFFIX}"))
+                .exists()
+        );
+        assert_eq!(Metadata::load(&tmp).unwrap().revision(), meta.revision());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn save_updates_and_deletes_rows() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.jpg", Some(3));
+        meta.set_rating("2020/b.jpg", Some(5));
+        meta.add_to_album("Best of", "2020/b.jpg");
+        meta.save(&tmp).unwrap();
+
+        meta.set_rating("2020/a.jpg", Some(4));
+        meta.forget_file("2020/b.jpg");
+        meta.delete_album("Best of");
+        meta.save(&tmp).unwrap();
+
+        let loaded = Metadata::load(&tmp).unwrap();
+        assert_eq!(loaded.get_rating("2020/a.jpg"), Some(4));
+        assert!(!loaded.files.contains_key("2020/b.jpg"));
+        assert!(loaded.albums.is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
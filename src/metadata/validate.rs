@@ -0,0 +1,162 @@
+use anyhow::Result;
+use console::style;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{COLOR_LABELS, FileInfo, Metadata, is_valid_position, tag_key};
+
+/// Refuse metadata needing corrections instead of correcting them, from
+/// `--strict`.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Correct metadata loaded from disk (edited by hand, or by an older
+/// version): each correction is reported on stderr, or refused with
+/// `--strict`. The next save writes the corrected metadata.
+pub(super) fn check(meta: &mut Metadata) -> Result<()> {
+    let issues = normalize(meta);
+    if issues.is_empty() {
+        return Ok(());
+    }
+    if STRICT.load(Ordering::Relaxed) {
+        anyhow::bail!("Metadata incorrectes :\n  {}", issues.join("\n  "));
+    }
+    for issue in &issues {
+        eprintln!("  {} Metadata : {issue}", style("!").yellow().bold());
+    }
+    Ok(())
+}
+
+/// Put `meta` in the form the setters produce, returning what needed more
+/// than a silent cleanup (an empty entry, trailing spaces).
+pub(super) fn normalize(meta: &mut Metadata) -> Vec<String> {
+    let mut issues = Vec::new();
+    let aliases = meta.tag_aliases.clone();
+    meta.files.retain(|file, info| {
+        if file.trim().is_empty() {
+            issues.push("entrée sans chemin ignorée".to_string());
+            return false;
+        }
+        for issue in normalize_file(info, &aliases) {
+            issues.push(format!("{file} : {issue}"));
+        }
+        *info != FileInfo::default()
+    });
+    meta.albums.retain(|name, files| {
+        if name.trim().is_empty() {
+            issues.push("album sans nom ignoré".to_string());
+            return false;
+        }
+        let mut seen = std::collections::HashSet::new();
+        files.retain(|f| seen.insert(f.clone()));
+        true
+    });
+    issues.sort();
+    issues
+}
+
+fn normalize_file(
+    info: &mut FileInfo,
+    aliases: &std::collections::BTreeMap<String, String>,
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    if let Some(rating) = info.rating
+        && !(1..=5).contains(&rating)
+    {
+        issues.push(format!("note {rating} hors de 1 à 5, ignorée"));
+        info.rating = None;
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in &info.tags {
+        let tag = aliases
+            .get(&tag_key(tag))
+            .cloned()
+            .unwrap_or_else(|| tag.trim().to_string());
+        if !tag.is_empty() && !tags.iter().any(|t| tag_key(t) == tag_key(&tag)) {
+            tags.push(tag);
+        }
+    }
+    info.tags = tags;
+    let mut people: Vec<String> = Vec::new();
+    for person in &info.people {
+        let person = person.trim();
+        if !person.is_empty() && !people.iter().any(|p| p == person) {
+            people.push(person.to_string());
+        }
+    }
+    info.people = people;
+    info.caption = (info.caption.as_deref().map(str::trim))
+        .filter(|c| !c.is_empty())
+        .map(str::to_string);
+
+    if let Some(label) = &info.label {
+        let lower = label.trim().to_lowercase();
+        if COLOR_LABELS.contains(&lower.as_str()) {
+            info.label = Some(lower);
+        } else {
+            issues.push(format!("étiquette « {label} » inconnue, ignorée"));
+            info.label = None;
+        }
+    }
+    match (info.lat, info.lon) {
+        (None, None) => {}
+        (Some(lat), Some(lon)) if is_valid_position(lat, lon) => {}
+        (lat, lon) => {
+            let show = |v: Option<f64>| v.map_or("?".to_string(), |v| v.to_string());
+            issues.push(format!(
+                "position ({}, {}) invalide, ignorée",
+                show(lat),
+                show(lon)
+            ));
+            info.lat = None;
+            info.lon = None;
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_hand_edited_entries() {
+        let mut meta: Metadata = serde_json::from_str(
+            r#"{"files":{
+                "2020/a.jpg":{"tags":[" plage ","Plage","","xmas"],"rating":7,
+                    "people":["Léa","Léa "],"caption":"  ","label":"Red"},
+                "2020/b.jpg":{"tags":["  "],"lat":95.0,"lon":2.0},
+                "2020/c.jpg":{"rating":4,"lat":48.5},
+                "":{"rating":3}},
+              "albums":{"Été":["2020/a.jpg","2020/a.jpg"],"":[]},
+              "tag_aliases":{"xmas":"noel"}}"#,
+        )
+        .unwrap();
+
+        let issues = normalize(&mut meta);
+        assert_eq!(
+            issues,
+            [
+                "2020/a.jpg : note 7 hors de 1 à 5, ignorée",
+                "2020/b.jpg : position (95, 2) invalide, ignorée",
+                "2020/c.jpg : position (48.5, ?) invalide, ignorée",
+                "album sans nom ignoré",
+                "entrée sans chemin ignorée",
+            ]
+        );
+        let a = &meta.files["2020/a.jpg"];
+        assert_eq!(a.tags, ["plage", "noel"]);
+        assert_eq!(a.people, ["Léa"]);
+        assert_eq!((a.rating, a.caption.as_deref()), (None, None));
+        assert_eq!(a.label.as_deref(), Some("red"));
+        // Nothing left of b.jpg
+        assert!(!meta.files.contains_key("2020/b.jpg"));
+        assert_eq!(meta.get_rating("2020/c.jpg"), Some(4));
+        assert_eq!(meta.albums.len(), 1);
+        assert_eq!(meta.albums["Été"], ["2020/a.jpg"]);
+        assert!(normalize(&mut meta).is_empty());
+    }
+}
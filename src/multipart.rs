@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+
+/// One part of a `multipart/form-data` body.
+#[derive(Debug, PartialEq)]
+pub struct Part {
+    /// Name of the form field.
+    pub name: String,
+    /// Name of the file sent, for file fields.
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Boundary of a `multipart/form-data` content type, `None` for any other
+/// type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    let kind = params.next()?.trim();
+    if !kind.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        (key.trim().eq_ignore_ascii_case("boundary"))
+            .then(|| value.trim().trim_matches('"').to_string())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Value of a parameter of a `Content-Disposition` header, quoted or not.
+fn disposition_param(header: &str, param: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|item| {
+        let (key, value) = item.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(param)
+            .then(|| value.trim().trim_matches('"').replace("\\\"", "\""))
+    })
+}
+
+/// Split a `multipart/form-data` body into its parts.
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>> {
+    let delimiter = format!("--{boundary}");
+    let start = find(body, delimiter.as_bytes()).context("Corps multipart invalide")?;
+    let mut rest = &body[start + delimiter.len()..];
+    let next_delimiter = format!("\r\n--{boundary}");
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .context("Corps multipart invalide")?;
+        let head_end = find(rest, b"\r\n\r\n").context("Corps multipart invalide")?;
+        let head = String::from_utf8_lossy(&rest[..head_end]);
+        let content = &rest[head_end + 4..];
+        let end = find(content, next_delimiter.as_bytes()).context("Corps multipart tronqué")?;
+
+        let disposition = (head.lines())
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim()
+                    .eq_ignore_ascii_case("Content-Disposition")
+                    .then_some(value)
+            })
+            .unwrap_or_default();
+        parts.push(Part {
+            name: disposition_param(disposition, "name").unwrap_or_default(),
+            filename: disposition_param(disposition, "filename"),
+            data: content[..end].to_vec(),
+        });
+        rest = &content[end + next_delimiter.len()..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boundary_is_read_from_the_content_type() {
+        assert_eq!(
+            boundary("multipart/form-data; boundary=----abc123").as_deref(),
+            Some("----abc123")
+        );
+        assert_eq!(
+            boundary("Multipart/Form-Data; charset=utf-8; boundary=\"x y\"").as_deref(),
+            Some("x y")
+        );
+        assert_eq!(boundary("image/jpeg"), None);
+        assert_eq!(boundary("multipart/form-data"), None);
+    }
+
+    #[test]
+    fn parts_are_split() {
+        let body = b"preamble\r\n--XX\r\n\
+            Content-Disposition: form-data; name=\"photo\"; filename=\"IMG 1.jpg\"\r\n\
+            Content-Type: image/jpeg\r\n\r\n\
+            \xff\xd8\r\n--X\xff\xd9\r\n\
+            --XX\r\n\
+            Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+            salut\r\n\
+            --XX--\r\n";
+        let parts = parse(body, "XX").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "photo");
+        assert_eq!(parts[0].filename.as_deref(), Some("IMG 1.jpg"));
+        assert_eq!(parts[0].data, b"\xff\xd8\r\n--X\xff\xd9");
+        assert_eq!(parts[1].name, "note");
+        assert_eq!(parts[1].filename, None);
+        assert_eq!(parts[1].data, b"salut");
+    }
+
+    #[test]
+    fn truncated_bodies_are_refused() {
+        assert!(parse(b"no delimiter", "XX").is_err());
+        let body = b"--XX\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nabc";
+        assert!(parse(body, "XX").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::gallery::collect_photos;
+use crate::metadata::Metadata;
+use crate::sort::{PROGRESS_FILE, hash_file, load_progress};
+
+/// Origins store (at the root of the output dir), one JSON record per line.
+pub const ORIGINS_FILE: &str = ".photo_sort_origins.jsonl";
+
+/// Per-year text file (`name <- source` lines) written by older versions.
+const LEGACY_ORIGINS_FILE: &str = ".photo_sort_origins";
+
+/// Where a sorted file came from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Origin {
+    /// Path relative to the output dir (`2020/2020-07-15_14-30-22.jpg`).
+    pub dest: String,
+    /// Original path of the file.
+    pub source: String,
+}
+
+/// Append one record to the store.
+pub fn append(dir: &Path, origin: &Origin) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(ORIGINS_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(origin)?)?;
+    Ok(())
+}
+
+/// Read every record, oldest first.
+pub fn load(dir: &Path) -> Result<Vec<Origin>> {
+    let path = dir.join(ORIGINS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path).context("Impossible de lire les origines")?;
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{ORIGINS_FILE} : ligne {} invalide", i + 1))
+        })
+        .collect()
+}
+
+/// Sorted files by content hash. The hashes recorded in the metadata and in
+/// the sort progress are used first; the other photos of the library are
+/// only hashed if some of `wanted` are still missing.
+pub fn sorted_by_hash(
+    dir: &Path,
+    metadata: &Metadata,
+    wanted: &HashSet<String>,
+) -> Result<HashMap<String, String>> {
+    let progress = load_progress(&dir.join(PROGRESS_FILE))?;
+    let mut by_hash: HashMap<String, String> = metadata
+        .files
+        .iter()
+        .filter_map(|(file, info)| Some((info.hash.clone()?, file.clone())))
+        .chain(progress.processed.into_iter().map(|e| (e.hash, e.dest)))
+        .filter(|(_, file)| dir.join(file).is_file())
+        .collect();
+    if wanted.iter().any(|hash| !by_hash.contains_key(hash)) {
+        // Files sorted before hashes were recorded
+        let hashed: HashSet<String> = by_hash.values().cloned().collect();
+        for file in collect_photos(dir).into_values().flatten() {
+            if !hashed.contains(&file)
+                && let Ok(hash) = hash_file(&dir.join(&file))
+            {
+                by_hash.entry(hash).or_insert(file);
+            }
+        }
+    }
+    Ok(by_hash)
+}
+
+/// Move the per-year `.photo_sort_origins` text files of older versions into
+/// the store, then delete them. Returns the number of records imported.
+pub fn migrate_legacy(dir: &Path) -> Result<usize> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(0);
+    };
+    let mut known: Option<HashSet<Origin>> = None;
+    let mut imported = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let legacy = entry.path().join(LEGACY_ORIGINS_FILE);
+        if !legacy.is_file() {
+            continue;
+        }
+        // Records already imported by an interrupted migration are skipped.
+        let known = match &mut known {
+            Some(k) => k,
+            None => known.insert(load(dir)?.into_iter().collect()),
+        };
+        let year = entry.file_name().to_string_lossy().to_string();
+        let text = fs::read_to_string(&legacy)
+            .with_context(|| format!("Impossible de lire {}", legacy.display()))?;
+        for line in text.lines() {
+            let Some((name, source)) = line.split_once(" <- ") else {
+                continue;
+            };
+            let origin = Origin {
+                dest: format!("{year}/{name}"),
+                source: source.to_string(),
+            };
+            if known.insert(origin.clone()) {
+                append(dir, &origin)?;
+                imported += 1;
+            }
+        }
+        fs::remove_file(&legacy)?;
+    }
+    Ok(imported)
+}
+
+/// Records matching `query`: a sorted path (`2020/…jpg`), an original path,
+/// or a bare file name matched against both sides.
+pub fn lookup<'a>(origins: &'a [Origin], query: &str) -> Vec<&'a Origin> {
+    let query = query.trim_end_matches('/');
+    let canonical = Path::new(query)
+        .canonicalize()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok();
+    let exact: Vec<&Origin> = origins
+        .iter()
+        .filter(|o| o.dest == query || o.source == query || canonical.as_ref() == Some(&o.source))
+        .collect();
+    if !exact.is_empty() || query.contains('/') {
+        return exact;
+    }
+    let file_name = |p: &str| p.rsplit('/').next().unwrap_or(p).to_string();
+    origins
+        .iter()
+        .filter(|o| file_name(&o.dest) == query || file_name(&o.source) == query)
+        .collect()
+}
+
+/// `photo-sort origin`: print where a sorted file came from, or where an
+/// original file was sorted to.
+pub fn run_origin(dir: &Path, query: &str) -> Result<()> {
+    migrate_legacy(dir)?;
+    let origins = load(dir)?;
+    let matches = lookup(&origins, query);
+    if matches.is_empty() {
+        println!(
+            "  {} Aucune origine connue pour {}",
+            style("!").yellow().bold(),
+            style(query).white().bold()
+        );
+        return Ok(());
+    }
+    for origin in matches {
+        println!(
+            "  {}  {}  {}",
+            style(&origin.dest).cyan(),
+            style("<-").dim(),
+            style(&origin.source).white()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> std::path::PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_origins_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn origin(dest: &str, source: &str) -> Origin {
+        Origin {
+            dest: dest.to_string(),
+            source: source.to_string(),
+        }
+    }
+
+    #[test]
+    fn append_and_load_roundtrip() {
+        let tmp = tmpdir();
+        append(&tmp, &origin("2020/a.jpg", "/photos/IMG_1.jpg")).unwrap();
+        append(&tmp, &origin("2020/b.jpg", "/photos/IMG_2.jpg")).unwrap();
+
+        let loaded = load(&tmp).unwrap();
+        assert_eq!(
+            loaded,
+            vec![
+                origin("2020/a.jpg", "/photos/IMG_1.jpg"),
+                origin("2020/b.jpg", "/photos/IMG_2.jpg")
+            ]
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn load_without_store_is_empty() {
+        let tmp = tmpdir();
+        assert!(load(&tmp).unwrap().is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn migrate_legacy_imports_and_removes_text_files() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(
+            tmp.join("2020/.photo_sort_origins"),
+            "a.jpg <- /photos/IMG_1.jpg\nraw/a.cr2 <- /photos/IMG_1.CR2\n",
+        )
+        .unwrap();
+        append(&tmp, &origin("2020/a.jpg", "/photos/IMG_1.jpg")).unwrap();
+
+        assert_eq!(migrate_legacy(&tmp).unwrap(), 1);
+        assert!(!tmp.join("2020/.photo_sort_origins").exists());
+        let loaded = load(&tmp).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[1], origin("2020/raw/a.cr2", "/photos/IMG_1.CR2"));
+        assert_eq!(migrate_legacy(&tmp).unwrap(), 0);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn lookup_both_directions() {
+        let origins = vec![
+            origin("2020/a.jpg", "/photos/vacances/IMG_1.jpg"),
+            origin("2021/b.jpg", "/photos/noel/IMG_2.jpg"),
+        ];
+        assert_eq!(lookup(&origins, "2020/a.jpg"), vec![&origins[0]]);
+        assert_eq!(
+            lookup(&origins, "/photos/noel/IMG_2.jpg"),
+            vec![&origins[1]]
+        );
+        assert_eq!(lookup(&origins, "IMG_2.jpg"), vec![&origins[1]]);
+        assert_eq!(lookup(&origins, "b.jpg"), vec![&origins[1]]);
+        assert!(lookup(&origins, "2020/b.jpg").is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// File listing paths to skip, in gitignore syntax. It applies to its own
+/// directory and below, like a `.gitignore`.
+pub const IGNORE_FILE: &str = ".photosortignore";
+
+/// `.photosortignore` rules met while walking a tree.
+#[derive(Default)]
+pub struct IgnoreRules {
+    /// One matcher per ignore file, parents before children.
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Load `dir/.photosortignore`, if any. Invalid lines are skipped.
+    fn enter_dir(&mut self, dir: &Path) {
+        let path = dir.join(IGNORE_FILE);
+        if !path.is_file() {
+            return;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        let _ = builder.add(&path);
+        if let Ok(matcher) = builder.build() {
+            self.matchers.push(matcher);
+        }
+    }
+
+    /// `true` if the closest ignore file with an opinion on `path` excludes it.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for matcher in self
+            .matchers
+            .iter()
+            .rev()
+            .filter(|m| path.starts_with(m.path()))
+        {
+            match matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// Predicate for `WalkDir::filter_entry`: rejects ignored entries (and so
+    /// whole ignored subtrees) and picks up the ignore file of each directory
+    /// the walk enters.
+    pub fn accept(&mut self, entry: &walkdir::DirEntry) -> bool {
+        let is_dir = entry.file_type().is_dir();
+        if entry.depth() > 0 && self.is_ignored(entry.path(), is_dir) {
+            return false;
+        }
+        if is_dir {
+            self.enter_dir(entry.path());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use walkdir::WalkDir;
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_ignore_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn walk(root: &Path) -> Vec<String> {
+        let mut rules = IgnoreRules::default();
+        let mut files: Vec<String> = WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| rules.accept(e))
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file() && e.file_name() != IGNORE_FILE)
+            .map(|e| {
+                e.path()
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn skips_patterns_and_subtrees() {
+        let tmp = tmpdir();
+        for file in [
+            "a.jpg",
+            "cache/b.jpg",
+            "exports/old/c.jpg",
+            "vacances/d.jpg",
+            "vacances/e.tmp.jpg",
+        ] {
+            let path = tmp.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::write(
+            tmp.join(IGNORE_FILE),
+            "# junk\ncache/\n/exports\n*.tmp.jpg\n",
+        )
+        .unwrap();
+
+        assert_eq!(walk(&tmp), vec!["a.jpg", "vacances/d.jpg"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn nested_files_can_reinclude() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("scans")).unwrap();
+        fs::write(tmp.join("a.png"), "x").unwrap();
+        fs::write(tmp.join("scans/b.png"), "x").unwrap();
+        fs::write(tmp.join("scans/c.png"), "x").unwrap();
+        fs::write(tmp.join(IGNORE_FILE), "*.png\n").unwrap();
+        fs::write(tmp.join("scans").join(IGNORE_FILE), "!b.png\n").unwrap();
+
+        assert_eq!(walk(&tmp), vec!["scans/b.png"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
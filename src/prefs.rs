@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PREFS_FILE: &str = ".photo_sort_prefs.json";
+
+/// Name of the cookie identifying a browser.
+pub const PREFS_COOKIE: &str = "photo_sort_client";
+
+/// Browsers whose preferences are kept: any request can make up a new
+/// cookie, so the least recently saved ones are forgotten past this.
+const MAX_CLIENTS: usize = 500;
+
+/// Gallery preferences (theme, filters, grid size…) per browser, keyed by the
+/// client id stored in the `photo_sort_client` cookie. Values are opaque JSON
+/// objects owned by the gallery script.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Prefs {
+    pub clients: HashMap<String, serde_json::Value>,
+    /// When each client last saved, as a counter: higher is more recent.
+    #[serde(default)]
+    saved: HashMap<String, u64>,
+}
+
+impl Prefs {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(PREFS_FILE);
+        if path.exists() {
+            let data =
+                std::fs::read_to_string(&path).context("Impossible de lire les préférences")?;
+            let mut prefs: Prefs = serde_json::from_str(&data).context("Fichier prefs invalide")?;
+            prefs.prune();
+            Ok(prefs)
+        } else {
+            Ok(Prefs::default())
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(PREFS_FILE);
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&path, json).context("Impossible de sauvegarder les préférences")?;
+        Ok(())
+    }
+
+    /// Preferences of a client, or an empty object.
+    pub fn get(&self, client: &str) -> serde_json::Value {
+        self.clients
+            .get(client)
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}))
+    }
+
+    /// Replace the preferences of a client. Only JSON objects are accepted.
+    pub fn set(&mut self, client: &str, value: serde_json::Value) -> Result<()> {
+        if !value.is_object() {
+            anyhow::bail!("Les préférences doivent être un objet JSON");
+        }
+        self.clients.insert(client.to_string(), value);
+        let latest = self.saved.values().max().map_or(0, |n| n + 1);
+        self.saved.insert(client.to_string(), latest);
+        self.prune();
+        Ok(())
+    }
+
+    /// Forget the least recently saved clients beyond `MAX_CLIENTS`.
+    fn prune(&mut self) {
+        self.saved
+            .retain(|client, _| self.clients.contains_key(client));
+        if self.clients.len() <= MAX_CLIENTS {
+            return;
+        }
+        let mut clients: Vec<(u64, String)> = (self.clients.keys())
+            .map(|client| (self.saved.get(client).copied().unwrap_or(0), client.clone()))
+            .collect();
+        clients.sort();
+        for (_, client) in &clients[..clients.len() - MAX_CLIENTS] {
+            self.clients.remove(client);
+            self.saved.remove(client);
+        }
+    }
+}
+
+/// Generate a new opaque client id.
+pub fn new_client_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let seed = format!(
+        "{nanos}-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    blake3::hash(seed.as_bytes()).to_hex()[..32].to_string()
+}
+
+/// Extract the client id from a `Cookie` header value.
+/// Ids are hex strings; anything else is ignored.
+pub fn client_from_cookie(header: &str) -> Option<String> {
+    header.split(';').find_map(|pair| {
+        let (k, v) = pair.trim().split_once('=')?;
+        (k == PREFS_COOKIE && !v.is_empty() && v.len() <= 64 && v.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| v.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> std::path::PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_prefs_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn get_unknown_client_is_empty_object() {
+        let prefs = Prefs::default();
+        assert_eq!(prefs.get("abc"), serde_json::json!({}));
+    }
+
+    #[test]
+    fn set_rejects_non_object() {
+        let mut prefs = Prefs::default();
+        assert!(prefs.set("abc", serde_json::json!([1, 2])).is_err());
+        assert!(prefs.clients.is_empty());
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let tmp = tmpdir();
+        let mut prefs = Prefs::default();
+        prefs
+            .set("abc", serde_json::json!({"theme": "dark", "minRating": 3}))
+            .unwrap();
+        prefs.save(&tmp).unwrap();
+
+        let loaded = Prefs::load(&tmp).unwrap();
+        assert_eq!(loaded.get("abc")["theme"], "dark");
+        assert_eq!(loaded.get("abc")["minRating"], 3);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn oldest_clients_are_forgotten() {
+        let tmp = tmpdir();
+        let mut prefs = Prefs::default();
+        for n in 0..MAX_CLIENTS + 2 {
+            prefs
+                .set(&format!("{n:x}"), serde_json::json!({"n": n}))
+                .unwrap();
+            // Saving again makes a client recent
+            if n == MAX_CLIENTS {
+                prefs.set("0", serde_json::json!({"n": 0})).unwrap();
+            }
+        }
+        assert_eq!(prefs.clients.len(), MAX_CLIENTS);
+        assert_eq!(prefs.get("0")["n"], 0);
+        assert_eq!(prefs.get("1"), serde_json::json!({}));
+        assert_eq!(prefs.get("2"), serde_json::json!({}));
+        assert_eq!(prefs.get("3")["n"], 3);
+
+        // Files written before the limit are trimmed when loaded
+        for n in 0..10 {
+            prefs
+                .clients
+                .insert(format!("old{n}"), serde_json::json!({}));
+        }
+        prefs.save(&tmp).unwrap();
+        assert_eq!(Prefs::load(&tmp).unwrap().clients.len(), MAX_CLIENTS);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn client_ids_are_unique_hex() {
+        let a = new_client_id();
+        let b = new_client_id();
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 32);
+        assert!(client_from_cookie(&format!("{PREFS_COOKIE}={a}")).is_some());
+    }
+
+    #[test]
+    fn client_from_cookie_parses_header() {
+        assert_eq!(
+            client_from_cookie("foo=bar; photo_sort_client=0a1b; x=y"),
+            Some("0a1b".to_string())
+        );
+        assert_eq!(client_from_cookie("foo=bar"), None);
+        assert_eq!(client_from_cookie("photo_sort_client=../etc"), None);
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::{Result, bail};
+use std::str::FromStr;
+
+use crate::metadata::Metadata;
+
+/// A photo selection, parsed from text such as
+/// `tag:vacances AND rating>=4 AND year:2019..2021 AND NOT tag:flou`.
+///
+/// Terms: `tag:`, `person:`, `album:`, `label:` (color), `caption:`
+/// (substring), `year:` and
+/// `rating` (`:N`, `:A..B`, `>=N`, `>N`, `<=N`, `<N`, `=N`), `is:favorite`,
+/// `is:hidden` and bare words (tag or person, or part of the file name or
+/// caption).
+/// Terms combine with `AND` (implicit between two terms), `OR`, `NOT` and
+/// parentheses; values with spaces go in double quotes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Tag(String),
+    Person(String),
+    Album(String),
+    Label(String),
+    Caption(String),
+    Text(String),
+    /// Inclusive rating range, 0 standing for "not rated".
+    Rating(u8, u8),
+    /// Inclusive year range.
+    Year(u16, u16),
+    Favorite,
+    Hidden,
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for c in text.chars() {
+        if quoted {
+            if c == '"' {
+                quoted = false;
+            } else {
+                word.push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                quoted = true;
+                in_word = true;
+            }
+            '(' | ')' => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        bail!("Requête invalide : guillemet non fermé");
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == keyword)
+    }
+
+    fn or(&mut self) -> Result<Query> {
+        let mut query = self.and()?;
+        while self.keyword("OR") {
+            self.pos += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query> {
+        let mut query = self.unary()?;
+        loop {
+            if self.keyword("AND") {
+                self.pos += 1;
+            } else if self.keyword("OR") || matches!(self.peek(), None | Some(Token::Close)) {
+                return Ok(query);
+            }
+            query = Query::And(Box::new(query), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Query> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        match token {
+            Some(Token::Word(w)) if w == "NOT" => Ok(Query::Not(Box::new(self.unary()?))),
+            Some(Token::Word(w)) if w == "AND" || w == "OR" => {
+                bail!("Requête invalide : {w} sans terme avant")
+            }
+            Some(Token::Word(w)) => term(w),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    bail!("Requête invalide : parenthèse non fermée");
+                }
+                self.pos += 1;
+                Ok(query)
+            }
+            Some(Token::Close) => bail!("Requête invalide : parenthèse fermante en trop"),
+            None => bail!("Requête invalide : terme manquant en fin de requête"),
+        }
+    }
+}
+
+/// Parse `N`, `A..B`, `A..` or `..B` into an inclusive range within `min..=max`.
+fn range<T>(value: &str, min: T, max: T) -> Option<(T, T)>
+where
+    T: FromStr + Copy,
+{
+    let bound = |s: &str, default: T| {
+        if s.is_empty() {
+            Some(default)
+        } else {
+            s.parse().ok()
+        }
+    };
+    match value.split_once("..") {
+        Some((from, to)) => Some((bound(from, min)?, bound(to, max)?)),
+        None => {
+            let v = value.parse().ok()?;
+            Some((v, v))
+        }
+    }
+}
+
+fn rating(op: &str, value: &str) -> Option<Query> {
+    if op == ":" {
+        let (from, to) = range::<u8>(value, 0, 5)?;
+        return Some(Query::Rating(from, to));
+    }
+    let n: u8 = value.parse().ok()?;
+    let (from, to) = match op {
+        ">=" => (n, 5),
+        ">" => (n.checked_add(1)?, 5),
+        "<=" => (0, n),
+        // `rating<0` matches nothing
+        "<" => n.checked_sub(1).map_or((1, 0), |m| (0, m)),
+        "=" => (n, n),
+        _ => return None,
+    };
+    Some(Query::Rating(from, to))
+}
+
+fn term(word: &str) -> Result<Query> {
+    if let Some(rest) = word.strip_prefix("rating") {
+        let op_len = rest
+            .find(|c: char| !matches!(c, '<' | '>' | '=' | ':'))
+            .unwrap_or(rest.len());
+        let (op, value) = rest.split_at(op_len);
+        if !op.is_empty() {
+            return rating(op, value)
+                .ok_or_else(|| anyhow::anyhow!("Requête invalide : note « {word} »"));
+        }
+    }
+    let Some((field, value)) = word.split_once(':') else {
+        return Ok(Query::Text(word.to_string()));
+    };
+    if value.is_empty() {
+        bail!("Requête invalide : valeur manquante après « {field}: »");
+    }
+    let value = value.to_string();
+    Ok(match field {
+        "tag" => Query::Tag(value),
+        "person" => Query::Person(value),
+        "album" => Query::Album(value),
+        "label" => Query::Label(value.to_lowercase()),
+        "caption" => Query::Caption(value),
+        "year" => {
+            let (from, to) = range::<u16>(&value, 0, 9999)
+                .ok_or_else(|| anyhow::anyhow!("Requête invalide : année « {value} »"))?;
+            Query::Year(from, to)
+        }
+        "is" if value == "favorite" || value == "fav" => Query::Favorite,
+        "is" if value == "hidden" => Query::Hidden,
+        "is" => bail!("Requête invalide : « is:{value} » (is:favorite ou is:hidden)"),
+        _ => bail!("Requête invalide : champ inconnu « {field} »"),
+    })
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        if parser.tokens.is_empty() {
+            bail!("Requête vide");
+        }
+        let query = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            bail!("Requête invalide : parenthèse fermante en trop");
+        }
+        Ok(query)
+    }
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl Query {
+    /// `true` if the photo at relative path `file` is selected. Tags compare
+    /// without case nor accents and through their aliases, people and albums
+    /// without case; the year is the first component of the path.
+    pub fn matches(&self, metadata: &Metadata, file: &str) -> bool {
+        match self {
+            Query::Tag(tag) => metadata.has_tag(file, tag),
+            Query::Person(person) => metadata.get_people(file).iter().any(|p| same(p, person)),
+            Query::Album(name) => metadata
+                .albums
+                .iter()
+                .any(|(album, files)| same(album, name) && files.iter().any(|f| f == file)),
+            Query::Label(label) => metadata.get_label(file) == Some(label.as_str()),
+            Query::Caption(text) => metadata
+                .get_caption(file)
+                .is_some_and(|c| contains(c, text)),
+            Query::Text(text) => {
+                let name = file.rsplit('/').next().unwrap_or(file);
+                Query::Tag(text.clone()).matches(metadata, file)
+                    || Query::Person(text.clone()).matches(metadata, file)
+                    || contains(name, text)
+                    || Query::Caption(text.clone()).matches(metadata, file)
+            }
+            Query::Rating(from, to) => {
+                (*from..=*to).contains(&metadata.get_rating(file).unwrap_or(0))
+            }
+            Query::Year(from, to) => file
+                .split('/')
+                .next()
+                .and_then(|y| y.parse::<u16>().ok())
+                .is_some_and(|y| (*from..=*to).contains(&y)),
+            Query::Favorite => metadata.is_favorite(file),
+            Query::Hidden => metadata.is_hidden(file),
+            Query::And(a, b) => a.matches(metadata, file) && b.matches(metadata, file),
+            Query::Or(a, b) => a.matches(metadata, file) || b.matches(metadata, file),
+            Query::Not(q) => !q.matches(metadata, file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Query {
+        text.parse().unwrap()
+    }
+
+    fn tag(t: &str) -> Box<Query> {
+        Box::new(Query::Tag(t.into()))
+    }
+
+    #[test]
+    fn parses_precedence_and_implicit_and() {
+        assert_eq!(
+            parse("tag:a OR tag:b tag:c"),
+            Query::Or(tag("a"), Box::new(Query::And(tag("b"), tag("c"))))
+        );
+        assert_eq!(
+            parse("(tag:a OR tag:b) AND NOT tag:c"),
+            Query::And(
+                Box::new(Query::Or(tag("a"), tag("b"))),
+                Box::new(Query::Not(tag("c")))
+            )
+        );
+        assert_eq!(parse(r#"tag:"noel 2020""#), Query::Tag("noel 2020".into()));
+    }
+
+    #[test]
+    fn parses_ratings_and_years() {
+        assert_eq!(parse("rating>=4"), Query::Rating(4, 5));
+        assert_eq!(parse("rating>3"), Query::Rating(4, 5));
+        assert_eq!(parse("rating<2"), Query::Rating(0, 1));
+        assert_eq!(parse("rating:0"), Query::Rating(0, 0));
+        assert_eq!(parse("rating:2..3"), Query::Rating(2, 3));
+        assert_eq!(parse("year:2020"), Query::Year(2020, 2020));
+        assert_eq!(parse("year:2019..2021"), Query::Year(2019, 2021));
+        assert_eq!(parse("year:..2010"), Query::Year(0, 2010));
+        assert_eq!(parse("is:favorite"), Query::Favorite);
+        assert_eq!(parse("is:hidden"), Query::Hidden);
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for text in [
+            "",
+            "tag:",
+            "color:red",
+            "rating>=x",
+            "year:20a0",
+            "(tag:a",
+            "tag:a)",
+            "tag:a AND",
+            "OR tag:a",
+            "tag:\"a",
+            "is:blurry",
+        ] {
+            assert!(text.parse::<Query>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn matches_metadata() {
+        let mut meta = Metadata::default();
+        meta.add_tag("2019/a.jpg", "Vacances");
+        meta.set_rating("2019/a.jpg", Some(5));
+        meta.add_tag("2020/b.jpg", "vacances");
+        meta.add_tag("2020/b.jpg", "flou");
+        meta.set_rating("2020/b.jpg", Some(4));
+        meta.add_person("2022/c.jpg", "Léa");
+        meta.set_caption("2022/c.jpg", "Anniversaire à la plage");
+        meta.set_favorite("2022/c.jpg", true);
+        meta.add_to_album("Best of", "2022/c.jpg");
+        meta.set_label("2020/b.jpg", Some("red"));
+        meta.set_hidden("2020/b.jpg", true);
+        meta.set_tag_alias("holidays", "vacances");
+        let files = ["2019/a.jpg", "2020/b.jpg", "2022/c.jpg"];
+        let select = |text: &str| -> Vec<&str> {
+            let query = parse(text);
+            files
+                .iter()
+                .copied()
+                .filter(|f| query.matches(&meta, f))
+                .collect()
+        };
+
+        assert_eq!(
+            select("tag:vacances AND rating>=4 AND year:2019..2021 AND NOT tag:flou"),
+            ["2019/a.jpg"]
+        );
+        assert_eq!(select("rating:0"), ["2022/c.jpg"]);
+        assert_eq!(
+            select("person:léa OR tag:flou"),
+            ["2020/b.jpg", "2022/c.jpg"]
+        );
+        assert_eq!(
+            select("caption:plage is:favorite album:\"best of\""),
+            ["2022/c.jpg"]
+        );
+        assert_eq!(select("b.jpg"), ["2020/b.jpg"]);
+        assert_eq!(select("label:Red"), ["2020/b.jpg"]);
+        assert_eq!(select("NOT is:hidden"), ["2019/a.jpg", "2022/c.jpg"]);
+        assert_eq!(select("Léa"), ["2022/c.jpg"]);
+        assert_eq!(select("tag:HOLIDAYS"), ["2019/a.jpg", "2020/b.jpg"]);
+    }
+}
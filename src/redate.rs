@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use console::style;
+use std::fs;
+use std::path::Path;
+
+use crate::journal;
+use crate::metadata::Metadata;
+use crate::sort::{DestPath, hash_file, resolve_dest_path};
+use crate::thumb;
+
+/// Parse a date given on the command line: `2020-07-15 14:30:22`, with a `T`,
+/// without seconds, or a bare day (noon then, the middle of the day being the
+/// safest guess for a scan).
+pub fn parse_datetime(value: &str) -> Result<NaiveDateTime> {
+    let value = value.trim();
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(dt);
+        }
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(12, 0, 0))
+        .with_context(|| format!("Date invalide : {value} (attendu AAAA-MM-JJ [HH:MM[:SS]])"))
+}
+
+/// Give `file` (relative to `dir`) a manual capture date and re-file it under
+/// the name and year folder that date calls for. Returns its new relative
+/// path.
+pub fn redate(dir: &Path, file: &str, date: NaiveDateTime) -> Result<String> {
+    let src = dir.join(file);
+    if !src.is_file() {
+        anyhow::bail!("Fichier introuvable : {file}");
+    }
+    let ext = src
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // The original name kept by `sort --keep-name` stays
+    let stem = src.file_stem().unwrap_or_default().to_string_lossy();
+    let original = stem.split_once("__").map(|(_, original)| original);
+
+    let dest = match resolve_dest_path(dir, None, &date, &ext, original, &hash_file(&src)?) {
+        DestPath::Free(dest) => Some(dest),
+        DestPath::Identical(dest) if dest == src => None,
+        DestPath::Identical(dest) => anyhow::bail!(
+            "Un fichier identique existe déjà : {}",
+            dest.strip_prefix(dir).unwrap_or(&dest).display()
+        ),
+    };
+    let Some(dest) = dest else {
+        let mut metadata = Metadata::load(dir)?;
+        metadata.set_date_override(file, Some(date));
+        metadata.save(dir)?;
+        return Ok(file.to_string());
+    };
+
+    let to = dest
+        .strip_prefix(dir)
+        .unwrap_or(&dest)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let op = journal::Operation::Move {
+        from: file.to_string(),
+        to: to.clone(),
+    };
+    journal::run(dir, &op, || {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&src, &dest)?;
+        thumb::invalidate_thumb(dir, file);
+        let mut metadata = Metadata::load(dir)?;
+        metadata.rename_file(file, &to);
+        metadata.set_date_override(&to, Some(date));
+        metadata.save(dir)
+    })?;
+    Ok(to)
+}
+
+pub fn run_redate(dir: &Path, file: &str, date: NaiveDateTime) -> Result<()> {
+    journal::recover_and_report(dir)?;
+    let to = redate(dir, file, date)?;
+    if to == file {
+        println!(
+            "  {} {file} daté du {}",
+            style("✔").green().bold(),
+            date.format("%Y-%m-%d %H:%M:%S")
+        );
+    } else {
+        println!(
+            "  {} {file} daté du {} → {}",
+            style("✔").green().bold(),
+            date.format("%Y-%m-%d %H:%M:%S"),
+            style(&to).white().bold()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_redate_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_command_line_dates() {
+        let dt = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            parse_datetime("1987-06-01 08:15:30").unwrap(),
+            dt("1987-06-01 08:15:30")
+        );
+        assert_eq!(
+            parse_datetime("1987-06-01T08:15").unwrap(),
+            dt("1987-06-01 08:15:00")
+        );
+        assert_eq!(
+            parse_datetime("1987-06-01").unwrap(),
+            dt("1987-06-01 12:00:00")
+        );
+        assert!(parse_datetime("01/06/1987").is_err());
+    }
+
+    #[test]
+    fn redate_moves_the_photo_and_its_metadata() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2023")).unwrap();
+        let file = "2023/2023-02-10_09-00-00__scan_042.jpg";
+        fs::write(tmp.join(file), "scan").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag(file, "grand-mere");
+        meta.add_to_album("Famille", file);
+        meta.save(&tmp).unwrap();
+
+        let date = parse_datetime("1987-06-01 08:15").unwrap();
+        let to = redate(&tmp, file, date).unwrap();
+        assert_eq!(to, "1987/1987-06-01_08-15-00__scan_042.jpg");
+        assert!(tmp.join(&to).is_file());
+        assert!(!tmp.join(file).exists());
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags(&to), ["grand-mere"]);
+        assert_eq!(meta.get_date_override(&to), Some(date));
+        assert_eq!(meta.albums["Famille"], [to.as_str()]);
+
+        // Same date again: already in place
+        assert_eq!(redate(&tmp, &to, date).unwrap(), to);
+        assert!(redate(&tmp, "2023/missing.jpg", date).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
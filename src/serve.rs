@@ -1,42 +1,199 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
 use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
 
-use crate::gallery::{collect_photos, generate_html};
-use crate::metadata::Metadata;
+use crate::develop::{self, Developer};
+use crate::gallery::{
+    GalleryOptions, cache_capture_times, cache_date_sources, collect_photos, generate_data,
+    generate_html, load_metadata,
+};
+use crate::inbox;
+use crate::journal;
+use crate::live::{self, Broadcaster, Change};
+use crate::metadata::{Conflict, FileChange, FileInfo, Metadata, Resolution};
+use crate::multipart;
+use crate::prefs::{self, Prefs};
+use crate::query::Query;
+use crate::redate;
+use crate::sort;
 use crate::thumb;
+use crate::trash;
+
+/// Maximum accepted size for a single uploaded file (200 Mo).
+const MAX_UPLOAD_SIZE: usize = 200 * 1024 * 1024;
+
+/// Maximum accepted size for a browser's preferences (64 Ko).
+const MAX_PREFS_SIZE: usize = 64 * 1024;
+
+/// Page size of `GET /api/photos` when the client gives none, and the
+/// largest it may ask for.
+const DEFAULT_PER_PAGE: usize = 100;
+const MAX_PER_PAGE: usize = 1000;
+
+/// Longest wait for the requests under way when the server stops.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Quiet time the watcher waits for before looking at the library again: a
+/// `sort` run adds photos by the hundred.
+const WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// Cookie keeping the access token once given in the gallery's URL.
+const TOKEN_COOKIE: &str = "photo_sort_token";
+
+/// Credentials required by `serve --auth`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicAuth {
+    pub user: String,
+    pub password: String,
+}
+
+impl BasicAuth {
+    /// Parse `utilisateur:mot_de_passe`.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.split_once(':') {
+            Some((user, password)) if !user.is_empty() && !password.is_empty() => Ok(Self {
+                user: user.to_string(),
+                password: password.to_string(),
+            }),
+            _ => {
+                anyhow::bail!("Identifiants invalides : {value} (attendu utilisateur:mot_de_passe)")
+            }
+        }
+    }
+
+    /// Check the value of an `Authorization: Basic …` header.
+    fn accepts(&self, encoded: &str) -> bool {
+        let Ok(decoded) = BASE64_STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+        let expected = format!("{}:{}", self.user, self.password);
+        same_secret(&decoded, expected.as_bytes())
+    }
+}
+
+/// Compare two secrets in a time that doesn't tell how much of them matched.
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Options for `run_serve`.
+#[derive(Debug, Default, Clone)]
+pub struct ServeOptions {
+    /// Secret token enabling guest uploads via `/guest?token=…`.
+    pub guest_token: Option<String>,
+    /// Login and password asked by the browser (HTTP Basic) for everything
+    /// but the guest page.
+    pub auth: Option<BasicAuth>,
+    /// Access token accepted instead: `Authorization: Bearer …`, or
+    /// `?token=…` in the URL, then kept in a cookie.
+    pub token: Option<String>,
+    /// External tool rendering RAW files for the lightbox.
+    pub raw_developer: Option<Developer>,
+    /// Language of the gallery's interface, among others.
+    pub gallery: GalleryOptions,
+}
+
+/// Compressing smaller responses isn't worth it.
+const MIN_GZIP_SIZE: usize = 1024;
+
+/// One generation of the gallery HTML, with what serving it needs.
+struct EncodedPage {
+    html: Arc<String>,
+    etag: String,
+    /// `html` gzip-compressed: several Mo of inlined JSON shrink a lot.
+    gzip: Vec<u8>,
+}
 
 /// Server state: caches the photo index and generated HTML.
 pub struct ServerState {
     pub dir: PathBuf,
+    options: ServeOptions,
+    /// Also serializes the saves of the request threads; saves from other
+    /// processes are merged by `Metadata::save`.
     metadata: Mutex<Metadata>,
+    prefs: Mutex<Prefs>,
     photo_index: Mutex<HashMap<String, Vec<String>>>,
     html_cache: Mutex<Option<Arc<String>>>,
+    /// ETag and compressed body of the cached HTML, rebuilt when it changes.
+    encoded_page: Mutex<Option<Arc<EncodedPage>>>,
     cache_gen: AtomicU64,
+    /// Galleries following the changes on `/api/events`.
+    live: Broadcaster,
+    /// Requests being handled, waited for when the server stops.
+    in_flight: AtomicUsize,
+}
+
+/// The server state for a request, which counts as under way while it lives.
+struct InFlight(Arc<ServerState>);
+
+impl InFlight {
+    fn start(state: &Arc<ServerState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(state))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl ServerState {
     /// Build the initial state: canonicalize dir, load metadata, collect photos,
     /// and pre-generate the HTML so the first request is instant.
-    pub fn new(dir: &Path) -> Result<Arc<Self>> {
+    pub fn new(dir: &Path, options: ServeOptions) -> Result<Arc<Self>> {
         let dir = dir
             .canonicalize()
             .with_context(|| format!("Dossier introuvable : {}", dir.display()))?;
-        let metadata = Metadata::load(&dir)?;
         let photo_index = collect_photos(&dir);
-        let html = generate_html(&photo_index, &metadata);
+        let metadata = load_metadata(&dir, &photo_index)?;
+        let prefs = Prefs::load(&dir)?;
+        let html = generate_html(&photo_index, &metadata, &options.gallery)?;
         Ok(Arc::new(Self {
             dir,
+            options,
             metadata: Mutex::new(metadata),
+            prefs: Mutex::new(prefs),
             photo_index: Mutex::new(photo_index),
             html_cache: Mutex::new(Some(Arc::new(html))),
+            encoded_page: Mutex::new(None),
             cache_gen: AtomicU64::new(0),
+            live: Broadcaster::default(),
+            in_flight: AtomicUsize::new(0),
         }))
     }
 
+    /// Stop cleanly once no more requests come in: end the event streams,
+    /// give the requests under way up to `grace` to finish, then save the
+    /// metadata if a change is still unsaved.
+    fn shutdown(&self, grace: Duration) -> Result<()> {
+        self.live.close();
+        let deadline = std::time::Instant::now() + grace;
+        while self.in_flight.load(Ordering::SeqCst) > 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let left = self.in_flight.load(Ordering::SeqCst);
+        if left > 0 {
+            eprintln!(
+                "  {} {left} requête(s) interrompue(s)",
+                console::style("⚠").yellow().bold()
+            );
+        }
+        let mut meta = self.metadata.lock().unwrap();
+        if meta.has_unsaved_changes() {
+            meta.save(&self.dir)?;
+        }
+        Ok(())
+    }
+
     /// Return the cached HTML, regenerating it if the cache was invalidated.
     pub fn get_cached_html(&self) -> Arc<String> {
         // Fast path: cache hit
@@ -51,7 +208,14 @@ impl ServerState {
         let gen_before = self.cache_gen.load(Ordering::Acquire);
         let index = self.photo_index.lock().unwrap().clone();
         let meta = self.metadata.lock().unwrap();
-        let html = Arc::new(generate_html(&index, &meta));
+        let html = match generate_html(&index, &meta, &self.options.gallery) {
+            Ok(html) => Arc::new(html),
+            // A custom template broken since the start shows why, uncached
+            Err(e) => {
+                let error = format!("{e:#}").replace('&', "&amp;").replace('<', "&lt;");
+                return Arc::new(format!("<!DOCTYPE html>\n<pre>{error}</pre>"));
+            }
+        };
         drop(meta);
 
         // Only store if no mutation happened while we were generating
@@ -63,6 +227,26 @@ impl ServerState {
         html
     }
 
+    /// The cached HTML with its ETag and gzip body, compressed once per
+    /// generation rather than per request.
+    fn get_encoded_page(&self) -> Arc<EncodedPage> {
+        let html = self.get_cached_html();
+        let mut encoded = self.encoded_page.lock().unwrap();
+        if let Some(page) = encoded.as_ref()
+            && Arc::ptr_eq(&page.html, &html)
+        {
+            return Arc::clone(page);
+        }
+        let page = Arc::new(EncodedPage {
+            // Weak: the same for the compressed and plain bodies
+            etag: format!("W/\"{}\"", &blake3::hash(html.as_bytes()).to_hex()[..16]),
+            gzip: gzip(html.as_bytes()),
+            html,
+        });
+        *encoded = Some(Arc::clone(&page));
+        page
+    }
+
     /// Bump the generation counter and clear the HTML cache.
     fn invalidate_cache(&self) {
         self.cache_gen.fetch_add(1, Ordering::Release);
@@ -70,6 +254,160 @@ impl ServerState {
         *cache = None;
     }
 
+    /// Return `true` if `token` matches the configured guest token.
+    fn guest_allowed(&self, token: Option<&String>) -> bool {
+        match (&self.options.guest_token, token) {
+            (Some(expected), Some(given)) => same_secret(expected.as_bytes(), given.as_bytes()),
+            _ => false,
+        }
+    }
+
+    /// How the request proves it may use the server, if it does: always
+    /// allowed without `--auth` nor `--token`.
+    fn access(&self, req: &Request, url: &str) -> Option<Access> {
+        let (auth, token) = (&self.options.auth, &self.options.token);
+        if auth.is_none() && token.is_none() {
+            return Some(Access::Open);
+        }
+        let token_ok = |given: &str| {
+            token
+                .as_ref()
+                .is_some_and(|t| same_secret(given.as_bytes(), t.as_bytes()))
+        };
+        for header in req.headers() {
+            let value = header.value.as_str();
+            if header.field.equiv("Authorization") {
+                let (scheme, credentials) = value.split_once(' ').unwrap_or((value, ""));
+                if scheme.eq_ignore_ascii_case("Basic")
+                    && auth.as_ref().is_some_and(|a| a.accepts(credentials))
+                    || scheme.eq_ignore_ascii_case("Bearer") && token_ok(credentials.trim())
+                {
+                    return Some(Access::Granted);
+                }
+            } else if header.field.equiv("Cookie")
+                && value.split(';').any(|pair| {
+                    pair.trim()
+                        .split_once('=')
+                        .is_some_and(|(k, v)| k == TOKEN_COOKIE && token_ok(v))
+                })
+            {
+                return Some(Access::Granted);
+            }
+        }
+        (parse_query(url).get("token").is_some_and(|t| token_ok(t))).then_some(Access::InUrl)
+    }
+
+    /// Insert a new relative path into the photo index (kept sorted).
+    /// `true` if `rel` is a photo of the library (in the index).
+    fn has_photo(&self, rel: &str) -> bool {
+        year_of(rel).is_some_and(|year| {
+            let index = self.photo_index.lock().unwrap();
+            index
+                .get(year)
+                .is_some_and(|files| files.binary_search(&rel.to_string()).is_ok())
+        })
+    }
+
+    fn index_insert(&self, rel: &str) {
+        if let Some(year) = year_of(rel) {
+            let mut index = self.photo_index.lock().unwrap();
+            let files = index.entry(year.to_string()).or_default();
+            // The watcher may have seen it first
+            if let Err(pos) = files.binary_search(&rel.to_string()) {
+                files.insert(pos, rel.to_string());
+            }
+        }
+    }
+
+    /// Take photos the server sorted into the library (approved, uploaded)
+    /// into the index and the metadata, and tell the open galleries.
+    fn add_photos(&self, added: &[String]) {
+        if added.is_empty() {
+            return;
+        }
+        let mut by_year: HashMap<String, Vec<String>> = HashMap::new();
+        for rel in added {
+            self.index_insert(rel);
+            if let Some(year) = year_of(rel) {
+                by_year
+                    .entry(year.to_string())
+                    .or_default()
+                    .push(rel.clone());
+            }
+        }
+        let mut meta = self.metadata.lock().unwrap();
+        let before = meta.revision();
+        cache_capture_times(&self.dir, &by_year, &mut meta);
+        cache_date_sources(&self.dir, &by_year, &mut meta);
+        let rev = meta.revision();
+        let saved = rev != before && meta.save(&self.dir).is_ok();
+        drop(meta);
+        self.invalidate_cache();
+        if saved {
+            self.live.publish(Change::Metadata { rev });
+        }
+        for path in added {
+            self.live.publish(Change::Added { path: path.clone() });
+        }
+        thumb::spawn_prewarm(self.dir.clone(), added.to_vec());
+    }
+
+    /// Bring the photo index in line with the disk after changes made
+    /// outside the server (a `sort` run into the library, a file manager…),
+    /// and tell the open galleries. Returns the photos added and removed.
+    fn rescan(&self) -> Result<(Vec<String>, Vec<String>)> {
+        let fresh = collect_photos(&self.dir);
+        let flat = |index: &HashMap<String, Vec<String>>| -> HashSet<String> {
+            index.values().flatten().cloned().collect()
+        };
+        let index = self.photo_index.lock().unwrap();
+        let (before, after) = (flat(&index), flat(&fresh));
+        let mut added: Vec<String> = after.difference(&before).cloned().collect();
+        let mut removed: Vec<String> = before.difference(&after).cloned().collect();
+        if added.is_empty() && removed.is_empty() {
+            return Ok((added, removed));
+        }
+        drop(index);
+        if !added.is_empty() {
+            let mut meta = self.metadata.lock().unwrap();
+            // Edits a failed save left in memory are merged into the disk
+            // first, not to be lost
+            if meta.has_unsaved_changes() {
+                meta.save(&self.dir)?;
+            }
+            // What the sort run saved, the capture times of the new photos,
+            // and the metadata of photos moved by hand
+            *meta = load_metadata(&self.dir, &fresh)?;
+        }
+        *self.photo_index.lock().unwrap() = fresh;
+        added.sort();
+        removed.sort();
+        for rel in &removed {
+            thumb::invalidate_thumb(&self.dir, rel);
+        }
+        if !added.is_empty() {
+            thumb::spawn_prewarm(self.dir.clone(), added.clone());
+        }
+        self.invalidate_cache();
+        for path in &removed {
+            self.live.publish(Change::Removed { path: path.clone() });
+        }
+        for path in &added {
+            self.live.publish(Change::Added { path: path.clone() });
+        }
+        Ok((added, removed))
+    }
+
+    /// `true` for a path the gallery may show: in a year folder, out of the
+    /// hidden ones (thumbnails…).
+    fn in_library(&self, path: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.dir) else {
+            return false;
+        };
+        let rel = rel.to_string_lossy();
+        year_of(&rel).is_some() && !rel.split('/').any(|part| part.starts_with('.'))
+    }
+
     /// Return all relative photo paths (flat list) from the index.
     pub fn all_photo_rels(&self) -> Vec<String> {
         let index = self.photo_index.lock().unwrap();
@@ -77,6 +415,70 @@ impl ServerState {
     }
 }
 
+/// Outcome of `ServerState::access` for an allowed request.
+#[derive(Debug, PartialEq)]
+enum Access {
+    /// Neither `--auth` nor `--token`: the server is open.
+    Open,
+    /// Credentials or token in the headers or the cookie.
+    Granted,
+    /// Token in the URL's `?token=`, to move into the cookie.
+    InUrl,
+}
+
+/// Check the request's credentials and answer it when it may not go further:
+/// 401, or the redirection storing a token given in the URL. The guest page
+/// and uploads keep their own token.
+fn check_access(req: Request, state: &ServerState) -> Option<Request> {
+    let url = req.url().to_string();
+    let path = url.split('?').next().unwrap_or(&url);
+    let guest = matches!(
+        (req.method(), path),
+        (&Method::Get, "/guest") | (&Method::Post, "/api/inbox")
+    );
+    if guest {
+        return Some(req);
+    }
+    match state.access(&req, &url) {
+        Some(Access::Open | Access::Granted) => Some(req),
+        // Same URL without the token, which then stays out of the history
+        Some(Access::InUrl) if *req.method() == Method::Get => {
+            let query: Vec<&str> = (url.split_once('?').map(|(_, q)| q).unwrap_or(""))
+                .split('&')
+                .filter(|pair| !pair.is_empty() && !pair.starts_with("token="))
+                .collect();
+            let location = if query.is_empty() {
+                path.to_string()
+            } else {
+                format!("{path}?{}", query.join("&"))
+            };
+            let token = state.options.token.as_deref().unwrap_or_default();
+            let cookie =
+                format!("{TOKEN_COOKIE}={token}; Path=/; Max-Age=31536000; SameSite=Lax; HttpOnly");
+            let resp = Response::empty(StatusCode(303))
+                .with_header(Header::from_bytes(&b"Location"[..], location.as_bytes()).unwrap())
+                .with_header(Header::from_bytes(&b"Set-Cookie"[..], cookie.as_bytes()).unwrap());
+            let _ = req.respond(resp);
+            None
+        }
+        Some(Access::InUrl) => Some(req),
+        None => {
+            let mut resp = json_error(401, "Authentification requise");
+            if state.options.auth.is_some() {
+                resp.add_header(
+                    Header::from_bytes(
+                        &b"WWW-Authenticate"[..],
+                        &b"Basic realm=\"photo-sort\", charset=\"UTF-8\""[..],
+                    )
+                    .unwrap(),
+                );
+            }
+            let _ = req.respond(resp);
+            None
+        }
+    }
+}
+
 /// MIME type from file extension.
 fn mime_type(path: &Path) -> &'static str {
     match path
@@ -91,6 +493,7 @@ fn mime_type(path: &Path) -> &'static str {
         "jpg" | "jpeg" => "image/jpeg",
         "png" => "image/png",
         "gif" => "image/gif",
+        "webp" => "image/webp",
         "heic" | "heif" => "image/heic",
         "cr2" | "cr3" | "nef" | "arw" | "dng" | "orf" | "rw2" | "raf" => "application/octet-stream",
         "tiff" | "tif" => "image/tiff",
@@ -100,9 +503,130 @@ fn mime_type(path: &Path) -> &'static str {
     }
 }
 
+/// Format used by `Last-Modified` and `If-Modified-Since`.
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// `true` if the client's copy is still current: `If-None-Match` lists
+/// `etag`, or, without it, `If-Modified-Since` isn't older than `modified`.
+fn not_modified(req: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    let header = |name: &'static str| {
+        (req.headers().iter())
+            .find(|h| h.field.equiv(name))
+            .map(|h| h.value.as_str().to_string())
+    };
+    if let Some(tags) = header("If-None-Match") {
+        return tags.split(',').map(str::trim).any(|tag| {
+            tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        });
+    }
+    match (header("If-Modified-Since"), modified) {
+        (Some(since), Some(modified)) => {
+            chrono::DateTime::parse_from_rfc2822(&since).is_ok_and(|since| {
+                chrono::DateTime::<chrono::Utc>::from(modified).timestamp() <= since.timestamp()
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Caching headers: the browser keeps the response and asks each time
+/// whether it changed, which costs a 304 instead of the whole file.
+fn with_validators<R: Read>(
+    resp: Response<R>,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> Response<R> {
+    let mut resp = resp
+        .with_header(Header::from_bytes(&b"ETag"[..], etag.as_bytes()).unwrap())
+        .with_header(Header::from_bytes(&b"Cache-Control"[..], &b"no-cache"[..]).unwrap());
+    if let Some(modified) = modified {
+        let date = chrono::DateTime::<chrono::Utc>::from(modified).format(HTTP_DATE);
+        resp.add_header(
+            Header::from_bytes(&b"Last-Modified"[..], date.to_string().as_bytes()).unwrap(),
+        );
+    }
+    resp
+}
+
+/// Send a file from disk, or 304 if the client has it already: its ETag
+/// comes from its size and modification time.
+fn serve_file(req: Request, path: &Path, mime: &str) {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => {
+            let _ = req.respond(json_error(500, "Erreur lecture fichier"));
+            return;
+        }
+    };
+    let meta = file.metadata().ok();
+    let len = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+    let modified = meta.and_then(|m| m.modified().ok());
+    let mtime = (modified.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok()))
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let etag = format!("\"{len:x}-{mtime:x}\"");
+    if not_modified(&req, &etag, modified) {
+        let _ = req.respond(with_validators(
+            Response::empty(StatusCode(304)),
+            &etag,
+            modified,
+        ));
+        return;
+    }
+    let resp = Response::from_file(file)
+        .with_header(Header::from_bytes(&b"Content-Type"[..], mime.as_bytes()).unwrap())
+        .with_header(
+            Header::from_bytes(&b"Content-Length"[..], len.to_string().as_bytes()).unwrap(),
+        );
+    let _ = req.respond(with_validators(resp, &etag, modified));
+}
+
+/// `true` if the client takes gzip-compressed responses.
+fn accepts_gzip(req: &Request) -> bool {
+    (req.headers().iter())
+        .filter(|h| h.field.equiv("Accept-Encoding"))
+        .flat_map(|h| h.value.as_str().split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let quality = (parts.find_map(|param| param.strip_prefix("q=")))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && quality > 0.0
+        })
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+/// Response with `body`, gzip-compressed if the client takes it and it's big
+/// enough to gain from it. `gzipped` is the body already compressed, if any.
+fn encoded_response(
+    req: &Request,
+    body: &[u8],
+    gzipped: Option<&[u8]>,
+    content_type: &str,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let vary = Header::from_bytes(&b"Vary"[..], &b"Accept-Encoding"[..]).unwrap();
+    let content_type = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    if body.len() >= MIN_GZIP_SIZE && accepts_gzip(req) {
+        let data = gzipped.map_or_else(|| gzip(body), <[u8]>::to_vec);
+        return Response::from_data(data)
+            .with_header(content_type)
+            .with_header(Header::from_bytes(&b"Content-Encoding"[..], &b"gzip"[..]).unwrap())
+            .with_header(vary);
+    }
+    Response::from_data(body.to_vec())
+        .with_header(content_type)
+        .with_header(vary)
+}
+
 /// JSON error response helper.
 fn json_error(status: u16, msg: &str) -> Response<std::io::Cursor<Vec<u8>>> {
-    let body = format!("{{\"error\":\"{}\"}}", msg.replace('"', "\\\""));
+    let body = serde_json::json!({"error": msg}).to_string();
     Response::from_string(body)
         .with_status_code(StatusCode(status))
         .with_header(
@@ -112,12 +636,45 @@ fn json_error(status: u16, msg: &str) -> Response<std::io::Cursor<Vec<u8>>> {
 
 /// JSON success response helper.
 fn json_ok(msg: &str) -> Response<std::io::Cursor<Vec<u8>>> {
-    let body = format!("{{\"ok\":\"{}\"}}", msg.replace('"', "\\\""));
+    let body = serde_json::json!({"ok": msg}).to_string();
     Response::from_string(body).with_header(
         Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
     )
 }
 
+/// Save the metadata, invalidate the HTML cache and reply with the new revision.
+fn store_metadata(
+    state: &ServerState,
+    mut meta: MutexGuard<Metadata>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    match meta.save(&state.dir) {
+        Ok(()) => {
+            let rev = meta.revision();
+            drop(meta);
+            state.invalidate_cache();
+            state.live.publish(Change::Metadata { rev: rev.clone() });
+            let body = serde_json::json!({"ok": "Metadata sauvegardé", "rev": rev});
+            Response::from_string(body.to_string()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            )
+        }
+        Err(e) => json_error(500, &e.to_string()),
+    }
+}
+
+/// 409 response listing the metadata conflicts to resolve.
+fn conflicts_response(conflicts: &[Conflict]) -> Response<std::io::Cursor<Vec<u8>>> {
+    let body = serde_json::json!({
+        "error": "Metadata modifié entre-temps",
+        "conflicts": conflicts,
+    });
+    Response::from_string(body.to_string())
+        .with_status_code(StatusCode(409))
+        .with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+        )
+}
+
 /// Read request body as string.
 fn read_body(req: &mut Request) -> Result<String> {
     let mut body = String::new();
@@ -127,6 +684,48 @@ fn read_body(req: &mut Request) -> Result<String> {
     Ok(body)
 }
 
+/// Read request body as raw bytes, refusing bodies larger than `limit`.
+fn read_body_bytes(req: &mut Request, limit: usize) -> Result<Vec<u8>> {
+    if req.body_length().is_some_and(|len| len > limit) {
+        anyhow::bail!("Fichier trop volumineux");
+    }
+    let mut body = Vec::new();
+    req.as_reader()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .context("Failed to read request body")?;
+    if body.len() > limit {
+        anyhow::bail!("Fichier trop volumineux");
+    }
+    Ok(body)
+}
+
+/// Client id from the request's prefs cookie, if any.
+fn request_client(req: &Request) -> Option<String> {
+    req.headers()
+        .iter()
+        .filter(|h| h.field.equiv("Cookie"))
+        .find_map(|h| prefs::client_from_cookie(h.value.as_str()))
+}
+
+/// Attach a JSON body and, for new clients, the `Set-Cookie` header.
+fn prefs_response(
+    body: String,
+    new_client: Option<&str>,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let mut resp = Response::from_string(body).with_header(
+        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+    );
+    if let Some(id) = new_client {
+        let cookie = format!(
+            "{}={id}; Path=/; Max-Age=31536000; SameSite=Lax",
+            prefs::PREFS_COOKIE
+        );
+        resp.add_header(Header::from_bytes(&b"Set-Cookie"[..], cookie.as_bytes()).unwrap());
+    }
+    resp
+}
+
 /// Parse query string into key-value pairs.
 pub fn parse_query(url: &str) -> HashMap<String, String> {
     let mut params = HashMap::new();
@@ -150,15 +749,14 @@ pub fn urldecode(s: &str) -> String {
     let bytes = s.as_bytes();
     let mut i = 0;
     while i < bytes.len() {
-        if bytes[i] == b'%' && i + 2 < bytes.len() {
-            if let Ok(val) = u8::from_str_radix(
-                &String::from_utf8_lossy(&bytes[i + 1..i + 3]),
-                16,
-            ) {
-                result.push(val);
-                i += 3;
-                continue;
-            }
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Ok(val) =
+                u8::from_str_radix(&String::from_utf8_lossy(&bytes[i + 1..i + 3]), 16)
+        {
+            result.push(val);
+            i += 3;
+            continue;
         }
         if bytes[i] == b'+' {
             result.push(b' ');
@@ -170,6 +768,11 @@ pub fn urldecode(s: &str) -> String {
     String::from_utf8_lossy(&result).to_string()
 }
 
+/// Decode a URL path segment: like `urldecode`, but `+` stays a literal `+`.
+pub fn decode_path(s: &str) -> String {
+    urldecode(&s.replace('+', "%2B"))
+}
+
 /// Validate that a relative path doesn't escape the base dir.
 pub fn safe_path(base: &Path, relative: &str) -> Option<PathBuf> {
     let clean = relative.replace('\\', "/");
@@ -196,8 +799,194 @@ fn year_of(rel: &str) -> Option<&str> {
     }
 }
 
+/// Move `src` (`src_path` on disk) into the folder `dest_dir`, keeping its
+/// name. Returns its new relative path.
+fn move_to_folder(
+    state: &ServerState,
+    src_path: &Path,
+    src: &str,
+    dest_dir: &str,
+) -> Result<String> {
+    let dest_subdir = state.dir.join(dest_dir);
+    std::fs::create_dir_all(&dest_subdir)?;
+    let filename = src_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let dest_path = dest_subdir.join(&filename);
+    let new_rel = format!("{dest_dir}/{filename}");
+    let op = journal::Operation::Move {
+        from: src.to_string(),
+        to: new_rel.clone(),
+    };
+    // Taken before the journal lock, in the same order as the other handlers
+    let mut meta = state.metadata.lock().unwrap();
+    journal::run(&state.dir, &op, || {
+        std::fs::rename(src_path, &dest_path)?;
+        thumb::invalidate_thumb(&state.dir, src);
+        meta.rename_file(src, &new_rel);
+        let _ = meta.save(&state.dir);
+        Ok(())
+    })?;
+    Ok(new_rel)
+}
+
+/// Update the photo index in place after a move: out of its old year, into
+/// its new one (sorted).
+fn reindex_moved(state: &ServerState, from: &str, to: &str) {
+    let mut index = state.photo_index.lock().unwrap();
+    if let Some(old_year) = year_of(from)
+        && let Some(files) = index.get_mut(old_year)
+    {
+        files.retain(|f| f != from);
+        if files.is_empty() {
+            index.remove(old_year);
+        }
+    }
+    if let Some(new_year) = year_of(to) {
+        let files = index.entry(new_year.to_string()).or_default();
+        // The watcher may have seen it first
+        if let Err(pos) = files.binary_search(&to.to_string()) {
+            files.insert(pos, to.to_string());
+        }
+    }
+}
+
+/// The selection asked of `GET /api/photos`: `q` in the query language of
+/// `list`, narrowed by `tag`, `person`, `album`, `year` (`2020` or
+/// `2019..2021`) and `min_rating`. `None` selects every photo.
+fn photo_selection(params: &HashMap<String, String>) -> Result<Option<Query>> {
+    let mut terms = Vec::new();
+    if let Some(text) = params.get("q").filter(|q| !q.trim().is_empty()) {
+        terms.push(text.parse::<Query>()?);
+    }
+    if let Some(tag) = params.get("tag") {
+        terms.push(Query::Tag(tag.clone()));
+    }
+    if let Some(person) = params.get("person") {
+        terms.push(Query::Person(person.clone()));
+    }
+    if let Some(album) = params.get("album") {
+        terms.push(Query::Album(album.clone()));
+    }
+    if let Some(year) = params.get("year") {
+        match format!("year:{year}").parse::<Query>() {
+            Ok(query @ Query::Year(..)) => terms.push(query),
+            _ => anyhow::bail!("Année invalide : {year}"),
+        }
+    }
+    if let Some(rating) = params.get("min_rating") {
+        match rating.parse::<u8>() {
+            Ok(min @ 0..=5) => terms.push(Query::Rating(min, 5)),
+            _ => anyhow::bail!("Note invalide : {rating} (attendu 0 à 5)"),
+        }
+    }
+    Ok(terms
+        .into_iter()
+        .reduce(|a, b| Query::And(Box::new(a), Box::new(b))))
+}
+
+/// One page of the photos selected by `params`, with their metadata, in the
+/// order of the index (by year, then path).
+fn list_photos(
+    index: &HashMap<String, Vec<String>>,
+    metadata: &Metadata,
+    params: &HashMap<String, String>,
+) -> Result<serde_json::Value> {
+    #[derive(serde::Serialize)]
+    struct Item<'a> {
+        path: &'a str,
+        year: &'a str,
+        #[serde(flatten)]
+        info: Option<&'a FileInfo>,
+    }
+
+    let number = |name: &str, default: usize| -> Result<usize> {
+        match params.get(name) {
+            None => Ok(default),
+            Some(value) => (value.parse::<usize>().ok())
+                .filter(|&n| n > 0)
+                .with_context(|| format!("Paramètre invalide : {name}={value}")),
+        }
+    };
+    let page = number("page", 1)?;
+    let per_page = number("per_page", DEFAULT_PER_PAGE)?.min(MAX_PER_PAGE);
+    let selection = photo_selection(params)?;
+
+    let mut years: Vec<&String> = index.keys().collect();
+    years.sort();
+    let selected: Vec<(&str, &str)> = (years.into_iter())
+        .flat_map(|year| {
+            index[year]
+                .iter()
+                .map(move |file| (year.as_str(), file.as_str()))
+        })
+        .filter(|(_, file)| selection.as_ref().is_none_or(|q| q.matches(metadata, file)))
+        .collect();
+    let photos: Vec<Item> = (selected.iter())
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .map(|&(year, path)| Item {
+            path,
+            year,
+            info: metadata.files.get(path),
+        })
+        .collect();
+    Ok(serde_json::json!({
+        "total": selected.len(),
+        "page": page,
+        "per_page": per_page,
+        "photos": photos,
+    }))
+}
+
+/// What `GET /api/exif` tells of a photo: capture time (with its UTC offset
+/// if recorded), camera and shot settings, GPS position and size, parsed;
+/// then every EXIF field as text in `fields`, for display.
+fn exif_info(path: &Path) -> serde_json::Value {
+    let mut fields: Vec<serde_json::Value> = Vec::new();
+    if let Some(exif) = sort::read_exif(path) {
+        for field in exif.fields() {
+            fields.push(serde_json::json!({
+                "tag": field.tag.to_string(),
+                "value": field.display_value().with_unit(&exif).to_string(),
+            }));
+        }
+    }
+
+    let size = std::fs::metadata(path).map(|meta| meta.len()).ok();
+    if let Some(size) = size {
+        let human = if size >= 1_048_576 {
+            format!("{:.1} Mo", size as f64 / 1_048_576.0)
+        } else {
+            format!("{:.0} Ko", size as f64 / 1024.0)
+        };
+        fields.push(serde_json::json!({
+            "tag": "Taille fichier",
+            "value": format!("{human} ({size} octets)"),
+        }));
+    }
+    if let Ok((w, h)) = image::image_dimensions(path) {
+        fields.push(serde_json::json!({"tag": "Dimensions", "value": format!("{w} × {h}")}));
+    }
+
+    let (taken, offset) = sort::exif_datetime(path).unzip();
+    serde_json::json!({
+        "taken": taken,
+        "offset": offset.flatten().map(|offset| offset.to_string()),
+        "camera": sort::exif_summary(path),
+        "gps": sort::exif_gps(path).map(|(lat, lon)| serde_json::json!({"lat": lat, "lon": lon})),
+        "size": size,
+        "fields": fields,
+    })
+}
+
 /// Handle a single HTTP request.
-pub fn handle_request(mut req: Request, state: &ServerState) {
+pub fn handle_request(req: Request, state: &ServerState) {
+    let Some(mut req) = check_access(req, state) else {
+        return;
+    };
     let url = req.url().to_string();
     let method = req.method().clone();
     let path = url.split('?').next().unwrap_or(&url);
@@ -205,31 +994,93 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
     match (&method, path) {
         // Gallery HTML — served from cache
         (&Method::Get, "/") => {
-            let html = state.get_cached_html();
-            let resp = Response::from_string(html.as_str()).with_header(
-                Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
-                    .unwrap(),
+            let page = state.get_encoded_page();
+            if not_modified(&req, &page.etag, None) {
+                let _ = req.respond(with_validators(
+                    Response::empty(StatusCode(304)),
+                    &page.etag,
+                    None,
+                ));
+                return;
+            }
+            let resp = encoded_response(
+                &req,
+                page.html.as_bytes(),
+                Some(&page.gzip),
+                "text/html; charset=utf-8",
             );
-            let _ = req.respond(resp);
+            let _ = req.respond(with_validators(resp, &page.etag, None));
+        }
+
+        // API: Photo list and metadata revision, for a page following the
+        // changes made elsewhere
+        (&Method::Get, "/api/gallery") => {
+            let index = state.photo_index.lock().unwrap().clone();
+            let meta = state.metadata.lock().unwrap();
+            let data = generate_data(&index, &meta, &state.options.gallery)
+                .and_then(|data| Ok(serde_json::from_str::<serde_json::Value>(&data)?));
+            let rev = meta.revision();
+            drop(meta);
+            match data {
+                Ok(mut data) => {
+                    data["rev"] = rev.into();
+                    let body = data.to_string();
+                    let resp = encoded_response(&req, body.as_bytes(), None, "application/json");
+                    let _ = req.respond(resp);
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(500, &e.to_string()));
+                }
+            }
+        }
+
+        // API: Photos matching a query, page by page, for scripts and other
+        // clients than the gallery
+        (&Method::Get, "/api/photos") => {
+            let index = state.photo_index.lock().unwrap().clone();
+            let meta = state.metadata.lock().unwrap();
+            let listed = list_photos(&index, &meta, &parse_query(&url));
+            drop(meta);
+            match listed {
+                Ok(list) => {
+                    let body = list.to_string();
+                    let resp = encoded_response(&req, body.as_bytes(), None, "application/json");
+                    let _ = req.respond(resp);
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                }
+            }
+        }
+
+        // API: Changes of the library as they happen (Server-Sent Events).
+        // The response never ends: the thread of the request stays with it.
+        (&Method::Get, "/api/events") => {
+            let changes = state.live.subscribe();
+            let mut out = req.into_writer();
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                        Cache-Control: no-cache\r\nConnection: close\r\n\r\n";
+            if out.write_all(head.as_bytes()).is_ok() {
+                live::stream(&mut out, &changes);
+            }
         }
 
-        // API: Save metadata
+        // API: Save metadata. With `?rev=`, the save is refused (409 + list of
+        // conflicts) if the metadata changed since the client loaded it.
         (&Method::Post, "/api/metadata") => {
+            let rev = parse_query(&url).get("rev").cloned();
             match read_body(&mut req) {
                 Ok(body) => match serde_json::from_str::<Metadata>(&body) {
                     Ok(new_meta) => {
                         let mut meta = state.metadata.lock().unwrap();
-                        *meta = new_meta;
-                        match meta.save(&state.dir) {
-                            Ok(()) => {
-                                drop(meta);
-                                state.invalidate_cache();
-                                let _ = req.respond(json_ok("Metadata sauvegardé"));
-                            }
-                            Err(e) => {
-                                let _ = req.respond(json_error(500, &e.to_string()));
-                            }
+                        if rev.is_some_and(|r| r != meta.revision()) {
+                            let conflicts = meta.conflicts(&new_meta);
+                            drop(meta);
+                            let _ = req.respond(conflicts_response(&conflicts));
+                            return;
                         }
+                        meta.replace(new_meta);
+                        let _ = req.respond(store_metadata(state, meta));
                     }
                     Err(e) => {
                         let _ = req.respond(json_error(400, &e.to_string()));
@@ -241,148 +1092,333 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
             }
         }
 
-        // API: Delete photo
-        (&Method::Delete, "/api/photo") => {
-            let params = parse_query(&url);
-            if let Some(file) = params.get("path") {
-                if let Some(full_path) = safe_path(&state.dir, file) {
-                    if full_path.exists() {
-                        match std::fs::remove_file(&full_path) {
-                            Ok(()) => {
-                                thumb::invalidate_thumb(&state.dir, file);
-                                // Update metadata
-                                {
-                                    let mut meta = state.metadata.lock().unwrap();
-                                    meta.files.remove(file.as_str());
-                                    let _ = meta.save(&state.dir);
-                                }
-                                // Update photo index in-place
-                                if let Some(year) = year_of(file) {
-                                    let mut index = state.photo_index.lock().unwrap();
-                                    if let Some(files) = index.get_mut(year) {
-                                        files.retain(|f| f != file);
-                                        if files.is_empty() {
-                                            index.remove(year);
-                                        }
-                                    }
-                                }
-                                state.invalidate_cache();
-                                let _ = req.respond(json_ok("Fichier supprimé"));
-                            }
-                            Err(e) => {
-                                let _ = req.respond(json_error(500, &e.to_string()));
-                            }
-                        }
-                    } else {
-                        let _ = req.respond(json_error(404, "Fichier introuvable"));
+        // API: Merge metadata (re-imported save or diverged client). Without
+        // `resolutions`, conflicts are returned (409) instead of being merged.
+        (&Method::Post, "/api/metadata/merge") => {
+            #[derive(serde::Deserialize)]
+            struct MergeReq {
+                metadata: Metadata,
+                resolutions: Option<HashMap<String, Resolution>>,
+            }
+            let parsed = read_body(&mut req)
+                .and_then(|body| Ok(serde_json::from_str::<MergeReq>(&body)?));
+            let merge = match parsed {
+                Ok(m) => m,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            let mut meta = state.metadata.lock().unwrap();
+            let resolutions = match merge.resolutions {
+                Some(r) => r,
+                None => {
+                    let conflicts = meta.conflicts(&merge.metadata);
+                    if !conflicts.is_empty() {
+                        drop(meta);
+                        let _ = req.respond(conflicts_response(&conflicts));
+                        return;
                     }
-                } else {
-                    let _ = req.respond(json_error(400, "Chemin invalide"));
+                    HashMap::new()
                 }
-            } else {
-                let _ = req.respond(json_error(400, "Paramètre path requis"));
+            };
+            meta.merge(&merge.metadata, &resolutions);
+            let _ = req.respond(store_metadata(state, meta));
+        }
+
+        // API: Change the metadata of one photo (tags, rating…) on top of what
+        // is saved, without sending the whole document nor undoing the
+        // changes saved meanwhile by others
+        (&Method::Patch, _) if path.starts_with("/api/metadata/") => {
+            let rel = decode_path(&path["/api/metadata/".len()..]);
+            if !state.has_photo(&rel) {
+                let _ = req.respond(json_error(404, "Photo introuvable"));
+                return;
+            }
+            let change =
+                read_body(&mut req).and_then(|body| Ok(serde_json::from_str::<FileChange>(&body)?));
+            let change = match change {
+                Ok(change) => change,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            let mut meta = state.metadata.lock().unwrap();
+            if let Err(e) = meta.apply(&rel, &change) {
+                drop(meta);
+                let _ = req.respond(json_error(400, &e.to_string()));
+                return;
             }
+            let _ = req.respond(store_metadata(state, meta));
         }
 
-        // API: Move photo
-        (&Method::Post, "/api/move") => {
-            match read_body(&mut req) {
-                Ok(body) => {
-                    #[derive(serde::Deserialize)]
-                    struct MoveReq {
-                        src: String,
-                        dest_dir: String,
-                    }
-                    match serde_json::from_str::<MoveReq>(&body) {
-                        Ok(mv) => {
-                            let src_path = match safe_path(&state.dir, &mv.src) {
-                                Some(p) => p,
-                                None => {
-                                    let _ = req.respond(json_error(400, "Chemin source invalide"));
-                                    return;
-                                }
-                            };
-                            if !src_path.exists() {
-                                let _ = req.respond(json_error(404, "Fichier source introuvable"));
-                                return;
-                            }
-                            let dest_subdir = state.dir.join(&mv.dest_dir);
-                            if let Err(e) = std::fs::create_dir_all(&dest_subdir) {
-                                let _ = req.respond(json_error(500, &e.to_string()));
-                                return;
-                            }
-                            let filename = src_path
-                                .file_name()
-                                .unwrap_or_default()
-                                .to_string_lossy()
-                                .to_string();
-                            let dest_path = dest_subdir.join(&filename);
-                            match std::fs::rename(&src_path, &dest_path) {
-                                Ok(()) => {
-                                    thumb::invalidate_thumb(&state.dir, &mv.src);
-                                    let new_rel = format!("{}/{}", mv.dest_dir, filename);
-                                    // Update metadata
-                                    {
-                                        let mut meta = state.metadata.lock().unwrap();
-                                        if let Some(info) = meta.files.remove(mv.src.as_str()) {
-                                            meta.files.insert(new_rel.clone(), info);
-                                        }
-                                        let _ = meta.save(&state.dir);
-                                    }
-                                    // Update photo index in-place
-                                    {
-                                        let mut index = state.photo_index.lock().unwrap();
-                                        // Remove from old year
-                                        if let Some(old_year) = year_of(&mv.src) {
-                                            if let Some(files) = index.get_mut(old_year) {
-                                                files.retain(|f| f != &mv.src);
-                                                if files.is_empty() {
-                                                    index.remove(old_year);
-                                                }
-                                            }
-                                        }
-                                        // Insert into new year (sorted)
-                                        if let Some(new_year) = year_of(&new_rel) {
-                                            let files = index
-                                                .entry(new_year.to_string())
-                                                .or_default();
-                                            let pos = files
-                                                .binary_search(&new_rel)
-                                                .unwrap_or_else(|i| i);
-                                            files.insert(pos, new_rel.clone());
+        // API: Undo the latest metadata save (gallery, CLI…)
+        (&Method::Post, "/api/undo") => {
+            let mut meta = state.metadata.lock().unwrap();
+            match meta.undo(&state.dir) {
+                Ok(Some(entry)) => {
+                    let rev = meta.revision();
+                    drop(meta);
+                    state.invalidate_cache();
+                    state.live.publish(Change::Metadata { rev: rev.clone() });
+                    let body = serde_json::json!({
+                        "ok": "Modification annulée",
+                        "rev": rev,
+                        "files": entry.files.len(),
+                    });
+                    let _ = req.respond(Response::from_string(body.to_string()).with_header(
+                        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+                    ));
+                }
+                Ok(None) => {
+                    let _ = req.respond(json_error(404, "Rien à annuler"));
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(500, &e.to_string()));
+                }
+            }
+        }
+
+        // API: Per-browser gallery preferences (keyed by cookie)
+        (&Method::Get, "/api/prefs") => {
+            let (client, new_client) = match request_client(&req) {
+                Some(id) => (id, None),
+                None => {
+                    let id = prefs::new_client_id();
+                    (id.clone(), Some(id))
+                }
+            };
+            let value = state.prefs.lock().unwrap().get(&client);
+            let _ = req.respond(prefs_response(value.to_string(), new_client.as_deref()));
+        }
+
+        (&Method::Post, "/api/prefs") => {
+            let parsed = read_body_bytes(&mut req, MAX_PREFS_SIZE)
+                .and_then(|body| Ok(serde_json::from_slice::<serde_json::Value>(&body)?));
+            let value = match parsed {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            let (client, new_client) = match request_client(&req) {
+                Some(id) => (id, None),
+                None => {
+                    let id = prefs::new_client_id();
+                    (id.clone(), Some(id))
+                }
+            };
+            let mut prefs = state.prefs.lock().unwrap();
+            if let Err(e) = prefs.set(&client, value) {
+                let _ = req.respond(json_error(400, &e.to_string()));
+                return;
+            }
+            match prefs.save(&state.dir) {
+                Ok(()) => {
+                    drop(prefs);
+                    let body = r#"{"ok":"Préférences sauvegardées"}"#.to_string();
+                    let _ = req.respond(prefs_response(body, new_client.as_deref()));
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(500, &e.to_string()));
+                }
+            }
+        }
+
+        // API: Delete photo
+        (&Method::Delete, "/api/photo") => {
+            let params = parse_query(&url);
+            if let Some(file) = params.get("path") {
+                if let Some(full_path) = safe_path(&state.dir, file) {
+                    // Only photos: not the metadata, journal… of the library
+                    if full_path.is_file() && state.has_photo(file) {
+                        let mut meta = state.metadata.lock().unwrap();
+                        let trashed = trash::delete(&state.dir, file, &mut meta);
+                        if trashed.is_ok() {
+                            let _ = meta.save(&state.dir);
+                        }
+                        drop(meta);
+                        match trashed {
+                            Ok(entry) => {
+                                // Update photo index in-place
+                                if let Some(year) = year_of(file) {
+                                    let mut index = state.photo_index.lock().unwrap();
+                                    if let Some(files) = index.get_mut(year) {
+                                        files.retain(|f| f != file);
+                                        if files.is_empty() {
+                                            index.remove(year);
                                         }
                                     }
-                                    state.invalidate_cache();
-                                    let resp_body = format!(
-                                        "{{\"ok\":\"Fichier déplacé\",\"new_path\":\"{}\"}}",
-                                        new_rel.replace('"', "\\\"")
-                                    );
-                                    let resp = Response::from_string(resp_body).with_header(
-                                        Header::from_bytes(
-                                            &b"Content-Type"[..],
-                                            &b"application/json"[..],
-                                        )
-                                        .unwrap(),
-                                    );
-                                    let _ = req.respond(resp);
-                                }
-                                Err(e) => {
-                                    let _ = req.respond(json_error(500, &e.to_string()));
                                 }
+                                state.invalidate_cache();
+                                state.live.publish(Change::Removed { path: file.clone() });
+                                let body = serde_json::json!({
+                                    "ok": "Photo mise à la corbeille",
+                                    "id": entry.id,
+                                });
+                                let resp = Response::from_string(body.to_string()).with_header(
+                                    Header::from_bytes(
+                                        &b"Content-Type"[..],
+                                        &b"application/json"[..],
+                                    )
+                                    .unwrap(),
+                                );
+                                let _ = req.respond(resp);
+                            }
+                            Err(e) => {
+                                let _ = req.respond(json_error(500, &e.to_string()));
                             }
                         }
+                    } else {
+                        let _ = req.respond(json_error(404, "Fichier introuvable"));
+                    }
+                } else {
+                    let _ = req.respond(json_error(400, "Chemin invalide"));
+                }
+            } else {
+                let _ = req.respond(json_error(400, "Paramètre path requis"));
+            }
+        }
+
+        // API: Photos in the trash, most recently deleted first
+        (&Method::Get, "/api/trash") => {
+            let body = serde_json::to_string(&trash::list(&state.dir))
+                .unwrap_or_else(|_| "[]".to_string());
+            let resp = encoded_response(&req, body.as_bytes(), None, "application/json");
+            let _ = req.respond(resp);
+        }
+
+        // API: Put a photo of the trash back where it was, with its metadata
+        (&Method::Post, "/api/restore") => {
+            #[derive(serde::Deserialize)]
+            struct RestoreReq {
+                id: String,
+            }
+            let parsed =
+                read_body(&mut req).and_then(|body| Ok(serde_json::from_str::<RestoreReq>(&body)?));
+            let id = match parsed {
+                Ok(r) => r.id,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            if !trash::contains(&state.dir, &id) {
+                let _ = req.respond(json_error(
+                    404,
+                    &format!("Introuvable dans la corbeille : {id}"),
+                ));
+                return;
+            }
+            let mut meta = state.metadata.lock().unwrap();
+            let restored = trash::restore(&state.dir, &id, &mut meta);
+            let entry = match restored {
+                Ok(entry) => entry,
+                // Another file took its place meanwhile
+                Err(e) => {
+                    drop(meta);
+                    let _ = req.respond(json_error(409, &e.to_string()));
+                    return;
+                }
+            };
+            let _ = meta.save(&state.dir);
+            drop(meta);
+            state.add_photos(std::slice::from_ref(&entry.path));
+            let body = serde_json::json!({"ok": "Photo restaurée", "path": entry.path});
+            let resp = Response::from_string(body.to_string()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            );
+            let _ = req.respond(resp);
+        }
+
+        // API: Move photo, to another folder or re-dated (renamed and re-filed
+        // under the year of its new date)
+        (&Method::Post, "/api/move") => {
+            #[derive(serde::Deserialize)]
+            struct MoveReq {
+                src: String,
+                dest_dir: Option<String>,
+                date: Option<String>,
+            }
+            let mv = match read_body(&mut req)
+                .and_then(|body| Ok(serde_json::from_str::<MoveReq>(&body)?))
+            {
+                Ok(mv) => mv,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            let src_path = match safe_path(&state.dir, &mv.src) {
+                Some(p) => p,
+                None => {
+                    let _ = req.respond(json_error(400, "Chemin source invalide"));
+                    return;
+                }
+            };
+            if !src_path.exists() {
+                let _ = req.respond(json_error(404, "Fichier source introuvable"));
+                return;
+            }
+            let moved = match (&mv.date, &mv.dest_dir) {
+                (Some(date), _) => {
+                    let date = match redate::parse_datetime(date) {
+                        Ok(date) => date,
                         Err(e) => {
                             let _ = req.respond(json_error(400, &e.to_string()));
+                            return;
                         }
+                    };
+                    // Saved by redate: the state follows what it wrote
+                    let mut meta = state.metadata.lock().unwrap();
+                    redate::redate(&state.dir, &mv.src, date).and_then(|new_rel| {
+                        *meta = Metadata::load(&state.dir)?;
+                        Ok(new_rel)
+                    })
+                }
+                (None, Some(dest_dir)) => {
+                    // Only into a year folder, out of the hidden ones
+                    let valid =
+                        safe_path(&state.dir, dest_dir).is_some_and(|p| state.in_library(&p));
+                    if !valid {
+                        let _ = req.respond(json_error(400, "Dossier de destination invalide"));
+                        return;
                     }
+                    move_to_folder(state, &src_path, &mv.src, dest_dir)
+                }
+                (None, None) => {
+                    let _ = req.respond(json_error(400, "Paramètre dest_dir ou date requis"));
+                    return;
+                }
+            };
+            match moved {
+                Ok(new_rel) => {
+                    reindex_moved(state, &mv.src, &new_rel);
+                    let rev = state.metadata.lock().unwrap().revision();
+                    state.invalidate_cache();
+                    state.live.publish(Change::Moved {
+                        from: mv.src.clone(),
+                        to: new_rel.clone(),
+                    });
+                    let body = serde_json::json!({
+                        "ok": "Fichier déplacé",
+                        "new_path": new_rel,
+                        "rev": rev,
+                    });
+                    let _ = req.respond(Response::from_string(body.to_string()).with_header(
+                        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+                    ));
                 }
                 Err(e) => {
-                    let _ = req.respond(json_error(400, &e.to_string()));
+                    let _ = req.respond(json_error(500, &e.to_string()));
                 }
             }
         }
 
-        // API: Rotate photo — no HTML invalidation (JS cache-busts the image)
+        // API: Rotate photo — the JS cache-busts the image; the HTML changes only
+        // when a visual hash is dropped
         (&Method::Post, "/api/rotate") => {
             match read_body(&mut req) {
                 Ok(body) => {
@@ -415,6 +1451,15 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
                             match rotate_image(&full_path, rot.angle) {
                                 Ok(()) => {
                                     thumb::invalidate_thumb(&state.dir, &rot.path);
+                                    // Computed again from the new thumbnail
+                                    let mut meta = state.metadata.lock().unwrap();
+                                    if meta.get_visual_hash(&rot.path).is_some() {
+                                        meta.set_visual_hash(&rot.path, None);
+                                        let _ = meta.save(&state.dir);
+                                        drop(meta);
+                                        state.invalidate_cache();
+                                    }
+                                    state.live.publish(Change::Rotated { path: rot.path });
                                     let _ = req.respond(json_ok("Photo tournée"));
                                 }
                                 Err(e) => {
@@ -433,70 +1478,203 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
             }
         }
 
-        // API: EXIF / file metadata
-        (&Method::Get, "/api/exif") => {
+        // Guest upload page (share link)
+        (&Method::Get, "/guest") => {
             let params = parse_query(&url);
-            if let Some(file) = params.get("path") {
-                if let Some(full_path) = safe_path(&state.dir, file) {
-                    if !full_path.is_file() {
-                        let _ = req.respond(json_error(404, "Fichier introuvable"));
-                        return;
+            if !state.guest_allowed(params.get("token")) {
+                let _ = req.respond(json_error(403, "Lien de partage invalide"));
+                return;
+            }
+            let html = inbox::guest_page_html(params.get("token").unwrap());
+            let resp = Response::from_string(html).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
+                    .unwrap(),
+            );
+            let _ = req.respond(resp);
+        }
+
+        // API: Guest upload into the inbox
+        (&Method::Post, "/api/inbox") => {
+            let params = parse_query(&url);
+            if !state.guest_allowed(params.get("token")) {
+                let _ = req.respond(json_error(403, "Lien de partage invalide"));
+                return;
+            }
+            let Some(name) = params.get("name") else {
+                let _ = req.respond(json_error(400, "Paramètre name requis"));
+                return;
+            };
+            match read_body_bytes(&mut req, MAX_UPLOAD_SIZE) {
+                Ok(data) => match inbox::store_upload(&state.dir, name, &data) {
+                    Ok(stored) => {
+                        let _ = req.respond(json_ok(&stored));
                     }
-                    let mut entries: Vec<String> = Vec::new();
-
-                    // Read EXIF data
-                    if let Ok(f) = std::fs::File::open(&full_path) {
-                        let mut reader = std::io::BufReader::new(f);
-                        if let Ok(exif) = exif::Reader::new().read_from_container(&mut reader) {
-                            for field in exif.fields() {
-                                let tag = format!("{}", field.tag);
-                                let value = field.display_value().with_unit(&exif).to_string();
-                                entries.push(format!(
-                                    "{{\"tag\":\"{}\",\"value\":\"{}\"}}",
-                                    tag.replace('"', "\\\""),
-                                    value.replace('\\', "\\\\").replace('"', "\\\"")
-                                ));
-                            }
-                        }
+                    Err(e) => {
+                        let _ = req.respond(json_error(400, &e.to_string()));
                     }
+                },
+                Err(e) => {
+                    let _ = req.respond(json_error(413, &e.to_string()));
+                }
+            }
+        }
 
-                    // File size
-                    if let Ok(meta) = std::fs::metadata(&full_path) {
-                        let size = meta.len();
-                        let human = if size >= 1_048_576 {
-                            format!("{:.1} Mo", size as f64 / 1_048_576.0)
-                        } else {
-                            format!("{:.0} Ko", size as f64 / 1024.0)
-                        };
-                        entries.push(format!(
-                            "{{\"tag\":\"Taille fichier\",\"value\":\"{human} ({size} octets)\"}}",
-                        ));
+        // API: Upload photos straight into the library (drag and drop,
+        // phones): multipart form, or a raw body named by `?name=`
+        (&Method::Post, "/api/upload") => {
+            let content_type = (req.headers().iter())
+                .find(|h| h.field.equiv("Content-Type"))
+                .map(|h| h.value.as_str().to_string())
+                .unwrap_or_default();
+            let data = match read_body_bytes(&mut req, MAX_UPLOAD_SIZE) {
+                Ok(data) => data,
+                Err(e) => {
+                    let _ = req.respond(json_error(413, &e.to_string()));
+                    return;
+                }
+            };
+            let files: Vec<(String, Vec<u8>)> = match multipart::boundary(&content_type) {
+                Some(boundary) => match multipart::parse(&data, &boundary) {
+                    Ok(parts) => (parts.into_iter())
+                        .filter_map(|part| Some((part.filename?, part.data)))
+                        .collect(),
+                    Err(e) => {
+                        let _ = req.respond(json_error(400, &e.to_string()));
+                        return;
                     }
-
-                    // Image dimensions
-                    if let Ok((w, h)) = image::image_dimensions(&full_path) {
-                        entries.push(format!(
-                            "{{\"tag\":\"Dimensions\",\"value\":\"{w} × {h}\"}}"
-                        ));
+                },
+                None => (parse_query(&url).get("name"))
+                    .map(|name| vec![(name.clone(), data)])
+                    .unwrap_or_default(),
+            };
+            if files.is_empty() {
+                let _ = req.respond(json_error(400, "Aucun fichier reçu"));
+                return;
+            }
+            let (mut added, mut duplicates, mut errors) = (Vec::new(), 0, Vec::new());
+            for (name, data) in &files {
+                match inbox::import(&state.dir, name, data) {
+                    Ok(inbox::Approval::Sorted(rel)) => added.push(rel),
+                    Ok(inbox::Approval::Duplicate) => duplicates += 1,
+                    Err(e) => {
+                        errors.push(serde_json::json!({"name": name, "error": e.to_string()}))
                     }
+                }
+            }
+            state.add_photos(&added);
+            let body = serde_json::json!({
+                "ok": format!("{} photo(s) ajoutée(s)", added.len()),
+                "added": added,
+                "duplicates": duplicates,
+                "errors": errors,
+            });
+            let resp = Response::from_string(body.to_string()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            );
+            let _ = req.respond(resp);
+        }
 
-                    let body = format!("[{}]", entries.join(","));
-                    let resp = Response::from_string(body).with_header(
+        // API: List inbox content
+        (&Method::Get, "/api/inbox") => {
+            let names = inbox::list_inbox(&state.dir);
+            let body = serde_json::to_string(&names).unwrap_or_else(|_| "[]".to_string());
+            let resp = encoded_response(&req, body.as_bytes(), None, "application/json");
+            let _ = req.respond(resp);
+        }
+
+        // API: Approve or reject an inbox file
+        (&Method::Post, "/api/inbox/approve" | "/api/inbox/reject") => {
+            #[derive(serde::Deserialize)]
+            struct InboxReq {
+                name: String,
+            }
+            let parsed = read_body(&mut req)
+                .and_then(|body| Ok(serde_json::from_str::<InboxReq>(&body)?));
+            let name = match parsed {
+                Ok(r) => r.name,
+                Err(e) => {
+                    let _ = req.respond(json_error(400, &e.to_string()));
+                    return;
+                }
+            };
+            if path == "/api/inbox/reject" {
+                match inbox::reject(&state.dir, &name) {
+                    Ok(()) => {
+                        let _ = req.respond(json_ok("Photo rejetée"));
+                    }
+                    Err(e) => {
+                        let _ = req.respond(json_error(404, &e.to_string()));
+                    }
+                }
+                return;
+            }
+            match inbox::approve(&state.dir, &name) {
+                Ok(inbox::Approval::Sorted(new_rel)) => {
+                    state.add_photos(std::slice::from_ref(&new_rel));
+                    let resp_body = format!(
+                        "{{\"ok\":\"Photo ajoutée\",\"new_path\":\"{}\"}}",
+                        new_rel.replace('"', "\\\"")
+                    );
+                    let resp = Response::from_string(resp_body).with_header(
                         Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
                             .unwrap(),
                     );
                     let _ = req.respond(resp);
-                } else {
-                    let _ = req.respond(json_error(400, "Chemin invalide"));
                 }
-            } else {
+                Ok(inbox::Approval::Duplicate) => {
+                    let _ = req.respond(json_ok("Doublon ignoré"));
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(500, &e.to_string()));
+                }
+            }
+        }
+
+        // API: EXIF / file metadata
+        (&Method::Get, "/api/exif") => {
+            let params = parse_query(&url);
+            let Some(file) = params.get("path") else {
                 let _ = req.respond(json_error(400, "Paramètre path requis"));
+                return;
+            };
+            let Some(full_path) = safe_path(&state.dir, file) else {
+                let _ = req.respond(json_error(400, "Chemin invalide"));
+                return;
+            };
+            if !full_path.is_file() {
+                let _ = req.respond(json_error(404, "Fichier introuvable"));
+                return;
+            }
+            let body = exif_info(&full_path).to_string();
+            let resp = encoded_response(&req, body.as_bytes(), None, "application/json");
+            let _ = req.respond(resp);
+        }
+
+        // Full-size JPEG of a RAW file (embedded preview or external render)
+        (&Method::Get, _) if path.starts_with("/developed/") => {
+            let rel = &decode_path(&path["/developed/".len()..]);
+            let Some(full_path) = safe_path(&state.dir, rel) else {
+                let _ = req.respond(json_error(400, "Chemin invalide"));
+                return;
+            };
+            if !full_path.is_file() {
+                let _ = req.respond(json_error(404, "Fichier introuvable"));
+                return;
+            }
+            match develop::get_or_develop(&state.dir, rel, state.options.raw_developer.as_ref()) {
+                Ok(Some(jpeg)) => serve_file(req, &jpeg, "image/jpeg"),
+                Ok(None) => {
+                    let _ = req.respond(json_error(404, "Aucun aperçu disponible"));
+                }
+                Err(e) => {
+                    let _ = req.respond(json_error(500, &e.to_string()));
+                }
             }
         }
 
         // Thumbnail serving
         (&Method::Get, _) if path.starts_with("/thumb/") => {
-            let rel = &path[7..]; // strip "/thumb/"
+            let rel = &decode_path(&path[7..]); // strip "/thumb/"
             if let Some(full_path) = safe_path(&state.dir, rel) {
                 if !full_path.is_file() {
                     let _ = req.respond(json_error(404, "Fichier introuvable"));
@@ -507,28 +1685,7 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
                     Ok(Some(thumb_path)) => thumb_path,
                     _ => full_path,
                 };
-                match std::fs::File::open(&serve_path) {
-                    Ok(file) => {
-                        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
-                        let mime = mime_type(&serve_path);
-                        let resp = Response::from_file(file)
-                            .with_header(
-                                Header::from_bytes(&b"Content-Type"[..], mime.as_bytes())
-                                    .unwrap(),
-                            )
-                            .with_header(
-                                Header::from_bytes(
-                                    &b"Content-Length"[..],
-                                    len.to_string().as_bytes(),
-                                )
-                                .unwrap(),
-                            );
-                        let _ = req.respond(resp);
-                    }
-                    Err(_) => {
-                        let _ = req.respond(json_error(500, "Erreur lecture fichier"));
-                    }
-                }
+                serve_file(req, &serve_path, mime_type(&serve_path));
             } else {
                 let _ = req.respond(json_error(400, "Chemin invalide"));
             }
@@ -536,31 +1693,10 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
 
         // Static file serving
         (&Method::Get, _) => {
-            let rel = &path[1..]; // strip leading /
+            let rel = &decode_path(&path[1..]); // strip leading /
             if let Some(full_path) = safe_path(&state.dir, rel) {
                 if full_path.is_file() {
-                    match std::fs::File::open(&full_path) {
-                        Ok(file) => {
-                            let len = file.metadata().map(|m| m.len()).unwrap_or(0);
-                            let mime = mime_type(&full_path);
-                            let resp = Response::from_file(file)
-                                .with_header(
-                                    Header::from_bytes(&b"Content-Type"[..], mime.as_bytes())
-                                        .unwrap(),
-                                )
-                                .with_header(
-                                    Header::from_bytes(
-                                        &b"Content-Length"[..],
-                                        len.to_string().as_bytes(),
-                                    )
-                                    .unwrap(),
-                                );
-                            let _ = req.respond(resp);
-                        }
-                        Err(_) => {
-                            let _ = req.respond(json_error(500, "Erreur lecture fichier"));
-                        }
-                    }
+                    serve_file(req, &full_path, mime_type(&full_path));
                 } else {
                     let _ = req.respond(json_error(404, "Fichier introuvable"));
                 }
@@ -577,49 +1713,225 @@ pub fn handle_request(mut req: Request, state: &ServerState) {
 
 /// Rotate an image file by the given angle (90, 180, 270 degrees clockwise).
 pub fn rotate_image(path: &Path, angle: u16) -> Result<()> {
-    let img = image::open(path).context("Impossible d'ouvrir l'image")?;
+    let img = thumb::open_image(path).context("Impossible d'ouvrir l'image")?;
     let rotated = match angle {
         90 => img.rotate90(),
         180 => img.rotate180(),
         270 => img.rotate270(),
         _ => anyhow::bail!("Angle invalide : {angle}"),
     };
+    #[cfg(feature = "heif")]
+    if crate::heif::is_heif(path) {
+        return crate::heif::save(&rotated, path)
+            .context("Impossible de sauvegarder l'image tournée");
+    }
     rotated
         .save(path)
         .context("Impossible de sauvegarder l'image tournée")?;
     Ok(())
 }
 
+/// Watch the library for photos added or removed outside the server, and
+/// follow them without a restart.
+fn spawn_watcher(state: Arc<ServerState>) -> Result<()> {
+    use notify::event::{EventKind, ModifyKind};
+    use notify::{RecursiveMode, Watcher};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&state.dir, RecursiveMode::Recursive)?;
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        // Rotations and saves only change the content of files: they come
+        // from the server itself, which followed them already
+        let relevant = |event: &notify::Event| {
+            matches!(
+                event.kind,
+                EventKind::Create(_)
+                    | EventKind::Remove(_)
+                    | EventKind::Modify(ModifyKind::Name(_))
+            ) && event.paths.iter().any(|path| state.in_library(path))
+        };
+        while let Ok(event) = rx.recv() {
+            if !event.as_ref().is_ok_and(relevant) {
+                continue;
+            }
+            while rx.recv_timeout(WATCH_SETTLE).is_ok() {}
+            match state.rescan() {
+                Ok((added, removed)) if !added.is_empty() || !removed.is_empty() => println!(
+                    "  {} Bibliothèque modifiée : {} photo(s) ajoutée(s), {} retirée(s)",
+                    console::style("✔").green().bold(),
+                    added.len(),
+                    removed.len()
+                ),
+                Ok(_) => {}
+                Err(e) => eprintln!(
+                    "  {} Relecture de la bibliothèque impossible : {e:#}",
+                    console::style("✘").red().bold()
+                ),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Destinations whose route tells which address this machine has on each
+/// network: the private ranges, then the internet. Nothing is sent to them.
+const ROUTE_PROBES: &[&str] = &[
+    "10.255.255.254:9",
+    "172.31.255.254:9",
+    "192.168.255.254:9",
+    "192.0.2.1:9",
+    "[fd00::1]:9",
+    "[2001:db8::1]:9",
+];
+
+/// Addresses other devices may reach this machine at, as the system would
+/// pick them to send to each network (connecting a UDP socket sends nothing).
+fn lan_addresses(ipv6: bool) -> Vec<IpAddr> {
+    let mut found = Vec::new();
+    for probe in ROUTE_PROBES {
+        let Ok(target) = probe.parse::<SocketAddr>() else {
+            continue;
+        };
+        if target.is_ipv6() != ipv6 {
+            continue;
+        }
+        let any = if ipv6 { "[::]:0" } else { "0.0.0.0:0" };
+        let Ok(socket) = UdpSocket::bind(any) else {
+            continue;
+        };
+        let ip = match socket.connect(target).and_then(|()| socket.local_addr()) {
+            Ok(local) => local.ip(),
+            Err(_) => continue,
+        };
+        if !ip.is_loopback() && !ip.is_unspecified() && !found.contains(&ip) {
+            found.push(ip);
+        }
+    }
+    found
+}
+
+/// URLs the gallery is reachable at: this machine first, then its network
+/// addresses when listening on all of them.
+fn server_urls(host: IpAddr, port: u16, lan: &[IpAddr]) -> Vec<String> {
+    if host.is_loopback() || host.is_unspecified() {
+        let mut urls = vec![format!("http://localhost:{port}")];
+        if host.is_unspecified() {
+            urls.extend(
+                lan.iter()
+                    .map(|ip| format!("http://{}", SocketAddr::new(*ip, port))),
+            );
+        }
+        urls
+    } else {
+        vec![format!("http://{}", SocketAddr::new(host, port))]
+    }
+}
+
 /// Start the HTTP server.
-pub fn run_serve(dir: &Path, port: u16) -> Result<()> {
-    let state = ServerState::new(dir)?;
+pub fn run_serve(dir: &Path, host: IpAddr, port: u16, options: ServeOptions) -> Result<()> {
+    journal::recover_and_report(dir)?;
+    let state = ServerState::new(dir, options)?;
 
     // Pre-generate thumbnails in the background
     let all_rels = state.all_photo_rels();
     thumb::spawn_prewarm(state.dir.clone(), all_rels);
+    if let Err(e) = spawn_watcher(Arc::clone(&state)) {
+        eprintln!(
+            "  {} Surveillance du dossier impossible, redémarrer le serveur après un tri : {e}",
+            console::style("⚠").yellow().bold()
+        );
+    }
 
-    let addr = format!("0.0.0.0:{port}");
-    let server =
-        Server::http(&addr).map_err(|e| anyhow::anyhow!("Impossible de démarrer le serveur: {e}"))?;
+    let server = Server::http(SocketAddr::new(host, port))
+        .map_err(|e| anyhow::anyhow!("Impossible de démarrer le serveur: {e}"))?;
 
+    let lan = if host.is_unspecified() {
+        lan_addresses(host.is_ipv6())
+    } else {
+        Vec::new()
+    };
+    let urls = server_urls(host, port, &lan);
     println!(
         "  {} Galerie disponible sur {}",
         console::style("✔").green().bold(),
-        console::style(format!("http://localhost:{port}")).cyan().bold()
+        console::style(&urls[0]).cyan().bold()
     );
+    for url in &urls[1..] {
+        println!(
+            "  {} Sur le réseau : {}",
+            console::style("✔").green().bold(),
+            console::style(url).cyan().bold()
+        );
+    }
+    if host.is_loopback() {
+        println!(
+            "  {} Accessible depuis cette machine seulement (--host 0.0.0.0 pour tout le réseau)",
+            console::style("→").cyan()
+        );
+    }
+    // Links are meant for other devices: prefer a network address
+    let shared = urls.last().unwrap_or(&urls[0]);
+    if let Some(token) = &state.options.token {
+        println!(
+            "  {} Lien d'accès : {}",
+            console::style("✔").green().bold(),
+            console::style(format!("{shared}/?token={token}"))
+                .cyan()
+                .bold()
+        );
+    }
+    if let Some(token) = &state.options.guest_token {
+        println!(
+            "  {} Lien invité : {}",
+            console::style("✔").green().bold(),
+            console::style(format!("{shared}/guest?token={token}"))
+                .cyan()
+                .bold()
+        );
+        if host.is_loopback() {
+            eprintln!(
+                "  {} Les invités ne pourront pas se connecter : lancer avec --host 0.0.0.0",
+                console::style("⚠").yellow().bold()
+            );
+        }
+    }
     println!(
         "  {} pour arrêter",
         console::style("Ctrl+C").yellow().bold()
     );
 
+    // Ctrl+C or SIGTERM: no more requests taken, the loop below ends. A
+    // second one doesn't wait for the requests under way.
+    let server = Arc::new(server);
+    let stopping = AtomicBool::new(false);
+    let waker = Arc::downgrade(&server);
+    ctrlc::set_handler(move || {
+        if stopping.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        if let Some(server) = waker.upgrade() {
+            server.unblock();
+        }
+    })
+    .context("Impossible d'intercepter Ctrl+C")?;
+
     for req in server.incoming_requests() {
-        let state = Arc::clone(&state);
+        // Counted from here, not to be missed by a shutdown starting now
+        let request = InFlight::start(&state);
         std::thread::spawn(move || {
-            handle_request(req, &state);
+            handle_request(req, &request.0);
         });
     }
 
-    Ok(())
+    // Closes the socket
+    drop(server);
+    println!(
+        "  {} Arrêt du serveur…",
+        console::style("⏹").yellow().bold()
+    );
+    state.shutdown(SHUTDOWN_GRACE)
 }
 
 #[cfg(test)]
@@ -668,6 +1980,120 @@ mod tests {
         assert_eq!(params.get("name").unwrap(), "hello world");
     }
 
+    // --- list_photos ---
+
+    #[test]
+    fn photos_are_listed_by_query_and_page() {
+        let index = HashMap::from([
+            ("2019".to_string(), vec!["2019/a.jpg".to_string()]),
+            (
+                "2020".to_string(),
+                vec!["2020/b.jpg".to_string(), "2020/c.jpg".to_string()],
+            ),
+        ]);
+        let mut meta = Metadata::default();
+        meta.add_tag("2019/a.jpg", "plage");
+        meta.add_tag("2020/c.jpg", "plage");
+        meta.set_rating("2020/c.jpg", Some(4));
+        let list = |query: &str| list_photos(&index, &meta, &parse_query(query));
+
+        let all = list("/api/photos").unwrap();
+        assert_eq!(all["total"], 3);
+        assert_eq!(all["photos"][0]["path"], "2019/a.jpg");
+        assert_eq!(all["photos"][0]["year"], "2019");
+        assert_eq!(all["photos"][0]["tags"][0], "plage");
+
+        let beach = list("/api/photos?tag=plage&min_rating=3").unwrap();
+        assert_eq!(beach["total"], 1);
+        assert_eq!(beach["photos"][0]["path"], "2020/c.jpg");
+        assert_eq!(beach["photos"][0]["rating"], 4);
+        let recent = list("/api/photos?year=2020..2021&q=NOT+tag:plage").unwrap();
+        assert_eq!(recent["photos"][0]["path"], "2020/b.jpg");
+
+        let second = list("/api/photos?per_page=2&page=2").unwrap();
+        assert_eq!(second["total"], 3);
+        assert_eq!(second["photos"].as_array().unwrap().len(), 1);
+        assert_eq!(second["photos"][0]["path"], "2020/c.jpg");
+
+        assert!(list("/api/photos?min_rating=9").is_err());
+        assert!(list("/api/photos?year=demain").is_err());
+        assert!(list("/api/photos?page=0").is_err());
+    }
+
+    // --- exif_info ---
+
+    #[test]
+    fn exif_is_parsed_for_the_api() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Rational, Tag, Value};
+        use image::ImageEncoder;
+
+        let field = |tag, value| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        };
+        let ascii = |tag, v: &[u8]| field(tag, Value::Ascii(vec![v.to_vec()]));
+        let dms = |tag, d: u32, m: u32| {
+            field(
+                tag,
+                Value::Rational(vec![(d, 1).into(), (m, 1).into(), Rational::from((0, 1))]),
+            )
+        };
+        let fields = [
+            ascii(Tag::Make, b"Canon"),
+            ascii(Tag::Model, b"Canon EOS 5D"),
+            field(Tag::FNumber, Value::Rational(vec![(28, 10).into()])),
+            ascii(Tag::DateTimeOriginal, b"2020:07:14 10:30:00"),
+            ascii(Tag::OffsetTimeOriginal, b"+02:00"),
+            ascii(Tag::GPSLatitudeRef, b"N"),
+            dms(Tag::GPSLatitude, 48, 30),
+            ascii(Tag::GPSLongitudeRef, b"W"),
+            dms(Tag::GPSLongitude, 4, 15),
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+
+        let tmp = tmpdir();
+        let path = tmp.join("brest.jpg");
+        let mut out = std::fs::File::create(&path).unwrap();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(tiff.into_inner()).unwrap();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 6))
+            .write_with_encoder(encoder)
+            .unwrap();
+
+        let info = exif_info(&path);
+        assert_eq!(info["taken"], "2020-07-14T10:30:00");
+        assert_eq!(info["offset"], "+02:00");
+        assert_eq!(info["camera"]["model"], "Canon EOS 5D");
+        assert_eq!(info["camera"]["aperture"], 2.8);
+        assert_eq!(info["gps"]["lat"], 48.5);
+        assert_eq!(info["gps"]["lon"], -4.25);
+        assert!(info["size"].as_u64().unwrap() > 0);
+        let fields = info["fields"].as_array().unwrap();
+        assert!(
+            fields
+                .iter()
+                .any(|f| f["tag"] == "Make" && f["value"] == "\"Canon\"")
+        );
+        assert!(
+            fields
+                .iter()
+                .any(|f| f["tag"] == "Dimensions" && f["value"] == "8 × 6")
+        );
+
+        std::fs::write(tmp.join("plain.jpg"), b"not a photo").unwrap();
+        let info = exif_info(&tmp.join("plain.jpg"));
+        assert!(info["taken"].is_null() && info["camera"].is_null() && info["gps"].is_null());
+        assert_eq!(info["fields"].as_array().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     // --- urldecode ---
 
     #[test]
@@ -677,6 +2103,11 @@ mod tests {
         assert_eq!(urldecode("a%2Fb"), "a/b");
     }
 
+    #[test]
+    fn decode_path_keeps_plus() {
+        assert_eq!(decode_path("inbox/a+b%20c.jpg"), "inbox/a+b c.jpg");
+    }
+
     // --- safe_path ---
 
     #[test]
@@ -712,13 +2143,15 @@ mod tests {
     // --- Integration: handle_request with real server ---
 
     fn spawn_test_server(dir: &Path) -> (u16, Arc<ServerState>) {
-        let state = ServerState::new(dir).unwrap();
+        let state = ServerState::new(dir, ServeOptions::default()).unwrap();
         let server = Server::http("127.0.0.1:0").unwrap();
         let port = server.server_addr().to_ip().unwrap().port();
         let state_clone = Arc::clone(&state);
+        // A thread per request, as in `run_serve`: event streams keep theirs
         std::thread::spawn(move || {
             for req in server.incoming_requests() {
-                handle_request(req, &state_clone);
+                let state = Arc::clone(&state_clone);
+                std::thread::spawn(move || handle_request(req, &state));
             }
         });
         (port, state)
@@ -773,6 +2206,89 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn deleted_photos_are_restored_from_the_trash() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+        state
+            .metadata
+            .lock()
+            .unwrap()
+            .add_tag("2020/a.jpg", "plage");
+
+        let resp = ureq_delete(&format!(
+            "http://127.0.0.1:{port}/api/photo?path=2020/a.jpg"
+        ));
+        let id = serde_json::from_str::<serde_json::Value>(&resp).unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(!tmp.join("2020/a.jpg").exists());
+        let listed: serde_json::Value =
+            serde_json::from_str(&ureq_get(&format!("http://127.0.0.1:{port}/api/trash"))).unwrap();
+        assert_eq!(listed[0]["id"], id.as_str());
+        assert_eq!(listed[0]["path"], "2020/a.jpg");
+        assert_eq!(listed[0]["info"]["tags"][0], "plage");
+
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/restore"),
+            &format!(r#"{{"id":"{id}"}}"#),
+        );
+        assert!(resp.contains("\"path\":\"2020/a.jpg\""), "{resp}");
+        assert_eq!(
+            std::fs::read(tmp.join("2020/a.jpg")).unwrap(),
+            b"fake jpg data"
+        );
+        assert_eq!(
+            state.metadata.lock().unwrap().get_tags("2020/a.jpg"),
+            ["plage"]
+        );
+        assert!(ureq_get(&format!("http://127.0.0.1:{port}/")).contains("2020/a.jpg"));
+        let restore = |id: &str| {
+            let body = format!(r#"{{"id":"{id}"}}"#);
+            http_raw(
+                port,
+                &format!(
+                    "POST /api/restore HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                ),
+            )
+        };
+        assert!(restore(&id).starts_with("HTTP/1.0 404"));
+
+        // Refused while another file holds its place
+        let resp = ureq_delete(&format!(
+            "http://127.0.0.1:{port}/api/photo?path=2020/a.jpg"
+        ));
+        let id = serde_json::from_str::<serde_json::Value>(&resp).unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        std::fs::write(tmp.join("2020/a.jpg"), b"new").unwrap();
+        assert!(restore(&id).starts_with("HTTP/1.0 409"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn api_delete_only_trashes_photos() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+        state.metadata.lock().unwrap().save(&tmp).unwrap();
+
+        for file in [".photo_sort_metadata.json", "2020/missing.jpg"] {
+            let resp = http_raw(
+                port,
+                &format!("DELETE /api/photo?path={file} HTTP/1.0\r\n\r\n"),
+            );
+            assert!(resp.starts_with("HTTP/1.0 404"), "{file}: {resp}");
+        }
+        assert!(tmp.join(".photo_sort_metadata.json").is_file());
+        assert!(trash::list(&tmp).is_empty());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn api_delete_rejects_traversal() {
         let tmp = tmpdir();
@@ -803,6 +2319,67 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn api_move_rejects_folders_out_of_the_library() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _) = spawn_test_server(&tmp);
+
+        for dest in ["..", "/tmp", ".thumbs", "2021/.cache", "vrac", ""] {
+            let body = serde_json::json!({"src": "2020/a.jpg", "dest_dir": dest}).to_string();
+            let resp = http_raw(
+                port,
+                &format!(
+                    "POST /api/move HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                ),
+            );
+            assert!(resp.starts_with("HTTP/1.0 400"), "{dest}: {resp}");
+        }
+        assert!(tmp.join("2020/a.jpg").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn json_error_escapes_the_message() {
+        let mut body = String::new();
+        json_error(400, "chemin \"C:\\x\"\n")
+            .into_reader()
+            .read_to_string(&mut body)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["error"], "chemin \"C:\\x\"\n");
+    }
+
+    #[test]
+    fn api_move_redates_a_photo() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.jpg", Some(3));
+        meta.save(&tmp).unwrap();
+        let (port, state) = spawn_test_server(&tmp);
+
+        let body = r#"{"src":"2020/a.jpg","date":"1987-06-14 10:30:00"}"#;
+        let resp = ureq_post(&format!("http://127.0.0.1:{port}/api/move"), body);
+        let to = "1987/1987-06-14_10-30-00.jpg";
+        assert!(resp.contains(&format!("\"new_path\":\"{to}\"")));
+        assert!(tmp.join(to).exists());
+        // The server's metadata follows, with the date kept
+        let meta = state.metadata.lock().unwrap();
+        assert_eq!(meta.get_rating(to), Some(3));
+        assert!(meta.get_date_override(to).is_some());
+        assert!(resp.contains(&format!("\"rev\":\"{}\"", meta.revision())));
+        drop(meta);
+        assert!(state.get_cached_html().contains(to));
+
+        let body = r#"{"src":"2020/b.jpg","date":"14/06/1987"}"#;
+        let resp = ureq_post(&format!("http://127.0.0.1:{port}/api/move"), body);
+        assert!(resp.contains("Date invalide"));
+        assert!(tmp.join("2020/b.jpg").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn api_metadata_save() {
         let tmp = tmpdir();
@@ -822,13 +2399,730 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn metadata_follows_files_renamed_while_stopped() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.jpg", Some(4));
+        meta.save(&tmp).unwrap();
+        let (port, _) = spawn_test_server(&tmp);
+
+        // The gallery doesn't send hashes back: the server keeps them
+        let body = r#"{"files":{"2020/a.jpg":{"rating":5}}}"#;
+        ureq_post(&format!("http://127.0.0.1:{port}/api/metadata"), body);
+        assert!(Metadata::load(&tmp).unwrap().files["2020/a.jpg"].hash.is_some());
+
+        std::fs::create_dir_all(tmp.join("2021")).unwrap();
+        std::fs::rename(tmp.join("2020/a.jpg"), tmp.join("2021/renamed.jpg")).unwrap();
+        spawn_test_server(&tmp);
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_rating("2021/renamed.jpg"), Some(5));
+        assert!(!meta.files.contains_key("2020/a.jpg"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn api_undo_reverts_last_save() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _) = spawn_test_server(&tmp);
+        let url = format!("http://127.0.0.1:{port}");
+
+        for rating in [2, 5] {
+            let body = format!(r#"{{"files":{{"2020/a.jpg":{{"rating":{rating}}}}}}}"#);
+            ureq_post(&format!("{url}/api/metadata"), &body);
+        }
+        let resp = ureq_post(&format!("{url}/api/undo"), "");
+        assert!(resp.contains("\"files\":1"), "{resp}");
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_rating("2020/a.jpg"), Some(2));
+
+        ureq_post(&format!("{url}/api/undo"), "");
+        assert_eq!(Metadata::load(&tmp).unwrap().get_rating("2020/a.jpg"), None);
+        let resp = ureq_post(&format!("{url}/api/undo"), "");
+        assert!(resp.contains("Rien à annuler"), "{resp}");
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn api_metadata_stale_revision_returns_conflicts() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+        let stale = state.metadata.lock().unwrap().revision();
+
+        let first = r#"{"files":{"2020/a.jpg":{"rating":2}}}"#;
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/metadata?rev={stale}"),
+            first,
+        );
+        let saved: serde_json::Value = serde_json::from_str(&resp).unwrap();
+        assert!(saved["rev"].is_string());
+
+        // A second client still holding the old revision is refused
+        let second = r#"{"files":{"2020/a.jpg":{"rating":5}}}"#;
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/metadata?rev={stale}"),
+            second,
+        );
+        let refused: serde_json::Value = serde_json::from_str(&resp).unwrap();
+        assert_eq!(refused["conflicts"][0]["file"], "2020/a.jpg");
+        assert_eq!(refused["conflicts"][0]["rating"], true);
+        assert_eq!(Metadata::load(&tmp).unwrap().get_rating("2020/a.jpg"), Some(2));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn api_metadata_merge_with_resolutions() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.save(&tmp).unwrap();
+        let (port, _) = spawn_test_server(&tmp);
+        let url = format!("http://127.0.0.1:{port}/api/metadata/merge");
+
+        let incoming = r#"{"files":{"2020/a.jpg":{"tags":["mer"]},"2021/c.jpg":{"rating":3}}}"#;
+        let resp = ureq_post(&url, &format!(r#"{{"metadata":{incoming}}}"#));
+        assert!(resp.contains("conflicts"));
+        assert_eq!(Metadata::load(&tmp).unwrap().get_tags("2020/a.jpg"), &["plage"]);
+
+        let body = format!(
+            r#"{{"metadata":{incoming},"resolutions":{{"2020/a.jpg":{{"tags":"both"}}}}}}"#
+        );
+        let resp = ureq_post(&url, &body);
+        assert!(resp.contains("ok"));
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), &["plage", "mer"]);
+        assert_eq!(meta.get_rating("2021/c.jpg"), Some(3));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    // --- Guest inbox ---
+
+    fn spawn_test_server_with(dir: &Path, options: ServeOptions) -> u16 {
+        let state = ServerState::new(dir, options).unwrap();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        std::thread::spawn(move || {
+            for req in server.incoming_requests() {
+                handle_request(req, &state);
+            }
+        });
+        port
+    }
+
+    fn guest_options() -> ServeOptions {
+        ServeOptions {
+            guest_token: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn guest_page_requires_token() {
+        let tmp = tmpdir();
+        let port = spawn_test_server_with(&tmp, guest_options());
+
+        let resp = ureq_get(&format!("http://127.0.0.1:{port}/guest?token=wrong"));
+        assert!(resp.contains("error"));
+        let resp = ureq_get(&format!("http://127.0.0.1:{port}/guest?token=secret"));
+        assert!(resp.contains("<!DOCTYPE html>"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn unchanged_files_answer_304() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _state) = spawn_test_server(&tmp);
+
+        let resp = http_raw(port, "GET /2020/a.jpg HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 200"));
+        let header = |resp: &str, name: &str| {
+            (resp.lines())
+                .find_map(|l| l.strip_prefix(name))
+                .unwrap()
+                .to_string()
+        };
+        let etag = header(&resp, "ETag: ");
+        let modified = header(&resp, "Last-Modified: ");
+        let again = |extra: &str| {
+            http_raw(
+                port,
+                &format!("GET /2020/a.jpg HTTP/1.0\r\n{extra}\r\n\r\n"),
+            )
+        };
+        assert!(again(&format!("If-None-Match: {etag}")).starts_with("HTTP/1.0 304"));
+        assert!(again(&format!("If-Modified-Since: {modified}")).starts_with("HTTP/1.0 304"));
+        assert!(again("If-None-Match: \"autre\"").starts_with("HTTP/1.0 200"));
+        let old = "If-Modified-Since: Mon, 01 Jan 2001 00:00:00 GMT";
+        assert!(again(old).starts_with("HTTP/1.0 200"));
+
+        let resp = http_raw(port, "GET / HTTP/1.0\r\n\r\n");
+        let etag = header(&resp, "ETag: ");
+        let resp = http_raw(
+            port,
+            &format!("GET / HTTP/1.0\r\nIf-None-Match: {etag}\r\n\r\n"),
+        );
+        assert!(resp.starts_with("HTTP/1.0 304"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn html_is_gzipped_for_clients_taking_it() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+
+        let plain = ureq_get_bytes(&format!("http://127.0.0.1:{port}/"));
+        assert_eq!(plain, state.get_cached_html().as_bytes());
+        let resp = http_raw(
+            port,
+            "GET / HTTP/1.0\r\nAccept-Encoding: br, gzip;q=0.8\r\n\r\n",
+        );
+        assert!(resp.contains("Content-Encoding: gzip\r\n"));
+        assert!(resp.contains("Vary: Accept-Encoding\r\n"));
+        let resp = http_raw(port, "GET / HTTP/1.0\r\nAccept-Encoding: gzip;q=0\r\n\r\n");
+        assert!(!resp.contains("Content-Encoding"));
+
+        let page = state.get_encoded_page();
+        assert!(Arc::ptr_eq(&page, &state.get_encoded_page()));
+        let mut html = String::new();
+        flate2::read::GzDecoder::new(&page.gzip[..])
+            .read_to_string(&mut html)
+            .unwrap();
+        assert_eq!(html, *state.get_cached_html());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn urls_follow_the_listening_address() {
+        let lan: [IpAddr; 2] = ["192.168.1.20".parse().unwrap(), "fd00::5".parse().unwrap()];
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(server_urls(local, 8080, &lan), ["http://localhost:8080"]);
+        let all: IpAddr = "0.0.0.0".parse().unwrap();
+        assert_eq!(
+            server_urls(all, 8080, &lan),
+            [
+                "http://localhost:8080",
+                "http://192.168.1.20:8080",
+                "http://[fd00::5]:8080"
+            ]
+        );
+        let one: IpAddr = "192.168.1.20".parse().unwrap();
+        assert_eq!(server_urls(one, 3000, &lan), ["http://192.168.1.20:3000"]);
+        assert!(
+            lan_addresses(false)
+                .iter()
+                .all(|ip| ip.is_ipv4() && !ip.is_loopback())
+        );
+    }
+
+    #[test]
+    fn shutdown_waits_for_requests_and_saves_the_metadata() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let state = ServerState::new(&tmp, ServeOptions::default()).unwrap();
+        let stream = state.live.subscribe();
+        state
+            .metadata
+            .lock()
+            .unwrap()
+            .add_tag("2020/a.jpg", "plage");
+
+        let request = InFlight::start(&state);
+        let slow = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(request);
+        });
+        let started = std::time::Instant::now();
+        state.shutdown(Duration::from_secs(5)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(state.in_flight.load(Ordering::SeqCst), 0);
+        slow.join().unwrap();
+
+        assert!(stream.recv().is_err());
+        assert_eq!(
+            Metadata::load(&tmp).unwrap().get_tags("2020/a.jpg"),
+            ["plage"]
+        );
+        assert!(!state.metadata.lock().unwrap().has_unsaved_changes());
+
+        // The wait is bounded
+        let _stuck = InFlight::start(&state);
+        let started = std::time::Instant::now();
+        state.shutdown(Duration::from_millis(100)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn changes_are_pushed_to_event_streams() {
+        use std::io::{BufRead, BufReader};
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _state) = spawn_test_server(&tmp);
+
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "GET /api/events HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n"
+        )
+        .unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        // Subscribed once the stream has started
+        while !line.starts_with("retry:") {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if line.starts_with("HTTP/") {
+                assert!(line.contains(" 200 "));
+            }
+        }
+
+        let resp = ureq_delete(&format!(
+            "http://127.0.0.1:{port}/api/photo?path=2020/a.jpg"
+        ));
+        assert!(resp.contains("ok"));
+        let mut lines = Vec::new();
+        while lines.len() < 2 {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            if !line.trim().is_empty() {
+                lines.push(line.trim().to_string());
+            }
+        }
+        assert_eq!(lines, ["event: removed", "data: {\"path\":\"2020/a.jpg\"}"]);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn gallery_data_follows_the_library() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+
+        let body = ureq_get(&format!("http://127.0.0.1:{port}/api/gallery"));
+        let data: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(data["rev"], state.metadata.lock().unwrap().revision());
+        let photos = data["photos"].as_array().unwrap();
+        assert!(photos.iter().any(|p| p["src"] == "2020/a.jpg"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn rescan_follows_photos_added_and_removed_outside() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let state = ServerState::new(&tmp, ServeOptions::default()).unwrap();
+        let changes = state.live.subscribe();
+        let html_before = state.get_cached_html();
+
+        std::fs::create_dir_all(tmp.join("2021")).unwrap();
+        std::fs::write(tmp.join("2021/c.jpg"), "fake jpg data").unwrap();
+        std::fs::remove_file(tmp.join("2020/a.jpg")).unwrap();
+        let (added, removed) = state.rescan().unwrap();
+        assert_eq!(added, ["2021/c.jpg"]);
+        assert_eq!(removed, ["2020/a.jpg"]);
+        assert!(state.all_photo_rels().contains(&"2021/c.jpg".to_string()));
+        let html = state.get_cached_html();
+        assert!(!Arc::ptr_eq(&html_before, &html));
+        assert!(html.contains("2021/c.jpg") && !html.contains("2020/a.jpg"));
+        let events: Vec<Change> = changes.try_iter().collect();
+        assert_eq!(
+            events,
+            [
+                Change::Removed {
+                    path: "2020/a.jpg".to_string()
+                },
+                Change::Added {
+                    path: "2021/c.jpg".to_string()
+                },
+            ]
+        );
+        assert_eq!(state.rescan().unwrap(), (vec![], vec![]));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn rescan_keeps_unsaved_edits() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let state = ServerState::new(&tmp, ServeOptions::default()).unwrap();
+        // A save that failed, and a sort run saving meanwhile
+        state
+            .metadata
+            .lock()
+            .unwrap()
+            .add_tag("2020/a.jpg", "vacances");
+        let mut outside = Metadata::load(&tmp).unwrap();
+        outside.add_tag("2020/b.jpg", "noel");
+        outside.save(&tmp).unwrap();
+
+        std::fs::create_dir_all(tmp.join("2021")).unwrap();
+        std::fs::write(tmp.join("2021/c.jpg"), "fake jpg data").unwrap();
+        state.rescan().unwrap();
+        let meta = state.metadata.lock().unwrap();
+        assert!(meta.has_tag("2020/a.jpg", "vacances"));
+        assert!(meta.has_tag("2020/b.jpg", "noel"));
+        assert!(!meta.has_unsaved_changes());
+        assert!(
+            Metadata::load(&tmp)
+                .unwrap()
+                .has_tag("2020/a.jpg", "vacances")
+        );
+        drop(meta);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn watcher_picks_up_new_photos() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let state = ServerState::new(&tmp, ServeOptions::default()).unwrap();
+        let changes = state.live.subscribe();
+        spawn_watcher(Arc::clone(&state)).unwrap();
+
+        std::fs::write(tmp.join(".photo_sort_notes"), "ignored").unwrap();
+        std::fs::write(tmp.join("2020/new.jpg"), "fake jpg data").unwrap();
+        let change = changes.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            change,
+            Change::Added {
+                path: "2020/new.jpg".to_string()
+            }
+        );
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn basic_auth_is_parsed() {
+        let auth = BasicAuth::parse("marie:s3cret:x").unwrap();
+        assert_eq!(
+            (auth.user.as_str(), auth.password.as_str()),
+            ("marie", "s3cret:x")
+        );
+        assert!(BasicAuth::parse("marie").is_err());
+        assert!(BasicAuth::parse(":s3cret").is_err());
+    }
+
+    #[test]
+    fn basic_auth_protects_all_but_the_guest_page() {
+        let tmp = tmpdir();
+        let options = ServeOptions {
+            auth: Some(BasicAuth::parse("marie:s3cret").unwrap()),
+            ..guest_options()
+        };
+        let port = spawn_test_server_with(&tmp, options);
+
+        let resp = http_raw(port, "GET / HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 401"));
+        assert!(resp.contains("WWW-Authenticate: Basic realm=\"photo-sort\""));
+        let resp = http_raw(port, "DELETE /api/photo?path=2020/a.jpg HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 401"));
+        let wrong = BASE64_STANDARD.encode("marie:faux");
+        let resp = http_raw(
+            port,
+            &format!("GET / HTTP/1.0\r\nAuthorization: Basic {wrong}\r\n\r\n"),
+        );
+        assert!(resp.starts_with("HTTP/1.0 401"));
+        let right = BASE64_STANDARD.encode("marie:s3cret");
+        let resp = http_raw(
+            port,
+            &format!("GET / HTTP/1.0\r\nAuthorization: Basic {right}\r\n\r\n"),
+        );
+        assert!(resp.starts_with("HTTP/1.0 200"));
+        let resp = http_raw(port, "GET /guest?token=secret HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 200"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn token_in_the_url_moves_into_a_cookie() {
+        let tmp = tmpdir();
+        let options = ServeOptions {
+            token: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        let port = spawn_test_server_with(&tmp, options);
+
+        let resp = http_raw(port, "GET /?slideshow=1&token=abc123 HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 303"));
+        assert!(resp.contains("Location: /?slideshow=1\r\n"));
+        assert!(resp.contains("Set-Cookie: photo_sort_token=abc123;"));
+        let resp = http_raw(port, "GET /?token=wrong HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 401"));
+        assert!(!resp.contains("WWW-Authenticate"));
+        let resp = http_raw(
+            port,
+            "GET / HTTP/1.0\r\nCookie: photo_sort_token=abc123\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.0 200"));
+        let resp = http_raw(
+            port,
+            "POST /api/undo HTTP/1.0\r\nAuthorization: Bearer abc123\r\n\r\n",
+        );
+        assert!(!resp.starts_with("HTTP/1.0 401"));
+        let resp = http_raw(port, "POST /api/undo HTTP/1.0\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.0 401"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn guest_upload_disabled_without_token_option() {
+        let tmp = tmpdir();
+        let (port, _) = spawn_test_server(&tmp);
+
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/inbox?token=&name=a.jpg"),
+            "data",
+        );
+        assert!(resp.contains("error"));
+        assert!(!tmp.join("inbox/a.jpg").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn guest_upload_then_approve() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let port = spawn_test_server_with(&tmp, guest_options());
+
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/inbox?token=secret&name=IMG%201.jpg"),
+            "guest data",
+        );
+        assert!(resp.contains("ok"));
+        assert!(tmp.join("inbox/IMG 1.jpg").exists());
+
+        let list = ureq_get(&format!("http://127.0.0.1:{port}/api/inbox"));
+        assert_eq!(list, r#"["IMG 1.jpg"]"#);
+
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/inbox/approve"),
+            r#"{"name":"IMG 1.jpg"}"#,
+        );
+        assert!(resp.contains("new_path"));
+        assert!(!tmp.join("inbox/IMG 1.jpg").exists());
+
+        let html = ureq_get(&format!("http://127.0.0.1:{port}/"));
+        let new_path: serde_json::Value = serde_json::from_str(&resp).unwrap();
+        assert!(html.contains(new_path["new_path"].as_str().unwrap()));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn guest_upload_reject() {
+        let tmp = tmpdir();
+        let port = spawn_test_server_with(&tmp, guest_options());
+
+        ureq_post(
+            &format!("http://127.0.0.1:{port}/api/inbox?token=secret&name=a.jpg"),
+            "x",
+        );
+        let resp = ureq_post(
+            &format!("http://127.0.0.1:{port}/api/inbox/reject"),
+            r#"{"name":"a.jpg"}"#,
+        );
+        assert!(resp.contains("ok"));
+        assert!(!tmp.join("inbox/a.jpg").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn metadata_of_one_photo_is_patched() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+        state
+            .metadata
+            .lock()
+            .unwrap()
+            .add_tag("2020/b.jpg", "plage");
+        let patch = |path: &str, body: &str| {
+            let resp = http_raw(
+                port,
+                &format!(
+                    "PATCH /api/metadata/{path} HTTP/1.0\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                ),
+            );
+            resp.split("\r\n\r\n").nth(1).unwrap().to_string()
+        };
+
+        let resp = patch("2020/a.jpg", r#"{"add_tags":["mer"],"rating":5}"#);
+        assert!(resp.contains("\"rev\""), "{resp}");
+        let meta = state.metadata.lock().unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["mer"]);
+        assert_eq!(meta.get_rating("2020/a.jpg"), Some(5));
+        assert_eq!(meta.get_tags("2020/b.jpg"), ["plage"]);
+        drop(meta);
+        let saved = Metadata::load(&tmp).unwrap();
+        assert_eq!(saved.get_rating("2020/a.jpg"), Some(5));
+
+        assert!(patch("2020/a.jpg", r#"{"rating":9}"#).contains("error"));
+        assert!(patch("2020/a.jpg", r#"{"rating":"x"}"#).contains("error"));
+        assert!(patch("2020/absent.jpg", r#"{"rating":1}"#).contains("introuvable"));
+        assert!(patch("../secret.jpg", r#"{"rating":1}"#).contains("introuvable"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn uploads_are_sorted_into_the_library() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, state) = spawn_test_server(&tmp);
+        let rx = state.live.subscribe();
+
+        let body = "--XX\r\n\
+            Content-Disposition: form-data; name=\"files\"; filename=\"IMG_1.jpg\"\r\n\r\n\
+            phone photo\r\n\
+            --XX\r\n\
+            Content-Disposition: form-data; name=\"files\"; filename=\"IMG_2.jpg\"\r\n\r\n\
+            phone photo\r\n\
+            --XX\r\n\
+            Content-Disposition: form-data; name=\"files\"; filename=\"notes.txt\"\r\n\r\n\
+            x\r\n\
+            --XX--\r\n";
+        let resp = http_raw(
+            port,
+            &format!(
+                "POST /api/upload HTTP/1.0\r\nContent-Type: multipart/form-data; boundary=XX\r\n\
+                 Content-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(resp.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let added = json["added"].as_array().unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(json["duplicates"], 1);
+        assert_eq!(json["errors"][0]["name"], "notes.txt");
+
+        let rel = added[0].as_str().unwrap();
+        assert!(tmp.join(rel).is_file());
+        assert!(crate::inbox::list_inbox(&tmp).is_empty());
+        let index = state.photo_index.lock().unwrap();
+        assert!(index.values().flatten().any(|p| p == rel));
+        drop(index);
+        assert!(state.metadata.lock().unwrap().files.contains_key(rel));
+        let changes: Vec<Change> = rx.try_iter().collect();
+        assert!(changes.contains(&Change::Added {
+            path: rel.to_string()
+        }));
+
+        let resp = ureq_post(&format!("http://127.0.0.1:{port}/api/upload"), "x");
+        assert!(resp.contains("Aucun fichier"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     // --- Cache tests ---
 
+    /// Send a raw HTTP/1.0 request and return the full response (headers + body).
+    fn http_raw(port: u16, request: &str) -> String {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut resp = Vec::new();
+        stream.read_to_end(&mut resp).unwrap();
+        String::from_utf8_lossy(&resp).to_string()
+    }
+
+    #[test]
+    fn api_prefs_roundtrip_with_cookie() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _) = spawn_test_server(&tmp);
+
+        let resp = http_raw(port, "GET /api/prefs HTTP/1.0\r\n\r\n");
+        assert!(resp.ends_with("{}"));
+        let cookie_line = resp
+            .lines()
+            .find(|l| l.to_ascii_lowercase().starts_with("set-cookie:"))
+            .expect("cookie set for new client");
+        let cookie = cookie_line.split_once(':').unwrap().1.trim();
+        let cookie = cookie.split(';').next().unwrap();
+
+        let body = r#"{"minRating":3,"tag":"vacances"}"#;
+        let resp = http_raw(
+            port,
+            &format!(
+                "POST /api/prefs HTTP/1.0\r\nCookie: {cookie}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        assert!(resp.contains("ok"));
+        assert!(!resp.to_ascii_lowercase().contains("set-cookie"));
+
+        let resp = http_raw(port, &format!("GET /api/prefs HTTP/1.0\r\nCookie: {cookie}\r\n\r\n"));
+        let json = resp.split("\r\n\r\n").nth(1).unwrap();
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(value["minRating"], 3);
+        assert_eq!(value["tag"], "vacances");
+
+        // Another browser does not see them
+        let resp = http_raw(port, "GET /api/prefs HTTP/1.0\r\n\r\n");
+        assert!(resp.ends_with("{}"));
+
+        // Persisted on disk for the next server run
+        let prefs = Prefs::load(&tmp).unwrap();
+        assert_eq!(prefs.clients.len(), 1);
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn api_prefs_rejects_non_object() {
+        let tmp = tmpdir();
+        setup_photos(&tmp);
+        let (port, _) = spawn_test_server(&tmp);
+
+        let resp = ureq_post(&format!("http://127.0.0.1:{port}/api/prefs"), "[1,2]");
+        assert!(resp.contains("error"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn developed_endpoint_uses_raw_developer() {
+        let tmp = tmpdir();
+        std::fs::create_dir_all(tmp.join("2020")).unwrap();
+        std::fs::write(tmp.join("2020/a b.cr2"), "raw data").unwrap();
+        let options = ServeOptions {
+            raw_developer: Some(Developer::parse("cp {input} {output}").unwrap()),
+            ..Default::default()
+        };
+        let port = spawn_test_server_with(&tmp, options);
+
+        let resp = ureq_get(&format!("http://127.0.0.1:{port}/developed/2020/a%20b.cr2"));
+        assert_eq!(resp, "raw data");
+        assert!(develop::developed_cache_path(&tmp, "2020/a b.cr2").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn developed_endpoint_404_without_developer() {
+        let tmp = tmpdir();
+        std::fs::create_dir_all(tmp.join("2020")).unwrap();
+        std::fs::write(tmp.join("2020/a.cr2"), "raw data").unwrap();
+        let (port, _) = spawn_test_server(&tmp);
+
+        let resp = ureq_get(&format!("http://127.0.0.1:{port}/developed/2020/a.cr2"));
+        assert!(resp.contains("error"));
+        let resp = ureq_get(&format!("http://127.0.0.1:{port}/developed/2020/missing.cr2"));
+        assert!(resp.contains("Fichier introuvable"));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn html_cache_hit() {
         let tmp = tmpdir();
         setup_photos(&tmp);
-        let state = ServerState::new(&tmp).unwrap();
+        let state = ServerState::new(&tmp, ServeOptions::default()).unwrap();
 
         let html1 = state.get_cached_html();
         let html2 = state.get_cached_html();
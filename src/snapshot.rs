@@ -0,0 +1,442 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::gallery::collect_photos;
+use crate::metadata::Metadata;
+use crate::origins::{self, ORIGINS_FILE};
+use crate::sort::hash_file;
+
+/// Index of the snapshots produced for a library (stored at its root).
+const SNAPSHOT_INDEX: &str = ".photo_sort_snapshots.json";
+
+/// Name of the checksum list inside an archive (`b3sum` format).
+const CHECKSUMS_FILE: &str = "checksums.b3";
+
+/// Name of the filtered metadata file inside an archive.
+const METADATA_ENTRY: &str = ".photo_sort_metadata.json";
+
+/// One archive recorded in the snapshot index.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotEntry {
+    pub year: String,
+    /// Absolute path of the archive.
+    pub archive: PathBuf,
+    pub created: NaiveDateTime,
+    /// Number of photos in the archive.
+    pub photos: usize,
+    /// BLAKE3 of the whole archive, to detect bit rot on the backup medium.
+    pub archive_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct SnapshotIndex {
+    pub snapshots: Vec<SnapshotEntry>,
+}
+
+impl SnapshotIndex {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(SNAPSHOT_INDEX);
+        if path.exists() {
+            let data = fs::read_to_string(&path)
+                .context("Impossible de lire l'index des snapshots")?;
+            let index: SnapshotIndex =
+                serde_json::from_str(&data).context("Index des snapshots invalide")?;
+            Ok(index)
+        } else {
+            Ok(SnapshotIndex::default())
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(SNAPSHOT_INDEX);
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json).context("Impossible de sauvegarder l'index des snapshots")?;
+        Ok(())
+    }
+
+    /// Add an entry, replacing any previous snapshot written to the same archive.
+    pub fn record(&mut self, entry: SnapshotEntry) {
+        self.snapshots.retain(|s| s.archive != entry.archive);
+        self.snapshots.push(entry);
+    }
+}
+
+/// Result of checking a snapshot against the library.
+#[derive(Debug, PartialEq)]
+pub struct SnapshotStatus {
+    /// Photos of the year present in the library but not in the archive.
+    pub missing: Vec<String>,
+}
+
+fn hash_reader(reader: &mut impl Read) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Write the archive of `year` into `out`: photos, the year's origins and
+/// metadata, and a `checksums.b3` list covering all of them.
+fn write_archive(dir: &Path, year: &str, files: &[String], out: &Path) -> Result<()> {
+    let entries: Vec<(String, PathBuf)> = files
+        .iter()
+        .map(|rel| (rel.clone(), dir.join(rel)))
+        .collect();
+    origins::migrate_legacy(dir)?;
+
+    let metadata = Metadata::load(dir)?;
+    let prefix = format!("{year}/");
+    let mut year_meta = Metadata::default();
+    year_meta.files = metadata
+        .files
+        .into_iter()
+        .filter(|(k, _)| k.starts_with(&prefix))
+        .collect();
+    // Albums restricted to the year's photos
+    year_meta.albums = metadata
+        .albums
+        .into_iter()
+        .filter_map(|(name, files)| {
+            let files: Vec<String> =
+                files.into_iter().filter(|f| f.starts_with(&prefix)).collect();
+            (!files.is_empty()).then_some((name, files))
+        })
+        .collect();
+    let mut generated = vec![(METADATA_ENTRY, serde_json::to_vec_pretty(&year_meta)?)];
+
+    let mut year_origins = Vec::new();
+    for origin in origins::load(dir)?
+        .into_iter()
+        .filter(|o| o.dest.starts_with(&prefix))
+    {
+        year_origins.extend(serde_json::to_vec(&origin)?);
+        year_origins.push(b'\n');
+    }
+    if !year_origins.is_empty() {
+        generated.push((ORIGINS_FILE, year_origins));
+    }
+
+    let file = fs::File::create(out)
+        .with_context(|| format!("Impossible de créer {}", out.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+
+    let mut checksums = String::new();
+    for (name, path) in &entries {
+        let size = fs::metadata(path)?.len();
+        zip.start_file(name.as_str(), options.large_file(size >= u32::MAX as u64))?;
+        let mut src = fs::File::open(path)
+            .with_context(|| format!("Impossible de lire {}", path.display()))?;
+        let mut hasher = blake3::Hasher::new();
+        let mut buf = [0u8; 65536];
+        loop {
+            let n = src.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            std::io::Write::write_all(&mut zip, &buf[..n])?;
+        }
+        checksums.push_str(&format!("{}  {name}\n", hasher.finalize().to_hex()));
+    }
+
+    for (name, data) in &generated {
+        zip.start_file(*name, options)?;
+        std::io::Write::write_all(&mut zip, data)?;
+        checksums.push_str(&format!("{}  {name}\n", blake3::hash(data).to_hex()));
+    }
+
+    zip.start_file(CHECKSUMS_FILE, options)?;
+    std::io::Write::write_all(&mut zip, checksums.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Re-read an archive and check every entry against its `checksums.b3`.
+/// Returns the paths listed in the checksums.
+pub fn verify_archive(archive: &Path) -> Result<Vec<String>> {
+    let file = fs::File::open(archive)
+        .with_context(|| format!("Archive introuvable : {}", archive.display()))?;
+    let mut zip = zip::ZipArchive::new(file).context("Archive ZIP illisible")?;
+
+    let mut expected: BTreeMap<String, String> = BTreeMap::new();
+    {
+        let list = zip
+            .by_name(CHECKSUMS_FILE)
+            .context("checksums.b3 absent de l'archive")?;
+        for line in BufReader::new(list).lines() {
+            let line = line?;
+            if let Some((hash, name)) = line.split_once("  ") {
+                expected.insert(name.to_string(), hash.to_string());
+            }
+        }
+    }
+
+    for (name, hash) in &expected {
+        let mut entry = zip
+            .by_name(name)
+            .with_context(|| format!("Entrée manquante : {name}"))?;
+        if &hash_reader(&mut entry)? != hash {
+            anyhow::bail!("Somme de contrôle invalide : {name}");
+        }
+    }
+    Ok(expected.into_keys().collect())
+}
+
+/// Build a verified archive of one year and record it in the snapshot index.
+pub fn create_snapshot(dir: &Path, year: &str, out: &Path) -> Result<SnapshotEntry> {
+    let mut photos = collect_photos(dir);
+    let files = photos
+        .remove(year)
+        .with_context(|| format!("Aucune photo pour l'année {year}"))?;
+
+    let tmp = out.with_extension("zip.tmp");
+    let result = write_archive(dir, year, &files, &tmp).and_then(|()| verify_archive(&tmp));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, out)
+        .with_context(|| format!("Impossible d'écrire {}", out.display()))?;
+
+    let archive = out.canonicalize()?;
+    let entry = SnapshotEntry {
+        year: year.to_string(),
+        archive_hash: hash_file(&archive)?,
+        archive,
+        created: chrono::Local::now().naive_local(),
+        photos: files.len(),
+    };
+    let mut index = SnapshotIndex::load(dir)?;
+    index.record(entry.clone());
+    index.save(dir)?;
+    Ok(entry)
+}
+
+/// Check a recorded snapshot: archive integrity, then coverage of the year's
+/// current photos.
+pub fn check_snapshot(dir: &Path, entry: &SnapshotEntry) -> Result<SnapshotStatus> {
+    let actual = hash_file(&entry.archive)
+        .with_context(|| format!("Archive introuvable : {}", entry.archive.display()))?;
+    if actual != entry.archive_hash {
+        anyhow::bail!("L'archive a été modifiée depuis sa création");
+    }
+    let archived: HashSet<String> = verify_archive(&entry.archive)?.into_iter().collect();
+    let mut missing: Vec<String> = collect_photos(dir)
+        .remove(&entry.year)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|rel| !archived.contains(rel))
+        .collect();
+    missing.sort();
+    Ok(SnapshotStatus { missing })
+}
+
+pub fn run_snapshot(dir: &Path, year: &str, out: &Path) -> Result<()> {
+    let entry = create_snapshot(dir, year, out)?;
+    println!(
+        "  {} {} photos de {} archivées et vérifiées → {}",
+        style("✔").green().bold(),
+        style(entry.photos).green().bold(),
+        style(&entry.year).white().bold(),
+        style(entry.archive.display()).white().bold()
+    );
+    Ok(())
+}
+
+pub fn run_verify(dir: &Path) -> Result<()> {
+    let index = SnapshotIndex::load(dir)?;
+    if index.snapshots.is_empty() {
+        println!("  {} Aucun snapshot enregistré.", style("!").yellow().bold());
+        return Ok(());
+    }
+
+    let mut failures = 0usize;
+    for entry in &index.snapshots {
+        match check_snapshot(dir, entry) {
+            Ok(status) => {
+                println!(
+                    "  {} {} : {} ({} photos, {})",
+                    style("✔").green().bold(),
+                    style(&entry.year).white().bold(),
+                    entry.archive.display(),
+                    entry.photos,
+                    entry.created.format("%Y-%m-%d")
+                );
+                if !status.missing.is_empty() {
+                    println!(
+                        "    {} {} photos ajoutées depuis ce snapshot",
+                        style("!").yellow().bold(),
+                        style(status.missing.len()).yellow().bold()
+                    );
+                }
+            }
+            Err(e) => {
+                failures += 1;
+                println!(
+                    "  {} {} : {} — {e}",
+                    style("✘").red().bold(),
+                    style(&entry.year).white().bold(),
+                    entry.archive.display()
+                );
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{failures} snapshot(s) en échec");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_snapshot_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn setup_library(dir: &Path) {
+        fs::create_dir_all(dir.join("2020")).unwrap();
+        fs::create_dir_all(dir.join("2021")).unwrap();
+        fs::write(dir.join("2020/a.jpg"), "photo a").unwrap();
+        fs::write(dir.join("2020/b.jpg"), "photo b").unwrap();
+        let origin = |dest: &str, source: &str| origins::Origin {
+            dest: dest.to_string(),
+            source: source.to_string(),
+        };
+        origins::append(dir, &origin("2020/a.jpg", "/src/a.jpg")).unwrap();
+        origins::append(dir, &origin("2021/c.jpg", "/src/c.jpg")).unwrap();
+        fs::write(dir.join("2021/c.jpg"), "photo c").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "vacances");
+        meta.add_tag("2021/c.jpg", "noel");
+        meta.save(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_contains_year_files_and_filtered_metadata() {
+        let tmp = tmpdir();
+        let lib = tmp.join("lib");
+        setup_library(&lib);
+        let out = tmp.join("2020.zip");
+
+        let entry = create_snapshot(&lib, "2020", &out).unwrap();
+        assert_eq!(entry.photos, 2);
+        assert!(!tmp.join("2020.zip.tmp").exists());
+
+        let names = verify_archive(&out).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                ".photo_sort_metadata.json",
+                ".photo_sort_origins.jsonl",
+                "2020/a.jpg",
+                "2020/b.jpg"
+            ]
+        );
+
+        let mut zip = zip::ZipArchive::new(fs::File::open(&out).unwrap()).unwrap();
+        let mut json = String::new();
+        zip.by_name(METADATA_ENTRY)
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        let meta: Metadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(meta.get_tags("2020/a.jpg"), &["vacances"]);
+        assert!(!meta.files.contains_key("2021/c.jpg"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn snapshot_unknown_year_fails() {
+        let tmp = tmpdir();
+        setup_library(&tmp);
+        assert!(create_snapshot(&tmp, "1999", &tmp.join("1999.zip")).is_err());
+        assert!(!tmp.join("1999.zip").exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn index_replaces_same_archive() {
+        let tmp = tmpdir();
+        let lib = tmp.join("lib");
+        setup_library(&lib);
+        let out = tmp.join("2020.zip");
+
+        create_snapshot(&lib, "2020", &out).unwrap();
+        create_snapshot(&lib, "2020", &out).unwrap();
+        create_snapshot(&lib, "2021", &tmp.join("2021.zip")).unwrap();
+
+        let index = SnapshotIndex::load(&lib).unwrap();
+        assert_eq!(index.snapshots.len(), 2);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn check_reports_photos_added_since_snapshot() {
+        let tmp = tmpdir();
+        let lib = tmp.join("lib");
+        setup_library(&lib);
+        let entry = create_snapshot(&lib, "2020", &tmp.join("2020.zip")).unwrap();
+
+        assert!(check_snapshot(&lib, &entry).unwrap().missing.is_empty());
+        fs::write(lib.join("2020/d.jpg"), "photo d").unwrap();
+        assert_eq!(
+            check_snapshot(&lib, &entry).unwrap().missing,
+            vec!["2020/d.jpg"]
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn check_detects_corrupted_archive() {
+        let tmp = tmpdir();
+        let lib = tmp.join("lib");
+        setup_library(&lib);
+        let out = tmp.join("2020.zip");
+        let mut entry = create_snapshot(&lib, "2020", &out).unwrap();
+
+        // Flip the content of a stored entry, keeping the zip structure intact
+        let mut bytes = fs::read(&out).unwrap();
+        let pos = bytes.windows(7).position(|w| w == b"photo a").unwrap();
+        bytes[pos + 6] = b'X';
+        fs::write(&out, &bytes).unwrap();
+
+        assert!(check_snapshot(&lib, &entry).is_err());
+        // Even with an updated archive hash, the per-file checksums catch it
+        entry.archive_hash = hash_file(&out).unwrap();
+        assert!(check_snapshot(&lib, &entry).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn check_fails_when_archive_missing() {
+        let tmp = tmpdir();
+        let lib = tmp.join("lib");
+        setup_library(&lib);
+        let out = tmp.join("2020.zip");
+        let entry = create_snapshot(&lib, "2020", &out).unwrap();
+        fs::remove_file(&out).unwrap();
+
+        assert!(check_snapshot(&lib, &entry).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use chrono::NaiveDateTime;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike};
 use console::style;
 use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use regex::Regex;
@@ -9,14 +9,43 @@ use std::fs;
 use std::io::{BufReader, Read as _};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::OnceLock;
 use walkdir::WalkDir;
 
+use crate::apple;
+use crate::events::{self, EventPlan};
+use crate::journal;
+use crate::metadata::{self, Metadata};
+use crate::origins::{self, Origin};
+use crate::photoignore::IgnoreRules;
+use crate::xmp;
+
 pub const PHOTO_EXTENSIONS: &[&str] = &[
     "jpg", "jpeg", "heic", "heif", "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "raf",
     "tiff", "tif",
 ];
 
+/// Extension list replacing `PHOTO_EXTENSIONS`, from `--extensions` / `--also-ext`.
+static PHOTO_EXTENSIONS_OVERRIDE: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Set by Ctrl-C during `run_sort`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Name of the progress file (at the root of the output dir).
+pub const PROGRESS_FILE: &str = ".photo_sort_progress.json";
+
+/// RAW formats (kept together with a JPEG of the same name when sorting).
+pub const RAW_EXTENSIONS: &[&str] = &["cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "raf"];
+
+/// Subfolder of a year dir receiving the non-preferred file of a RAW+JPEG pair.
+pub const PAIR_STASH_DIR: &str = "raw";
+
+/// Folder of the output dir receiving unreadable files (`--quarantine`).
+pub const QUARANTINE_DIR: &str = "_quarantine";
+
+/// Reason log inside `QUARANTINE_DIR`.
+const QUARANTINE_LOG: &str = "quarantine.log";
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ProcessedEntry {
     pub source: String,
@@ -31,7 +60,7 @@ pub struct Progress {
     pub processed: Vec<ProcessedEntry>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum DateSource {
     Exif,
     Dirname,
@@ -49,13 +78,117 @@ impl DateSource {
 }
 
 pub fn is_photo(path: &Path) -> bool {
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    let ext = ext.to_lowercase();
+    match PHOTO_EXTENSIONS_OVERRIDE.get() {
+        Some(list) => list.contains(&ext),
+        None => PHOTO_EXTENSIONS.contains(&ext.as_str()),
+    }
+}
+
+/// Extension list to use instead of `PHOTO_EXTENSIONS`: `extensions` replaces
+/// it, `also` extends it. `None` when both are empty. Entries are lowercased
+/// and may be given with a leading dot.
+pub fn photo_extensions_from(extensions: &[String], also: &[String]) -> Option<Vec<String>> {
+    let normalize = |list: &[String]| -> Vec<String> {
+        list.iter()
+            .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect()
+    };
+    let mut list = if extensions.is_empty() {
+        if also.is_empty() {
+            return None;
+        }
+        PHOTO_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+    } else {
+        normalize(extensions)
+    };
+    for ext in normalize(also) {
+        if !list.contains(&ext) {
+            list.push(ext);
+        }
+    }
+    Some(list)
+}
+
+/// Install the extension list recognised by `is_photo` for this process.
+/// Only the first call has an effect.
+pub fn set_photo_extensions(list: Vec<String>) {
+    let _ = PHOTO_EXTENSIONS_OVERRIDE.set(list);
+}
+
+pub fn is_raw(path: &Path) -> bool {
+    RAW_EXTENSIONS.contains(&lower_ext(path).as_str())
+}
+
+fn lower_ext(path: &Path) -> String {
     path.extension()
         .and_then(|e| e.to_str())
-        .is_some_and(|ext| PHOTO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Find RAW+JPEG pairs (same folder, same stem) and return the photos
+/// reordered so each RAW directly follows its JPEG, with the JPEG → RAW map.
+/// A stem with several JPEGs or several RAWs is left unpaired.
+pub fn pair_photos(photos: Vec<PathBuf>) -> (Vec<PathBuf>, HashMap<PathBuf, PathBuf>) {
+    // Key: parent dir joined with the lowercased stem; value: (JPEGs, RAWs).
+    let mut groups: HashMap<PathBuf, (Vec<&PathBuf>, Vec<&PathBuf>)> = HashMap::new();
+    for path in &photos {
+        let ext = lower_ext(path);
+        let is_jpeg = ext == "jpg" || ext == "jpeg";
+        if !is_jpeg && !is_raw(path) {
+            continue;
+        }
+        let stem = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        let key = path.parent().unwrap_or(Path::new("")).join(stem);
+        let group = groups.entry(key).or_default();
+        if is_jpeg {
+            group.0.push(path);
+        } else {
+            group.1.push(path);
+        }
+    }
+
+    let pairs: HashMap<PathBuf, PathBuf> = groups
+        .into_values()
+        .filter(|(jpegs, raws)| jpegs.len() == 1 && raws.len() == 1)
+        .map(|(jpegs, raws)| (jpegs[0].clone(), raws[0].clone()))
+        .collect();
+    let paired_raws: HashSet<&PathBuf> = pairs.values().collect();
+
+    let mut ordered = Vec::with_capacity(photos.len());
+    for path in &photos {
+        if paired_raws.contains(path) {
+            continue;
+        }
+        ordered.push(path.clone());
+        if let Some(raw) = pairs.get(path) {
+            ordered.push(raw.clone());
+        }
+    }
+    (ordered, pairs)
 }
 
+/// Files from this size on (videos, large RAW) are memory-mapped and hashed
+/// on all cores. Smaller files are read sequentially: mapping them costs more
+/// than it saves.
+const PARALLEL_HASH_THRESHOLD: u64 = 32 * 1024 * 1024;
+
 pub fn hash_file(path: &Path) -> Result<String> {
     let mut file = fs::File::open(path)?;
+    if file.metadata()?.len() >= PARALLEL_HASH_THRESHOLD {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_mmap_rayon(path)?;
+        return Ok(hasher.finalize().to_hex().to_string());
+    }
     let mut hasher = blake3::Hasher::new();
     let mut buf = [0u8; 65536];
     loop {
@@ -69,25 +202,206 @@ pub fn hash_file(path: &Path) -> Result<String> {
 }
 
 pub fn date_from_exif(path: &Path) -> Option<NaiveDateTime> {
-    let file = fs::File::open(path).ok()?;
-    let mut reader = BufReader::new(file);
-    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    exif_datetime(path).map(|(dt, _)| dt)
+}
 
-    for tag in [
-        exif::Tag::DateTimeOriginal,
-        exif::Tag::DateTimeDigitized,
-        exif::Tag::DateTime,
+/// Capture time from EXIF, with the UTC offset recorded next to it
+/// (`OffsetTimeOriginal`, `OffsetTimeDigitized` or `OffsetTime`) if any.
+pub fn exif_datetime(path: &Path) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let exif = read_exif(path)?;
+    let ascii = |tag: exif::Tag| {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .and_then(|f| match &f.value {
+                exif::Value::Ascii(v) => v.first().map(|b| String::from_utf8_lossy(b).to_string()),
+                _ => None,
+            })
+    };
+
+    for (tag, subsec_tag, offset_tag) in [
+        (
+            exif::Tag::DateTimeOriginal,
+            exif::Tag::SubSecTimeOriginal,
+            exif::Tag::OffsetTimeOriginal,
+        ),
+        (
+            exif::Tag::DateTimeDigitized,
+            exif::Tag::SubSecTimeDigitized,
+            exif::Tag::OffsetTimeDigitized,
+        ),
+        (exif::Tag::DateTime, exif::Tag::SubSecTime, exif::Tag::OffsetTime),
     ] {
         if let Some(field) = exif.get_field(tag, exif::In::PRIMARY) {
             let val = field.display_value().to_string();
             if let Ok(dt) = NaiveDateTime::parse_from_str(&val, "%Y-%m-%d %H:%M:%S") {
-                return Some(dt);
+                let nanos = ascii(subsec_tag).and_then(|s| subsec_nanos(&s));
+                let dt = match nanos {
+                    Some(n) => dt.with_nanosecond(n).unwrap_or(dt),
+                    None => dt,
+                };
+                return Some((dt, ascii(offset_tag).and_then(|s| parse_utc_offset(&s))));
             }
         }
     }
     None
 }
 
+/// EXIF block of a photo (HEIC/HEIF through libheif when enabled).
+pub fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+    match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => Some(exif),
+        #[cfg(feature = "heif")]
+        Err(_) if crate::heif::is_heif(path) => crate::heif::read_exif(path).ok(),
+        Err(_) => None,
+    }
+}
+
+/// GPS position from EXIF, in decimal degrees (latitude, longitude).
+pub fn exif_gps(path: &Path) -> Option<(f64, f64)> {
+    let exif = read_exif(path)?;
+    let coordinate = |tag: exif::Tag, ref_tag: exif::Tag, negative: u8| {
+        let exif::Value::Rational(parts) = &exif.get_field(tag, exif::In::PRIMARY)?.value else {
+            return None;
+        };
+        let exif::Value::Ascii(reference) = &exif.get_field(ref_tag, exif::In::PRIMARY)?.value
+        else {
+            return None;
+        };
+        let degrees = gps_degrees(parts)?;
+        let negative = reference.first()?.first() == Some(&negative);
+        Some(if negative { -degrees } else { degrees })
+    };
+    let lat = coordinate(exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b'S')?;
+    let lon = coordinate(exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b'W')?;
+    metadata::is_valid_position(lat, lon).then_some((lat, lon))
+}
+
+/// Camera, lens, shot settings and dimensions of a photo, `None` if it has
+/// neither EXIF nor a readable image header.
+pub fn exif_summary(path: &Path) -> Option<metadata::ExifSummary> {
+    let exif = read_exif(path);
+    let field = |tag: exif::Tag| exif.as_ref()?.get_field(tag, exif::In::PRIMARY);
+    let text = |tag: exif::Tag| {
+        let exif::Value::Ascii(value) = &field(tag)?.value else {
+            return None;
+        };
+        let text = String::from_utf8_lossy(value.first()?);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    };
+    let rational = |tag: exif::Tag| match &field(tag)?.value {
+        exif::Value::Rational(parts) => parts.first().filter(|r| r.denom != 0).map(|r| r.to_f64()),
+        _ => None,
+    };
+    let uint = |tag: exif::Tag| field(tag)?.value.get_uint(0);
+
+    let dimensions = uint(exif::Tag::PixelXDimension)
+        .zip(uint(exif::Tag::PixelYDimension))
+        .or_else(|| image::image_dimensions(path).ok());
+    let summary = metadata::ExifSummary {
+        make: text(exif::Tag::Make),
+        model: text(exif::Tag::Model),
+        lens: text(exif::Tag::LensModel),
+        width: dimensions.map(|(w, _)| w),
+        height: dimensions.map(|(_, h)| h),
+        iso: uint(exif::Tag::PhotographicSensitivity),
+        aperture: rational(exif::Tag::FNumber),
+        exposure: field(exif::Tag::ExposureTime).map(|f| f.display_value().to_string()),
+        focal_length: rational(exif::Tag::FocalLength),
+    };
+    (summary != metadata::ExifSummary::default()).then_some(summary)
+}
+
+/// Degrees, minutes and seconds (any of them possibly fractional) to
+/// decimal degrees.
+fn gps_degrees(parts: &[exif::Rational]) -> Option<f64> {
+    if parts.is_empty() || parts.iter().any(|r| r.denom == 0) {
+        return None;
+    }
+    Some(
+        parts
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|(r, div)| r.to_f64() / div)
+            .sum(),
+    )
+}
+
+/// Parse an EXIF `OffsetTime*` value (`"+09:00"`, `"-05:30"`).
+pub fn parse_utc_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim().trim_end_matches('\0');
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest.get(..2)?, rest.get(2..)?));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Time zone in which `--timezone` expresses dates (folders and names).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// The system time zone.
+    Local,
+    Fixed(FixedOffset),
+    Named(chrono_tz::Tz),
+}
+
+impl std::str::FromStr for Zone {
+    type Err = String;
+
+    /// `local`, `UTC`, a fixed offset (`+02:00`) or an IANA name (`Europe/Paris`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "local" => Ok(Zone::Local),
+            "UTC" | "utc" | "Z" => Ok(Zone::Fixed(FixedOffset::east_opt(0).unwrap())),
+            other => parse_utc_offset(other)
+                .map(Zone::Fixed)
+                .or_else(|| other.parse::<chrono_tz::Tz>().ok().map(Zone::Named))
+                .ok_or_else(|| format!("Fuseau horaire inconnu : {other}")),
+        }
+    }
+}
+
+impl Zone {
+    /// Wall-clock time in this zone of the instant `dt` (read at `offset`).
+    pub fn convert(&self, dt: NaiveDateTime, offset: FixedOffset) -> NaiveDateTime {
+        match offset.from_local_datetime(&dt).single() {
+            Some(instant) => self.wall_clock(instant.naive_utc()),
+            None => dt,
+        }
+    }
+
+    fn wall_clock(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Zone::Local => chrono::Local.from_utc_datetime(&utc).naive_local(),
+            Zone::Fixed(offset) => offset.from_utc_datetime(&utc).naive_local(),
+            Zone::Named(tz) => tz.from_utc_datetime(&utc).naive_local(),
+        }
+    }
+}
+
+/// Convert an EXIF `SubSecTime*` value (fraction digits, e.g. `"12"` = 0.12 s)
+/// to nanoseconds.
+pub fn subsec_nanos(subsec: &str) -> Option<u32> {
+    let digits = subsec.trim().trim_end_matches('\0');
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut padded: String = digits.chars().take(9).collect();
+    while padded.len() < 9 {
+        padded.push('0');
+    }
+    padded.parse().ok()
+}
+
 pub fn date_from_dirname(path: &Path) -> Option<NaiveDateTime> {
     let re = Regex::new(r"(19|20)\d{2}").unwrap();
     let path_str = path.to_string_lossy();
@@ -107,14 +421,28 @@ pub fn date_from_filesystem(path: &Path) -> Option<NaiveDateTime> {
     Some(dt.naive_local())
 }
 
-pub fn detect_date(path: &Path) -> (NaiveDateTime, DateSource) {
-    if let Some(dt) = date_from_exif(path) {
+/// Capture date of a photo and where it came from. With a `zone`, EXIF times
+/// carrying an offset and filesystem times are converted to it; EXIF times
+/// without offset are kept as-is.
+pub fn detect_date(path: &Path, zone: Option<&Zone>) -> (NaiveDateTime, DateSource) {
+    if let Some((dt, offset)) = exif_datetime(path) {
+        let dt = match (zone, offset) {
+            (Some(zone), Some(offset)) => zone.convert(dt, offset),
+            _ => dt,
+        };
         return (dt, DateSource::Exif);
     }
     if let Some(dt) = date_from_dirname(path) {
         return (dt, DateSource::Dirname);
     }
     if let Some(dt) = date_from_filesystem(path) {
+        let dt = match zone {
+            Some(zone) => {
+                let local = chrono::Local.from_local_datetime(&dt).earliest();
+                local.map_or(dt, |l| zone.wall_clock(l.naive_utc()))
+            }
+            None => dt,
+        };
         return (dt, DateSource::Filesystem);
     }
     (
@@ -123,24 +451,266 @@ pub fn detect_date(path: &Path) -> (NaiveDateTime, DateSource) {
     )
 }
 
-pub fn build_dest_path(output_dir: &Path, dt: &NaiveDateTime, ext: &str) -> PathBuf {
+/// Options controlling how `run_sort` names and copies files.
+#[derive(Debug, Default, Clone)]
+pub struct SortOptions {
+    /// Append the original file stem to the date-based name
+    /// (`2020-07-15_14-30-22__IMG_1234.jpg`).
+    pub keep_name: bool,
+    /// Apply the EXIF Orientation tag to the pixels of JPEG/PNG files while
+    /// copying them, then reset the tag.
+    pub auto_orient: bool,
+    /// For RAW+JPEG pairs, keep only this one in the year folder and stash
+    /// the other in `raw/`. `None` keeps both side by side.
+    pub pair_prefer: Option<PairPreference>,
+    /// Copy files that can't be read (no EXIF date and undecodable pixels)
+    /// into `_quarantine/` instead of dating them from the filesystem.
+    pub quarantine: bool,
+    /// Only copy photos whose detected date is on or after this day.
+    pub from: Option<NaiveDate>,
+    /// Only copy photos whose detected date is on or before this day.
+    pub to: Option<NaiveDate>,
+    /// Set the modification time of copied files to their detected date.
+    pub touch: bool,
+    /// Express dates in this time zone, using the EXIF `OffsetTime*` tags.
+    pub timezone: Option<Zone>,
+    /// Sort into event folders (`2020/2020-07-12_event-03/`) instead of year
+    /// folders; a new event starts after this many hours without photos.
+    pub group_events: Option<u32>,
+    /// Hash each copy right after writing it and reject it when it differs
+    /// from the source.
+    pub verify_copy: bool,
+    /// Tag photos coming from an Apple Photos library with their albums and
+    /// keywords.
+    pub apple_albums: bool,
+}
+
+impl SortOptions {
+    /// `true` if `dt` falls within `--from` / `--to` (both inclusive).
+    pub fn accepts_date(&self, dt: &NaiveDateTime) -> bool {
+        let day = dt.date();
+        self.from.is_none_or(|from| day >= from) && self.to.is_none_or(|to| day <= to)
+    }
+
+    fn has_date_range(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+}
+
+/// Which file of a RAW+JPEG pair stays in the year folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PairPreference {
+    Raw,
+    Jpeg,
+}
+
+/// Extensions whose pixels `--auto-orient` may rewrite.
+const ORIENTABLE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// `src` with its EXIF orientation applied to the pixels and the Orientation
+/// tag reset to 1 (the rest of the EXIF block is kept), encoded in memory.
+/// `None` when there is nothing to do: no Orientation tag, or already upright.
+pub fn auto_oriented(src: &Path) -> Result<Option<Vec<u8>>> {
+    use image::metadata::Orientation;
+    use image::{ImageDecoder, ImageEncoder, ImageFormat};
+
+    // Only files asking for a rotation are decoded
+    let tagged = read_exif(src).and_then(|exif| {
+        (exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?)
+            .value
+            .get_uint(0)
+    });
+    if tagged.is_none_or(|orientation| orientation <= 1) {
+        return Ok(None);
+    }
+
+    let format = ImageFormat::from_path(src)?;
+    let mut decoder = image::ImageReader::open(src)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let Some(mut exif) = decoder.exif_metadata()? else {
+        return Ok(None);
+    };
+    let Some(orientation) = Orientation::remove_from_exif_chunk(&mut exif) else {
+        return Ok(None);
+    };
+    if orientation == Orientation::NoTransforms {
+        return Ok(None);
+    }
+
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+
+    let mut out = std::io::Cursor::new(Vec::new());
+    match format {
+        ImageFormat::Jpeg => {
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, 95);
+            let _ = encoder.set_exif_metadata(exif);
+            img.write_with_encoder(encoder)?;
+        }
+        ImageFormat::Png => {
+            let mut encoder = image::codecs::png::PngEncoder::new(&mut out);
+            let _ = encoder.set_exif_metadata(exif);
+            img.write_with_encoder(encoder)?;
+        }
+        _ => anyhow::bail!("Format non pris en charge pour l'orientation automatique"),
+    }
+    Ok(Some(out.into_inner()))
+}
+
+/// What `--auto-orient` writes instead of a plain copy of `src`, if anything.
+fn oriented_content(src: &Path, ext: &str, options: &SortOptions) -> Result<Option<Vec<u8>>> {
+    if !options.auto_orient || !ORIENTABLE_EXTENSIONS.contains(&ext) {
+        return Ok(None);
+    }
+    auto_oriented(src).map_err(|e| {
+        Unorientable {
+            source: src.to_path_buf(),
+            reason: format!("{e:#}"),
+        }
+        .into()
+    })
+}
+
+/// Hash of what ends up at the destination: the oriented content if any,
+/// else the source's.
+fn content_hash(oriented: Option<&[u8]>, source_hash: &str) -> String {
+    match oriented {
+        Some(bytes) => blake3::hash(bytes).to_hex().to_string(),
+        None => source_hash.to_string(),
+    }
+}
+
+/// Result of looking for a destination name that does not clobber anything.
+#[derive(Debug, PartialEq)]
+pub enum DestPath {
+    /// Nothing exists at this path yet.
+    Free(PathBuf),
+    /// A file with the same content already exists at this (colliding) path.
+    Identical(PathBuf),
+}
+
+/// Destination dir and base name (without counter nor extension). The dir is
+/// the year folder, or `folder` (relative to `output_dir`) when given.
+fn dest_base(
+    output_dir: &Path,
+    folder: Option<&Path>,
+    dt: &NaiveDateTime,
+    original_stem: Option<&str>,
+) -> (PathBuf, String) {
     let year = dt.format("%Y").to_string();
-    let base_name = dt.format("%Y-%m-%d_%H-%M-%S").to_string();
-    let year_dir = output_dir.join(&year);
+    let mut base_name = dt.format("%Y-%m-%d_%H-%M-%S").to_string();
+    if dt.nanosecond() != 0 {
+        base_name.push_str(&format!(".{:03}", dt.nanosecond() / 1_000_000));
+    }
+    if let Some(stem) = original_stem.filter(|s| !s.is_empty()) {
+        base_name.push_str("__");
+        base_name.push_str(stem);
+    }
+    let dir = match folder {
+        Some(folder) => output_dir.join(folder),
+        None => output_dir.join(&year),
+    };
+    (dir, base_name)
+}
 
-    let candidate = year_dir.join(format!("{base_name}.{ext}"));
-    if !candidate.exists() {
-        return candidate;
+fn dest_candidate(year_dir: &Path, base_name: &str, counter: u32, ext: &str) -> PathBuf {
+    if counter == 0 {
+        year_dir.join(format!("{base_name}.{ext}"))
+    } else {
+        year_dir.join(format!("{base_name}_{counter}.{ext}"))
     }
+}
 
-    let mut counter = 1u32;
+/// First free destination for a photo taken at `dt`, adding `_N` to the name
+/// on collision. Every colliding file is hashed first: if one of them already
+/// holds the same content as the source, it is returned as
+/// `DestPath::Identical` instead of allocating a new `_N` name.
+pub fn resolve_dest_path(
+    output_dir: &Path,
+    folder: Option<&Path>,
+    dt: &NaiveDateTime,
+    ext: &str,
+    original_stem: Option<&str>,
+    source_hash: &str,
+) -> DestPath {
+    let (dir, base_name) = dest_base(output_dir, folder, dt, original_stem);
+    let mut counter = 0u32;
     loop {
-        let candidate = year_dir.join(format!("{base_name}_{counter}.{ext}"));
+        let candidate = dest_candidate(&dir, &base_name, counter, ext);
+        match slot_state(&candidate, source_hash) {
+            Slot::Free => return DestPath::Free(candidate),
+            Slot::Identical => return DestPath::Identical(candidate),
+            Slot::Taken => counter += 1,
+        }
+    }
+}
+
+/// What a destination candidate holds, compared to the file to place there.
+#[derive(Debug, PartialEq)]
+enum Slot {
+    /// Nothing exists at this path yet.
+    Free,
+    /// A file with the same content already exists at this path.
+    Identical,
+    /// Another file is at this path.
+    Taken,
+}
+
+fn slot_state(candidate: &Path, source_hash: &str) -> Slot {
+    if !candidate.exists() {
+        Slot::Free
+    } else if hash_file(candidate).is_ok_and(|h| h == source_hash) {
+        Slot::Identical
+    } else {
+        Slot::Taken
+    }
+}
+
+/// Why `path` looks corrupt: neither an EXIF date nor decodable pixels.
+/// Formats this build can't decode (RAW, HEIC without the `heif` feature) are
+/// given the benefit of the doubt.
+pub fn corruption_reason(path: &Path) -> Option<String> {
+    if date_from_exif(path).is_some() || !crate::thumb::can_generate_thumb(path) {
+        return None;
+    }
+    crate::thumb::open_image(path)
+        .err()
+        .map(|e| format!("EXIF illisible, image non décodable ({})", e.root_cause()))
+}
+
+/// Copy an unreadable file into `<output_dir>/_quarantine/` under its original
+/// name (suffixed `_N` on collision) and log `reason` next to it.
+pub fn quarantine_file(abs_source: &Path, output_dir: &Path, reason: &str) -> Result<PathBuf> {
+    use std::io::Write;
+    let dir = output_dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&dir)?;
+
+    let stem = abs_source
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let ext = lower_ext(abs_source);
+    let mut counter = 0u32;
+    let dest = loop {
+        let candidate = dest_candidate(&dir, &stem, counter, &ext);
         if !candidate.exists() {
-            return candidate;
+            break candidate;
         }
         counter += 1;
-    }
+    };
+    fs::copy(abs_source, &dest)
+        .with_context(|| format!("Impossible de copier {}", abs_source.display()))?;
+
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(QUARANTINE_LOG))?;
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    writeln!(log, "{name} <- {} : {reason}", abs_source.display())?;
+    Ok(dest)
 }
 
 pub fn load_progress(path: &Path) -> Result<Progress> {
@@ -161,22 +731,319 @@ pub fn save_progress(path: &Path, progress: &Progress) -> Result<()> {
     Ok(())
 }
 
-fn append_origin(year_dir: &Path, new_name: &str, original_path: &str) -> Result<()> {
-    use std::io::Write;
-    let origins_path = year_dir.join(".photo_sort_origins");
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&origins_path)?;
-    writeln!(file, "{new_name} <- {original_path}")?;
+/// Where `place_file` put a photo.
+#[derive(Debug)]
+pub struct Placement {
+    pub dest: PathBuf,
+    pub date: NaiveDateTime,
+    pub date_source: DateSource,
+    /// `false` when an identical file was already present at a colliding
+    /// destination name, in which case nothing was copied.
+    pub copied: bool,
+    /// BLAKE3 hash of the file at `dest`: the source's, unless `--auto-orient`
+    /// rewrote it.
+    pub hash: String,
+}
+
+/// Copy one photo into its year folder under `output_dir` (or into `folder`,
+/// relative to it), named after its detected date. `source_hash` is the
+/// BLAKE3 hash of the source, used to recognise a destination that already
+/// holds the same content (once oriented, under `--auto-orient`).
+pub fn place_file(
+    abs_source: &Path,
+    output_dir: &Path,
+    folder: Option<&Path>,
+    options: &SortOptions,
+    source_hash: &str,
+) -> Result<Placement> {
+    let (dt, date_source) = detect_date(abs_source, options.timezone.as_ref());
+
+    let ext = abs_source
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg")
+        .to_lowercase();
+
+    let original_stem = abs_source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string());
+    let oriented = oriented_content(abs_source, &ext, options)?;
+    let hash = content_hash(oriented.as_deref(), source_hash);
+    let dest_path = match resolve_dest_path(
+        output_dir,
+        folder,
+        &dt,
+        &ext,
+        original_stem.as_deref().filter(|_| options.keep_name),
+        &hash,
+    ) {
+        DestPath::Free(p) => p,
+        DestPath::Identical(p) => {
+            return Ok(Placement {
+                dest: p,
+                date: dt,
+                date_source,
+                copied: false,
+                hash,
+            });
+        }
+    };
+
+    copy_photo(
+        abs_source,
+        &dest_path,
+        oriented.as_deref(),
+        &dt,
+        &hash,
+        options,
+    )?;
+
+    Ok(Placement {
+        dest: dest_path,
+        date: dt,
+        date_source,
+        copied: true,
+        hash,
+    })
+}
+
+/// Copy `abs_source` to `dest_path` (or write its `oriented` content),
+/// creating the parent dir and applying `--verify-copy` and `--touch` when
+/// requested. `hash` is the hash of what should end up at `dest_path`.
+fn copy_photo(
+    abs_source: &Path,
+    dest_path: &Path,
+    oriented: Option<&[u8]>,
+    date: &NaiveDateTime,
+    hash: &str,
+    options: &SortOptions,
+) -> Result<()> {
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let written = match oriented {
+        Some(content) => fs::write(dest_path, content),
+        None => fs::copy(abs_source, dest_path).map(|_| ()),
+    };
+    written.with_context(|| {
+        format!(
+            "Erreur de copie : {} → {}",
+            abs_source.display(),
+            dest_path.display()
+        )
+    })?;
+    if options.verify_copy && hash_file(dest_path).ok().as_deref() != Some(hash) {
+        let _ = fs::remove_file(dest_path);
+        return Err(CopyMismatch {
+            dest: dest_path.to_path_buf(),
+        }
+        .into());
+    }
+    if options.touch {
+        set_mtime(dest_path, date)?;
+    }
+    Ok(())
+}
+
+/// A copy whose content, read back, differs from its source (`--verify-copy`).
+/// The bad copy has been deleted.
+#[derive(Debug)]
+pub struct CopyMismatch {
+    pub dest: PathBuf,
+}
+
+impl std::fmt::Display for CopyMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Copie corrompue, contenu relu différent de la source : {}",
+            self.dest.display()
+        )
+    }
+}
+
+impl std::error::Error for CopyMismatch {}
+
+/// A photo `--auto-orient` should rotate but can't decode or re-encode.
+/// Nothing has been written for it.
+#[derive(Debug)]
+pub struct Unorientable {
+    pub source: PathBuf,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Unorientable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Orientation impossible : {} ({})",
+            self.source.display(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for Unorientable {}
+
+/// Set the modification time of `path` to `date`, read as local time.
+pub fn set_mtime(path: &Path, date: &NaiveDateTime) -> Result<()> {
+    let local = date
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .with_context(|| format!("Date locale invalide : {date}"))?;
+    // Copies of read-only sources are read-only too; the owner may still
+    // change the times through a read handle.
+    let file = fs::File::options()
+        .write(true)
+        .open(path)
+        .or_else(|_| fs::File::open(path))?;
+    file.set_modified(local.into())
+        .with_context(|| format!("Impossible de dater {}", path.display()))?;
     Ok(())
 }
 
-pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
+/// Date of a RAW+JPEG pair: the JPEG's, or the RAW's when only the RAW has
+/// usable EXIF.
+pub fn pair_date(jpeg: &Path, raw: &Path, zone: Option<&Zone>) -> (NaiveDateTime, DateSource) {
+    let detected = detect_date(jpeg, zone);
+    if !matches!(detected.1, DateSource::Exif)
+        && let (raw_dt, DateSource::Exif) = detect_date(raw, zone)
+    {
+        return (raw_dt, DateSource::Exif);
+    }
+    detected
+}
+
+/// Place a JPEG and its RAW companion under the same base name, picking a
+/// counter that is free (or identical) for both. The date comes from
+/// `pair_date`.
+pub fn place_pair(
+    jpeg: &Path,
+    raw: &Path,
+    output_dir: &Path,
+    folder: Option<&Path>,
+    options: &SortOptions,
+    jpeg_hash: &str,
+    raw_hash: &str,
+) -> Result<(Placement, Placement)> {
+    let (dt, date_source) = pair_date(jpeg, raw, options.timezone.as_ref());
+
+    let jpeg_ext = lower_ext(jpeg);
+    let raw_ext = lower_ext(raw);
+    let original_stem = jpeg
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string());
+    let (dir, base_name) = dest_base(
+        output_dir,
+        folder,
+        &dt,
+        original_stem.as_deref().filter(|_| options.keep_name),
+    );
+    let stash_dir = dir.join(PAIR_STASH_DIR);
+    let (jpeg_dir, raw_dir) = match options.pair_prefer {
+        Some(PairPreference::Raw) => (&stash_dir, &dir),
+        Some(PairPreference::Jpeg) => (&dir, &stash_dir),
+        None => (&dir, &dir),
+    };
+
+    // RAW files are never oriented
+    let oriented = oriented_content(jpeg, &jpeg_ext, options)?;
+    let jpeg_hash = content_hash(oriented.as_deref(), jpeg_hash);
+
+    let mut counter = 0u32;
+    let (jpeg_dest, jpeg_free, raw_dest, raw_free) = loop {
+        let j = dest_candidate(jpeg_dir, &base_name, counter, &jpeg_ext);
+        let r = dest_candidate(raw_dir, &base_name, counter, &raw_ext);
+        let (jpeg_slot, raw_slot) = (slot_state(&j, &jpeg_hash), slot_state(&r, raw_hash));
+        if jpeg_slot != Slot::Taken && raw_slot != Slot::Taken {
+            break (j, jpeg_slot == Slot::Free, r, raw_slot == Slot::Free);
+        }
+        counter += 1;
+    };
+
+    if jpeg_free {
+        copy_photo(
+            jpeg,
+            &jpeg_dest,
+            oriented.as_deref(),
+            &dt,
+            &jpeg_hash,
+            options,
+        )?;
+    }
+    if raw_free {
+        copy_photo(raw, &raw_dest, None, &dt, raw_hash, options)?;
+    }
+
+    let placement = |dest: PathBuf, copied: bool, hash: &str| Placement {
+        dest,
+        date: dt,
+        date_source,
+        copied,
+        hash: hash.to_string(),
+    };
+    Ok((
+        placement(jpeg_dest, jpeg_free, &jpeg_hash),
+        placement(raw_dest, raw_free, raw_hash),
+    ))
+}
+
+/// Event folders for the photos `run_sort` is about to place: those not
+/// already processed and within the date range. RAW companions follow their
+/// JPEG when it is placed too.
+fn plan_run_events(
+    photos: &[PathBuf],
+    pairs: &HashMap<PathBuf, PathBuf>,
+    processed_index: &HashMap<String, u64>,
+    output_dir: &Path,
+    options: &SortOptions,
+    gap_hours: u32,
+) -> EventPlan {
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_message("Regroupement par événements…");
+    let jpeg_of: HashMap<&PathBuf, &PathBuf> = pairs.iter().map(|(j, r)| (r, j)).collect();
+    let mut dated: Vec<(PathBuf, NaiveDateTime)> = Vec::new();
+    let mut planned: HashSet<&PathBuf> = HashSet::new();
+    for photo in photos {
+        if jpeg_of.get(photo).is_some_and(|jpeg| planned.contains(jpeg)) {
+            continue;
+        }
+        let abs = photo.canonicalize().unwrap_or_else(|_| photo.clone());
+        let size = fs::metadata(&abs).map(|m| m.len()).unwrap_or(0);
+        if processed_index.get(abs.to_string_lossy().as_ref()) == Some(&size) {
+            continue;
+        }
+        let zone = options.timezone.as_ref();
+        let (dt, _) = match pairs.get(photo) {
+            Some(raw) => pair_date(&abs, raw, zone),
+            None => detect_date(&abs, zone),
+        };
+        if options.accepts_date(&dt) {
+            dated.push((photo.clone(), dt));
+            planned.insert(photo);
+        }
+        spinner.tick();
+    }
+    spinner.finish_and_clear();
+
+    let mut plan = events::plan_events(output_dir, &dated, TimeDelta::hours(gap_hours.into()));
+    for (jpeg, raw) in pairs {
+        if let Some(folder) = plan.folders.get(jpeg).cloned() {
+            plan.folders.insert(raw.clone(), folder);
+        }
+    }
+    plan
+}
+
+pub fn run_sort(source: &Path, output_dir: &Path, options: &SortOptions) -> Result<()> {
     fs::create_dir_all(output_dir)?;
+    journal::recover_and_report(output_dir)?;
 
-    let progress_path = output_dir.join(".photo_sort_progress.json");
+    let progress_path = output_dir.join(PROGRESS_FILE);
     let mut progress = load_progress(&progress_path)?;
+    let mut metadata = Metadata::load(output_dir)?;
+    origins::migrate_legacy(output_dir)?;
 
     let mut processed_index: HashMap<String, u64> = HashMap::new();
     let mut known_hashes: HashSet<String> = HashSet::new();
@@ -185,11 +1052,11 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         known_hashes.insert(entry.hash.clone());
     }
 
-    let interrupted = Arc::new(AtomicBool::new(false));
-    let interrupted_clone = Arc::clone(&interrupted);
-    ctrlc::set_handler(move || {
-        interrupted_clone.store(true, Ordering::SeqCst);
-    })?;
+    // Installed by the first run only: `init` sorts several sources in a row
+    match ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        Ok(()) | Err(ctrlc::Error::MultipleHandlers) => {}
+        Err(e) => return Err(e.into()),
+    }
 
     let scan_spinner = ProgressBar::new_spinner();
     scan_spinner.set_style(
@@ -203,12 +1070,22 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
     let mut source_dirs: HashSet<PathBuf> = HashSet::new();
     let mut total_size: u64 = 0;
 
+    let mut libraries: Vec<PathBuf> = Vec::new();
+
+    let mut ignore_rules = IgnoreRules::default();
     for entry in WalkDir::new(source)
         .into_iter()
-        .filter_entry(|e| !e.file_type().is_dir() || e.file_name() != ".thumbnails")
+        .filter_entry(|e| {
+            (!e.file_type().is_dir() || e.file_name() != ".thumbnails")
+                && !apple::is_derivative(e.path())
+                && ignore_rules.accept(e)
+        })
         .filter_map(|e| e.ok())
     {
         if entry.file_type().is_dir() {
+            if apple::is_library(entry.path()) {
+                libraries.push(entry.path().to_path_buf());
+            }
             source_dirs.insert(entry.into_path());
         } else if entry.file_type().is_file() && is_photo(entry.path()) {
             total_size += entry.metadata().map(|m| m.len()).unwrap_or(0);
@@ -217,8 +1094,23 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         scan_spinner.tick();
     }
 
+    // Albums and keywords of Apple Photos libraries, keyed by original
+    let mut library_tags: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    if options.apple_albums {
+        for library in &libraries {
+            match apple::read_library_tags(library) {
+                Ok(tags) => library_tags.extend(tags),
+                Err(e) => scan_spinner.suspend(|| {
+                    eprintln!("  {} {} : {e:#}", style("!").yellow().bold(), library.display());
+                }),
+            }
+        }
+    }
+
     scan_spinner.finish_and_clear();
 
+    let (photos, pairs) = pair_photos(photos);
+    let jpeg_of: HashMap<&PathBuf, &PathBuf> = pairs.iter().map(|(j, r)| (r, j)).collect();
     let total = photos.len();
     let dir_count = source_dirs.len();
 
@@ -250,6 +1142,22 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         style("Taille").dim(),
         style(HumanBytes(total_size)).white()
     );
+    if !libraries.is_empty() {
+        println!(
+            "  {}  {} bibliothèque(s) Apple Photos, originaux seulement",
+            style("Photos").dim(),
+            style(libraries.len()).yellow().bold()
+        );
+    }
+    if options.has_date_range() {
+        let bound = |d: Option<NaiveDate>| d.map(|d| d.to_string()).unwrap_or_else(|| "…".into());
+        println!(
+            "  {}  {} → {}",
+            style("Période").dim(),
+            style(bound(options.from)).white(),
+            style(bound(options.to)).white()
+        );
+    }
     if !processed_index.is_empty() {
         println!(
             "  {}  {} fichiers déjà traités",
@@ -264,13 +1172,27 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         return Ok(());
     }
 
-    let pb = ProgressBar::new(total as u64);
+    let events = match options.group_events {
+        Some(hours) => plan_run_events(
+            &photos,
+            &pairs,
+            &processed_index,
+            output_dir,
+            options,
+            hours,
+        ),
+        None => EventPlan::default(),
+    };
+
+    // Progress is counted in bytes: one RAW weighs as much as a hundred
+    // small JPEGs. The prefix holds the file count.
+    let pb = ProgressBar::new(total_size);
     pb.set_style(
         ProgressStyle::default_bar()
             .template(
-                "  {bar:40.green/dark_gray} {pos}/{len}  {percent}%  \
-                 {msg}\n  \
-                 {elapsed_precise} écoulé  ·  ETA {eta_precise}  ·  {per_sec}",
+                "  {bar:40.green/dark_gray} {bytes}/{total_bytes}  {percent}%  \
+                 {prefix} fichiers  {msg}\n  \
+                 {elapsed_precise} écoulé  ·  ETA {eta_precise}  ·  {bytes_per_sec}",
             )
             .unwrap()
             .progress_chars("━╸─"),
@@ -279,17 +1201,34 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
     let mut copied = 0usize;
     let mut skipped = 0usize;
     let mut duplicates = 0usize;
+    let mut already_present = 0usize;
+    let mut quarantined = 0usize;
+    let mut out_of_range = 0usize;
+    let mut unverified = 0usize;
+    let mut unoriented = 0usize;
+    let mut curated = 0usize;
+    let mut located = 0usize;
     let mut by_method: HashMap<&str, usize> = HashMap::new();
     let mut years_created: HashSet<String> = HashSet::new();
+    // RAW companions already placed with their JPEG, waiting for their turn.
+    let mut pending: HashMap<PathBuf, (Placement, String)> = HashMap::new();
+    let relative = |p: &Path| {
+        p.strip_prefix(output_dir)
+            .unwrap_or(p)
+            .to_string_lossy()
+            .to_string()
+    };
 
-    for photo_path in &photos {
-        if interrupted.load(Ordering::SeqCst) {
+    for (index, photo_path) in photos.iter().enumerate() {
+        pb.set_prefix(format!("{}/{total}", index + 1));
+        if INTERRUPTED.load(Ordering::SeqCst) {
             pb.abandon_with_message(
                 style("Interruption — progression sauvegardée")
                     .yellow()
                     .to_string(),
             );
             save_progress(&progress_path, &progress)?;
+            metadata.save(output_dir)?;
             std::process::exit(0);
         }
 
@@ -299,24 +1238,26 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         let source_str = abs_source.to_string_lossy().to_string();
 
         let file_size = fs::metadata(&abs_source).map(|m| m.len()).unwrap_or(0);
+        let pending_pair = pending.remove(photo_path);
 
-        if let Some(&prev_size) = processed_index.get(&source_str) {
-            if prev_size == file_size {
-                skipped += 1;
-                pb.set_message(format!(
-                    "{} {}",
-                    style("skip").dim(),
-                    style(
-                        abs_source
-                            .file_name()
-                            .unwrap_or_default()
-                            .to_string_lossy()
-                    )
-                    .dim()
-                ));
-                pb.inc(1);
-                continue;
-            }
+        if pending_pair.is_none()
+            && let Some(&prev_size) = processed_index.get(&source_str)
+            && prev_size == file_size
+        {
+            skipped += 1;
+            pb.set_message(format!(
+                "{} {}",
+                style("skip").dim(),
+                style(
+                    abs_source
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                )
+                .dim()
+            ));
+            pb.inc(file_size);
+            continue;
         }
 
         let filename = abs_source
@@ -325,70 +1266,197 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
             .to_string_lossy()
             .to_string();
 
-        let file_hash = hash_file(&abs_source).unwrap_or_default();
-        if known_hashes.contains(&file_hash) {
-            duplicates += 1;
-            pb.set_message(format!(
-                "{} {}",
-                style("dupe").magenta(),
-                style(&filename).dim()
-            ));
-            pb.inc(1);
-            continue;
+        // Out-of-range photos are not recorded: a later run with another
+        // range will pick them up.
+        if pending_pair.is_none() && options.has_date_range() {
+            // A pair is dated as a whole, as `place_pair` does
+            let zone = options.timezone.as_ref();
+            let (dt, _) = match (pairs.get(photo_path), jpeg_of.get(photo_path)) {
+                (Some(raw), _) => pair_date(&abs_source, raw, zone),
+                (None, Some(jpeg)) => pair_date(jpeg, &abs_source, zone),
+                (None, None) => detect_date(&abs_source, zone),
+            };
+            if !options.accepts_date(&dt) {
+                out_of_range += 1;
+                pb.set_message(format!(
+                    "{} {}",
+                    style("hors période").dim(),
+                    style(&filename).dim()
+                ));
+                pb.inc(file_size);
+                continue;
+            }
         }
 
-        let (dt, date_source) = detect_date(&abs_source);
-
-        let ext = abs_source
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("jpg")
-            .to_lowercase();
-
-        let dest_path = build_dest_path(output_dir, &dt, &ext);
+        let (placement, file_hash) = match pending_pair {
+            Some(done) => done,
+            None => {
+                let hashed = hash_file(&abs_source);
+                let quarantine_reason = match &hashed {
+                    _ if !options.quarantine => None,
+                    Err(e) => Some(format!("lecture impossible ({e})")),
+                    Ok(h) if known_hashes.contains(h) => None,
+                    Ok(_) => corruption_reason(&abs_source),
+                };
+                let file_hash = hashed.unwrap_or_default();
+
+                if let Some(reason) = quarantine_reason {
+                    match quarantine_file(&abs_source, output_dir, &reason) {
+                        Ok(dest) => {
+                            quarantined += 1;
+                            pb.set_message(format!(
+                                "{} {}",
+                                style("quarantaine").red(),
+                                style(&filename).dim()
+                            ));
+                            progress.processed.push(ProcessedEntry {
+                                source: source_str.clone(),
+                                dest: relative(&dest),
+                                size: file_size,
+                                hash: file_hash.clone(),
+                                date_source: "quarantine".to_string(),
+                            });
+                            processed_index.insert(source_str, file_size);
+                            if !file_hash.is_empty() {
+                                known_hashes.insert(file_hash);
+                            }
+                            save_progress(&progress_path, &progress)?;
+                        }
+                        Err(e) => {
+                            pb.suspend(|| {
+                                eprintln!("  {} {filename} : {e:#}", style("✘").red().bold());
+                            });
+                        }
+                    }
+                    pb.inc(file_size);
+                    continue;
+                }
+
+                if known_hashes.contains(&file_hash) {
+                    duplicates += 1;
+                    pb.set_message(format!(
+                        "{} {}",
+                        style("dupe").magenta(),
+                        style(&filename).dim()
+                    ));
+                    pb.inc(file_size);
+                    continue;
+                }
+
+                // A RAW companion is placed together with its JPEG, unless it
+                // was already sorted or is a duplicate.
+                let companion = pairs.get(photo_path).and_then(|raw| {
+                    let raw_abs = raw.canonicalize().unwrap_or_else(|_| raw.clone());
+                    let raw_size = fs::metadata(&raw_abs).map(|m| m.len()).unwrap_or(0);
+                    if processed_index.get(raw_abs.to_string_lossy().as_ref()) == Some(&raw_size) {
+                        return None;
+                    }
+                    let raw_hash = hash_file(&raw_abs).ok()?;
+                    (!known_hashes.contains(&raw_hash) && raw_hash != file_hash)
+                        .then_some((raw, raw_abs, raw_hash))
+                });
 
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
+                let event_folder = events.folders.get(photo_path).map(PathBuf::as_path);
+                let placed = match companion {
+                    Some((raw, raw_abs, raw_hash)) => place_pair(
+                        &abs_source,
+                        &raw_abs,
+                        output_dir,
+                        event_folder,
+                        options,
+                        &file_hash,
+                        &raw_hash,
+                    )
+                    .map(|(jpeg_placement, raw_placement)| {
+                        metadata.set_pair(
+                            &relative(&jpeg_placement.dest),
+                            &relative(&raw_placement.dest),
+                        );
+                        pending.insert(raw.clone(), (raw_placement, raw_hash));
+                        jpeg_placement
+                    }),
+                    None => place_file(&abs_source, output_dir, event_folder, options, &file_hash),
+                };
+                // A bad copy or a photo that can't be rotated is not
+                // recorded: the next run tries again.
+                let placement = match placed {
+                    Ok(placement) => placement,
+                    Err(e) if e.is::<CopyMismatch>() || e.is::<Unorientable>() => {
+                        if e.is::<CopyMismatch>() {
+                            unverified += 1;
+                        } else {
+                            unoriented += 1;
+                        }
+                        pb.suspend(|| {
+                            eprintln!("  {} {filename} : {e}", style("✘").red().bold());
+                        });
+                        pb.inc(file_size);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                (placement, file_hash)
+            }
+        };
+        let dest_path = &placement.dest;
+        let date_source = &placement.date_source;
 
-        pb.set_message(format!(
-            "{} {}",
-            style(date_source.as_str()).cyan(),
-            style(&filename).white()
-        ));
+        let dest_relative = relative(dest_path);
 
-        fs::copy(&abs_source, &dest_path).with_context(|| {
-            format!(
-                "Erreur de copie : {} → {}",
-                abs_source.display(),
-                dest_path.display()
-            )
-        })?;
+        if placement.copied {
+            pb.set_message(format!(
+                "{} {}",
+                style(date_source.as_str()).cyan(),
+                style(&filename).white()
+            ));
 
-        copied += 1;
-        *by_method.entry(date_source.as_str()).or_insert(0) += 1;
+            copied += 1;
+            *by_method.entry(date_source.as_str()).or_insert(0) += 1;
 
-        let year = dt.format("%Y").to_string();
-        years_created.insert(year);
+            years_created.insert(placement.date.format("%Y").to_string());
 
-        let dest_relative = dest_path
-            .strip_prefix(output_dir)
-            .unwrap_or(&dest_path)
-            .to_string_lossy()
-            .to_string();
+            if matches!(date_source, DateSource::Exif) {
+                metadata.set_taken(&dest_relative, Some(placement.date));
+            }
+            metadata.set_date_source(&dest_relative, Some(date_source.as_str()));
+            metadata.set_hash(&dest_relative, &placement.hash);
+            metadata.set_exif(&dest_relative, exif_summary(&abs_source));
+            if let Some((lat, lon)) = exif_gps(&abs_source) {
+                located += 1;
+                metadata.set_position(&dest_relative, Some((lat, lon)));
+            }
 
-        let dest_filename = dest_path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
+            // Keep the curation done in other tools (Lightroom, Picasa…)
+            let embedded = xmp::read_embedded(&abs_source);
+            if !embedded.is_empty() {
+                curated += 1;
+                if embedded.rating.is_some() && metadata.get_rating(&dest_relative).is_none() {
+                    metadata.set_rating(&dest_relative, embedded.rating);
+                }
+                for keyword in &embedded.keywords {
+                    metadata.add_tag(&dest_relative, keyword);
+                }
+            }
+            for tag in library_tags.get(&abs_source).into_iter().flatten() {
+                metadata.add_tag(&dest_relative, tag);
+            }
 
-        if let Some(year_dir) = dest_path.parent() {
-            if let Err(e) = append_origin(year_dir, &dest_filename, &source_str) {
+            let origin = Origin {
+                dest: dest_relative.clone(),
+                source: source_str.clone(),
+            };
+            if let Err(e) = origins::append(output_dir, &origin) {
                 pb.suspend(|| {
                     eprintln!("  {} origins : {e}", style("!").yellow().bold());
                 });
             }
+        } else {
+            already_present += 1;
+            pb.set_message(format!(
+                "{} {}",
+                style("présent").blue(),
+                style(&filename).dim()
+            ));
         }
 
         let entry = ProcessedEntry {
@@ -404,10 +1472,11 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
         known_hashes.insert(file_hash);
 
         save_progress(&progress_path, &progress)?;
-        pb.inc(1);
+        pb.inc(file_size);
     }
 
     pb.finish_and_clear();
+    metadata.save(output_dir)?;
 
     println!();
     println!("  {} Terminé !", style("✔").green().bold());
@@ -431,6 +1500,63 @@ pub fn run_sort(source: &Path, output_dir: &Path) -> Result<()> {
             style(duplicates).magenta().bold()
         );
     }
+    if already_present > 0 {
+        println!(
+            "  {}  {} (déjà dans la sortie)",
+            style("Présentes").dim(),
+            style(already_present).blue().bold()
+        );
+    }
+
+    if out_of_range > 0 {
+        println!(
+            "  {}  {} (date hors de --from/--to)",
+            style("Hors période").dim(),
+            style(out_of_range).yellow().bold()
+        );
+    }
+    if curated > 0 {
+        println!(
+            "  {}  {} (notes et mots-clés XMP/IPTC importés)",
+            style("Curation").dim(),
+            style(curated).cyan().bold()
+        );
+    }
+    if located > 0 {
+        println!(
+            "  {}  {} (coordonnées GPS EXIF)",
+            style("Localisées").dim(),
+            style(located).cyan().bold()
+        );
+    }
+    if unverified > 0 {
+        println!(
+            "  {}  {} (copie relue différente de la source, à relancer)",
+            style("Échecs").dim(),
+            style(unverified).red().bold()
+        );
+    }
+    if unoriented > 0 {
+        println!(
+            "  {}  {} (à pivoter mais illisibles, non triées)",
+            style("Non redressées").dim(),
+            style(unoriented).red().bold()
+        );
+    }
+    if events.created > 0 {
+        println!(
+            "  {}  {} (nouveaux dossiers)",
+            style("Événements").dim(),
+            style(events.created).cyan().bold()
+        );
+    }
+    if quarantined > 0 {
+        println!(
+            "  {}  {} (illisibles, voir {QUARANTINE_DIR}/{QUARANTINE_LOG})",
+            style("Quarantaine").dim(),
+            style(quarantined).red().bold()
+        );
+    }
 
     if !by_method.is_empty() {
         let parts: Vec<String> = ["exif", "dirname", "filesystem"]
@@ -474,10 +1600,14 @@ mod tests {
 
     pub fn tmpdir() -> PathBuf {
         let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let dir = std::env::temp_dir().join(format!(
-            "photo_sort_test_{}_{id}",
-            std::process::id()
-        ));
+        // Spell the pid with letters: digits like "20241" would be read as a
+        // year by `date_from_dirname`.
+        let pid: String = std::process::id()
+            .to_string()
+            .bytes()
+            .map(|b| (b - b'0' + b'a') as char)
+            .collect();
+        let dir = std::env::temp_dir().join(format!("photo_sort_test_{pid}_{id}"));
         let _ = fs::remove_dir_all(&dir);
         fs::create_dir_all(&dir).unwrap();
         dir
@@ -487,6 +1617,19 @@ mod tests {
         NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
     }
 
+    /// Destination of content unlike any file already there.
+    fn build_dest_path(
+        output_dir: &Path,
+        dt: &NaiveDateTime,
+        ext: &str,
+        original_stem: Option<&str>,
+    ) -> PathBuf {
+        match resolve_dest_path(output_dir, None, dt, ext, original_stem, "") {
+            DestPath::Free(path) => path,
+            DestPath::Identical(path) => panic!("{} ne devrait pas correspondre", path.display()),
+        }
+    }
+
     #[test]
     fn is_photo_recognizes_supported_extensions() {
         for ext in PHOTO_EXTENSIONS {
@@ -497,6 +1640,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn photo_extensions_from_replaces_or_extends() {
+        assert_eq!(photo_extensions_from(&[], &[]), None);
+
+        let replaced =
+            photo_extensions_from(&["JPG".into(), ".webp".into(), " ".into()], &[]).unwrap();
+        assert_eq!(replaced, vec!["jpg", "webp"]);
+
+        let extended =
+            photo_extensions_from(&[], &["webp".into(), "GIF".into(), "jpg".into()]).unwrap();
+        assert_eq!(extended.len(), PHOTO_EXTENSIONS.len() + 2);
+        assert!(extended.ends_with(&["webp".to_string(), "gif".to_string()]));
+    }
+
     #[test]
     fn is_photo_case_insensitive() {
         assert!(is_photo(Path::new("photo.JPG")));
@@ -550,7 +1707,7 @@ mod tests {
     #[test]
     fn dest_path_basic_format() {
         let tmp = tmpdir();
-        let result = build_dest_path(&tmp, &parse_dt("2008-07-15 14:30:22"), "jpg");
+        let result = build_dest_path(&tmp, &parse_dt("2008-07-15 14:30:22"), "jpg", None);
         assert_eq!(result, tmp.join("2008/2008-07-15_14-30-22.jpg"));
         let _ = fs::remove_dir_all(&tmp);
     }
@@ -564,16 +1721,630 @@ mod tests {
         let date = parse_dt("2020-03-10 09:00:00");
 
         fs::write(year_dir.join("2020-03-10_09-00-00.jpg"), "a").unwrap();
-        let result = build_dest_path(&tmp, &date, "jpg");
+        let result = build_dest_path(&tmp, &date, "jpg", None);
         assert_eq!(result, tmp.join("2020/2020-03-10_09-00-00_1.jpg"));
 
         fs::write(year_dir.join("2020-03-10_09-00-00_1.jpg"), "b").unwrap();
-        let result = build_dest_path(&tmp, &date, "jpg");
+        let result = build_dest_path(&tmp, &date, "jpg", None);
         assert_eq!(result, tmp.join("2020/2020-03-10_09-00-00_2.jpg"));
 
         let _ = fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn resolve_dest_path_detects_identical_content() {
+        let tmp = tmpdir();
+        let year_dir = tmp.join("2020");
+        fs::create_dir_all(&year_dir).unwrap();
+        let date = parse_dt("2020-03-10 09:00:00");
+
+        fs::write(year_dir.join("2020-03-10_09-00-00.jpg"), "other").unwrap();
+        fs::write(year_dir.join("2020-03-10_09-00-00_1.jpg"), "same").unwrap();
+        let src = tmp.join("src.jpg");
+        fs::write(&src, "same").unwrap();
+        let hash = hash_file(&src).unwrap();
+
+        assert_eq!(
+            resolve_dest_path(&tmp, None, &date, "jpg", None, &hash),
+            DestPath::Identical(year_dir.join("2020-03-10_09-00-00_1.jpg"))
+        );
+
+        fs::write(&src, "different").unwrap();
+        let hash = hash_file(&src).unwrap();
+        assert_eq!(
+            resolve_dest_path(&tmp, None, &date, "jpg", None, &hash),
+            DestPath::Free(year_dir.join("2020-03-10_09-00-00_2.jpg"))
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_file_skips_identical_destination() {
+        let tmp = tmpdir();
+        let src_dir = tmp.join("src 2015");
+        let out = tmp.join("out");
+        fs::create_dir_all(&src_dir).unwrap();
+        let src = src_dir.join("photo.jpg");
+        fs::write(&src, "content").unwrap();
+        let hash = hash_file(&src).unwrap();
+
+        let first = place_file(&src, &out, None, &SortOptions::default(), &hash).unwrap();
+        assert!(first.copied);
+        let second = place_file(&src, &out, None, &SortOptions::default(), &hash).unwrap();
+        assert!(!second.copied);
+        assert_eq!(first.dest, second.dest);
+        assert_eq!(fs::read_dir(out.join("2015")).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn verify_copy_rejects_mismatching_copy() {
+        let tmp = tmpdir();
+        let src_dir = tmp.join("src 2015");
+        let out = tmp.join("out");
+        fs::create_dir_all(&src_dir).unwrap();
+        let src = src_dir.join("photo.jpg");
+        fs::write(&src, "content").unwrap();
+        let options = SortOptions {
+            verify_copy: true,
+            ..Default::default()
+        };
+
+        // A hash that the copy can't match stands for a corrupted write
+        let err = place_file(&src, &out, None, &options, "0000").unwrap_err();
+        let mismatch = err.downcast_ref::<CopyMismatch>().unwrap();
+        assert!(!mismatch.dest.exists());
+
+        let hash = hash_file(&src).unwrap();
+        let placed = place_file(&src, &out, None, &options, &hash).unwrap();
+        assert!(placed.copied);
+        assert_eq!(placed.dest, mismatch.dest);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn pair_photos_orders_raw_after_jpeg() {
+        let photos = vec![
+            PathBuf::from("/s/IMG_1.CR2"),
+            PathBuf::from("/s/IMG_2.jpg"),
+            PathBuf::from("/s/IMG_1.JPG"),
+            PathBuf::from("/other/IMG_1.nef"),
+        ];
+        let (ordered, pairs) = pair_photos(photos);
+        assert_eq!(
+            ordered,
+            vec![
+                PathBuf::from("/s/IMG_2.jpg"),
+                PathBuf::from("/s/IMG_1.JPG"),
+                PathBuf::from("/s/IMG_1.CR2"),
+                PathBuf::from("/other/IMG_1.nef"),
+            ]
+        );
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[&PathBuf::from("/s/IMG_1.JPG")], PathBuf::from("/s/IMG_1.CR2"));
+    }
+
+    #[test]
+    fn pair_photos_ignores_ambiguous_stems() {
+        let photos = vec![
+            PathBuf::from("/s/IMG_1.jpg"),
+            PathBuf::from("/s/IMG_1.jpeg"),
+            PathBuf::from("/s/IMG_1.cr2"),
+        ];
+        let (ordered, pairs) = pair_photos(photos.clone());
+        assert!(pairs.is_empty());
+        assert_eq!(ordered, photos);
+    }
+
+    fn setup_pair(tmp: &Path) -> (PathBuf, PathBuf) {
+        let src_dir = tmp.join("src 2015");
+        fs::create_dir_all(&src_dir).unwrap();
+        let jpeg = src_dir.join("IMG_1234.JPG");
+        let raw = src_dir.join("IMG_1234.CR2");
+        fs::write(&jpeg, "jpeg data").unwrap();
+        fs::write(&raw, "raw data").unwrap();
+        (jpeg, raw)
+    }
+
+    #[test]
+    fn place_pair_shares_base_name() {
+        let tmp = tmpdir();
+        let (jpeg, raw) = setup_pair(&tmp);
+        let out = tmp.join("out");
+        // Occupy the JPEG name only: the pair must move to _1 together
+        fs::create_dir_all(out.join("2015")).unwrap();
+        fs::write(out.join("2015/2015-01-01_00-00-00.jpg"), "other").unwrap();
+
+        let (j, r) = place_pair(
+            &jpeg,
+            &raw,
+            &out,
+            None,
+            &SortOptions::default(),
+            &hash_file(&jpeg).unwrap(),
+            &hash_file(&raw).unwrap(),
+        )
+        .unwrap();
+        assert!(j.copied && r.copied);
+        assert_eq!(j.dest, out.join("2015/2015-01-01_00-00-00_1.jpg"));
+        assert_eq!(r.dest, out.join("2015/2015-01-01_00-00-00_1.cr2"));
+        assert_eq!(fs::read(&r.dest).unwrap(), b"raw data");
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn date_range_keeps_pairs_together() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Tag, Value};
+
+        let tmp = tmpdir();
+        let (_, raw) = setup_pair(&tmp);
+        // Only the RAW has EXIF: the pair is dated by it, not by the folder
+        let mut writer = Writer::new();
+        let date = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2016:06:30 23:59:59".to_vec()]),
+        };
+        writer.push_field(&date);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        fs::write(&raw, tiff.into_inner()).unwrap();
+
+        let sort_until = |to: u32, out: &Path| {
+            let options = SortOptions {
+                from: NaiveDate::from_ymd_opt(2016, 1, 1),
+                to: NaiveDate::from_ymd_opt(2016, 6, to),
+                ..Default::default()
+            };
+            run_sort(&tmp.join("src 2015"), out, &options).unwrap();
+        };
+        let out = tmp.join("out");
+        sort_until(30, &out);
+        assert!(out.join("2016/2016-06-30_23-59-59.jpg").is_file());
+        assert!(out.join("2016/2016-06-30_23-59-59.cr2").is_file());
+        let before = tmp.join("before");
+        sort_until(29, &before);
+        assert!(!before.join("2016").exists() && !before.join("2015").exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_pair_stashes_non_preferred_file() {
+        let tmp = tmpdir();
+        let (jpeg, raw) = setup_pair(&tmp);
+        let out = tmp.join("out");
+        let options = SortOptions {
+            pair_prefer: Some(PairPreference::Jpeg),
+            ..Default::default()
+        };
+
+        let (j, r) = place_pair(
+            &jpeg,
+            &raw,
+            &out,
+            None,
+            &options,
+            &hash_file(&jpeg).unwrap(),
+            &hash_file(&raw).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(j.dest, out.join("2015/2015-01-01_00-00-00.jpg"));
+        assert_eq!(r.dest, out.join("2015/raw/2015-01-01_00-00-00.cr2"));
+        assert!(r.dest.exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn subsec_nanos_parses_fraction_digits() {
+        assert_eq!(subsec_nanos("123"), Some(123_000_000));
+        assert_eq!(subsec_nanos("12"), Some(120_000_000));
+        assert_eq!(subsec_nanos(" 5 "), Some(500_000_000));
+        assert_eq!(subsec_nanos("1234567891"), Some(123_456_789));
+        assert_eq!(subsec_nanos(""), None);
+        assert_eq!(subsec_nanos("ab"), None);
+    }
+
+    #[test]
+    fn dest_path_includes_subseconds() {
+        let tmp = tmpdir();
+        let date = parse_dt("2020-07-15 14:30:22")
+            .with_nanosecond(123_000_000)
+            .unwrap();
+        let result = build_dest_path(&tmp, &date, "jpg", None);
+        assert_eq!(result, tmp.join("2020/2020-07-15_14-30-22.123.jpg"));
+
+        let later = date.with_nanosecond(124_000_000).unwrap();
+        assert!(
+            build_dest_path(&tmp, &date, "jpg", None) < build_dest_path(&tmp, &later, "jpg", None)
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    /// Write a 100×80 JPEG whose EXIF block only holds the given Orientation.
+    fn write_oriented_jpeg(path: &Path, orientation: u16) {
+        use image::ImageEncoder;
+        let mut exif = vec![0x49, 0x49, 42, 0, 8, 0, 0, 0, 1, 0];
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&orientation.to_le_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        let img = image::RgbImage::from_fn(100, 80, |x, y| image::Rgb([x as u8, y as u8, 0]));
+        let mut out = fs::File::create(path).unwrap();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(exif).unwrap();
+        image::DynamicImage::ImageRgb8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+    }
+
+    fn exif_orientation(path: &Path) -> Option<u32> {
+        let mut reader = BufReader::new(fs::File::open(path).ok()?);
+        let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+        exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+            .value
+            .get_uint(0)
+    }
+
+    #[test]
+    fn exif_gps_reads_signed_decimal_degrees() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Rational, Tag, Value};
+        use image::ImageEncoder;
+
+        let ascii = |tag, v: &[u8]| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![v.to_vec()]),
+        };
+        let dms = |tag, d: u32, m: u32, s: (u32, u32)| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![
+                Rational::from((d, 1)),
+                Rational::from((m, 1)),
+                Rational::from(s),
+            ]),
+        };
+        let fields = [
+            ascii(Tag::GPSLatitudeRef, b"S"),
+            dms(Tag::GPSLatitude, 33, 51, (5400, 100)),
+            ascii(Tag::GPSLongitudeRef, b"E"),
+            dms(Tag::GPSLongitude, 151, 12, (3600, 100)),
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+
+        let tmp = tmpdir();
+        let path = tmp.join("sydney.jpg");
+        let img = image::RgbImage::new(8, 8);
+        let mut out = fs::File::create(&path).unwrap();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(tiff.into_inner()).unwrap();
+        image::DynamicImage::ImageRgb8(img)
+            .write_with_encoder(encoder)
+            .unwrap();
+
+        let (lat, lon) = exif_gps(&path).unwrap();
+        assert!((lat + 33.865).abs() < 1e-9, "{lat}");
+        assert!((lon - 151.21).abs() < 1e-9, "{lon}");
+        assert_eq!(exif_gps(&tmp.join("missing.jpg")), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn exif_summary_reads_camera_and_settings() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Rational, Tag, Value};
+        use image::ImageEncoder;
+
+        let field = |tag, value| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        };
+        let ascii = |tag, v: &[u8]| field(tag, Value::Ascii(vec![v.to_vec()]));
+        let rational = |tag, r: (u32, u32)| field(tag, Value::Rational(vec![Rational::from(r)]));
+        let fields = [
+            ascii(Tag::Make, b"Canon"),
+            ascii(Tag::Model, b"Canon EOS 5D"),
+            ascii(Tag::LensModel, b"EF 50mm f/1.8"),
+            field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+            rational(Tag::FNumber, (28, 10)),
+            rational(Tag::ExposureTime, (1, 250)),
+            rational(Tag::FocalLength, (50, 1)),
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+
+        let tmp = tmpdir();
+        let path = tmp.join("canon.jpg");
+        let mut out = fs::File::create(&path).unwrap();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut out);
+        encoder.set_exif_metadata(tiff.into_inner()).unwrap();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(12, 8))
+            .write_with_encoder(encoder)
+            .unwrap();
+
+        let summary = exif_summary(&path).unwrap();
+        assert_eq!(summary.make.as_deref(), Some("Canon"));
+        assert_eq!(summary.model.as_deref(), Some("Canon EOS 5D"));
+        assert_eq!(summary.lens.as_deref(), Some("EF 50mm f/1.8"));
+        // No PixelXDimension: read from the image header
+        assert_eq!((summary.width, summary.height), (Some(12), Some(8)));
+        assert_eq!(summary.iso, Some(400));
+        assert_eq!(summary.aperture, Some(2.8));
+        assert_eq!(summary.exposure.as_deref(), Some("1/250"));
+        assert_eq!(summary.focal_length, Some(50.0));
+
+        fs::write(tmp.join("text.jpg"), "not an image").unwrap();
+        assert_eq!(exif_summary(&tmp.join("text.jpg")), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn auto_orient_rotates_pixels_and_resets_tag() {
+        let tmp = tmpdir();
+        let src = tmp.join("src.jpg");
+        let dest = tmp.join("dest.jpg");
+        write_oriented_jpeg(&src, 6);
+
+        fs::write(&dest, auto_oriented(&src).unwrap().unwrap()).unwrap();
+        assert_eq!(image::image_dimensions(&dest).unwrap(), (80, 100));
+        assert_eq!(exif_orientation(&dest), Some(1));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn auto_orient_noop_when_upright() {
+        let tmp = tmpdir();
+        let src = tmp.join("src.jpg");
+        write_oriented_jpeg(&src, 1);
+        assert_eq!(auto_oriented(&src).unwrap(), None);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_file_auto_orient_recognises_its_own_copies() {
+        let tmp = tmpdir();
+        let src = tmp.join("src.jpg");
+        write_oriented_jpeg(&src, 6);
+        let source_hash = hash_file(&src).unwrap();
+        let options = SortOptions {
+            auto_orient: true,
+            verify_copy: true,
+            ..Default::default()
+        };
+
+        let out = tmp.join("out");
+        let first = place_file(&src, &out, None, &options, &source_hash).unwrap();
+        assert!(first.copied);
+        assert_ne!(first.hash, source_hash);
+        assert_eq!(hash_file(&first.dest).unwrap(), first.hash);
+        // Sorting again (without the progress file) finds the oriented copy
+        let second = place_file(&src, &out, None, &options, &source_hash).unwrap();
+        assert!(!second.copied);
+        assert_eq!(second.dest, first.dest);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_file_auto_orient_reports_undecodable_images() {
+        let tmp = tmpdir();
+        let valid = tmp.join("valid.jpg");
+        write_oriented_jpeg(&valid, 6);
+        // Keep the EXIF block, give the frame a zero width
+        let mut bytes = fs::read(&valid).unwrap();
+        let sof = bytes.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        bytes[sof + 7..sof + 9].fill(0);
+        let src = tmp.join("broken.jpg");
+        fs::write(&src, bytes).unwrap();
+        let options = SortOptions {
+            auto_orient: true,
+            ..Default::default()
+        };
+
+        let err = place_file(&src, &tmp.join("out"), None, &options, "h").unwrap_err();
+        assert!(err.is::<Unorientable>(), "{err}");
+        assert!(!tmp.join("out").exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn sort_skips_photos_that_cannot_be_rotated() {
+        let tmp = tmpdir();
+        let src = tmp.join("src");
+        fs::create_dir_all(&src).unwrap();
+        write_oriented_jpeg(&src.join("a.jpg"), 6);
+        write_oriented_jpeg(&src.join("c.jpg"), 1);
+        // Rotation asked, but the frame has a zero width
+        let mut bytes = fs::read(src.join("a.jpg")).unwrap();
+        let sof = bytes.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        bytes[sof + 7..sof + 9].fill(0);
+        fs::write(src.join("b.jpg"), bytes).unwrap();
+        let options = SortOptions {
+            auto_orient: true,
+            ..Default::default()
+        };
+
+        let out = tmp.join("out");
+        run_sort(&src, &out, &options).unwrap();
+        let progress = load_progress(&out.join(PROGRESS_FILE)).unwrap();
+        let mut sorted: Vec<&str> = (progress.processed.iter())
+            .map(|e| e.source.rsplit('/').next().unwrap())
+            .collect();
+        sorted.sort();
+        assert_eq!(sorted, ["a.jpg", "c.jpg"]);
+        assert!(
+            progress
+                .processed
+                .iter()
+                .all(|e| out.join(&e.dest).is_file())
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_file_auto_orient_copies_files_without_orientation() {
+        let tmp = tmpdir();
+        let src_dir = tmp.join("src 2015");
+        fs::create_dir_all(&src_dir).unwrap();
+        let src = src_dir.join("photo.jpg");
+        fs::write(&src, "not a real jpeg").unwrap();
+        let options = SortOptions {
+            auto_orient: true,
+            ..Default::default()
+        };
+
+        let placed = place_file(&src, &tmp.join("out"), None, &options, "h").unwrap();
+        assert_eq!(fs::read(&placed.dest).unwrap(), b"not a real jpeg");
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn corruption_reason_needs_undecodable_pixels() {
+        let tmp = tmpdir();
+        let broken = tmp.join("broken.jpg");
+        fs::write(&broken, "not a real jpeg").unwrap();
+        assert!(corruption_reason(&broken).is_some());
+
+        let valid = tmp.join("valid.png");
+        image::RgbImage::new(4, 4).save(&valid).unwrap();
+        assert!(corruption_reason(&valid).is_none());
+
+        // RAW files can't be decoded here: never judged corrupt
+        let raw = tmp.join("raw.cr2");
+        fs::write(&raw, "raw data").unwrap();
+        assert!(corruption_reason(&raw).is_none());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn quarantine_file_copies_and_logs_reason() {
+        let tmp = tmpdir();
+        let src = tmp.join("IMG_1.JPG");
+        fs::write(&src, "garbage").unwrap();
+        let out = tmp.join("out");
+
+        let first = quarantine_file(&src, &out, "test").unwrap();
+        let second = quarantine_file(&src, &out, "encore").unwrap();
+        assert_eq!(first, out.join("_quarantine/IMG_1.jpg"));
+        assert_eq!(second, out.join("_quarantine/IMG_1_1.jpg"));
+        assert_eq!(fs::read(&first).unwrap(), b"garbage");
+
+        let log = fs::read_to_string(out.join("_quarantine/quarantine.log")).unwrap();
+        assert!(log.contains(&format!("IMG_1.jpg <- {} : test", src.display())));
+        assert!(log.contains("IMG_1_1.jpg <- "));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn place_file_touch_sets_mtime_to_date() {
+        let tmp = tmpdir();
+        let src_dir = tmp.join("src 2015");
+        fs::create_dir_all(&src_dir).unwrap();
+        let src = src_dir.join("photo.jpg");
+        fs::write(&src, "data").unwrap();
+        let options = SortOptions {
+            touch: true,
+            ..Default::default()
+        };
+
+        let placed = place_file(&src, &tmp.join("out"), None, &options, "h").unwrap();
+        let mtime: chrono::DateTime<chrono::Local> =
+            fs::metadata(&placed.dest).unwrap().modified().unwrap().into();
+        assert_eq!(mtime.naive_local(), parse_dt("2015-01-01 00:00:00"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn parse_utc_offset_formats() {
+        assert_eq!(parse_utc_offset("+09:00"), FixedOffset::east_opt(9 * 3600));
+        assert_eq!(parse_utc_offset("-05:30\0"), FixedOffset::west_opt(5 * 3600 + 1800));
+        assert_eq!(parse_utc_offset("+0200"), FixedOffset::east_opt(2 * 3600));
+        assert_eq!(parse_utc_offset("09:00"), None);
+        assert_eq!(parse_utc_offset("+25:00"), None);
+        assert_eq!(parse_utc_offset(""), None);
+    }
+
+    #[test]
+    fn zone_parses_names_and_offsets() {
+        assert_eq!("local".parse::<Zone>(), Ok(Zone::Local));
+        assert_eq!(
+            "UTC".parse::<Zone>(),
+            Ok(Zone::Fixed(FixedOffset::east_opt(0).unwrap()))
+        );
+        assert_eq!(
+            "+02:00".parse::<Zone>(),
+            Ok(Zone::Fixed(FixedOffset::east_opt(7200).unwrap()))
+        );
+        assert_eq!(
+            "Europe/Paris".parse::<Zone>(),
+            Ok(Zone::Named(chrono_tz::Europe::Paris))
+        );
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+    }
+
+    #[test]
+    fn zone_convert_can_change_year() {
+        // New Year's morning in Tokyo is still New Year's Eve in Paris
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let paris: Zone = "Europe/Paris".parse().unwrap();
+        assert_eq!(
+            paris.convert(parse_dt("2020-01-01 05:00:00"), tokyo),
+            parse_dt("2019-12-31 21:00:00")
+        );
+        let same: Zone = "+09:00".parse().unwrap();
+        assert_eq!(
+            same.convert(parse_dt("2020-01-01 05:00:00"), tokyo),
+            parse_dt("2020-01-01 05:00:00")
+        );
+    }
+
+    #[test]
+    fn accepts_date_is_inclusive() {
+        let options = SortOptions {
+            from: NaiveDate::from_ymd_opt(2015, 1, 1),
+            to: NaiveDate::from_ymd_opt(2018, 12, 31),
+            ..Default::default()
+        };
+        assert!(options.accepts_date(&parse_dt("2015-01-01 00:00:00")));
+        assert!(options.accepts_date(&parse_dt("2018-12-31 23:59:59")));
+        assert!(!options.accepts_date(&parse_dt("2014-12-31 23:59:59")));
+        assert!(!options.accepts_date(&parse_dt("2019-01-01 00:00:00")));
+
+        let open_ended = SortOptions {
+            from: NaiveDate::from_ymd_opt(2015, 1, 1),
+            ..Default::default()
+        };
+        assert!(open_ended.accepts_date(&parse_dt("2030-06-01 12:00:00")));
+        assert!(SortOptions::default().accepts_date(&parse_dt("1990-01-01 00:00:00")));
+    }
+
+    #[test]
+    fn dest_path_keeps_original_name() {
+        let tmp = tmpdir();
+        let date = parse_dt("2020-07-15 14:30:22");
+        let result = build_dest_path(&tmp, &date, "jpg", Some("IMG_1234"));
+        assert_eq!(result, tmp.join("2020/2020-07-15_14-30-22__IMG_1234.jpg"));
+
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        fs::write(&result, "a").unwrap();
+        let result = build_dest_path(&tmp, &date, "jpg", Some("IMG_1234"));
+        assert_eq!(result, tmp.join("2020/2020-07-15_14-30-22__IMG_1234_1.jpg"));
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn progress_load_missing_file_returns_empty() {
         let progress = load_progress(Path::new("/nonexistent/path.json")).unwrap();
@@ -624,20 +2395,6 @@ mod tests {
         let _ = fs::remove_dir_all(&tmp);
     }
 
-    #[test]
-    fn append_origin_creates_and_appends() {
-        let tmp = tmpdir();
-        append_origin(&tmp, "2020-01-01_00-00-00.jpg", "/photos/a.jpg").unwrap();
-        append_origin(&tmp, "2020-01-01_00-00-00_1.jpg", "/photos/b.jpg").unwrap();
-
-        let content = fs::read_to_string(tmp.join(".photo_sort_origins")).unwrap();
-        let lines: Vec<&str> = content.lines().collect();
-        assert_eq!(lines.len(), 2);
-        assert_eq!(lines[0], "2020-01-01_00-00-00.jpg <- /photos/a.jpg");
-        assert_eq!(lines[1], "2020-01-01_00-00-00_1.jpg <- /photos/b.jpg");
-        let _ = fs::remove_dir_all(&tmp);
-    }
-
     #[test]
     fn filesystem_date_from_real_file() {
         let tmp = tmpdir();
@@ -665,7 +2422,7 @@ mod tests {
         let file = subdir.join("photo.jpg");
         fs::write(&file, "not a real jpeg").unwrap();
 
-        let (date, source) = detect_date(&file);
+        let (date, source) = detect_date(&file, None);
         assert_eq!(date, parse_dt("2015-01-01 00:00:00"));
         assert_eq!(source.as_str(), "dirname");
         let _ = fs::remove_dir_all(&tmp);
@@ -677,7 +2434,7 @@ mod tests {
         let file = tmp.join("photo.jpg");
         fs::write(&file, "not a real jpeg").unwrap();
 
-        let (_, source) = detect_date(&file);
+        let (_, source) = detect_date(&file, None);
         assert_eq!(source.as_str(), "filesystem");
         let _ = fs::remove_dir_all(&tmp);
     }
@@ -738,6 +2495,21 @@ mod tests {
         let _ = fs::remove_dir_all(&tmp);
     }
 
+    #[test]
+    fn hash_file_large_files_match_sequential_hash() {
+        let tmp = tmpdir();
+        let file = tmp.join("video.mov");
+        let data: Vec<u8> = (0..PARALLEL_HASH_THRESHOLD + 12345)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        fs::write(&file, &data).unwrap();
+        assert_eq!(
+            hash_file(&file).unwrap(),
+            blake3::hash(&data).to_hex().to_string()
+        );
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
     #[test]
     fn hash_file_missing_returns_error() {
         assert!(hash_file(Path::new("/nonexistent/file.bin")).is_err());
@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+use console::style;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::metadata::{Metadata, is_valid_position};
+use crate::origins;
+use crate::sort::{hash_file, is_photo};
+
+/// Longest sidecar name (without `.json`) written by Takeout: longer photo
+/// names are cut.
+const MAX_SIDECAR_STEM: usize = 46;
+
+/// Suffixes of the edited copies Google Photos exports next to the original
+/// (`IMG_1234-edited.jpg`, or localized); they share its sidecar.
+const EDITED_SUFFIXES: &[&str] = &["-edited", "-modifié", "-bearbeitet", "-editado"];
+
+/// The fields used from a Google Photos sidecar (`IMG_1234.jpg.json`).
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+struct Sidecar {
+    description: String,
+    photo_taken_time: Option<Timestamp>,
+    geo_data: Option<Geo>,
+    geo_data_exif: Option<Geo>,
+    people: Vec<Person>,
+    favorited: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct Timestamp {
+    /// Seconds since the epoch, as a string.
+    timestamp: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Geo {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Person {
+    name: String,
+}
+
+impl Sidecar {
+    fn taken(&self) -> Option<NaiveDateTime> {
+        let seconds = self.photo_taken_time.as_ref()?.timestamp.parse().ok()?;
+        Some(
+            DateTime::from_timestamp(seconds, 0)?
+                .with_timezone(&Local)
+                .naive_local(),
+        )
+    }
+
+    /// The position set in Google Photos, else the one from the EXIF; both
+    /// are (0, 0) when unknown.
+    fn position(&self) -> Option<(f64, f64)> {
+        [&self.geo_data, &self.geo_data_exif]
+            .into_iter()
+            .flatten()
+            .map(|g| (g.latitude, g.longitude))
+            .find(|&(lat, lon)| is_valid_position(lat, lon))
+    }
+}
+
+/// Candidate sidecar names of a Takeout photo, most likely first:
+/// `a.jpg.json`, `a.jpg.supplemental-metadata.json`, `a.json`, the
+/// `a.jpg(1).json` of a duplicate `a(1).jpg`, a truncated name, and the
+/// sidecar of the original of an edited copy.
+fn sidecar_names(name: &str) -> Vec<String> {
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) => (stem, format!(".{ext}")),
+        None => (name, String::new()),
+    };
+    let mut names = vec![
+        format!("{name}.json"),
+        format!("{name}.supplemental-metadata.json"),
+        format!("{stem}.json"),
+    ];
+    if let Some((base, counter)) = stem.rsplit_once('(')
+        && counter.ends_with(')')
+    {
+        names.push(format!("{base}{ext}({counter}.json"));
+    }
+    if name.chars().count() > MAX_SIDECAR_STEM {
+        let cut: String = name.chars().take(MAX_SIDECAR_STEM).collect();
+        names.push(format!("{cut}.json"));
+    }
+    for suffix in EDITED_SUFFIXES {
+        if let Some(original) = stem.strip_suffix(suffix) {
+            names.extend(sidecar_names(&format!("{original}{ext}")));
+        }
+    }
+    names
+}
+
+fn sidecar_of(photo: &Path) -> Option<PathBuf> {
+    let name = photo.file_name()?.to_string_lossy();
+    sidecar_names(&name)
+        .into_iter()
+        .map(|sidecar| photo.with_file_name(sidecar))
+        .find(|sidecar| sidecar.is_file())
+}
+
+/// Merge the capture time, description, people, position and favorite flag
+/// of the Google Photos sidecars of a Takeout export into the metadata of the
+/// sorted library. Photos are found by content, whatever their new name.
+pub fn run_import_takeout(takeout: &Path, dir: &Path) -> Result<()> {
+    if !takeout.is_dir() {
+        anyhow::bail!("Dossier Takeout introuvable : {}", takeout.display());
+    }
+    let mut metadata = Metadata::load(dir)?;
+
+    let mut exported: Vec<(String, Sidecar)> = Vec::new();
+    for entry in WalkDir::new(takeout)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_photo(e.path()))
+    {
+        let Some(path) = sidecar_of(entry.path()) else {
+            continue;
+        };
+        let sidecar = fs::read_to_string(&path)
+            .context("Sidecar illisible")
+            .and_then(|json| Ok(serde_json::from_str::<Sidecar>(&json)?));
+        match (sidecar, hash_file(entry.path())) {
+            (Ok(sidecar), Ok(hash)) => exported.push((hash, sidecar)),
+            (Err(e), _) | (_, Err(e)) => eprintln!(
+                "  {} {} : {e:#}",
+                style("!").yellow().bold(),
+                entry.path().display()
+            ),
+        }
+    }
+
+    let wanted: HashSet<String> = exported.iter().map(|(hash, _)| hash.clone()).collect();
+    let by_hash = origins::sorted_by_hash(dir, &metadata, &wanted)?;
+
+    let mut incoming = Metadata::default();
+    let mut missing = 0usize;
+    for (hash, sidecar) in &exported {
+        let Some(file) = by_hash.get(hash) else {
+            missing += 1;
+            continue;
+        };
+        incoming.set_hash(file, hash);
+        if let Some(taken) = sidecar.taken() {
+            incoming.set_taken(file, Some(taken));
+        }
+        // A caption written in the library is kept
+        if metadata.get_caption(file).is_none() {
+            incoming.set_caption(file, &sidecar.description);
+        }
+        for person in &sidecar.people {
+            if !person.name.trim().is_empty() {
+                incoming.add_person(file, person.name.trim());
+            }
+        }
+        if let Some(position) = sidecar.position() {
+            incoming.set_position(file, Some(position));
+        }
+        if sidecar.favorited {
+            incoming.set_favorite(file, true);
+        }
+    }
+
+    let before = metadata.revision();
+    metadata.merge(&incoming, &HashMap::new());
+    if metadata.revision() != before {
+        metadata.save(dir)?;
+    }
+
+    println!(
+        "  {} {} photo(s) Takeout avec métadonnées, {} retrouvée(s) dans la photothèque",
+        style("✔").green().bold(),
+        style(exported.len()).green().bold(),
+        style(incoming.files.len()).green().bold()
+    );
+    if missing > 0 {
+        println!(
+            "  {} {} photo(s) absente(s) de la photothèque (à trier d'abord)",
+            style("!").yellow().bold(),
+            missing
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "photo_sort_takeout_test_{}_{id}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sidecar_names_cover_takeout_variants() {
+        let names = sidecar_names("IMG_1234(1).jpg");
+        assert!(names.contains(&"IMG_1234(1).jpg.json".to_string()));
+        assert!(names.contains(&"IMG_1234.jpg(1).json".to_string()));
+
+        let names = sidecar_names("IMG_1234-modifié.jpg");
+        assert!(names.contains(&"IMG_1234.jpg.json".to_string()));
+
+        let long = format!("{}.jpg", "a".repeat(50));
+        assert!(sidecar_names(&long).contains(&format!("{}.json", "a".repeat(46))));
+    }
+
+    #[test]
+    fn imports_sidecars_by_content() {
+        let tmp = tmpdir();
+        let takeout = tmp.join("Takeout/Google Photos/Vacances");
+        let library = tmp.join("library");
+        fs::create_dir_all(&takeout).unwrap();
+        fs::create_dir_all(library.join("2020")).unwrap();
+
+        fs::write(takeout.join("IMG_1.jpg"), "photo 1").unwrap();
+        fs::write(
+            takeout.join("IMG_1.jpg.json"),
+            r#"{"title":"IMG_1.jpg","description":"Plage de Carnac",
+                "photoTakenTime":{"timestamp":"1594823422"},
+                "geoData":{"latitude":0.0,"longitude":0.0},
+                "geoDataExif":{"latitude":47.58,"longitude":-3.07},
+                "people":[{"name":"Léa"}],"favorited":true}"#,
+        )
+        .unwrap();
+        fs::write(takeout.join("IMG_2.jpg"), "photo 2").unwrap();
+        fs::write(takeout.join("IMG_2.jpg.json"), r#"{"description":"Texte"}"#).unwrap();
+        fs::write(takeout.join("IMG_3.jpg"), "not sorted").unwrap();
+        fs::write(takeout.join("IMG_3.jpg.json"), r#"{"description":"x"}"#).unwrap();
+
+        // Sorted under new names; only one has its hash recorded
+        fs::write(library.join("2020/2020-07-15_14-30-22.jpg"), "photo 1").unwrap();
+        fs::write(library.join("2020/2020-07-16_10-00-00.jpg"), "photo 2").unwrap();
+        let mut meta = Metadata::default();
+        meta.set_hash(
+            "2020/2020-07-15_14-30-22.jpg",
+            &hash_file(&takeout.join("IMG_1.jpg")).unwrap(),
+        );
+        meta.set_caption("2020/2020-07-16_10-00-00.jpg", "Déjà légendée");
+        meta.save(&library).unwrap();
+
+        run_import_takeout(&tmp.join("Takeout"), &library).unwrap();
+
+        let meta = Metadata::load(&library).unwrap();
+        let first = "2020/2020-07-15_14-30-22.jpg";
+        assert_eq!(meta.get_caption(first), Some("Plage de Carnac"));
+        assert_eq!(meta.get_people(first), ["Léa"]);
+        assert_eq!(meta.get_position(first), Some((47.58, -3.07)));
+        assert!(meta.is_favorite(first));
+        assert!(meta.get_taken(first).is_some());
+        let second = "2020/2020-07-16_10-00-00.jpg";
+        assert_eq!(meta.get_caption(second), Some("Déjà légendée"));
+        assert!(meta.files[second].hash.is_some());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
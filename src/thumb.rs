@@ -1,5 +1,7 @@
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Name of the thumbnail cache directory (created inside the photo base dir).
 pub const THUMB_DIR: &str = ".photo_sort_thumbs";
@@ -10,8 +12,46 @@ const THUMB_MAX_SIZE: u32 = 300;
 /// JPEG quality for thumbnails (0–100).
 const THUMB_QUALITY: u8 = 80;
 
-/// Extensions that the `image` crate can decode (subset of PHOTO_EXTENSIONS).
-const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tiff", "tif"];
+/// Extensions that the `image` crate can decode.
+#[cfg(not(feature = "heif"))]
+const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tiff", "tif", "webp", "gif"];
+
+/// Extensions we can decode, HEIC/HEIF included through libheif.
+#[cfg(feature = "heif")]
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "tiff", "tif", "webp", "gif", "heic", "heif",
+];
+
+/// Generations in progress, keyed by cache path. Each cell is initialised by
+/// exactly one thread; concurrent callers for the same key block on it.
+type InFlight = Mutex<HashMap<PathBuf, Arc<OnceLock<Result<(), String>>>>>;
+
+fn in_flight() -> &'static InFlight {
+    static IN_FLIGHT: OnceLock<InFlight> = OnceLock::new();
+    IN_FLIGHT.get_or_init(Default::default)
+}
+
+/// Run `generate` for `key` unless another thread is already doing it, in
+/// which case wait for that run and share its result.
+pub fn generate_once(key: &Path, generate: impl FnOnce() -> Result<()>) -> Result<()> {
+    let cell = {
+        let mut map = in_flight().lock().unwrap();
+        Arc::clone(map.entry(key.to_path_buf()).or_default())
+    };
+    let result = cell
+        .get_or_init(|| generate().map_err(|e| format!("{e:#}")))
+        .clone();
+    {
+        let mut map = in_flight().lock().unwrap();
+        if map.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+            map.remove(key);
+        }
+    }
+    result.map_err(anyhow::Error::msg)
+}
+
+/// Largest side of the placeholders shown blurred while thumbnails load.
+const PLACEHOLDER_SIZE: u32 = 16;
 
 /// Return the cache path for a given relative photo path.
 /// E.g. `thumb_cache_path("/photos", "2020/a.jpg")` → `/photos/.photo_sort_thumbs/2020/a.jpg`
@@ -22,6 +62,19 @@ pub fn thumb_cache_path(base: &Path, rel: &str) -> PathBuf {
     p
 }
 
+/// Path of the cached thumbnail relative to the library, as a URL for the
+/// static gallery. `None` if the format is not supported.
+pub fn thumb_url(rel: &str) -> Option<String> {
+    if !can_generate_thumb(Path::new(rel)) {
+        return None;
+    }
+    Some(
+        thumb_cache_path(Path::new(""), rel)
+            .to_string_lossy()
+            .replace('\\', "/"),
+    )
+}
+
 /// Return `true` if the cached thumbnail is still fresh (newer than the source).
 pub fn thumb_is_fresh(source: &Path, cached: &Path) -> bool {
     let Ok(src_meta) = source.metadata() else {
@@ -46,10 +99,19 @@ pub fn can_generate_thumb(path: &Path) -> bool {
         .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
 }
 
+/// Decode an image, going through libheif for HEIC/HEIF files when the
+/// `heif` feature is enabled.
+pub fn open_image(path: &Path) -> Result<image::DynamicImage> {
+    #[cfg(feature = "heif")]
+    if crate::heif::is_heif(path) {
+        return crate::heif::open(path);
+    }
+    image::open(path).with_context(|| format!("Cannot open image: {}", path.display()))
+}
+
 /// Generate a JPEG thumbnail from `source` and write it to `dest`.
 pub fn generate_thumb(source: &Path, dest: &Path) -> Result<()> {
-    let img = image::open(source)
-        .with_context(|| format!("Cannot open image: {}", source.display()))?;
+    let img = open_image(source)?;
 
     let thumb = img.thumbnail(THUMB_MAX_SIZE, THUMB_MAX_SIZE);
 
@@ -58,17 +120,37 @@ pub fn generate_thumb(source: &Path, dest: &Path) -> Result<()> {
             .with_context(|| format!("Cannot create thumb dir: {}", parent.display()))?;
     }
 
-    let mut out = std::fs::File::create(dest)
-        .with_context(|| format!("Cannot create thumb file: {}", dest.display()))?;
+    // Write next to the destination then rename, so readers never see a
+    // partially written thumbnail.
+    let partial = dest.with_extension("partial");
+    let mut out = std::fs::File::create(&partial)
+        .with_context(|| format!("Cannot create thumb file: {}", partial.display()))?;
 
     let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, THUMB_QUALITY);
-    thumb
-        .write_with_encoder(encoder)
-        .context("Failed to encode thumbnail")?;
+    if let Err(e) = thumb.write_with_encoder(encoder) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e).context("Failed to encode thumbnail");
+    }
+    std::fs::rename(&partial, dest)
+        .with_context(|| format!("Cannot write thumb file: {}", dest.display()))?;
 
     Ok(())
 }
 
+/// Encode `source` as a JPEG fitting in `max_size` (never enlarged), in
+/// memory.
+pub fn encode_jpeg(source: &Path, max_size: u32, quality: u8) -> Result<Vec<u8>> {
+    let mut img = open_image(source)?;
+    if img.width() > max_size || img.height() > max_size {
+        img = img.thumbnail(max_size, max_size);
+    }
+    let mut out = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality);
+    img.write_with_encoder(encoder)
+        .context("Failed to encode image")?;
+    Ok(out)
+}
+
 /// Return the path to a cached (or freshly generated) thumbnail.
 /// Returns `None` if the format is not supported for thumbnail generation.
 pub fn get_or_create_thumb(base: &Path, rel: &str) -> Result<Option<PathBuf>> {
@@ -82,13 +164,40 @@ pub fn get_or_create_thumb(base: &Path, rel: &str) -> Result<Option<PathBuf>> {
         return Ok(Some(cached));
     }
 
-    generate_thumb(&source, &cached)?;
+    generate_once(&cached, || {
+        // Another thread may have finished it while we were waiting
+        if cached.exists() && thumb_is_fresh(&source, &cached) {
+            return Ok(());
+        }
+        generate_thumb(&source, &cached)
+    })?;
     Ok(Some(cached))
 }
 
+/// A tiny PNG of the photo, made from its thumbnail and cached next to it.
+/// Returns `None` if the format is not supported.
+pub fn placeholder(base: &Path, rel: &str) -> Result<Option<Vec<u8>>> {
+    let Some(thumb) = get_or_create_thumb(base, rel)? else {
+        return Ok(None);
+    };
+    let cached = thumb.with_extension("placeholder.png");
+    if thumb_is_fresh(&thumb, &cached) {
+        return Ok(Some(std::fs::read(&cached)?));
+    }
+    let img = open_image(&thumb)?.thumbnail(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE);
+    let mut png = Vec::new();
+    img.to_rgb8()
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("Failed to encode placeholder")?;
+    std::fs::write(&cached, &png)
+        .with_context(|| format!("Cannot write placeholder: {}", cached.display()))?;
+    Ok(Some(png))
+}
+
 /// Delete the cached thumbnail for a given relative path (if it exists).
 pub fn invalidate_thumb(base: &Path, rel: &str) {
     let cached = thumb_cache_path(base, rel);
+    let _ = std::fs::remove_file(cached.with_extension("placeholder.png"));
     let _ = std::fs::remove_file(cached);
 }
 
@@ -101,7 +210,7 @@ pub fn spawn_prewarm(base: PathBuf, rels: Vec<String>) {
 }
 
 /// Pre-generate thumbnails in parallel using a scoped thread pool.
-fn prewarm_thumbnails(base: &Path, rels: &[String]) {
+pub fn prewarm_thumbnails(base: &Path, rels: &[String]) {
     // Filter to only photos that need a thumbnail generated
     let to_generate: Vec<&String> = rels
         .iter()
@@ -124,7 +233,7 @@ fn prewarm_thumbnails(base: &Path, rels: &[String]) {
         .unwrap_or(4);
 
     let chunks: Vec<&[&String]> = to_generate.chunks(
-        (to_generate.len() + n_workers - 1) / n_workers
+        to_generate.len().div_ceil(n_workers)
     ).collect();
 
     std::thread::scope(|s| {
@@ -201,6 +310,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn encode_jpeg_shrinks_but_never_enlarges() {
+        let tmp = tmpdir();
+        let src = tmp.join("photo.jpg");
+        create_test_jpeg(&src);
+
+        let small = image::load_from_memory(&encode_jpeg(&src, 50, 80).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (50, 40));
+        let same = image::load_from_memory(&encode_jpeg(&src, 1600, 80).unwrap()).unwrap();
+        assert_eq!((same.width(), same.height()), (100, 80));
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn thumb_url_is_relative_to_the_library() {
+        assert_eq!(
+            thumb_url("2020/img.png").as_deref(),
+            Some(".photo_sort_thumbs/2020/img.jpg")
+        );
+        assert_eq!(thumb_url("2020/img.cr2"), None);
+    }
+
     // --- can_generate_thumb ---
 
     #[test]
@@ -210,10 +341,13 @@ mod tests {
         assert!(can_generate_thumb(Path::new("photo.png")));
         assert!(can_generate_thumb(Path::new("photo.tiff")));
         assert!(can_generate_thumb(Path::new("photo.tif")));
+        #[cfg(feature = "heif")]
+        assert!(can_generate_thumb(Path::new("photo.HEIC")));
     }
 
     #[test]
     fn cannot_generate_for_unsupported_formats() {
+        #[cfg(not(feature = "heif"))]
         assert!(!can_generate_thumb(Path::new("photo.heic")));
         assert!(!can_generate_thumb(Path::new("photo.cr2")));
         assert!(!can_generate_thumb(Path::new("photo.nef")));
@@ -347,6 +481,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "heif"))]
     fn get_or_create_returns_none_for_heic() {
         let tmp = tmpdir();
         let src = tmp.join("2020/photo.heic");
@@ -358,6 +493,58 @@ mod tests {
         let _ = std::fs::remove_dir_all(&tmp);
     }
 
+    // --- generate_once ---
+
+    #[test]
+    fn generate_once_runs_single_generation_for_concurrent_callers() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Barrier;
+
+        let key = tmpdir().join("key.jpg");
+        let runs = AtomicUsize::new(0);
+        let barrier = Barrier::new(8);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    barrier.wait();
+                    generate_once(&key, || {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_millis(200));
+                        Ok(())
+                    })
+                    .unwrap();
+                });
+            }
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(!in_flight().lock().unwrap().contains_key(&key));
+    }
+
+    #[test]
+    fn generate_once_shares_errors_and_allows_retry() {
+        let key = tmpdir().join("failing.jpg");
+        let err = generate_once(&key, || anyhow::bail!("boom")).unwrap_err();
+        assert!(err.to_string().contains("boom"));
+        // The failed attempt is not cached: the next call runs again
+        assert!(generate_once(&key, || Ok(())).is_ok());
+    }
+
+    #[test]
+    fn concurrent_thumb_requests_produce_one_valid_file() {
+        let tmp = tmpdir();
+        create_test_jpeg(&tmp.join("2020/photo.jpg"));
+
+        std::thread::scope(|s| {
+            for _ in 0..6 {
+                s.spawn(|| get_or_create_thumb(&tmp, "2020/photo.jpg").unwrap().unwrap());
+            }
+        });
+        let cached = thumb_cache_path(&tmp, "2020/photo.jpg");
+        assert!(image::open(&cached).is_ok());
+        assert!(!cached.with_extension("partial").exists());
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+
     // --- invalidate_thumb ---
 
     #[test]
@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::journal;
+use crate::metadata::{FileInfo, Metadata};
+use crate::thumb;
+
+/// Photos deleted from the gallery, kept until the trash is emptied (inside
+/// the photo base dir). Each one has its own folder, named by its id.
+pub const TRASH_DIR: &str = ".photo_sort_trash";
+
+/// What the trash keeps of a photo beside the file, in its folder.
+const ENTRY_FILE: &str = "entry.json";
+
+/// A photo in the trash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    #[serde(skip_deserializing)]
+    pub id: String,
+    /// Where it was in the library, and goes back to.
+    pub path: String,
+    pub deleted: NaiveDateTime,
+    /// Its metadata (tags, rating…) when it was deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<FileInfo>,
+    /// The albums it was in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub albums: Vec<String>,
+}
+
+pub fn trash_dir(base: &Path) -> PathBuf {
+    base.join(TRASH_DIR)
+}
+
+/// Folder of a trash entry, rejecting anything that is not a plain id.
+fn entry_dir(base: &Path, id: &str) -> Option<PathBuf> {
+    let plain = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    plain.then(|| trash_dir(base).join(id))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Reserve a new entry in the trash. Returns its id: the deletion time, made
+/// unique with a counter.
+fn reserve(base: &Path) -> Result<String> {
+    let dir = trash_dir(base);
+    fs::create_dir_all(&dir).context("Impossible de créer la corbeille")?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    for n in 0u32.. {
+        let id = if n == 0 {
+            stamp.clone()
+        } else {
+            format!("{stamp}-{n}")
+        };
+        match fs::create_dir(dir.join(&id)) {
+            Ok(()) => return Ok(id),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).context("Impossible de créer la corbeille"),
+        }
+    }
+    unreachable!()
+}
+
+/// Move `path` into the trash entry `id` with its metadata, which the library
+/// forgets. Picks up where it stopped if interrupted (journal replay).
+pub fn put(base: &Path, path: &str, id: &str, metadata: &mut Metadata) -> Result<Entry> {
+    let dir = entry_dir(base, id).context("Identifiant invalide")?;
+    fs::create_dir_all(&dir)?;
+    let entry_path = dir.join(ENTRY_FILE);
+    let entry = match fs::read_to_string(&entry_path) {
+        Ok(data) => serde_json::from_str(&data)?,
+        Err(_) => {
+            let entry = Entry {
+                id: id.to_string(),
+                path: path.to_string(),
+                deleted: chrono::Local::now().naive_local(),
+                info: metadata.files.get(path).cloned(),
+                albums: (metadata.albums.iter())
+                    .filter(|(_, files)| files.iter().any(|f| f == path))
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+            };
+            fs::write(&entry_path, serde_json::to_string_pretty(&entry)?)?;
+            entry
+        }
+    };
+    let (src, dest) = (base.join(path), dir.join(file_name(path)));
+    if src.exists() && !dest.exists() {
+        fs::rename(&src, &dest).with_context(|| format!("Impossible de déplacer {path}"))?;
+    }
+    thumb::invalidate_thumb(base, path);
+    metadata.forget_file(path);
+    Ok(Entry {
+        id: id.to_string(),
+        ..entry
+    })
+}
+
+/// Move a photo of the library into the trash, through the journal.
+/// `metadata` is the caller's to save.
+pub fn delete(base: &Path, path: &str, metadata: &mut Metadata) -> Result<Entry> {
+    let id = reserve(base)?;
+    let op = journal::Operation::Trash {
+        path: path.to_string(),
+        id: id.clone(),
+    };
+    journal::run(base, &op, || put(base, path, &id, metadata))
+}
+
+fn load_entry(base: &Path, id: &str) -> Option<Entry> {
+    let dir = entry_dir(base, id)?;
+    let data = fs::read_to_string(dir.join(ENTRY_FILE)).ok()?;
+    let entry: Entry = serde_json::from_str(&data).ok()?;
+    // An interrupted restore leaves the entry without its file
+    dir.join(file_name(&entry.path)).is_file().then(|| Entry {
+        id: id.to_string(),
+        ..entry
+    })
+}
+
+/// `true` if the trash holds the photo `id`.
+pub fn contains(base: &Path, id: &str) -> bool {
+    load_entry(base, id).is_some()
+}
+
+/// The photos in the trash, most recently deleted first.
+pub fn list(base: &Path) -> Vec<Entry> {
+    let Ok(dirs) = fs::read_dir(trash_dir(base)) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<Entry> = dirs
+        .filter_map(|e| e.ok())
+        .filter_map(|e| load_entry(base, &e.file_name().to_string_lossy()))
+        .collect();
+    entries.sort_by(|a, b| b.deleted.cmp(&a.deleted).then_with(|| b.id.cmp(&a.id)));
+    entries
+}
+
+/// Put a photo back where it was, with its metadata and albums. Refused if
+/// another file took its place meanwhile. `metadata` is the caller's to save.
+pub fn restore(base: &Path, id: &str, metadata: &mut Metadata) -> Result<Entry> {
+    let entry =
+        load_entry(base, id).with_context(|| format!("Introuvable dans la corbeille : {id}"))?;
+    let dest = base.join(&entry.path);
+    if dest.exists() {
+        anyhow::bail!("Un fichier existe déjà à {}", entry.path);
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let dir = trash_dir(base).join(id);
+    fs::rename(dir.join(file_name(&entry.path)), &dest)
+        .with_context(|| format!("Impossible de restaurer {}", entry.path))?;
+    if let Some(info) = &entry.info {
+        metadata.files.insert(entry.path.clone(), info.clone());
+    }
+    for album in &entry.albums {
+        metadata.add_to_album(album, &entry.path);
+    }
+    let _ = fs::remove_dir_all(&dir);
+    Ok(entry)
+}
+
+/// Delete for good the photos in the trash, or only those deleted before
+/// `before`. Returns how many.
+pub fn empty(base: &Path, before: Option<NaiveDateTime>) -> Result<usize> {
+    let mut removed = 0;
+    for entry in list(base) {
+        if before.is_some_and(|limit| entry.deleted >= limit) {
+            continue;
+        }
+        fs::remove_dir_all(trash_dir(base).join(&entry.id))
+            .with_context(|| format!("Impossible de supprimer {}", entry.path))?;
+        removed += 1;
+    }
+    // Entries left behind by interrupted operations
+    if before.is_none() && trash_dir(base).exists() {
+        fs::remove_dir_all(trash_dir(base)).context("Impossible de vider la corbeille")?;
+    }
+    Ok(removed)
+}
+
+pub fn run_list(dir: &Path) -> Result<()> {
+    let entries = list(dir);
+    if entries.is_empty() {
+        println!("La corbeille est vide");
+    }
+    for entry in &entries {
+        println!(
+            "{}  {}  {}",
+            entry.id,
+            entry.deleted.format("%Y-%m-%d %H:%M"),
+            entry.path
+        );
+    }
+    Ok(())
+}
+
+pub fn run_restore(dir: &Path, id: &str) -> Result<()> {
+    journal::recover_and_report(dir)?;
+    let mut metadata = Metadata::load(dir)?;
+    let entry = restore(dir, id, &mut metadata)?;
+    metadata.save(dir)?;
+    println!("  {} {} restaurée", style("✔").green().bold(), entry.path);
+    Ok(())
+}
+
+pub fn run_empty(dir: &Path, older_than_days: Option<u32>) -> Result<()> {
+    journal::recover_and_report(dir)?;
+    let before = older_than_days
+        .map(|days| chrono::Local::now().naive_local() - chrono::Duration::days(days.into()));
+    let removed = empty(dir, before)?;
+    println!(
+        "  {} {removed} photo(s) supprimée(s) définitivement",
+        style("✔").green().bold()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir =
+            std::env::temp_dir().join(format!("photo_sort_trash_test_{}_{id}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("2020")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn deleted_photos_come_back_with_their_metadata() {
+        let tmp = tmpdir();
+        fs::write(tmp.join("2020/a.jpg"), b"photo").unwrap();
+        let mut meta = Metadata::default();
+        meta.add_tag("2020/a.jpg", "plage");
+        meta.set_rating("2020/a.jpg", Some(4));
+        meta.add_to_album("Été", "2020/a.jpg");
+
+        let entry = delete(&tmp, "2020/a.jpg", &mut meta).unwrap();
+        assert!(!tmp.join("2020/a.jpg").exists());
+        assert!(meta.get_tags("2020/a.jpg").is_empty());
+        assert!(meta.albums["Été"].is_empty());
+        assert_eq!(list(&tmp), vec![entry.clone()]);
+        assert_eq!(entry.albums, ["Été"]);
+
+        let restored = restore(&tmp, &entry.id, &mut meta).unwrap();
+        assert_eq!(restored.path, "2020/a.jpg");
+        assert_eq!(fs::read(tmp.join("2020/a.jpg")).unwrap(), b"photo");
+        assert_eq!(meta.get_tags("2020/a.jpg"), ["plage"]);
+        assert_eq!(meta.get_rating("2020/a.jpg"), Some(4));
+        assert_eq!(meta.albums["Été"], ["2020/a.jpg"]);
+        assert!(list(&tmp).is_empty());
+        assert!(restore(&tmp, &entry.id, &mut meta).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn restore_never_overwrites() {
+        let tmp = tmpdir();
+        fs::write(tmp.join("2020/a.jpg"), b"old").unwrap();
+        let mut meta = Metadata::default();
+        let entry = delete(&tmp, "2020/a.jpg", &mut meta).unwrap();
+        fs::write(tmp.join("2020/a.jpg"), b"new").unwrap();
+        assert!(restore(&tmp, &entry.id, &mut meta).is_err());
+        assert_eq!(fs::read(tmp.join("2020/a.jpg")).unwrap(), b"new");
+        assert_eq!(list(&tmp).len(), 1);
+        assert!(restore(&tmp, "../2020", &mut meta).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn emptying_keeps_the_recent_deletions_if_asked() {
+        let tmp = tmpdir();
+        let mut meta = Metadata::default();
+        for name in ["a", "b"] {
+            fs::write(tmp.join(format!("2020/{name}.jpg")), name).unwrap();
+            delete(&tmp, &format!("2020/{name}.jpg"), &mut meta).unwrap();
+        }
+        let ids: Vec<String> = list(&tmp).into_iter().map(|e| e.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+
+        let yesterday = chrono::Local::now().naive_local() - chrono::Duration::days(1);
+        assert_eq!(empty(&tmp, Some(yesterday)).unwrap(), 0);
+        assert_eq!(list(&tmp).len(), 2);
+        assert_eq!(empty(&tmp, None).unwrap(), 2);
+        assert!(list(&tmp).is_empty());
+        assert!(!trash_dir(&tmp).exists());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}
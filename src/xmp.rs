@@ -0,0 +1,767 @@
+use anyhow::{Context, Result};
+use console::style;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use crate::gallery::collect_photos;
+use crate::metadata::{FileInfo, Metadata, Pick, Resolution};
+
+/// Bytes scanned for an XMP packet in non-JPEG files (TIFF, DNG, HEIC…),
+/// where it usually sits near the start.
+const XMP_SCAN_LIMIT: u64 = 1024 * 1024;
+
+/// APP1 header of a JPEG XMP segment.
+const XMP_APP1_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// APP13 header of a JPEG Photoshop segment (holding IPTC records).
+const PHOTOSHOP_APP13_HEADER: &[u8] = b"Photoshop 3.0\0";
+
+/// Photoshop image resource holding IPTC-NAA records.
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+/// Largest XMP packet fitting in one APP1 segment (Extended XMP is not
+/// written).
+const MAX_XMP_SEGMENT: usize = 65533 - XMP_APP1_HEADER.len();
+
+static RATING_ATTR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s+xmp:Rating\s*=\s*("[^"]*"|'[^']*')"#).unwrap());
+static RATING_ELEM_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)\s*<xmp:Rating>.*?</xmp:Rating>").unwrap());
+static SUBJECT_ELEM_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\s*(<dc:subject\s*/>|<dc:subject>.*?</dc:subject>)").unwrap()
+});
+static DESCRIPTION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<rdf:Description\b([^>]*?)(/?)>").unwrap());
+
+static RATING_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"xmp:Rating(?:\s*=\s*["']|>\s*)(-?\d+)"#).unwrap());
+static SUBJECT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<dc:subject>(.*?)</dc:subject>").unwrap());
+static LI_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap());
+static GPS_LAT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"exif:GPSLatitude(?:\s*=\s*["']|>\s*)([^"'<]+)"#).unwrap());
+static GPS_LON_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"exif:GPSLongitude(?:\s*=\s*["']|>\s*)([^"'<]+)"#).unwrap());
+
+/// How `xmp-import` settles a tag list or rating present both in the
+/// metadata and in a sidecar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportPolicy {
+    /// Keep the metadata, only fill what is missing
+    Ours,
+    /// Take the sidecar
+    Theirs,
+    /// Union of the tags, highest rating
+    Merge,
+}
+
+/// Curation left in a photo by other tools (Lightroom, Picasa, digiKam…).
+#[derive(Debug, Default, PartialEq)]
+pub struct Embedded {
+    /// XMP rating, when between 1 and 5 (0 and -1 "rejected" are ignored).
+    pub rating: Option<u8>,
+    /// XMP `dc:subject` and IPTC keywords, without duplicates.
+    pub keywords: Vec<String>,
+}
+
+impl Embedded {
+    pub fn is_empty(&self) -> bool {
+        self.rating.is_none() && self.keywords.is_empty()
+    }
+
+    fn add_keyword(&mut self, keyword: &str) {
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !self.keywords.iter().any(|k| k == keyword) {
+            self.keywords.push(keyword.to_string());
+        }
+    }
+}
+
+/// Read the rating and keywords embedded in `path`. Unreadable files and
+/// files without any yield an empty result.
+pub fn read_embedded(path: &Path) -> Embedded {
+    let (xmp, iptc) = match jpeg_segments(path) {
+        Some(found) => found,
+        None => (scan_packet(path), Vec::new()),
+    };
+    let mut embedded = xmp.map(|xmp| parse_xmp(&xmp)).unwrap_or_default();
+    for keyword in iptc {
+        embedded.add_keyword(&keyword);
+    }
+    embedded
+}
+
+/// XMP packet within the first `XMP_SCAN_LIMIT` bytes of `path`.
+fn scan_packet(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(XMP_SCAN_LIMIT)
+        .read_to_end(&mut head)
+        .ok()?;
+    find_packet(&head).map(str::to_string)
+}
+
+/// XMP packet (`<x:xmpmeta>…</x:xmpmeta>`) found in raw bytes.
+fn find_packet(data: &[u8]) -> Option<&str> {
+    let start = find(data, b"<x:xmpmeta")?;
+    let end = start + find(&data[start..], b"</x:xmpmeta>")? + b"</x:xmpmeta>".len();
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// XMP packet and IPTC keywords of a JPEG, read from its APP1 / APP13
+/// segments (only the headers before the image data are read). `None` if
+/// `path` is not a readable JPEG.
+fn jpeg_segments(path: &Path) -> Option<(Option<String>, Vec<String>)> {
+    let mut reader = std::io::BufReader::new(fs::File::open(path).ok()?);
+    let mut marker = [0u8; 4];
+    reader.read_exact(&mut marker[..2]).ok()?;
+    if marker[..2] != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut xmp = None;
+    let mut keywords = Vec::new();
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        // Start of scan or end of image: no more metadata segments
+        if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 {
+            break;
+        }
+        let len = u16::from_be_bytes([marker[2], marker[3]]) as usize;
+        let mut data = vec![0u8; len.saturating_sub(2)];
+        reader.read_exact(&mut data).ok()?;
+        match marker[1] {
+            0xE1 if data.starts_with(XMP_APP1_HEADER) => {
+                xmp = String::from_utf8(data[XMP_APP1_HEADER.len()..].to_vec()).ok();
+            }
+            0xED if data.starts_with(PHOTOSHOP_APP13_HEADER) => {
+                keywords.extend(iptc_keywords(&data[PHOTOSHOP_APP13_HEADER.len()..]));
+            }
+            _ => {}
+        }
+    }
+    Some((xmp, keywords))
+}
+
+/// Keywords (IPTC dataset 2:25) of the Photoshop image resources `data`.
+fn iptc_keywords(data: &[u8]) -> Vec<String> {
+    let mut keywords = Vec::new();
+    let mut pos = 0;
+    while let Some(block) = data.get(pos..pos + 7)
+        && block.starts_with(b"8BIM")
+    {
+        let id = u16::from_be_bytes([block[4], block[5]]);
+        // Pascal name, padded to an even length (length byte included)
+        let name_len = (block[6] as usize + 2) & !1;
+        let size_at = pos + 6 + name_len;
+        let Some(size) = data.get(size_at..size_at + 4) else {
+            break;
+        };
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let Some(resource) = data.get(size_at + 4..size_at + 4 + size) else {
+            break;
+        };
+        if id == IPTC_RESOURCE_ID {
+            let mut i = 0;
+            while let Some(record) = resource.get(i..i + 5)
+                && record[0] == 0x1C
+            {
+                let len = u16::from_be_bytes([record[3], record[4]]) as usize;
+                let Some(value) = resource.get(i + 5..i + 5 + len) else {
+                    break;
+                };
+                if record[1] == 2 && record[2] == 25 {
+                    keywords.push(String::from_utf8_lossy(value).to_string());
+                }
+                i += 5 + len;
+            }
+        }
+        pos = size_at + 4 + size + (size & 1);
+    }
+    keywords
+}
+
+/// Rating and `dc:subject` keywords of an XMP packet.
+pub fn parse_xmp(xmp: &str) -> Embedded {
+    let mut embedded = Embedded {
+        rating: RATING_RE
+            .captures(xmp)
+            .and_then(|c| c[1].parse::<i8>().ok())
+            .filter(|r| (1..=5).contains(r))
+            .map(|r| r as u8),
+        ..Default::default()
+    };
+    if let Some(subject) = SUBJECT_RE.captures(xmp) {
+        for item in LI_RE.captures_iter(&subject[1]) {
+            embedded.add_keyword(&unescape(&item[1]));
+        }
+    }
+    embedded
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `xmp` with its rating and `dc:subject` replaced by the given ones (removed
+/// when `None` / empty). Every other property is kept as is. `None` if the
+/// packet has no `rdf:Description` to hold them.
+fn update_packet(xmp: &str, rating: Option<u8>, keywords: &[String]) -> Option<String> {
+    let xmp = RATING_ATTR_RE.replace_all(xmp, "");
+    let xmp = RATING_ELEM_RE.replace_all(&xmp, "");
+    let xmp = SUBJECT_ELEM_RE.replace_all(&xmp, "");
+
+    let description = DESCRIPTION_RE.captures(&xmp)?;
+    let whole = description.get(0)?;
+    let mut attributes = description[1].to_string();
+    for (prefix, uri) in [
+        ("xmp", "http://ns.adobe.com/xap/1.0/"),
+        ("dc", "http://purl.org/dc/elements/1.1/"),
+    ] {
+        if !xmp.contains(&format!("xmlns:{prefix}=")) {
+            attributes.push_str(&format!(" xmlns:{prefix}=\"{uri}\""));
+        }
+    }
+    let mut properties = String::new();
+    if let Some(rating) = rating {
+        properties.push_str(&format!("<xmp:Rating>{rating}</xmp:Rating>"));
+    }
+    if !keywords.is_empty() {
+        properties.push_str("<dc:subject><rdf:Bag>");
+        for keyword in keywords {
+            properties.push_str(&format!("<rdf:li>{}</rdf:li>", escape(keyword)));
+        }
+        properties.push_str("</rdf:Bag></dc:subject>");
+    }
+    // A self-closing description gets a body for the new properties
+    let closing = if &description[2] == "/" {
+        "</rdf:Description>"
+    } else {
+        ""
+    };
+    Some(format!(
+        "{}<rdf:Description{attributes}>{properties}{closing}{}",
+        &xmp[..whole.start()],
+        &xmp[whole.end()..]
+    ))
+}
+
+/// Minimal packet for a JPEG without XMP.
+fn new_packet(rating: Option<u8>, keywords: &[String]) -> String {
+    let body = update_packet(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\"><rdf:Description rdf:about=\"\"/></rdf:RDF></x:xmpmeta>",
+        rating,
+        keywords,
+    )
+    .unwrap_or_default();
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>{body}<?xpacket end=\"w\"?>"
+    )
+}
+
+/// Write `rating` and `keywords` into the XMP packet of the JPEG at `path`,
+/// replacing the ones already there. The file is rewritten next to itself,
+/// read back, then renamed over the original: an interruption leaves either
+/// the old or the new file. Returns `false` if nothing had to change.
+pub fn write_jpeg(path: &Path, rating: Option<u8>, keywords: &[String]) -> Result<bool> {
+    let data = fs::read(path).with_context(|| format!("Impossible de lire {}", path.display()))?;
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        anyhow::bail!("{} n'est pas un JPEG", path.display());
+    }
+
+    // Metadata segments: (start, end) of each, and where the XMP one is
+    let mut pos = 2;
+    let mut xmp_segment = None;
+    let mut insert_at = 2;
+    while let Some(header) = data.get(pos..pos + 4)
+        && header[0] == 0xFF
+    {
+        let marker = header[1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let end = pos + 2 + u16::from_be_bytes([header[2], header[3]]) as usize;
+        if end > data.len() {
+            anyhow::bail!("{} : segment JPEG tronqué", path.display());
+        }
+        match marker {
+            0xE1 if data[pos + 4..end].starts_with(XMP_APP1_HEADER) => {
+                xmp_segment = Some((pos, end))
+            }
+            // After JFIF (APP0) and EXIF (APP1), as the XMP spec suggests
+            0xE0 | 0xE1 => insert_at = end,
+            _ => {}
+        }
+        pos = end;
+    }
+
+    let packet = match xmp_segment {
+        Some((start, end)) => {
+            let old = std::str::from_utf8(&data[start + 4 + XMP_APP1_HEADER.len()..end])
+                .context("Paquet XMP illisible")?;
+            let current = parse_xmp(old);
+            if current.rating == rating && current.keywords == keywords {
+                return Ok(false);
+            }
+            update_packet(old, rating, keywords).context("Paquet XMP sans rdf:Description")?
+        }
+        None if rating.is_none() && keywords.is_empty() => return Ok(false),
+        None => new_packet(rating, keywords),
+    };
+    if packet.len() > MAX_XMP_SEGMENT {
+        anyhow::bail!("{} : paquet XMP trop volumineux", path.display());
+    }
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&((packet.len() + XMP_APP1_HEADER.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(XMP_APP1_HEADER);
+    segment.extend_from_slice(packet.as_bytes());
+    let (cut_start, cut_end) = xmp_segment.unwrap_or((insert_at, insert_at));
+    let mut out = Vec::with_capacity(data.len() + segment.len());
+    out.extend_from_slice(&data[..cut_start]);
+    out.extend_from_slice(&segment);
+    out.extend_from_slice(&data[cut_end..]);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.xmp-tmp"));
+    let written = (|| -> Result<()> {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&out)?;
+        file.sync_all()?;
+        let check = read_embedded(&tmp);
+        if check.rating != rating || check.keywords != keywords {
+            anyhow::bail!("relecture du XMP différente");
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.context(format!("Impossible de réécrire {}", path.display())));
+    }
+    Ok(true)
+}
+
+/// GPS position of an XMP packet (`exif:GPSLatitude="48,51.5N"`, degrees
+/// then decimal minutes, or degrees, minutes and seconds).
+pub fn parse_position(xmp: &str) -> Option<(f64, f64)> {
+    let lat = parse_coordinate(GPS_LAT_RE.captures(xmp)?[1].trim(), 'N', 'S')?;
+    let lon = parse_coordinate(GPS_LON_RE.captures(xmp)?[1].trim(), 'E', 'W')?;
+    crate::metadata::is_valid_position(lat, lon).then_some((lat, lon))
+}
+
+fn parse_coordinate(value: &str, positive: char, negative: char) -> Option<f64> {
+    let direction = value.chars().last()?.to_ascii_uppercase();
+    let sign = match direction {
+        d if d == positive => 1.0,
+        d if d == negative => -1.0,
+        _ => return None,
+    };
+    let parts: Vec<f64> = value[..value.len() - 1]
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    let degrees: f64 = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(v, d)| v / d)
+        .sum();
+    Some(sign * degrees)
+}
+
+/// Sidecars of a library file: `a.cr2.xmp` (darktable, digiKam) and
+/// `a.xmp` (Lightroom, Capture One), lower or upper case.
+fn sidecars_of(path: &Path) -> Vec<std::path::PathBuf> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    [name, stem]
+        .iter()
+        .flat_map(|base| [format!("{base}.xmp"), format!("{base}.XMP")])
+        .map(|sidecar| path.with_file_name(sidecar))
+        .filter(|sidecar| sidecar.is_file())
+        .collect()
+}
+
+/// Merge metadata imported from another tool following `policy`, and return
+/// the number of files changed. A side without a value never wins; captions
+/// and color labels already set are only replaced with `Theirs`.
+pub fn merge_imported(metadata: &mut Metadata, incoming: &Metadata, policy: ImportPolicy) -> usize {
+    let pick = match policy {
+        ImportPolicy::Ours => Pick::Current,
+        ImportPolicy::Theirs => Pick::Incoming,
+        ImportPolicy::Merge => Pick::Both,
+    };
+    let mut resolutions = HashMap::new();
+    for (file, theirs) in &incoming.files {
+        let Some(ours) = metadata.files.get(file) else {
+            continue;
+        };
+        // A side without a value never wins over one that has it
+        resolutions.insert(
+            file.clone(),
+            Resolution {
+                tags: if ours.tags.is_empty() {
+                    Pick::Incoming
+                } else if theirs.tags.is_empty() {
+                    Pick::Current
+                } else {
+                    pick
+                },
+                rating: if ours.rating.is_none() {
+                    Pick::Incoming
+                } else if theirs.rating.is_none() {
+                    Pick::Current
+                } else {
+                    pick
+                },
+            },
+        );
+    }
+    let before: HashMap<String, FileInfo> = incoming
+        .files
+        .keys()
+        .filter_map(|f| Some((f.clone(), metadata.files.get(f)?.clone())))
+        .collect();
+    metadata.merge(incoming, &resolutions);
+    for (file, info) in &incoming.files {
+        let Some(ours) = before.get(file) else {
+            continue;
+        };
+        if policy == ImportPolicy::Theirs {
+            if let (Some(lat), Some(lon)) = (info.lat, info.lon) {
+                metadata.set_position(file, Some((lat, lon)));
+            }
+            if info.label.is_some() {
+                metadata.set_label(file, info.label.as_deref());
+            }
+        } else if let Some(caption) = &ours.caption {
+            // `merge` lets the incoming caption win
+            metadata.set_caption(file, caption);
+        }
+    }
+    incoming
+        .files
+        .keys()
+        .filter(|f| before.get(*f) != metadata.files.get(*f))
+        .count()
+}
+
+/// Merge the rating, keywords and GPS position of the XMP sidecars found
+/// next to the library's photos into its metadata.
+pub fn run_xmp_import(dir: &Path, policy: ImportPolicy) -> Result<()> {
+    let mut metadata = Metadata::load(dir)?;
+    let mut incoming = Metadata::default();
+    let mut read = 0usize;
+    for file in collect_photos(dir).into_values().flatten() {
+        for sidecar in sidecars_of(&dir.join(&file)) {
+            let Ok(xmp) = fs::read_to_string(&sidecar) else {
+                eprintln!(
+                    "  {} {} illisible",
+                    style("!").yellow().bold(),
+                    sidecar.display()
+                );
+                continue;
+            };
+            read += 1;
+            let embedded = parse_xmp(&xmp);
+            if embedded.rating.is_some() {
+                incoming.set_rating(&file, embedded.rating);
+            }
+            for keyword in &embedded.keywords {
+                incoming.add_tag(&file, keyword);
+            }
+            if let Some(position) = parse_position(&xmp) {
+                incoming.set_position(&file, Some(position));
+            }
+        }
+    }
+
+    let updated = merge_imported(&mut metadata, &incoming, policy);
+    metadata.save(dir)?;
+
+    println!(
+        "  {} {} sidecar(s) XMP lu(s), {} fichier(s) mis à jour",
+        style("✔").green().bold(),
+        style(read).green().bold(),
+        style(updated).green().bold()
+    );
+    Ok(())
+}
+
+/// Embed the rating and tags of the library (or of `files` only) into the
+/// XMP of its JPEGs.
+pub fn run_embed(dir: &Path, files: &[String]) -> Result<()> {
+    let metadata = Metadata::load(dir)?;
+    let targets: Vec<String> = if files.is_empty() {
+        collect_photos(dir).into_values().flatten().collect()
+    } else {
+        files.to_vec()
+    };
+
+    let (mut updated, mut failed) = (0usize, 0usize);
+    for file in targets.iter().filter(|f| {
+        Path::new(f)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg"))
+    }) {
+        let rating = metadata.get_rating(file);
+        match write_jpeg(&dir.join(file), rating, metadata.get_tags(file)) {
+            Ok(true) => updated += 1,
+            Ok(false) => {}
+            Err(e) => {
+                failed += 1;
+                eprintln!("  {} {e:#}", style("✘").red().bold());
+            }
+        }
+    }
+
+    println!(
+        "  {} {} JPEG mis à jour (note et tags dans le XMP)",
+        style("✔").green().bold(),
+        style(updated).green().bold()
+    );
+    if failed > 0 {
+        println!("  {} {failed} échec(s)", style("!").yellow().bold());
+    }
+    Ok(())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn tmpdir() -> PathBuf {
+        let id = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir =
+            std::env::temp_dir().join(format!("photo_sort_xmp_test_{}_{id}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const LIGHTROOM_XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmp:Rating="4">
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>vacances</rdf:li>
+     <rdf:li>Tom &amp; Léa</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut seg = vec![0xFF, marker];
+        seg.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        seg.extend_from_slice(payload);
+        seg
+    }
+
+    fn iptc_app13(keywords: &[&str]) -> Vec<u8> {
+        let mut records = Vec::new();
+        for kw in keywords {
+            records.extend_from_slice(&[0x1C, 2, 25]);
+            records.extend_from_slice(&(kw.len() as u16).to_be_bytes());
+            records.extend_from_slice(kw.as_bytes());
+        }
+        let mut data = PHOTOSHOP_APP13_HEADER.to_vec();
+        data.extend_from_slice(b"8BIM");
+        data.extend_from_slice(&IPTC_RESOURCE_ID.to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&(records.len() as u32).to_be_bytes());
+        data.extend_from_slice(&records);
+        if records.len() % 2 == 1 {
+            data.push(0);
+        }
+        data
+    }
+
+    #[test]
+    fn parses_xmp_rating_and_subject() {
+        let embedded = parse_xmp(LIGHTROOM_XMP);
+        assert_eq!(embedded.rating, Some(4));
+        assert_eq!(embedded.keywords, vec!["vacances", "Tom & Léa"]);
+
+        let element = parse_xmp("<x:xmpmeta><xmp:Rating>2</xmp:Rating></x:xmpmeta>");
+        assert_eq!(element.rating, Some(2));
+        assert_eq!(parse_xmp(r#"xmp:Rating="-1""#).rating, None);
+        assert_eq!(parse_xmp(r#"xmp:Rating="0""#).rating, None);
+    }
+
+    #[test]
+    fn reads_jpeg_xmp_and_iptc() {
+        let tmp = tmpdir();
+        let mut xmp = XMP_APP1_HEADER.to_vec();
+        xmp.extend_from_slice(LIGHTROOM_XMP.as_bytes());
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend(segment(0xE1, &xmp));
+        jpeg.extend(segment(0xED, &iptc_app13(&["noel", "vacances"])));
+        jpeg.extend([0xFF, 0xDA, 0, 2, 1, 2, 3, 0xFF, 0xD9]);
+        let path = tmp.join("a.jpg");
+        fs::write(&path, jpeg).unwrap();
+
+        let embedded = read_embedded(&path);
+        assert_eq!(embedded.rating, Some(4));
+        assert_eq!(embedded.keywords, vec!["vacances", "Tom & Léa", "noel"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    fn write_plain_jpeg(path: &Path) {
+        let img =
+            image::RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 10, y as u8 * 20, 0]));
+        image::DynamicImage::ImageRgb8(img).save(path).unwrap();
+    }
+
+    #[test]
+    fn writes_rating_and_keywords_into_jpeg_without_xmp() {
+        let tmp = tmpdir();
+        let path = tmp.join("a.jpg");
+        write_plain_jpeg(&path);
+        let before = image::open(&path).unwrap().to_rgb8();
+
+        let keywords = vec!["plage".to_string(), "Tom & Léa".to_string()];
+        assert!(write_jpeg(&path, Some(4), &keywords).unwrap());
+        let embedded = read_embedded(&path);
+        assert_eq!(embedded.rating, Some(4));
+        assert_eq!(embedded.keywords, keywords);
+        // Pixels untouched, no temporary file left behind
+        assert_eq!(image::open(&path).unwrap().to_rgb8(), before);
+        assert_eq!(fs::read_dir(&tmp).unwrap().count(), 1);
+
+        assert!(!write_jpeg(&path, Some(4), &keywords).unwrap());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn rewrites_existing_packet_keeping_other_properties() {
+        let tmp = tmpdir();
+        let plain = tmp.join("plain.jpg");
+        write_plain_jpeg(&plain);
+        let data = fs::read(&plain).unwrap();
+        let packet = LIGHTROOM_XMP.replace(
+            "xmp:Rating=\"4\"",
+            "xmp:Rating=\"4\" photoshop:City=\"Biarritz\"",
+        );
+        let mut xmp = XMP_APP1_HEADER.to_vec();
+        xmp.extend_from_slice(packet.as_bytes());
+        let mut jpeg = data[..2].to_vec();
+        jpeg.extend(segment(0xE1, &xmp));
+        jpeg.extend_from_slice(&data[2..]);
+        let path = tmp.join("a.jpg");
+        fs::write(&path, jpeg).unwrap();
+
+        assert!(write_jpeg(&path, Some(2), &["noel".to_string()]).unwrap());
+        let embedded = read_embedded(&path);
+        assert_eq!(embedded.rating, Some(2));
+        assert_eq!(embedded.keywords, vec!["noel"]);
+        let (xmp, _) = jpeg_segments(&path).unwrap();
+        let xmp = xmp.unwrap();
+        assert!(xmp.contains("photoshop:City=\"Biarritz\""));
+        assert_eq!(xmp.matches("xmp:Rating").count(), 2);
+
+        assert!(write_jpeg(&path, None, &[]).unwrap());
+        assert!(read_embedded(&path).is_empty());
+        assert!(write_jpeg(&tmp.join("missing.jpg"), Some(1), &[]).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn parses_xmp_gps_position() {
+        let xmp = r#"<rdf:Description exif:GPSLatitude="43,29.0N" exif:GPSLongitude="1,33,30W"/>"#;
+        let (lat, lon) = parse_position(xmp).unwrap();
+        assert!((lat - 43.483333).abs() < 1e-6, "{lat}");
+        assert!((lon + 1.558333).abs() < 1e-6, "{lon}");
+        assert_eq!(parse_position(LIGHTROOM_XMP), None);
+        assert_eq!(
+            parse_position(r#"exif:GPSLatitude="43,29.0X" exif:GPSLongitude="1,2E""#),
+            None
+        );
+    }
+
+    #[test]
+    fn imports_sidecars_following_policy() {
+        let tmp = tmpdir();
+        fs::create_dir_all(tmp.join("2020")).unwrap();
+        for name in ["a.cr2", "b.jpg", "c.jpg"] {
+            fs::write(tmp.join("2020").join(name), "x").unwrap();
+        }
+        // darktable-style and Lightroom-style sidecars
+        fs::write(tmp.join("2020/a.cr2.xmp"), LIGHTROOM_XMP).unwrap();
+        fs::write(
+            tmp.join("2020/b.xmp"),
+            r#"<x:xmpmeta><rdf:Description xmp:Rating="5" exif:GPSLatitude="43,29.0N" exif:GPSLongitude="1,33.5W"><dc:subject><rdf:Bag><rdf:li>surf</rdf:li></rdf:Bag></dc:subject></rdf:Description></x:xmpmeta>"#,
+        )
+        .unwrap();
+        let mut meta = Metadata::default();
+        meta.set_rating("2020/a.cr2", Some(2));
+        meta.add_tag("2020/a.cr2", "famille");
+        meta.set_rating("2020/c.jpg", Some(1));
+        meta.save(&tmp).unwrap();
+
+        run_xmp_import(&tmp, ImportPolicy::Ours).unwrap();
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_rating("2020/a.cr2"), Some(2));
+        assert_eq!(meta.get_tags("2020/a.cr2"), ["famille"]);
+        assert_eq!(meta.get_rating("2020/b.jpg"), Some(5));
+        assert_eq!(meta.get_tags("2020/b.jpg"), ["surf"]);
+        assert!(meta.get_position("2020/b.jpg").is_some());
+        assert_eq!(meta.get_rating("2020/c.jpg"), Some(1));
+
+        run_xmp_import(&tmp, ImportPolicy::Merge).unwrap();
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_rating("2020/a.cr2"), Some(4));
+        assert_eq!(
+            meta.get_tags("2020/a.cr2"),
+            ["famille", "vacances", "Tom & Léa"]
+        );
+
+        run_xmp_import(&tmp, ImportPolicy::Theirs).unwrap();
+        let meta = Metadata::load(&tmp).unwrap();
+        assert_eq!(meta.get_tags("2020/a.cr2"), ["vacances", "Tom & Léa"]);
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    #[test]
+    fn finds_packet_in_other_formats() {
+        let tmp = tmpdir();
+        let path = tmp.join("a.dng");
+        let mut data = b"II*\0 binary \xff\xfe ".to_vec();
+        data.extend_from_slice(LIGHTROOM_XMP.as_bytes());
+        fs::write(&path, data).unwrap();
+        assert_eq!(read_embedded(&path).rating, Some(4));
+
+        fs::write(tmp.join("b.jpg"), "not a photo").unwrap();
+        assert!(read_embedded(&tmp.join("b.jpg")).is_empty());
+        assert!(read_embedded(&tmp.join("missing.jpg")).is_empty());
+        let _ = fs::remove_dir_all(&tmp);
+    }
+}